@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate, NaiveTime};
+
+use crate::config::AlarmConfig;
+
+/// Fires once per day at the configured local time.
+pub struct Alarm {
+    pub config: AlarmConfig,
+    at: NaiveTime,
+    last_fired: Option<NaiveDate>,
+}
+
+impl Alarm {
+    pub fn new(config: AlarmConfig) -> Option<Self> {
+        match NaiveTime::parse_from_str(&config.time, "%H:%M") {
+            Ok(at) => Some(Self {
+                config,
+                at,
+                last_fired: None,
+            }),
+            Err(err) => {
+                log::warn!("Invalid alarm time {:?}: {err}", config.time);
+                None
+            }
+        }
+    }
+
+    /// Returns true the first time it is called within the alarm minute.
+    ///
+    /// Starting shelltrax after the alarm time does not fire it retroactively.
+    pub fn due(&mut self) -> bool {
+        let now = Local::now();
+        let today = now.date_naive();
+        let time = now.time();
+
+        if self.last_fired == Some(today) {
+            return false;
+        }
+
+        // Adding a minute to 23:59 wraps around to 00:00, so compare how
+        // far past the alarm time it is instead
+        let late = time.signed_duration_since(self.at);
+        if late >= chrono::Duration::zero() && late < chrono::Duration::minutes(1) {
+            self.last_fired = Some(today);
+            return true;
+        }
+
+        false
+    }
+}
+
+/// Linear volume fade from silence up to `target`.
+pub struct VolumeRamp {
+    start: Instant,
+    duration: Duration,
+    target: f32,
+}
+
+impl VolumeRamp {
+    pub fn new(duration: Duration, target: f32) -> Self {
+        Self {
+            start: Instant::now(),
+            duration,
+            target,
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        if self.duration.is_zero() {
+            return self.target;
+        }
+
+        let progress = self.start.elapsed().as_secs_f32() / self.duration.as_secs_f32();
+        self.target * progress.min(1.0)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.start.elapsed() >= self.duration
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::library::{LibraryTrack, UNKNOWN_ALBUM, UNKNOWN_ARTIST};
+use crate::list::ListSelector;
+use crate::musicbrainz::{self, Release, ReleaseDetail};
+use crate::tag_writer::AlbumTags;
+
+/// Releases searched by track count alone are checked against the files'
+/// lengths; ones off by more than this per track on average are left out.
+const MAX_AVERAGE_LENGTH_DIFF_SECS: u64 = 10;
+
+/// Releases fetched to compare lengths with. Each costs a request, and
+/// requests go out a second apart.
+const LENGTH_SEARCH_LIMIT: usize = 3;
+
+/// Where a lookup has got to.
+pub enum LookupStep {
+    Searching,
+    /// Releases to pick from, best first
+    Candidates(ListSelector<Release>),
+    /// Fetching the picked release's tracks
+    Loading,
+    /// What each file's tags will be, waiting to be confirmed
+    Preview(ListSelector<AlbumChange>),
+    Failed(String),
+}
+
+/// What one file's tags are and what the release says they should be.
+#[derive(Debug, Clone)]
+pub struct AlbumChange {
+    pub track: LibraryTrack,
+    pub tags: AlbumTags,
+}
+
+enum Reply {
+    Candidates(Vec<Release>),
+    Release(ReleaseDetail),
+}
+
+/// Finding an album's files on MusicBrainz, with the requests made on a
+/// background thread.
+pub struct AlbumLookup {
+    /// The album's files, in disc and track order
+    pub tracks: Vec<LibraryTrack>,
+    pub step: LookupStep,
+    sender: Sender<anyhow::Result<Reply>>,
+    replies: Receiver<anyhow::Result<Reply>>,
+}
+
+impl AlbumLookup {
+    /// Starts searching for the release `tracks` are from, by their album
+    /// and artist tags, or by how many there are and how long they are when
+    /// there's no album tag.
+    pub fn start(mut tracks: Vec<LibraryTrack>) -> Self {
+        tracks.sort_by_key(|t| (t.disc_number.unwrap_or(1), t.track_number, t.path.clone()));
+        let (sender, replies) = mpsc::channel();
+
+        let first = &tracks[0];
+        let artist = first.album_artist.clone();
+        let album = first.album.clone();
+        let lengths: Vec<Option<u64>> = tracks.iter().map(|t| t.duration).collect();
+        let reply = sender.clone();
+        thread::spawn(move || {
+            let found = match album == UNKNOWN_ALBUM {
+                true => search_by_lengths(&artist, &lengths),
+                false => search_by_tags(&artist, &album, lengths.len()),
+            };
+            reply.send(found.map(Reply::Candidates)).ok();
+        });
+
+        Self {
+            tracks,
+            step: LookupStep::Searching,
+            sender,
+            replies,
+        }
+    }
+
+    /// Picks up the answer to the request in flight, if it's in.
+    pub fn poll(&mut self) {
+        let Ok(reply) = self.replies.try_recv() else {
+            return;
+        };
+
+        self.step = match reply {
+            Ok(Reply::Candidates(releases)) if releases.is_empty() => {
+                LookupStep::Failed("No matching releases found".to_string())
+            }
+            Ok(Reply::Candidates(releases)) => LookupStep::Candidates(ListSelector::new(releases)),
+            Ok(Reply::Release(release)) => match changes(&self.tracks, &release) {
+                changes if changes.is_empty() => LookupStep::Failed(
+                    "The release's tracks don't line up with the files".to_string(),
+                ),
+                changes => LookupStep::Preview(ListSelector::new(changes)),
+            },
+            Err(err) => LookupStep::Failed(format!("Lookup failed: {err}")),
+        };
+    }
+
+    /// Fetches the tracks of the release picked from the candidates.
+    pub fn choose(&mut self) {
+        let LookupStep::Candidates(candidates) = &self.step else {
+            return;
+        };
+        let Some(release) = candidates.selected_item() else {
+            return;
+        };
+
+        let id = release.id.clone();
+        let reply = self.sender.clone();
+        thread::spawn(move || {
+            reply
+                .send(musicbrainz::release(&id).map(Reply::Release))
+                .ok();
+        });
+        self.step = LookupStep::Loading;
+    }
+}
+
+/// Releases matching the album and artist tags, the ones with as many
+/// tracks as there are files first.
+fn search_by_tags(artist: &str, album: &str, count: usize) -> anyhow::Result<Vec<Release>> {
+    let mut releases = musicbrainz::search_releases(artist, album)?;
+    releases.sort_by_key(|r| r.track_count != count);
+    Ok(releases)
+}
+
+/// Releases with as many tracks as there are files, by the artist if the
+/// files name one, whose track lengths come close to the files'. Each one
+/// has to be fetched to compare, so only the best few search hits are.
+fn search_by_lengths(artist: &str, lengths: &[Option<u64>]) -> anyhow::Result<Vec<Release>> {
+    let mut query = format!("tracks:{}", lengths.len());
+    if !artist.is_empty() && artist != UNKNOWN_ARTIST {
+        query.push_str(&format!(" AND artist:{}", musicbrainz::phrase(artist)));
+    }
+
+    let mut matches = Vec::new();
+    for release in musicbrainz::search(&query, LENGTH_SEARCH_LIMIT)? {
+        thread::sleep(musicbrainz::REQUEST_INTERVAL);
+        // One release that won't load doesn't spoil the others
+        let detail = match musicbrainz::release(&release.id) {
+            Ok(detail) => detail,
+            Err(err) => {
+                log::warn!("Failed to fetch release {}: {err}", release.id);
+                continue;
+            }
+        };
+        let release_lengths = detail
+            .media
+            .iter()
+            .flat_map(|m| &m.tracks)
+            .map(|t| t.length);
+
+        let diffs: Vec<u64> = lengths
+            .iter()
+            .zip(release_lengths)
+            .filter_map(|(file, track)| Some((*file)?.abs_diff(track? / 1000)))
+            .collect();
+        if diffs.is_empty() {
+            continue;
+        }
+        let average = diffs.iter().sum::<u64>() / diffs.len() as u64;
+        if average <= MAX_AVERAGE_LENGTH_DIFF_SECS {
+            matches.push((average, release));
+        }
+    }
+
+    matches.sort_by_key(|(average, _)| *average);
+    Ok(matches.into_iter().map(|(_, release)| release).collect())
+}
+
+/// Pairs each file with a track of `release`: by disc and track number when
+/// every file has a track number, else in order.
+fn changes(tracks: &[LibraryTrack], release: &ReleaseDetail) -> Vec<AlbumChange> {
+    let release_tracks: Vec<(u32, &musicbrainz::ReleaseTrack)> = release
+        .media
+        .iter()
+        .flat_map(|medium| {
+            medium
+                .tracks
+                .iter()
+                .map(move |track| (medium.position, track))
+        })
+        .collect();
+    let numbered = tracks.iter().all(|t| t.track_number.is_some());
+
+    tracks
+        .iter()
+        .enumerate()
+        .filter_map(|(i, track)| {
+            let (disc, found) = match numbered {
+                true => release_tracks.iter().find(|(disc, found)| {
+                    *disc == track.disc_number.unwrap_or(1)
+                        && Some(found.position) == track.track_number
+                })?,
+                false => release_tracks.get(i)?,
+            };
+            let artist = match found.artist() {
+                artist if artist.is_empty() => release.artist(),
+                artist => artist,
+            };
+            Some(AlbumChange {
+                track: track.clone(),
+                tags: AlbumTags {
+                    title: found.title.clone(),
+                    artist,
+                    album: release.title.clone(),
+                    album_artist: release.artist(),
+                    track_number: found.position,
+                    disc_number: *disc,
+                    year: release.year(),
+                },
+            })
+        })
+        .collect()
+}
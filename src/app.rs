@@ -1,32 +1,207 @@
-use std::path::PathBuf;
+use std::cell::{RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use rand::seq::IndexedRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::album_lookup::{AlbumLookup, LookupStep};
+
+use crate::alarm::{Alarm, VolumeRamp};
+
+use crate::artwork;
+
+use crate::autodj;
+
 use crate::browser::BrowserState;
 
+use crate::chapters::{self, Chapter};
+
+use crate::collation::NameOrder;
+
+use crate::config::{self, AudioBackend, Config};
+
+use crate::cover_fetch::{CoverArtFetch, CoverRequest};
+
+use crate::duplicates::{self, DuplicateView};
+
+use crate::fingerprint::{FingerprintRequest, FingerprintScan};
+
+use crate::history::{self, HistoryEntry};
+
+use crate::incoming::IncomingWatcher;
+
 use crate::library::{
+    AlbumNode,
+    Bookmark,
+    LibraryFocus,
     LibraryState,
-    LibraryTrack
+    LibraryTrack,
+    scan_path_for_tracks,
+    scan_path_with_progress,
 };
 
-use crate::persistence;
+use crate::keymap::Keymap;
+
+use crate::list::ListSelector;
+
+use crate::loudness::LoudnessScanner;
+
+use crate::now_playing::{self, NowPlaying};
+
+use crate::persistence::{self, PlaybackState};
+
+use crate::playlist;
+
+use crate::prompt::{Prompt, PromptAction};
+
+use crate::query::{Query, SmartPlaylist};
+
+use crate::radio::Station;
+
+use crate::replaygain::ReplayGainMode;
+
+use crate::replaygain_scan::ReplayGainScan;
+
+use crate::retag::{self, RetagField, RetagMenu, RetagPreview};
+
+use crate::player::dsp::{EQ_BANDS, EQ_MAX_DB, EQ_PRESETS};
+use crate::player::{self, IcyInfo, Player, PlayerError};
+
+use crate::scan::{LibraryScan, ScanFilter, ScanJob, ScanProgress, ScanResult};
+use crate::scrobble_log;
+
+use crate::search::Search;
+
+use crate::session::Session;
+
+use crate::shuffle::ShuffleOrder;
+
+use crate::stats::{self, LibraryStats, StatsPeriod};
+
+use crate::tag_writer::{self, TagUpdate, TagWriter};
+use crate::watcher::LibraryWatcher;
+
+/// Largest per-track gain offset either way, in dB
+const MAX_TRACK_GAIN_DB: f32 = 12.0;
 
-use crate::player::Player;
+/// How many of the last played tracks the auto-DJ won't pick again
+const AUTO_DJ_RECENT: usize = 50;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+/// How many tracks the recently played view lists
+const RECENT_LIMIT: usize = 100;
+
+/// Slowest and fastest playback speeds.
+const MIN_SPEED: f32 = 0.5;
+const MAX_SPEED: f32 = 2.0;
+
+/// Going back a chapter within this much of its start goes to the one
+/// before instead of restarting it.
+const CHAPTER_RESTART_GRACE: Duration = Duration::from_secs(3);
+
+/// How often to look for a new output device after losing one.
+const OUTPUT_RETRY: Duration = Duration::from_secs(1);
+
+/// Rows of the settings popup.
+pub const SETTINGS: [&str; 1] = ["Balance"];
+
+/// Balance change per key press.
+const BALANCE_STEP: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AppScreen {
     Library,
     Browser,
+    History,
+    Recent,
+    Stats,
+    Duplicates,
+}
+
+/// What autoplay does at the end of a track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    /// Stop after the last track of the queue or album
+    #[default]
+    Off,
+    /// Play the same track again
+    Track,
+    /// Start the queue or album over after its last track
+    All,
+}
+
+impl RepeatMode {
+    pub fn next(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::Track,
+            RepeatMode::Track => RepeatMode::All,
+            RepeatMode::All => RepeatMode::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "off",
+            RepeatMode::Track => "one",
+            RepeatMode::All => "all",
+        }
+    }
 }
 
 pub struct App {
     pub screen: AppScreen,
     pub browser: BrowserState,
     pub library: Arc<Mutex<LibraryState>>,
-    pub player: Arc<Mutex<Player>>,
+    /// Only used from the UI thread; the player shares what its own
+    /// threads need through atomics
+    pub player: Rc<RefCell<Player>>,
     pub play_queue: Vec<PathBuf>,
     pub queue_index: usize,
     pub autoplay_enabled: bool,
+
+    /// Autoplay picks tracks by genre and tempo instead of library order
+    pub auto_dj: bool,
+
+    pub repeat: RepeatMode,
+
+    /// Output is silenced while playback goes on
+    pub muted: bool,
+
+    /// Stop once the current track ends instead of autoplaying; cleared
+    /// when it takes effect
+    pub stop_after_current: bool,
+
+    /// Autoplay goes through the library's tracks in random order
+    pub shuffle: bool,
+    shuffle_order: Option<ShuffleOrder>,
+
+    /// Which ReplayGain tags level the volume between tracks
+    pub replay_gain: ReplayGainMode,
+
+    /// Playback speed, 1.0 being normal
+    pub speed: f32,
+    /// Speed set by the playing track's folder preset, used instead of
+    /// `speed` until a track from elsewhere starts
+    folder_speed: Option<f32>,
+
+    /// A-B loop points in the current track; B is unset while marking
+    pub ab_loop: Option<(Duration, Option<Duration>)>,
+
+    /// User volume (0.0 - 1.0)
+    pub volume: f32,
+
+    /// Output device of the current stream and the volume last used on each
+    pub active_device: Option<String>,
+    pub device_volumes: HashMap<String, f32>,
+
     pub current_track: Option<LibraryTrack>,
 
     /// Playback duration in seconds
@@ -36,35 +211,280 @@ pub struct App {
     pub playback_start: Option<Instant>,
 
     pub paused_at: Option<Instant>,
-    pub paused_duration: Duration,
+
+    pub config: Config,
+    pub keymap: Keymap,
+    pub alarm: Option<Alarm>,
+
+    /// Remembered playback positions (seconds) for preset folders
+    pub positions: HashMap<PathBuf, u64>,
+
+    /// Active alarm fade-in, if any
+    pub volume_ramp: Option<VolumeRamp>,
+
+    /// Text input that currently captures the keyboard
+    pub prompt: Option<Prompt>,
+
+    /// Bookmarks popup for the current track
+    pub bookmark_list: Option<ListSelector<Bookmark>>,
+
+    /// Chapters embedded in the playing file, in order
+    pub chapters: Vec<Chapter>,
+
+    /// Chapter list popup for the current track
+    pub chapter_list: Option<ListSelector<Chapter>>,
+
+    /// Label facet popup: each label with its track count, `None` for all
+    pub label_list: Option<ListSelector<(Option<String>, usize)>>,
+
+    /// Completed plays, newest first
+    pub history: ListSelector<HistoryEntry>,
+
+    /// Duplicate files, found when the duplicates view is opened
+    pub duplicates: DuplicateView,
+
+    /// Time window shown in the stats view
+    pub stats_period: StatsPeriod,
+
+    /// What's in the library, when the stats view shows that instead of
+    /// the history
+    pub library_stats: Option<LibraryStats>,
+
+    /// Distinct tracks from the history, for the recently played view
+    pub recent: ListSelector<HistoryEntry>,
+
+    /// When `now_playing.json` was last written
+    now_playing_published: Option<Instant>,
+
+    /// Background tag writer, when `write_tags` is enabled
+    pub tag_writer: Option<TagWriter>,
+
+    /// Background loudness meter, when `analyze_loudness` is enabled
+    loudness_scanner: Option<LoudnessScanner>,
+
+    /// ReplayGain tagging started from the library, while it runs
+    replay_gain_scan: Option<ReplayGainScan>,
+
+    /// Albums having their covers looked up online
+    cover_fetch: Option<CoverArtFetch>,
+
+    /// The library being fingerprinted to find copies of the same recording
+    fingerprint_scan: Option<FingerprintScan>,
+
+    /// Files found to sound the same by the last fingerprint search, shown
+    /// in the duplicates view instead of tracks with matching tags
+    pub sound_duplicates: Option<Vec<Vec<PathBuf>>>,
+
+    /// Watches `incoming_dir` for new downloads
+    incoming: Option<IncomingWatcher>,
+
+    /// Watches the library folders, when `watch_library` is on
+    library_watcher: Option<LibraryWatcher>,
+
+    /// Folder being added to the library in the background
+    pub library_scan: Option<LibraryScan>,
+
+    /// Saved sessions popup, when open
+    pub session_list: Option<ListSelector<Session>>,
+
+    /// Saved radio stations popup
+    pub station_list: Option<ListSelector<Station>>,
+
+    /// Smart playlists popup, with how many tracks each matches
+    pub smart_list: Option<ListSelector<(SmartPlaylist, usize)>>,
+
+    /// Picking which tag to change on the tracks being retagged
+    pub retag_menu: Option<RetagMenu>,
+    /// Tag changes shown for confirmation before they're written
+    pub retag_preview: Option<RetagPreview>,
+
+    /// An album being looked up on MusicBrainz, when that popup is open
+    pub album_lookup: Option<AlbumLookup>,
+
+    /// Library tracks whose files are gone, when that popup is open
+    pub missing_list: Option<ListSelector<LibraryTrack>>,
+
+    /// The library search overlay, when open
+    pub search: Option<Search>,
+
+    /// Selected band of the equalizer popup, when open
+    pub equalizer_band: Option<usize>,
+
+    /// Selected row while the settings popup is open
+    pub settings_row: Option<usize>,
+
+    /// Whether the output diagnostics popup is open
+    pub show_diagnostics: bool,
+
+    /// Party mode: guests can play and queue, but restricted actions are
+    /// refused until unlocked with `party_code`
+    pub party_locked: bool,
+
+    /// Short notice shown over the screen, and when it appeared
+    toast: Option<(String, Instant)>,
+
+    /// Set while the output device is gone, with the last time we looked
+    /// for another one
+    output_lost: Option<Instant>,
+
+    /// Track and position to start once there's an output device to play on
+    waiting_output: Option<(PathBuf, Duration)>,
+
+    /// A track that couldn't be read or decoded, to be skipped
+    unplayable: Option<PathBuf>,
+
+    /// Whether the current track's play has been added to the history yet
+    play_recorded: bool,
+
+    /// Whether the current track's play count has gone up yet
+    play_counted: bool,
+
+    /// Track handed to the player to follow the current one without a gap
+    gapless_next: Option<PathBuf>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         let artists = persistence::load_library().unwrap_or_else(|_| vec![]);
 
+        let alarm = config.alarm.clone().and_then(Alarm::new);
+        let keymap = Keymap::new(
+            &config.keys,
+            &config.leader,
+            Duration::from_millis(config.key_timeout_ms),
+        );
+
+        let tag_writer = config.write_tags.then(TagWriter::spawn);
+        let loudness_scanner = config.analyze_loudness.then(LoudnessScanner::spawn);
+        let incoming = config.incoming_dir.clone().map(IncomingWatcher::new);
+
+        let state = persistence::load_state().unwrap_or_default();
+        let mut player = Player::new();
+        player.set_volume(state.volume);
+        let speed = state.speed.clamp(MIN_SPEED, MAX_SPEED);
+        player.set_speed(speed);
+        player
+            .dsp
+            .crossfeed
+            .store(state.crossfeed, Ordering::Relaxed);
+        for (band, db) in state.eq_gains.iter().enumerate() {
+            player.dsp.set_eq_gain(band, *db);
+        }
+        player.dsp.set_crossfeed_level(config.crossfeed_level);
+        player.dsp.set_night_ceiling(config.night_volume_ceiling);
+        player.dsp.set_silence_detection(
+            config.silence_threshold_db,
+            Duration::from_secs_f32(config.silence_min_secs.max(0.0)),
+        );
+        player.dsp.set_balance(config.balance);
+        player.set_fade_length(Duration::from_millis(config.fade_ms));
+        player.set_buffer_frames(config.output_buffer_frames);
+        player.set_audio_host(config.audio_host.as_deref());
+        player.set_output(config.output_device.clone(), config.bit_perfect);
+        if config.audio_backend == AudioBackend::Pipe {
+            player.set_pipe_command(Some(config.pipe_command.clone()));
+        }
+        // Headless boxes and restarting sound servers have no device yet;
+        // keep looking until one turns up
+        let output_lost = (!player.output_available()).then(Instant::now);
+        player.mirrors = config.mirror_outputs.clone();
+        player.device_presets = config.devices.clone();
+
         let library = Arc::new(Mutex::new(LibraryState::new()));
         library.lock().unwrap().artists = artists;
-        library.lock().unwrap().rebuild_visible_rows(); // Make sure UI stays in sync
+        library.lock().unwrap().roots = persistence::load_library_roots().unwrap_or_default();
+        library.lock().unwrap().scan_filter =
+            ScanFilter::new(&config.scan_include, &config.scan_exclude);
+
+        let library_watcher = match config.watch_library {
+            true => LibraryWatcher::new(&library.lock().unwrap().roots)
+                .map_err(|err| log::warn!("Failed to watch the library folders: {err}"))
+                .ok(),
+            false => None,
+        };
+        library.lock().unwrap().album_sort = config.album_sort;
+        library.lock().unwrap().track_sort = config.track_sort;
+        library.lock().unwrap().name_order = NameOrder::new(
+            config.sort_collate,
+            config.sort_locale.as_deref(),
+            &config.sort_articles,
+        );
+        library.lock().unwrap().sort_tree(); // Make sure UI stays in sync
 
         Self {
             screen: AppScreen::Browser,
             browser: BrowserState::new(),
             library: library,
-            player: Arc::new(Mutex::new(Player::new())),
+            player: Rc::new(RefCell::new(player)),
             play_queue: Vec::new(),
             queue_index: 0,
-            autoplay_enabled: true,
+            autoplay_enabled: state.autoplay,
+            auto_dj: state.auto_dj,
+            repeat: state.repeat,
+            shuffle: state.shuffle,
+            shuffle_order: None,
+            muted: false,
+            stop_after_current: false,
+            replay_gain: state.replay_gain,
+            speed,
+            folder_speed: None,
+            ab_loop: None,
+            volume: state.volume,
+            active_device: None,
+            device_volumes: state.device_volumes,
             current_track: None,
             playback_duration: 0,
             playback_start: None,
             paused_at: None,
-            paused_duration: Duration::from_secs(0),
+            config,
+            keymap,
+            alarm,
+            positions: persistence::load_positions().unwrap_or_default(),
+            volume_ramp: None,
+            prompt: None,
+            bookmark_list: None,
+            chapters: Vec::new(),
+            chapter_list: None,
+            label_list: None,
+            history: ListSelector::new(persistence::load_history().unwrap_or_default()),
+            stats_period: StatsPeriod::Week,
+            library_stats: None,
+            recent: ListSelector::new(Vec::new()),
+            duplicates: DuplicateView::new(Vec::new()),
+            now_playing_published: None,
+            tag_writer,
+            loudness_scanner,
+            replay_gain_scan: None,
+            cover_fetch: None,
+            fingerprint_scan: None,
+            sound_duplicates: None,
+            incoming,
+            library_watcher,
+            library_scan: None,
+            session_list: None,
+            station_list: None,
+            smart_list: None,
+            retag_menu: None,
+            retag_preview: None,
+            album_lookup: None,
+            missing_list: None,
+            search: None,
+            equalizer_band: None,
+            settings_row: None,
+            show_diagnostics: false,
+            party_locked: false,
+            toast: None,
+            output_lost,
+            waiting_output: None,
+            unplayable: None,
+            play_recorded: false,
+            play_counted: false,
+            gapless_next: None,
         }
     }
 
-    pub fn player_mut(&self) -> std::sync::MutexGuard<'_, Player> {
-        self.player.lock().unwrap()
+    pub fn player_mut(&self) -> RefMut<'_, Player> {
+        self.player.borrow_mut()
     }
 
     pub fn library_mut(&self) -> std::sync::MutexGuard<'_, LibraryState> {
@@ -72,6 +492,57 @@ impl App {
     }
 
     pub fn update(&mut self) {
+        if self.alarm.as_mut().is_some_and(|alarm| alarm.due()) {
+            self.fire_alarm();
+        }
+
+        if let Some(ramp) = &self.volume_ramp {
+            let level = ramp.level();
+            let done = ramp.is_done();
+            self.send_gain(level);
+
+            if done {
+                self.volume_ramp = None;
+                self.set_volume(level);
+            }
+        }
+
+        if self
+            .now_playing_published
+            .is_none_or(|at| at.elapsed() >= Duration::from_secs(1))
+        {
+            self.publish_now_playing();
+        }
+
+        self.count_listened_play();
+        self.poll_incoming();
+        self.poll_library_watcher();
+        self.poll_library_scan();
+        self.store_loudness();
+        self.store_replay_gain();
+        self.poll_cover_fetch();
+        self.poll_fingerprint_scan();
+        if let Some(lookup) = &mut self.album_lookup {
+            lookup.poll();
+        }
+
+        let ended = self.player_mut().take_ended_recording();
+        if let Some(result) = ended {
+            self.report_recording(result);
+        }
+
+        if self.player_mut().take_stream_failure() {
+            self.lose_output();
+        }
+        self.retry_output();
+
+        if self.player_mut().wants_next() {
+            self.queue_gapless_next();
+        }
+        if self.player_mut().take_transition() {
+            self.advance_gapless();
+        }
+
         if self.autoplay_enabled
             && self.player_mut().is_loaded()
             && self.player_mut().is_done()
@@ -81,67 +552,2849 @@ impl App {
         }
     }
 
-    pub fn goto_screen(&mut self, screen: AppScreen) {
-        self.screen = screen
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        if let Some(device) = &self.active_device {
+            self.device_volumes.insert(device.clone(), self.volume);
+        }
+
+        self.apply_volume();
+        self.save_state();
     }
 
-    pub fn play_next_track(&mut self) {
-        if self.queue_index + 1 < self.play_queue.len() {
-            self.queue_index += 1;
-            let next_path = self.play_queue[self.queue_index].clone();
+    /// Pushes the user volume, adjusted for the output device and the
+    /// current track, to the player.
+    fn apply_volume(&mut self) {
+        self.send_gain(self.volume);
+    }
 
-           // Scope the library borrow once
-            let next_track = {
-                let mut lib = self.library_mut();
-                lib.select_track_by_path(&next_path);
-                lib.track_by_path(&next_path).cloned()
-            };
+    /// Hands the player `level` with the device and track gains applied.
+    fn send_gain(&self, level: f32) {
+        let mut player = self.player_mut();
+        player.set_volume(level * self.output_gain());
+        player.set_track_gain(self.track_gain());
+    }
 
-            if let Some(track) = next_track {
-                self.current_track = Some(track);
-                self.playback_start = Some(Instant::now());
-            } else {
-                log::warn!("Could not find LibraryTrack for path: {:?}", next_path);
-                self.current_track = None;
-                self.playback_start = None;
+    /// Linear gain from the device and per-track offsets combined.
+    fn output_gain(&self) -> f32 {
+        self.device_gain() * self.track_gain()
+    }
+
+    /// Linear gain from the playing track's ReplayGain and own offset.
+    fn track_gain(&self) -> f32 {
+        let Some(track) = &self.current_track else {
+            return 1.0;
+        };
+
+        let replay_gain = match self.replay_gain_mode() {
+            // Tracks not measured yet go by their tags
+            ReplayGainMode::Loudness => track.loudness.map_or_else(
+                || track.replay_gain.factor(ReplayGainMode::Track),
+                |loudness| loudness.factor(self.config.loudness_target),
+            ),
+            mode => track.replay_gain.factor(mode),
+        };
+        replay_gain * 10f32.powf(track.gain_db / 20.0)
+    }
+
+    /// The ReplayGain mode in effect: the playing track's folder preset's,
+    /// or the user's.
+    pub fn replay_gain_mode(&self) -> ReplayGainMode {
+        self.current_track
+            .as_ref()
+            .and_then(|track| self.config.folder_preset(&track.path)?.replay_gain)
+            .unwrap_or(self.replay_gain)
+    }
+
+    /// The playback speed in effect: the playing track's folder preset's, or
+    /// the user's.
+    pub fn playback_speed(&self) -> f32 {
+        self.folder_speed.unwrap_or(self.speed)
+    }
+
+    /// Switches to the speed of the folder preset `path` falls under, or
+    /// back to the user's speed.
+    fn apply_folder_speed(&mut self, path: &Path) {
+        self.folder_speed = self
+            .config
+            .folder_preset(path)
+            .and_then(|preset| preset.speed)
+            .map(|speed| speed.clamp(MIN_SPEED, MAX_SPEED));
+        let speed = self.playback_speed();
+        self.player_mut().set_speed(speed);
+    }
+
+    /// Linear gain of the active device's configured volume offset.
+    fn device_gain(&self) -> f32 {
+        self.active_device
+            .as_deref()
+            .and_then(|device| self.config.device_preset(device))
+            .map(|preset| 10f32.powf(preset.volume_offset_db / 20.0))
+            .unwrap_or(1.0)
+    }
+
+    /// Restores the remembered volume when playback moves to another device.
+    fn sync_output_device(&mut self) {
+        let device = self.player_mut().device_name.clone();
+        if device == self.active_device {
+            return;
+        }
+
+        log::debug!(
+            "Output device changed: {:?} -> {:?}",
+            self.active_device,
+            device
+        );
+        self.active_device = device;
+
+        if let Some(volume) = self
+            .active_device
+            .as_ref()
+            .and_then(|device| self.device_volumes.get(device))
+        {
+            self.volume = *volume;
+        }
+
+        if self.volume_ramp.is_none() {
+            self.apply_volume();
+        }
+    }
+
+    /// Notes that the output stream died, so playback can pick up at the
+    /// same place once there's a device again.
+    fn lose_output(&mut self) {
+        if self.output_lost.is_some() || !self.player_mut().is_loaded() {
+            return;
+        }
+
+        log::warn!("Lost output device {:?}", self.active_device);
+        self.output_lost = Some(Instant::now());
+    }
+
+    /// Restarts a lost stream, or starts the track that was waiting for
+    /// one, on whatever the default device is now.
+    fn retry_output(&mut self) {
+        if self
+            .output_lost
+            .is_none_or(|tried| tried.elapsed() < OUTPUT_RETRY)
+        {
+            return;
+        }
+
+        if !self.player_mut().output_available() {
+            self.output_lost = Some(Instant::now());
+            return;
+        }
+        self.output_lost = None;
+
+        let (path, position, paused) = match self.waiting_output.take() {
+            Some((path, start)) => (path, start, false),
+            None => {
+                let Some(path) = self.playing_path() else {
+                    return;
+                };
+                (path, self.elapsed(), self.player_mut().is_paused)
             }
+        };
+        let track = self.current_track.clone();
 
-            self.player_mut().play(&next_path);
-        } else {
-            log::debug!("Reached end of queue");
-            self.queue_index = 0;
-            self.play_queue.clear();
-            self.current_track = None;
-            self.playback_start = None;
+        self.play_path_at(&path, track, position);
+        if self.output_lost.is_some() {
+            // Still nothing to play on
+            return;
+        }
+        if paused {
+            self.pause();
         }
+
+        let device = self
+            .active_device
+            .clone()
+            .unwrap_or_else(|| "default".into());
+        self.show_toast(format!("Now playing on {device}"));
     }
 
+    pub fn change_volume(&mut self, delta: f32) {
+        // Manual changes cancel an alarm fade-in
+        self.volume_ramp = None;
+        self.set_volume(self.volume + delta);
+    }
 
-    pub fn set_play_queue(&mut self, tracks: Vec<PathBuf>, start_index: usize) {
-        self.play_queue = tracks;
-        self.queue_index = start_index;
+    /// Lets the current track finish and then stops, once.
+    pub fn toggle_stop_after_current(&mut self) {
+        self.stop_after_current = !self.stop_after_current;
+        if self.stop_after_current && self.gapless_next.take().is_some() {
+            self.player_mut().cancel_next();
+        }
     }
 
-    pub fn pause(&mut self) {
-        let mut player = self.player.lock().unwrap();
-        player.set_paused(true);
-        self.paused_at = Some(Instant::now());
+    /// Silences the output, or brings it back at the same volume.
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        let muted = self.muted;
+        self.player_mut().set_muted(muted);
     }
 
-    pub fn resume(&mut self) {
-        let mut player = self.player.lock().unwrap();
-        player.set_paused(false);
+    /// Steps the playback speed by `delta`, keeping the pitch.
+    pub fn change_speed(&mut self, delta: f32) {
+        // Rounded so repeated steps land on tidy values
+        let speed = ((self.playback_speed() + delta) * 100.0).round() / 100.0;
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        if speed == self.playback_speed() {
+            return;
+        }
 
-        if let Some(paused_at) = self.paused_at {
-            self.paused_duration += paused_at.elapsed();
+        let position = self.elapsed();
+        // A folder preset's speed is changed for as long as it applies
+        match &mut self.folder_speed {
+            Some(folder_speed) => *folder_speed = speed,
+            None => self.speed = speed,
         }
+        self.player_mut().set_speed(speed);
 
+        // Drops audio already decoded at the old speed, so the change is
+        // heard right away
+        if self.player_mut().is_loaded() {
+            self.seek_to(position);
+        }
+        self.save_state();
+    }
+
+    pub fn toggle_autoplay(&mut self) {
+        self.autoplay_enabled = !self.autoplay_enabled;
+        self.save_state();
+    }
+
+    pub fn toggle_auto_dj(&mut self) {
+        self.auto_dj = !self.auto_dj;
+        self.save_state();
+    }
+
+    /// Switches ReplayGain between off, track and album levelling.
+    pub fn cycle_replay_gain(&mut self) {
+        self.replay_gain = self.replay_gain.next();
+        self.apply_volume();
+        self.save_state();
+        self.show_toast(format!("ReplayGain: {}", self.replay_gain.label()));
+    }
+
+    /// Toggles vocal attenuation for this session.
+    pub fn toggle_karaoke(&mut self) {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        dsp.karaoke.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn toggle_crossfeed(&mut self) {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        dsp.crossfeed.fetch_xor(true, Ordering::Relaxed);
+        self.save_state();
+    }
+
+    /// Toggles compression and the volume ceiling for this session.
+    pub fn toggle_night_mode(&mut self) {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        dsp.night_mode.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Toggles fast-forwarding through long silences for this session.
+    pub fn toggle_skip_silence(&mut self) {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        dsp.skip_silence.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn toggle_equalizer(&mut self) {
+        self.equalizer_band = match self.equalizer_band {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    /// Moves the equalizer popup's selection by `delta` bands.
+    pub fn select_eq_band(&mut self, delta: isize) {
+        if let Some(band) = &mut self.equalizer_band {
+            *band = band.saturating_add_signed(delta).min(EQ_BANDS.len() - 1);
+        }
+    }
+
+    /// Boosts or cuts the selected equalizer band by `delta` dB.
+    pub fn adjust_eq_band(&mut self, delta: f32) {
+        let Some(band) = self.equalizer_band else {
+            return;
+        };
+
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        let db = (dsp.eq_gains()[band] + delta).clamp(-EQ_MAX_DB, EQ_MAX_DB);
+        dsp.set_eq_gain(band, db);
+        self.save_state();
+    }
+
+    /// Switches to the preset after the one in use, starting over at flat.
+    pub fn cycle_eq_preset(&mut self) {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        let next = self
+            .eq_preset()
+            .and_then(|current| EQ_PRESETS.iter().position(|(name, _)| *name == current))
+            .map_or(0, |index| (index + 1) % EQ_PRESETS.len());
+
+        let (name, gains) = EQ_PRESETS[next];
+        for (band, db) in gains.iter().enumerate() {
+            dsp.set_eq_gain(band, *db);
+        }
+        self.save_state();
+        self.show_toast(format!("Equalizer: {name}"));
+    }
+
+    pub fn toggle_settings(&mut self) {
+        self.settings_row = match self.settings_row {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    /// Starts recording the output to a new WAV file in `recordings_dir`, or
+    /// stops and saves the running recording.
+    pub fn toggle_recording(&mut self) {
+        if self.player_mut().recording_path().is_some() {
+            self.stop_recording();
+            return;
+        }
+
+        let name = chrono::Local::now()
+            .format("shelltrax-%Y%m%d-%H%M%S.wav")
+            .to_string();
+        let path = self.config.recordings_dir.join(name);
+        let started = std::fs::create_dir_all(&self.config.recordings_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.player_mut().start_recording(&path));
+
+        match started {
+            Ok(()) => self.show_toast(format!("Recording to {}", path.display())),
+            Err(err) => self.show_toast(format!("Can't record: {err}")),
+        }
+    }
+
+    /// Saves the running recording, if any.
+    pub fn stop_recording(&mut self) {
+        let stopped = self.player_mut().stop_recording();
+        if let Some(result) = stopped {
+            self.report_recording(result);
+        }
+    }
+
+    fn report_recording(&mut self, result: std::io::Result<PathBuf>) {
+        match result {
+            Ok(path) => self.show_toast(format!("Recording saved to {}", path.display())),
+            Err(err) => {
+                log::error!("Failed to save recording: {err}");
+                self.show_toast(format!("Failed to save recording: {err}"));
+            }
+        }
+    }
+
+    pub fn toggle_diagnostics(&mut self) {
+        self.show_diagnostics = !self.show_diagnostics;
+    }
+
+    /// Moves the settings popup's selection by `delta` rows.
+    pub fn select_setting(&mut self, delta: isize) {
+        if let Some(row) = &mut self.settings_row {
+            *row = row.saturating_add_signed(delta).min(SETTINGS.len() - 1);
+        }
+    }
+
+    /// Steps the selected setting up or down.
+    pub fn adjust_setting(&mut self, delta: f32) {
+        if self.settings_row == Some(0) {
+            self.set_balance(self.config.balance + delta * BALANCE_STEP);
+        }
+    }
+
+    /// Current value of a settings row, for display.
+    pub fn setting_value(&self, row: usize) -> String {
+        match row {
+            0 => {
+                let percent = (self.config.balance * 100.0).round() as i32;
+                match percent {
+                    0 => "center".to_string(),
+                    p if p < 0 => format!("left {}%", -p),
+                    p => format!("right {p}%"),
+                }
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Shifts the output towards the left (negative) or right speaker and
+    /// saves it to `config.json`.
+    pub fn set_balance(&mut self, balance: f32) {
+        // Rounded so repeated steps land back on center
+        let balance = ((balance * 100.0).round() / 100.0).clamp(-1.0, 1.0);
+        self.config.balance = balance;
+        self.player_mut().dsp.set_balance(balance);
+
+        let saved = config::update_config_file(|config| {
+            config.insert("balance".to_string(), balance.into());
+        });
+        if let Err(err) = saved {
+            log::warn!("Failed to save balance: {err}");
+        }
+    }
+
+    /// Name of the preset matching the current equalizer settings, if any.
+    pub fn eq_preset(&self) -> Option<&'static str> {
+        let gains = self.player_mut().dsp.eq_gains();
+        EQ_PRESETS
+            .iter()
+            .find(|(_, preset)| *preset == gains)
+            .map(|(name, _)| *name)
+    }
+
+    /// Short names of the effects currently enabled, for the footer.
+    pub fn active_effects(&self) -> Vec<&'static str> {
+        let player = self.player_mut();
+        // Effects are bypassed while the file plays unchanged
+        if player.bit_perfect_active && player.is_loaded() {
+            return vec!["bit-perfect"];
+        }
+        let dsp = Arc::clone(&player.dsp);
+        drop(player);
+        let mut effects = Vec::new();
+
+        if dsp.karaoke.load(Ordering::Relaxed) {
+            effects.push("karaoke");
+        }
+        if dsp.crossfeed.load(Ordering::Relaxed) {
+            effects.push("crossfeed");
+        }
+        if dsp.night_mode.load(Ordering::Relaxed) {
+            effects.push("night");
+        }
+        if dsp.skip_silence.load(Ordering::Relaxed) {
+            effects.push("skip silence");
+        }
+        if dsp.eq_active() {
+            effects.push("eq");
+        }
+
+        effects
+    }
+
+    pub fn save_state(&self) {
+        let state = PlaybackState {
+            volume: self.volume,
+            autoplay: self.autoplay_enabled,
+            device_volumes: self.device_volumes.clone(),
+            crossfeed: self.player_mut().dsp.crossfeed.load(Ordering::Relaxed),
+            auto_dj: self.auto_dj,
+            replay_gain: self.replay_gain,
+            repeat: self.repeat,
+            shuffle: self.shuffle,
+            eq_gains: self.player_mut().dsp.eq_gains(),
+            speed: self.speed,
+        };
+
+        if let Err(err) = persistence::save_state(&state) {
+            log::error!("Failed to save playback state: {err}");
+        }
+    }
+
+    /// Writes the current track for `shelltrax now-playing` to pick up.
+    fn publish_now_playing(&mut self) {
+        let snapshot = self.current_track.as_ref().map(|track| NowPlaying {
+            path: track.path.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            album_artist: track.album_artist.clone(),
+            position: self.elapsed().as_secs(),
+            duration: track.duration.unwrap_or(0),
+            paused: self.paused_at.is_some(),
+            volume: (self.volume * 100.0).round() as u32,
+            updated_at: now_playing::unix_now(),
+            art: artwork::cached_thumbnail(track),
+        });
+
+        if let Err(err) = now_playing::publish(snapshot.as_ref()) {
+            log::error!("Failed to publish now playing: {err}");
+        }
+        self.now_playing_published = Some(Instant::now());
+    }
+
+    pub fn clear_now_playing(&self) {
+        now_playing::publish(None).ok();
+    }
+
+    pub fn goto_screen(&mut self, screen: AppScreen) {
+        if screen == AppScreen::Duplicates {
+            self.refresh_duplicates();
+        }
+        if screen == AppScreen::Recent {
+            self.refresh_recent();
+        }
+        if screen == AppScreen::Stats && self.library_stats.is_some() {
+            self.refresh_library_stats();
+        }
+        self.screen = screen
+    }
+
+    pub fn refresh_library_stats(&mut self) {
+        let stats = stats::library(&self.library_mut().artists);
+        self.library_stats = Some(stats);
+    }
+
+    /// Switches the stats view between the history and the library.
+    pub fn toggle_library_stats(&mut self) {
+        match self.library_stats {
+            Some(_) => self.library_stats = None,
+            None => self.refresh_library_stats(),
+        }
+    }
+
+    pub fn refresh_recent(&mut self) {
+        let recent = history::recent(&self.history.entries, RECENT_LIMIT);
+        self.recent.set_entries(recent);
+    }
+
+    /// Re-runs duplicate detection, keeping the selection roughly in place.
+    /// Groups found by fingerprint are kept, less the files that are gone.
+    pub fn refresh_duplicates(&mut self) {
+        let groups = match &self.sound_duplicates {
+            Some(paths) => duplicates::recording_groups(&self.library_mut().artists, paths),
+            None => duplicates::find_duplicates(&self.library_mut().artists),
+        };
+        let selected = self.duplicates.list.selected;
+
+        self.duplicates = DuplicateView::new(groups);
+        for _ in 0..selected {
+            self.duplicates.list.move_down();
+        }
+    }
+
+    /// Moves the selected duplicate to the trash folder and drops it from the library.
+    pub fn trash_selected_duplicate(&mut self) {
+        let Some(path) = self
+            .duplicates
+            .selected_file()
+            .map(|f| f.track.path.clone())
+        else {
+            return;
+        };
+        if self.refuse_in_use(&path) {
+            return;
+        }
+
+        match duplicates::move_to_trash(&path, &self.config.trash_dir) {
+            Ok(target) => {
+                log::debug!("Moved {:?} to {:?}", path, target);
+                self.library_mut().remove_track(&path);
+                self.refresh_duplicates();
+            }
+            Err(err) => log::error!("Failed to move {:?} to the trash: {err}", path),
+        }
+    }
+
+    /// Moves every other copy in the selected file's group to the trash
+    /// folder, keeping just that one.
+    pub fn keep_selected_duplicate(&mut self) {
+        let Some(&(group, keep)) = self.duplicates.list.selected_item() else {
+            return;
+        };
+        let others: Vec<PathBuf> = self.duplicates.groups[group]
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != keep)
+            .map(|(_, file)| file.track.path.clone())
+            .collect();
+
+        let mut moved = 0;
+        let mut kept = 0;
+        for path in others {
+            if self.is_in_use(&path) {
+                kept += 1;
+                continue;
+            }
+            match duplicates::move_to_trash(&path, &self.config.trash_dir) {
+                Ok(target) => {
+                    log::debug!("Moved {:?} to {:?}", path, target);
+                    self.library_mut().remove_track(&path);
+                    moved += 1;
+                }
+                Err(err) => log::error!("Failed to move {:?} to the trash: {err}", path),
+            }
+        }
+        self.refresh_duplicates();
+        match kept {
+            0 => self.show_toast(format!("Kept one copy, moved {moved} to the trash")),
+            _ => self.show_toast(format!(
+                "Moved {moved} copies to the trash, left {kept} that are playing"
+            )),
+        }
+    }
+
+    /// Whether `path` is playing or lined up to play next without a gap, so
+    /// its file can't go away.
+    fn is_in_use(&self, path: &Path) -> bool {
+        let playing = self.player_mut().current_path.clone();
+        playing.as_deref() == Some(path)
+            || self.playing_path().as_deref() == Some(path)
+            || self.gapless_next.as_deref() == Some(path)
+    }
+
+    /// Says so and returns true when `path` is in use and can't be removed.
+    fn refuse_in_use(&mut self, path: &Path) -> bool {
+        let in_use = self.is_in_use(path);
+        if in_use {
+            self.show_toast("That file is playing; stop it first");
+        }
+        in_use
+    }
+
+    /// Starts fingerprinting every file in the library to find copies of
+    /// the same recording, whatever their tags say. When those are already
+    /// shown, goes back to matching by tags.
+    pub fn find_sound_duplicates(&mut self) {
+        if let Some(scan) = &self.fingerprint_scan {
+            let message = format!("Still fingerprinting, {} of {}", scan.done, scan.total);
+            self.show_toast(message);
+            return;
+        }
+        if self.sound_duplicates.take().is_some() {
+            self.refresh_duplicates();
+            self.show_toast("Matching duplicates by their tags");
+            return;
+        }
+
+        // Cue tracks share their file, so there's nothing to compare them by
+        let files: Vec<FingerprintRequest> = self
+            .library_mut()
+            .artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .filter(|track| track.cue.is_none() && !player::is_url(&track.path))
+            .map(|track| FingerprintRequest {
+                path: track.path.clone(),
+                stamp: track.stamp,
+                duration: track.duration,
+            })
+            .collect();
+
+        self.show_toast(format!("Fingerprinting {} files", files.len()));
+        self.fingerprint_scan = Some(FingerprintScan::spawn(files));
+    }
+
+    /// How far fingerprinting has got, as files done and in all.
+    pub fn fingerprint_progress(&self) -> Option<(usize, usize)> {
+        self.fingerprint_scan
+            .as_ref()
+            .map(|scan| (scan.done, scan.total))
+    }
+
+    fn poll_fingerprint_scan(&mut self) {
+        let Some(scan) = &mut self.fingerprint_scan else {
+            return;
+        };
+
+        let done = scan.is_done();
+        scan.poll();
+        if !done {
+            return;
+        }
+        let groups = self
+            .fingerprint_scan
+            .take()
+            .and_then(FingerprintScan::finish);
+        let Some(groups) = groups else {
+            self.show_toast("Fingerprinting failed");
+            return;
+        };
+
+        let count = groups.len();
+        self.sound_duplicates = Some(groups);
+        self.goto_screen(AppScreen::Duplicates);
+        self.show_toast(format!("Found {count} recordings with more than one copy"));
+    }
+
+    pub fn prompt_delete_selected_duplicate(&mut self) {
+        let Some(path) = self
+            .duplicates
+            .selected_file()
+            .map(|f| f.track.path.clone())
+        else {
+            return;
+        };
+        if self.refuse_in_use(&path) {
+            return;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.prompt = Some(Prompt::new(
+            format!("Delete {name} permanently? (y/n)"),
+            "",
+            PromptAction::DeleteFile { path },
+        ));
+    }
+
+    /// Starts playing `path`, resolving its metadata from the library when possible.
+    pub fn play_path(&mut self, path: &Path) {
+        self.remember_position();
+
+        let track =
+            self.library_mut().track_by_path(path).cloned().or_else(|| {
+                match player::is_url(path) {
+                    true => Some(LibraryTrack::stream(path)),
+                    false => scan_path_for_tracks(path).into_iter().next(),
+                }
+            });
+
+        let start = self.resume_position(path);
+        if self.config.ask_to_resume && !start.is_zero() && self.prompt.is_none() {
+            self.play_path_at(path, track, Duration::ZERO);
+            self.offer_track_resume(path, start);
+            return;
+        }
+        self.play_path_at(path, track, start);
+    }
+
+    /// Asks whether to jump to the saved `position` of the track that just
+    /// started.
+    fn offer_track_resume(&mut self, path: &Path, position: Duration) {
+        let position = position.as_secs();
+        let label = format!("Resume from {}? (y/n)", now_playing::format_time(position));
+        let action = PromptAction::ResumeTrack {
+            path: path.to_path_buf(),
+            position,
+        };
+        self.prompt = Some(Prompt::new(label, "", action));
+    }
+
+    /// Where `path` picks up when played again: its saved position, if any.
+    fn resume_position(&self, path: &Path) -> Duration {
+        // Positions are only saved for tracks that qualify, so any saved
+        // position is used as long as one of the two features is on
+        let resumes = self.config.auto_resume_mins.is_some()
+            || self
+                .config
+                .folder_preset(path)
+                .is_some_and(|preset| preset.remember_position);
+
+        match self.positions.get(path) {
+            Some(secs) if resumes => Duration::from_secs(*secs),
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn play_path_at(&mut self, path: &Path, track: Option<LibraryTrack>, start: Duration) {
+        let (file, offset, end) = self.playback_range(path, track.as_ref());
+        self.apply_folder_speed(path);
+
+        let result = {
+            let mut player = self.player_mut();
+            player.set_paused(false);
+            player.play_range(&file, offset + start, end)
+        };
+        if let Err(err) = result {
+            self.fail_track(path, track, start, err);
+            return;
+        }
+        self.sync_output_device();
+        self.gapless_next = None;
+
+        self.start_track(track, offset, end);
+    }
+
+    /// Reports a track the player couldn't start. Files that can't be read
+    /// or decoded are skipped, as if they had ended.
+    /// Tracks that fail for want of an output device start once there is
+    /// one.
+    fn fail_track(
+        &mut self,
+        path: &Path,
+        track: Option<LibraryTrack>,
+        start: Duration,
+        err: PlayerError,
+    ) {
+        log::error!("Failed to play {:?}: {err}", path);
+
+        self.playback_start = None;
+        self.gapless_next = None;
+
+        match err {
+            PlayerError::Open { .. } | PlayerError::Unsupported { .. } => {
+                let name = path
+                    .file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy();
+                match &err {
+                    PlayerError::Unsupported { codec, .. } => {
+                        self.show_toast(format!("Skipped {name}: {codec} isn't supported"))
+                    }
+                    _ => self.show_toast(format!("Skipped unplayable {name}")),
+                }
+                self.current_track = None;
+                self.unplayable = Some(path.to_path_buf());
+            }
+            err => {
+                if self.output_lost.is_none() {
+                    self.show_toast(format!("No audio output: {err}"));
+                }
+                self.current_track = track;
+                self.output_lost = Some(Instant::now());
+                self.waiting_output = Some((path.to_path_buf(), start));
+            }
+        }
+    }
+
+    /// Whether there's no output device to play on right now.
+    pub fn output_missing(&self) -> bool {
+        self.output_lost.is_some()
+    }
+
+    /// Moves on from a track that couldn't be played, if autoplay is on.
+    pub fn skip_unplayable(&mut self) {
+        if let Some(path) = self.unplayable.take() {
+            self.autoplay_next(Some(path));
+        }
+    }
+
+    /// The file to play for `path`, with the range of it that belongs to
+    /// the track.
+    fn playback_range(
+        &self,
+        path: &Path,
+        track: Option<&LibraryTrack>,
+    ) -> (PathBuf, Duration, Option<Duration>) {
+        // Cue sheet tracks play a range of the album's single audio file
+        match track.and_then(|t| t.cue.as_ref()) {
+            Some(range) => {
+                let (offset, end) = range.bounds(self.config.skip_pregaps);
+                (range.file.clone(), offset, end)
+            }
+            None => (path.to_path_buf(), Duration::ZERO, None),
+        }
+    }
+
+    /// Resets the per-track state for a track the player just started.
+    fn start_track(
+        &mut self,
+        track: Option<LibraryTrack>,
+        offset: Duration,
+        end: Option<Duration>,
+    ) {
+        self.playback_duration = match end {
+            Some(end) => end.saturating_sub(offset).as_secs(),
+            None => track.as_ref().and_then(|t| t.duration).unwrap_or(0),
+        };
+        if let (Some(order), Some(track)) = (&mut self.shuffle_order, &track) {
+            order.played(&track.path);
+        }
+        self.current_track = track;
+        self.ab_loop = None;
+        self.paused_at = None;
+        let file = self.player_mut().current_path.clone();
+        self.chapters = file
+            .as_deref()
+            .filter(|file| !player::is_url(file))
+            .map(chapters::read)
+            .unwrap_or_default();
+        self.chapter_list = None;
+        self.playback_start = Some(Instant::now());
+        self.play_recorded = false;
+        self.play_counted = false;
+
+        if self.volume_ramp.is_none() {
+            self.apply_volume();
+        }
+    }
+
+    /// Hands the track autoplay would start next to the player, so it plays
+    /// straight on from the current one.
+    fn queue_gapless_next(&mut self) {
+        if self.stop_after_current {
+            return;
+        }
+        if !self.autoplay_enabled && self.repeat != RepeatMode::Track {
+            return;
+        }
+
+        // Same order as autoplay: repeating the track, the queue, then
+        // auto-DJ, then the library
+        let next = match self.play_queue.is_empty() {
+            _ if self.repeat == RepeatMode::Track => self.playing_path(),
+            false => self
+                .queue_next_index()
+                .map(|index| self.play_queue[index].clone()),
+            true if self.auto_dj => self.pick_auto_dj_track(),
+            true => self
+                .playing_path()
+                .and_then(|current| self.library_next_path(&current)),
+        };
+
+        let Some(path) = next else {
+            return;
+        };
+
+        // A repeated track starts over rather than at its saved position
+        let resume = match self.repeat {
+            RepeatMode::Track => Duration::ZERO,
+            _ => self.resume_position(&path),
+        };
+
+        let track = self.library_mut().track_by_path(&path).cloned();
+        let (file, start, end) = self.playback_range(&path, track.as_ref());
+        self.player_mut().set_next(&file, start + resume, end);
+        self.gapless_next = Some(path);
+    }
+
+    /// Moves on to the track queued by [`App::queue_gapless_next`] once the
+    /// player reached it.
+    fn advance_gapless(&mut self) {
+        let Some(path) = self.gapless_next.take() else {
+            return;
+        };
+
+        self.record_play();
+
+        // The finished track starts over next time
+        let finished = self.current_track.as_ref().map(|t| t.path.clone());
+        if let Some(finished) = finished.filter(|path| self.positions.contains_key(path)) {
+            self.positions.remove(&finished);
+            if let Err(err) = persistence::save_positions(&self.positions) {
+                log::error!("Failed to save playback positions: {err}");
+            }
+        }
+
+        if self.stop_after_current {
+            // The decoder had already moved on, so stop where it did
+            self.stop_after_current = false;
+            self.player_mut().stop();
+            self.current_track = None;
+            self.playback_start = None;
+            return;
+        }
+
+        let queued = self
+            .queue_next_index()
+            .filter(|index| self.play_queue[*index] == path);
+        if let Some(index) = queued.filter(|_| self.repeat != RepeatMode::Track) {
+            self.queue_index = index;
+        }
+
+        let track = {
+            let mut lib = self.library_mut();
+            lib.select_track_by_path(&path);
+            lib.track_by_path(&path).cloned()
+        };
+        let (_, offset, end) = self.playback_range(&path, track.as_ref());
+        self.apply_folder_speed(&path);
+
+        self.start_track(track, offset, end);
+    }
+
+    /// Moves the playback position by `secs`, staying within the track.
+    pub fn seek_by(&mut self, secs: i64) {
+        if !self.player_mut().is_loaded() {
+            return;
+        }
+
+        let length = self.track_length().map(|length| length.as_secs());
+
+        let elapsed = self.elapsed().as_secs() as i64;
+        let mut position = (elapsed + secs).max(0) as u64;
+        if let Some(length) = length {
+            position = position.min(length);
+        }
+        self.seek_to(Duration::from_secs(position));
+    }
+
+    /// Length of the current track: its part of a cue sheet's file, the
+    /// length the library has for it, or else what the player makes of the
+    /// file.
+    pub fn track_length(&self) -> Option<Duration> {
+        match self.playback_duration {
+            0 => self
+                .player_mut()
+                .duration()
+                .map(|length| length.saturating_sub(self.track_offset())),
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Jumps to `position` into the current track.
+    fn seek_to(&mut self, position: Duration) {
+        let offset = self.track_offset();
+        self.player_mut().seek(offset + position);
+    }
+
+    /// Where the current track starts in its file; cue sheet tracks start
+    /// partway in.
+    fn track_offset(&self) -> Duration {
+        self.current_track
+            .as_ref()
+            .and_then(|t| t.cue.as_ref())
+            .map(|range| range.bounds(self.config.skip_pregaps).0)
+            .unwrap_or_default()
+    }
+
+    /// Index of the chapter playback is in, if the file has chapters.
+    pub fn current_chapter(&self) -> Option<usize> {
+        if self.chapters.is_empty() || self.playback_start.is_none() {
+            return None;
+        }
+
+        let position = self.elapsed() + self.track_offset();
+        let index = self.chapters.partition_point(|c| c.start <= position);
+        Some(index.saturating_sub(1))
+    }
+
+    /// Skips to the start of the next chapter.
+    pub fn next_chapter(&mut self) {
+        let Some(index) = self.current_chapter() else {
+            return;
+        };
+
+        match self.chapters.get(index + 1).cloned() {
+            Some(chapter) => self.seek_to_chapter(&chapter),
+            None => self.show_toast("Last chapter"),
+        }
+    }
+
+    /// Goes back to the start of the chapter, or to the previous one when
+    /// the chapter has only just started.
+    pub fn previous_chapter(&mut self) {
+        let Some(index) = self.current_chapter() else {
+            return;
+        };
+
+        let position = self.elapsed() + self.track_offset();
+        let into_chapter = position.saturating_sub(self.chapters[index].start);
+        let target = match into_chapter < CHAPTER_RESTART_GRACE {
+            true => index.saturating_sub(1),
+            false => index,
+        };
+        let chapter = self.chapters[target].clone();
+        self.seek_to_chapter(&chapter);
+    }
+
+    fn seek_to_chapter(&mut self, chapter: &Chapter) {
+        let position = chapter.start.saturating_sub(self.track_offset());
+        self.seek_to(position);
+        self.show_toast(format!("Chapter: {}", chapter.title));
+    }
+
+    pub fn toggle_chapter_list(&mut self) {
+        if self.chapter_list.take().is_some() {
+            return;
+        }
+
+        let Some(current) = self.current_chapter() else {
+            self.show_toast("No chapters in this track");
+            return;
+        };
+
+        let mut list = ListSelector::new(self.chapters.clone());
+        for _ in 0..current {
+            list.move_down();
+        }
+        self.chapter_list = Some(list);
+    }
+
+    pub fn jump_to_selected_chapter(&mut self) {
+        let chapter = self
+            .chapter_list
+            .take()
+            .and_then(|list| list.selected_item().cloned());
+
+        if let Some(chapter) = chapter {
+            self.seek_to_chapter(&chapter);
+        }
+    }
+
+    /// Sets loop point A, then B, then clears the loop.
+    ///
+    /// Once both points are set, playback jumps back to A and repeats the
+    /// part between them until the loop is cleared or the track changes.
+    pub fn mark_loop(&mut self) {
+        if !self.player_mut().is_loaded() {
+            return;
+        }
+
+        let position = self.elapsed();
+        match self.ab_loop {
+            Some((a, None)) if position > a => {
+                self.ab_loop = Some((a, Some(position)));
+                let offset = self.track_offset();
+                self.player_mut()
+                    .set_loop(Some((offset + a, offset + position)));
+                self.show_toast("Looping A-B");
+            }
+            Some((_, Some(_))) => {
+                self.ab_loop = None;
+                self.player_mut().set_loop(None);
+                self.show_toast("Loop cleared");
+            }
+            // B before A starts over with a new A
+            _ => {
+                self.ab_loop = Some((position, None));
+                self.show_toast("Loop start (A) set");
+            }
+        }
+    }
+
+    /// Library path of what's playing; cue sheet tracks share their audio
+    /// file, so this is not always the player's path.
+    pub fn playing_path(&self) -> Option<PathBuf> {
+        let file = self.player_mut().current_path.clone()?;
+        Some(
+            self.current_track
+                .as_ref()
+                .map_or(file, |track| track.path.clone()),
+        )
+    }
+
+    /// Time played in the current track, as counted by the player, so it
+    /// stands still while paused and follows seeks and A-B loops.
+    pub fn elapsed(&self) -> Duration {
+        if self.playback_start.is_none() {
+            return Duration::ZERO;
+        }
+
+        let position = self.player_mut().position();
+        position.saturating_sub(self.track_offset())
+    }
+
+    /// Stores the current position if the playing track's folder asks for it
+    /// or the track is long enough for automatic resume.
+    ///
+    /// Tracks that were played to the end are forgotten so they start over.
+    pub fn remember_position(&mut self) {
+        let Some(path) = self.playing_path() else {
+            return;
+        };
+
+        let preset = self
+            .config
+            .folder_preset(&path)
+            .is_some_and(|preset| preset.remember_position);
+
+        let length = self.track_length().map(|length| length.as_secs());
+        let long_form = self
+            .config
+            .auto_resume_mins
+            .zip(length)
+            .is_some_and(|(mins, length)| length >= mins * 60);
+
+        if !preset && !long_form {
+            return;
+        }
+
+        let finished = self.player_mut().is_decoder_done.load(Ordering::SeqCst);
+
+        if finished {
+            self.positions.remove(&path);
+        } else {
+            self.positions.insert(path, self.elapsed().as_secs());
+        }
+
+        if let Err(err) = persistence::save_positions(&self.positions) {
+            log::error!("Failed to save playback positions: {err}");
+        }
+    }
+
+    /// Counts a play of the current track in the library once
+    /// `play_count_percent` of it has been heard, so tracks skipped near the
+    /// end still count.
+    fn count_listened_play(&mut self) {
+        if self.play_counted {
+            return;
+        }
+        let Some(duration) = self.current_track.as_ref().and_then(|t| t.duration) else {
+            return;
+        };
+
+        let needed = duration * u64::from(self.config.play_count_percent.min(100)) / 100;
+        if self.elapsed().as_secs() >= needed.max(1) {
+            self.count_play();
+        }
+    }
+
+    /// Adds one to the current track's play count and stamps it as last
+    /// played, once per play.
+    fn count_play(&mut self) {
+        if self.play_counted {
+            return;
+        }
+        self.play_counted = true;
+
+        let Some(path) = self.current_track.as_ref().map(|t| t.path.clone()) else {
+            return;
+        };
+        let updated = self.library_mut().update_track(&path, |track| {
+            track.play_count += 1;
+            track.last_played = Some(now_playing::unix_now());
+        });
+        if let Some(track) = updated {
+            self.queue_tag_update(&track);
+            self.current_track = Some(track);
+        }
+    }
+
+    /// Records a play of the current track in the history once it has
+    /// played to the end, counting it in the library if it wasn't yet.
+    pub fn record_play(&mut self) {
+        if self.play_recorded {
+            return;
+        }
+        self.play_recorded = true;
+
+        let Some(track) = self.current_track.clone() else {
+            return;
+        };
+        let listened = self.elapsed().as_secs();
+
+        let entry = HistoryEntry {
+            played_at: now_playing::unix_now(),
+            path: track.path,
+            title: track.title,
+            artist: track.artist,
+            album: track.album,
+            album_artist: track.album_artist,
+            listened,
+        };
+
+        if let Some(log_path) = &self.config.scrobbler_log {
+            let length = track.duration.unwrap_or(listened);
+            if let Err(err) = scrobble_log::append(log_path, &entry, track.track_number, length) {
+                log::error!("Failed to write scrobbler log: {err}");
+            }
+        }
+
+        self.history.entries.insert(0, entry);
+        if let Err(err) = persistence::save_history(&self.history.entries) {
+            log::error!("Failed to save listening history: {err}");
+        }
+        if self.screen == AppScreen::Recent {
+            self.refresh_recent();
+        }
+
+        self.count_play();
+    }
+
+    /// Changes the rating by `delta` stars; zero stars clears it.
+    pub fn change_rating(&mut self, delta: i8) {
+        self.rate(|rating| {
+            let stars = (rating.unwrap_or(0) as i8 + delta).clamp(0, 5) as u8;
+            (stars > 0).then_some(stars)
+        });
+    }
+
+    /// Gives the track `stars` out of 5, or clears its rating.
+    pub fn set_rating(&mut self, stars: Option<u8>) {
+        self.rate(|_| stars);
+    }
+
+    /// Rates the track selected in the track pane while it has focus, or
+    /// else the one playing.
+    fn rate(&mut self, rating: impl FnOnce(Option<u8>) -> Option<u8>) {
+        let selected = {
+            let lib = self.library_mut();
+            match (self.screen == AppScreen::Library, &lib.focus) {
+                (true, LibraryFocus::Right) => lib
+                    .visible_tracks()
+                    .get(lib.track_index)
+                    .map(|t| t.path.clone()),
+                _ => None,
+            }
+        };
+        let Some(path) = selected.or_else(|| self.current_track.as_ref().map(|t| t.path.clone()))
+        else {
+            return;
+        };
+
+        let updated = self
+            .library_mut()
+            .update_track(&path, |track| track.rating = rating(track.rating));
+
+        match updated {
+            Some(track) => {
+                self.queue_tag_update(&track);
+                if self
+                    .current_track
+                    .as_ref()
+                    .is_some_and(|t| t.path == track.path)
+                {
+                    self.current_track = Some(track);
+                }
+            }
+            None => log::warn!("Cannot rate {:?}: not in library", path),
+        }
+    }
+
+    /// Adjusts the current track's saved gain offset by `delta` dB.
+    pub fn change_track_gain(&mut self, delta: f32) {
+        let Some(path) = self.current_track.as_ref().map(|t| t.path.clone()) else {
+            return;
+        };
+
+        let updated = self.library_mut().update_track(&path, |track| {
+            track.gain_db = (track.gain_db + delta).clamp(-MAX_TRACK_GAIN_DB, MAX_TRACK_GAIN_DB);
+        });
+
+        match updated {
+            Some(track) => {
+                self.current_track = Some(track);
+                if self.volume_ramp.is_none() {
+                    self.apply_volume();
+                }
+            }
+            None => log::warn!("Cannot set gain for {:?}: not in library", path),
+        }
+    }
+
+    fn queue_tag_update(&self, track: &LibraryTrack) {
+        // A cue track's tags would belong to the whole album file
+        if track.cue.is_some() {
+            return;
+        }
+
+        if let Some(writer) = &self.tag_writer {
+            writer.queue(TagUpdate {
+                path: track.path.clone(),
+                rating: track.rating,
+                play_count: track.play_count,
+            });
+        }
+    }
+
+    pub fn prompt_url(&mut self) {
+        self.prompt = Some(Prompt::new("Play URL", "", PromptAction::PlayUrl));
+    }
+
+    pub fn prompt_bookmark(&mut self) {
+        let Some(path) = self.playing_path() else {
+            return;
+        };
+
+        let position = self.elapsed().as_secs();
+        let default_name = format!("{:02}:{:02}", position / 60, position % 60);
+
+        self.prompt = Some(Prompt::new(
+            "Bookmark name",
+            default_name,
+            PromptAction::AddBookmark { path, position },
+        ));
+    }
+
+    /// Asks for labels for the selected track, album or artist, or for the
+    /// playing track outside the library view.
+    pub fn prompt_labels(&mut self) {
+        let paths: Vec<PathBuf> = if self.screen == AppScreen::Library {
+            let lib = self.library_mut();
+            let tracks = match lib.focus {
+                LibraryFocus::Left => lib.selected_tracks(),
+                LibraryFocus::Right => lib
+                    .visible_tracks()
+                    .get(lib.track_index)
+                    .cloned()
+                    .into_iter()
+                    .collect(),
+            };
+            tracks.into_iter().map(|t| t.path).collect()
+        } else {
+            self.current_track.iter().map(|t| t.path.clone()).collect()
+        };
+
+        if paths.is_empty() {
+            return;
+        }
+
+        let label = format!("Labels for {} tracks (-label removes)", paths.len());
+        self.prompt = Some(Prompt::new(label, "", PromptAction::EditLabels { paths }));
+    }
+
+    pub fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+
+        match prompt.action {
+            PromptAction::AddBookmark { path, position } => {
+                let name = match prompt.input.trim() {
+                    "" => format!("{:02}:{:02}", position / 60, position % 60),
+                    name => name.to_string(),
+                };
+                let bookmark = Bookmark { name, position };
+
+                let updated = self.library_mut().add_bookmark(&path, bookmark);
+                match updated {
+                    Some(track) => self.current_track = Some(track),
+                    None => log::warn!("Cannot bookmark {:?}: not in library", path),
+                }
+            }
+            PromptAction::PlayUrl => {
+                let url = Path::new(prompt.input.trim());
+                match player::is_url(url) {
+                    true => self.play_path(url),
+                    false => self.show_toast("Not an http:// or https:// URL"),
+                }
+            }
+            PromptAction::SaveSession => {
+                let name = prompt.input.trim();
+                if !name.is_empty() {
+                    self.save_session(name);
+                }
+            }
+            PromptAction::UnlockParty => {
+                if self.config.party_code.as_deref() == Some(prompt.input.as_str()) {
+                    self.party_locked = false;
+                    self.show_toast("Party mode unlocked");
+                } else {
+                    self.show_toast("Wrong code");
+                }
+            }
+            PromptAction::EditLabels { paths } => {
+                let mut add = Vec::new();
+                let mut remove = Vec::new();
+                for label in prompt
+                    .input
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                {
+                    match label.strip_prefix('-') {
+                        Some(label) => remove.push(label.trim().to_string()),
+                        None => add.push(label.to_string()),
+                    }
+                }
+
+                let updated = self.library_mut().edit_labels(&paths, &add, &remove);
+                log::debug!("Labelled {updated} tracks: +{add:?} -{remove:?}");
+
+                let current = self.current_track.as_ref().map(|t| t.path.clone());
+                let refreshed = current.and_then(|p| self.library_mut().track_by_path(&p).cloned());
+                if refreshed.is_some() {
+                    self.current_track = refreshed;
+                }
+            }
+            PromptAction::DeleteFile { path } => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.delete_file(&path);
+                }
+            }
+            PromptAction::ResumeTrack { path, position } => {
+                let playing = self.playing_path().is_some_and(|playing| playing == path);
+                if playing && prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.seek_to(Duration::from_secs(position));
+                }
+            }
+            PromptAction::ResumeLastSession { session } => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.restore_session(&session);
+                }
+            }
+            PromptAction::Retag { field, tracks } => {
+                let value = prompt.input.trim();
+                if !value.is_empty() {
+                    self.preview_retag(field, &tracks, value);
+                }
+            }
+            PromptAction::SmartPlaylistQuery => {
+                let query = prompt.input.trim();
+                if query.is_empty() {
+                    return;
+                }
+                match Query::parse(query) {
+                    Ok(_) => {
+                        let action = PromptAction::SaveSmartPlaylist {
+                            query: query.to_string(),
+                        };
+                        self.prompt = Some(Prompt::new("Name smart playlist", "", action));
+                    }
+                    Err(err) => self.show_toast(format!("Bad query: {err}")),
+                }
+            }
+            PromptAction::SaveSmartPlaylist { query } => {
+                let name = prompt.input.trim();
+                if !name.is_empty() {
+                    self.save_smart_playlist(name, query);
+                }
+            }
+        }
+    }
+
+    fn delete_file(&mut self, path: &Path) {
+        // It may have started playing while the prompt was up
+        if self.refuse_in_use(path) {
+            return;
+        }
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                log::debug!("Deleted {:?}", path);
+                self.library_mut().remove_track(path);
+                self.refresh_duplicates();
+            }
+            Err(err) => log::error!("Failed to delete {:?}: {err}", path),
+        }
+    }
+
+    pub fn toggle_label_list(&mut self) {
+        if self.label_list.take().is_some() {
+            return;
+        }
+
+        let lib = self.library_mut();
+        let mut entries = vec![(None, 0)];
+        entries.extend(lib.label_counts().into_iter().map(|(l, n)| (Some(l), n)));
+
+        let active = entries
+            .iter()
+            .position(|(label, _)| *label == lib.label_filter)
+            .unwrap_or(0);
+        drop(lib);
+
+        let mut list = ListSelector::new(entries);
+        for _ in 0..active {
+            list.move_down();
+        }
+        self.label_list = Some(list);
+    }
+
+    /// Filters the library by the label selected in the facet popup.
+    pub fn apply_selected_label(&mut self) {
+        let Some(label) = self
+            .label_list
+            .take()
+            .and_then(|list| list.selected_item().map(|(label, _)| label.clone()))
+        else {
+            return;
+        };
+
+        self.library_mut().set_label_filter(label);
+        self.screen = AppScreen::Library;
+    }
+
+    pub fn prompt_save_session(&mut self) {
+        self.prompt = Some(Prompt::new(
+            "Save session as",
+            "",
+            PromptAction::SaveSession,
+        ));
+    }
+
+    /// Snapshots the queue, playback and view as `name`, replacing any
+    /// session of that name.
+    pub fn save_session(&mut self, name: &str) {
+        let session = self.capture_session(name);
+
+        let mut sessions = persistence::load_sessions().unwrap_or_default();
+        sessions.retain(|s| s.name != session.name);
+        sessions.push(session);
+        sessions.sort_by_key(|s| s.name.to_lowercase());
+
+        match persistence::save_sessions(&sessions) {
+            Ok(()) => self.show_toast(format!("Saved session \"{name}\"")),
+            Err(err) => log::error!("Failed to save sessions: {err}"),
+        }
+    }
+
+    /// Snapshot of what's playing and how the app is set up.
+    fn capture_session(&self, name: &str) -> Session {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        Session {
+            name: name.to_string(),
+            saved_at: now_playing::unix_now(),
+            screen: self.screen,
+            label_filter: self.library_mut().label_filter.clone(),
+            queue: self.play_queue.clone(),
+            queue_index: self.queue_index,
+            track: self.current_track.as_ref().map(|t| t.path.clone()),
+            position: self.elapsed().as_secs(),
+            paused: self.paused_at.is_some(),
+            volume: self.volume,
+            autoplay: self.autoplay_enabled,
+            auto_dj: self.auto_dj,
+            karaoke: dsp.karaoke.load(Ordering::Relaxed),
+            crossfeed: dsp.crossfeed.load(Ordering::Relaxed),
+            night_mode: dsp.night_mode.load(Ordering::Relaxed),
+            repeat: self.repeat,
+            shuffle: self.shuffle,
+        }
+    }
+
+    /// Saves what's playing on quit, for [`App::offer_resume`] next time.
+    pub fn save_last_session(&self) {
+        if let Err(err) = persistence::save_last_session(&self.capture_session("last")) {
+            log::error!("Failed to save the last session: {err}");
+        }
+    }
+
+    /// Asks whether to carry on with the track that was playing on quit.
+    pub fn offer_resume(&mut self) {
+        let session = match persistence::load_last_session() {
+            Ok(Some(session)) => session,
+            Ok(None) => return,
+            Err(err) => {
+                log::error!("Failed to load the last session: {err}");
+                return;
+            }
+        };
+        let Some(path) = &session.track else {
+            return;
+        };
+
+        let title = self
+            .library_mut()
+            .track_by_path(path)
+            .map(|track| track.title.clone())
+            .unwrap_or_else(|| path.display().to_string());
+        let label = format!(
+            "Resume \"{title}\" at {:02}:{:02}? (y/n)",
+            session.position / 60,
+            session.position % 60
+        );
+
+        let action = PromptAction::ResumeLastSession {
+            session: Box::new(session),
+        };
+        self.prompt = Some(Prompt::new(label, "", action));
+    }
+
+    pub fn toggle_session_list(&mut self) {
+        if self.session_list.take().is_some() {
+            return;
+        }
+
+        match persistence::load_sessions() {
+            Ok(sessions) => self.session_list = Some(ListSelector::new(sessions)),
+            Err(err) => log::error!("Failed to load sessions: {err}"),
+        }
+    }
+
+    /// Restores the session selected in the popup.
+    pub fn restore_selected_session(&mut self) {
+        let Some(session) = self
+            .session_list
+            .take()
+            .and_then(|list| list.selected_item().cloned())
+        else {
+            return;
+        };
+
+        self.restore_session(&session);
+        self.show_toast(format!("Restored session \"{}\"", session.name));
+    }
+
+    fn restore_session(&mut self, session: &Session) {
+        let dsp = Arc::clone(&self.player_mut().dsp);
+        dsp.karaoke.store(session.karaoke, Ordering::Relaxed);
+        dsp.crossfeed.store(session.crossfeed, Ordering::Relaxed);
+        dsp.night_mode.store(session.night_mode, Ordering::Relaxed);
+
+        self.autoplay_enabled = session.autoplay;
+        self.auto_dj = session.auto_dj;
+        self.repeat = session.repeat;
+        self.shuffle = session.shuffle;
+        self.shuffle_order = None;
+        self.volume_ramp = None;
+        self.set_volume(session.volume);
+
+        self.library_mut()
+            .set_label_filter(session.label_filter.clone());
+        self.goto_screen(session.screen);
+        self.set_play_queue(session.queue.clone(), session.queue_index);
+
+        match &session.track {
+            Some(path) => {
+                let track = self
+                    .library_mut()
+                    .track_by_path(path)
+                    .cloned()
+                    .or_else(|| scan_path_for_tracks(path).into_iter().next());
+                self.play_path_at(path, track, Duration::from_secs(session.position));
+
+                if session.paused {
+                    self.pause();
+                }
+            }
+            None => {
+                self.player_mut().stop();
+                self.current_track = None;
+                self.playback_start = None;
+            }
+        }
+    }
+
+    pub fn delete_selected_session(&mut self) {
+        let Some(list) = self.session_list.take() else {
+            return;
+        };
+
+        let mut sessions = list.entries;
+        if list.selected < sessions.len() {
+            sessions.remove(list.selected);
+        }
+
+        if let Err(err) = persistence::save_sessions(&sessions) {
+            log::error!("Failed to save sessions: {err}");
+        }
+        self.session_list = Some(ListSelector::new(sessions));
+    }
+
+    pub fn toggle_station_list(&mut self) {
+        if self.station_list.take().is_some() {
+            return;
+        }
+
+        match persistence::load_stations() {
+            Ok(stations) => self.station_list = Some(ListSelector::new(stations)),
+            Err(err) => log::error!("Failed to load stations: {err}"),
+        }
+    }
+
+    /// Tunes in to the station selected in the popup.
+    pub fn play_selected_station(&mut self) {
+        let Some(station) = self
+            .station_list
+            .take()
+            .and_then(|list| list.selected_item().cloned())
+        else {
+            return;
+        };
+
+        self.remember_position();
+        let path = PathBuf::from(&station.url);
+        let mut track = LibraryTrack::stream(&path);
+        track.title = station.name;
+        self.play_path_at(&path, Some(track), Duration::ZERO);
+    }
+
+    /// Adds the stream playing to the stations, named after what the
+    /// server calls it or else its title.
+    pub fn save_current_station(&mut self) {
+        let Some(list) = self.station_list.as_mut() else {
+            return;
+        };
+        let Some(path) = self.current_track.as_ref().map(|t| t.path.clone()) else {
+            return;
+        };
+        if !player::is_url(&path) {
+            self.show_toast("Only streams can be saved as stations");
+            return;
+        }
+
+        let url = path.to_string_lossy().into_owned();
+        if list.entries.iter().any(|station| station.url == url) {
+            self.show_toast("Station already saved");
+            return;
+        }
+
+        let name = self
+            .icy_info()
+            .and_then(|icy| icy.station)
+            .or_else(|| self.current_track.as_ref().map(|t| t.title.clone()))
+            .unwrap_or_else(|| url.clone());
+        self.show_toast(format!("Saved station \"{name}\""));
+
+        let mut stations = self
+            .station_list
+            .take()
+            .map(|list| list.entries)
+            .unwrap_or_default();
+        stations.push(Station { name, url });
+        if let Err(err) = persistence::save_stations(&stations) {
+            log::error!("Failed to save stations: {err}");
+        }
+        self.station_list = Some(ListSelector::new(stations));
+    }
+
+    pub fn delete_selected_station(&mut self) {
+        let Some(list) = self.station_list.take() else {
+            return;
+        };
+
+        let mut stations = list.entries;
+        if list.selected < stations.len() {
+            stations.remove(list.selected);
+        }
+
+        if let Err(err) = persistence::save_stations(&stations) {
+            log::error!("Failed to save stations: {err}");
+        }
+        self.station_list = Some(ListSelector::new(stations));
+    }
+
+    /// Opens the retag menu for the marked tracks, or else the tracks under
+    /// the selection in the library's focused pane.
+    pub fn open_retag(&mut self) {
+        if self.screen != AppScreen::Library {
+            return;
+        }
+
+        let lib = self.library_mut();
+        let tracks = match (lib.marked.is_empty(), &lib.focus) {
+            (false, _) => lib.marked_tracks(),
+            (true, LibraryFocus::Left) => lib.visible_tracks(),
+            (true, LibraryFocus::Right) => lib
+                .visible_tracks()
+                .into_iter()
+                .skip(lib.track_index)
+                .take(1)
+                .collect(),
+        };
+        drop(lib);
+
+        // Cue sheet tracks share one file, which has only one set of tags
+        let tracks: Vec<LibraryTrack> = tracks
+            .into_iter()
+            .filter(|t| t.cue.is_none() && tag_writer::can_retag(&t.path))
+            .collect();
+        if tracks.is_empty() {
+            self.show_toast("No MP3 or FLAC files to retag");
+            return;
+        }
+        self.retag_menu = Some(RetagMenu::new(tracks));
+    }
+
+    /// Goes on with the tag picked in the retag menu, asking for its new
+    /// value if it needs one.
+    pub fn choose_retag_field(&mut self) {
+        let Some(menu) = self.retag_menu.take() else {
+            return;
+        };
+        let Some(field) = menu.fields.selected_item().copied() else {
+            return;
+        };
+
+        if !field.takes_value() {
+            self.preview_retag(field, &menu.tracks, "");
+            return;
+        }
+
+        let current = menu
+            .tracks
+            .first()
+            .map(|t| field.current(t))
+            .unwrap_or_default();
+        let label = format!("{} of {} tracks", field.label(), menu.tracks.len());
+        let action = PromptAction::Retag {
+            field,
+            tracks: menu.tracks,
+        };
+        self.prompt = Some(Prompt::new(label, current, action));
+    }
+
+    fn preview_retag(&mut self, field: RetagField, tracks: &[LibraryTrack], value: &str) {
+        let preview = retag::preview(field, tracks, value);
+        if preview.changes.entries.is_empty() {
+            self.show_toast("Nothing to change");
+            return;
+        }
+        self.retag_preview = Some(preview);
+    }
+
+    /// Writes the previewed tag changes and reads the files back into the
+    /// library.
+    pub fn apply_retag(&mut self) {
+        let Some(preview) = self.retag_preview.take() else {
+            return;
+        };
+
+        let mut written = Vec::new();
+        let mut failed = 0;
+        for change in preview.changes.entries {
+            match tag_writer::write_retag(&change.path, preview.field, &change.new) {
+                Ok(()) => written.push(change.path),
+                Err(err) => {
+                    log::error!("Failed to retag {:?}: {err}", change.path);
+                    failed += 1;
+                }
+            }
+        }
+
+        let mut lib = self.library_mut();
+        lib.marked.clear();
+        lib.refresh_paths(&written);
+        drop(lib);
+        self.reload_current_track();
+
+        match failed {
+            0 => self.show_toast(format!("Retagged {} tracks", written.len())),
+            failed => self.show_toast(format!(
+                "Retagged {} tracks, {failed} failed (see the log)",
+                written.len()
+            )),
+        }
+    }
+
+    /// Looks up the selected album on MusicBrainz, to pick the release it
+    /// is and fix its files' tags from it.
+    pub fn lookup_album(&mut self) {
+        if self.screen != AppScreen::Library {
+            return;
+        }
+
+        let lib = self.library_mut();
+        let albums = lib.selected_albums();
+        let tracks: Option<Vec<LibraryTrack>> = match albums.as_slice() {
+            [album] => Some(album.tracks.clone()),
+            _ => None,
+        };
+        drop(lib);
+
+        let Some(tracks) = tracks else {
+            self.show_toast("Select an album to look up");
+            return;
+        };
+        // Cue sheet tracks share one file, which has only one set of tags
+        let tracks: Vec<LibraryTrack> = tracks
+            .into_iter()
+            .filter(|t| t.cue.is_none() && tag_writer::can_retag(&t.path))
+            .collect();
+        if tracks.is_empty() {
+            self.show_toast("No MP3 or FLAC files to tag");
+            return;
+        }
+        self.album_lookup = Some(AlbumLookup::start(tracks));
+    }
+
+    /// Writes the tags of the release confirmed in the lookup popup to the
+    /// album's files and reads them back into the library.
+    pub fn apply_album_lookup(&mut self) {
+        let Some(lookup) = self.album_lookup.take() else {
+            return;
+        };
+        let LookupStep::Preview(changes) = lookup.step else {
+            self.album_lookup = Some(lookup);
+            return;
+        };
+
+        let mut written = Vec::new();
+        for change in changes.entries {
+            match tag_writer::write_album_tags(&change.track.path, &change.tags) {
+                Ok(()) => written.push(change.track.path),
+                Err(err) => log::error!("Failed to tag {:?}: {err}", change.track.path),
+            }
+        }
+
+        self.library_mut().refresh_paths(&written);
+        self.reload_current_track();
+        self.show_toast(format!("Tagged {} tracks from MusicBrainz", written.len()));
+    }
+
+    /// Opens the smart playlists popup, counting each one's tracks in the
+    /// library as it is now, or closes it.
+    pub fn toggle_smart_list(&mut self) {
+        if self.smart_list.take().is_some() {
+            return;
+        }
+
+        match persistence::load_smart_playlists() {
+            Ok(playlists) => self.smart_list = Some(self.count_smart_playlists(playlists)),
+            Err(err) => log::error!("Failed to load smart playlists: {err}"),
+        }
+    }
+
+    fn count_smart_playlists(
+        &self,
+        playlists: Vec<SmartPlaylist>,
+    ) -> ListSelector<(SmartPlaylist, usize)> {
+        let lib = self.library_mut();
+        let entries = playlists
+            .into_iter()
+            .map(|playlist| {
+                let count = Query::parse(&playlist.query)
+                    .map(|query| lib.matching_tracks(&query).len())
+                    .unwrap_or(0);
+                (playlist, count)
+            })
+            .collect();
+        ListSelector::new(entries)
+    }
+
+    fn selected_smart_playlist(&mut self) -> Option<(String, Query)> {
+        let (playlist, _) = self.smart_list.take()?.selected_item()?.clone();
+        match Query::parse(&playlist.query) {
+            Ok(query) => Some((playlist.name, query)),
+            Err(err) => {
+                self.show_toast(format!("Bad query in \"{}\": {err}", playlist.name));
+                None
+            }
+        }
+    }
+
+    /// Narrows the library to the smart playlist selected in the popup, or
+    /// back to everything when it's the one already shown.
+    pub fn apply_selected_smart_playlist(&mut self) {
+        let Some((name, query)) = self.selected_smart_playlist() else {
+            return;
+        };
+
+        let mut lib = self.library_mut();
+        let active = lib
+            .smart_filter
+            .as_ref()
+            .is_some_and(|(shown, _)| *shown == name);
+        lib.set_smart_filter((!active).then_some((name, query)));
+        drop(lib);
+        self.screen = AppScreen::Library;
+    }
+
+    /// Plays every track matching the smart playlist selected in the popup.
+    pub fn play_selected_smart_playlist(&mut self) {
+        let Some((name, query)) = self.selected_smart_playlist() else {
+            return;
+        };
+
+        let paths: Vec<PathBuf> = self
+            .library_mut()
+            .matching_tracks(&query)
+            .into_iter()
+            .map(|t| t.path.clone())
+            .collect();
+        if paths.is_empty() {
+            self.show_toast(format!("Nothing in the library matches \"{name}\""));
+            return;
+        }
+        self.play_all(paths, 0);
+    }
+
+    /// Closes the popup to ask for a new smart playlist's query.
+    pub fn prompt_smart_playlist(&mut self) {
+        self.smart_list = None;
+        self.prompt = Some(Prompt::new(
+            "Smart playlist query",
+            "",
+            PromptAction::SmartPlaylistQuery,
+        ));
+    }
+
+    /// Saves `query` as the smart playlist `name`, replacing any of that name.
+    pub fn save_smart_playlist(&mut self, name: &str, query: String) {
+        let mut playlists = match persistence::load_smart_playlists() {
+            Ok(playlists) => playlists,
+            Err(err) => {
+                log::error!("Failed to load smart playlists: {err}");
+                return;
+            }
+        };
+        playlists.retain(|playlist| playlist.name != name);
+        playlists.push(SmartPlaylist {
+            name: name.to_string(),
+            query,
+        });
+
+        if let Err(err) = persistence::save_smart_playlists(&playlists) {
+            log::error!("Failed to save smart playlists: {err}");
+            return;
+        }
+        self.show_toast(format!("Saved smart playlist \"{name}\""));
+    }
+
+    pub fn delete_selected_smart_playlist(&mut self) {
+        let Some(list) = self.smart_list.take() else {
+            return;
+        };
+
+        let mut playlists: Vec<SmartPlaylist> = list
+            .entries
+            .iter()
+            .map(|(playlist, _)| playlist.clone())
+            .collect();
+        if list.selected < playlists.len() {
+            playlists.remove(list.selected);
+        }
+
+        if let Err(err) = persistence::save_smart_playlists(&playlists) {
+            log::error!("Failed to save smart playlists: {err}");
+        }
+        self.smart_list = Some(self.count_smart_playlists(playlists));
+    }
+
+    /// Checks every library track's file and lists the ones that are gone,
+    /// or closes the list.
+    pub fn toggle_missing_list(&mut self) {
+        if self.missing_list.take().is_some() {
+            return;
+        }
+
+        let missing = self.library_mut().missing_tracks();
+        match missing.is_empty() {
+            true => self.show_toast("Every library file is where it should be"),
+            false => self.missing_list = Some(ListSelector::new(missing)),
+        }
+    }
+
+    /// Removes the missing track selected in the popup from the library.
+    pub fn remove_selected_missing(&mut self) {
+        let Some(list) = self.missing_list.as_mut() else {
+            return;
+        };
+        if list.selected >= list.entries.len() {
+            return;
+        }
+
+        let track = list.entries.remove(list.selected);
+        list.selected = list.selected.min(list.entries.len().saturating_sub(1));
+        list.state.select(Some(list.selected));
+        if list.entries.is_empty() {
+            self.missing_list = None;
+        }
+
+        self.library_mut().remove_track(&track.path);
+        self.show_toast(format!("Removed {} from the library", track.title));
+    }
+
+    /// Removes every track in the missing files popup from the library.
+    pub fn remove_all_missing(&mut self) {
+        let Some(list) = self.missing_list.take() else {
+            return;
+        };
+
+        let paths: Vec<PathBuf> = list.entries.into_iter().map(|track| track.path).collect();
+        self.library_mut().remove_tracks(&paths);
+        self.show_toast(format!(
+            "Removed {} missing tracks from the library",
+            paths.len()
+        ));
+    }
+
+    /// Sorts the focused library pane the next way and saves that to
+    /// `config.json`.
+    pub fn cycle_sort(&mut self) {
+        let mut lib = self.library.lock().unwrap();
+        let (key, value, message) = match lib.focus {
+            LibraryFocus::Left => {
+                lib.album_sort = lib.album_sort.next();
+                self.config.album_sort = lib.album_sort;
+                let message = format!("Albums sorted by {}", lib.album_sort.label());
+                ("album_sort", serde_json::to_value(lib.album_sort), message)
+            }
+            LibraryFocus::Right => {
+                lib.track_sort = lib.track_sort.next();
+                self.config.track_sort = lib.track_sort;
+                let message = format!("Tracks sorted by {}", lib.track_sort.label());
+                ("track_sort", serde_json::to_value(lib.track_sort), message)
+            }
+        };
+        lib.sort_tree();
+        drop(lib);
+
+        let value = value.unwrap_or_default();
+        let saved = config::update_config_file(|config| {
+            config.insert(key.to_string(), value);
+        });
+        if let Err(err) = saved {
+            log::warn!("Failed to save {key}: {err}");
+        }
+        self.show_toast(message);
+    }
+
+    /// Opens the library search overlay, or closes it.
+    pub fn toggle_search(&mut self) {
+        if self.search.take().is_none() {
+            self.search = Some(Search::new());
+        }
+    }
+
+    /// Adds `c` to the search query and ranks the library against it again.
+    pub fn type_search(&mut self, c: char) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.push(c);
+        }
+        self.update_search();
+    }
+
+    /// Takes the last character off the search query.
+    pub fn erase_search(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.pop();
+        }
+        self.update_search();
+    }
+
+    fn update_search(&mut self) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let lib = self.library.lock().unwrap();
+        search.update(
+            lib.artists
+                .iter()
+                .flat_map(|a| &a.albums)
+                .flat_map(|alb| &alb.tracks),
+        );
+    }
+
+    /// Plays the selected search result and closes the overlay.
+    pub fn play_search_result(&mut self) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+        let Some(track) = search.results.selected_item() else {
+            return;
+        };
+
+        self.library_mut().select_track_by_path(&track.path);
+        self.play_path(&track.path);
+    }
+
+    /// Queues the selected search result, leaving the overlay open to pick
+    /// more.
+    pub fn enqueue_search_result(&mut self) {
+        let Some(track) = self.search.as_ref().and_then(|s| s.results.selected_item()) else {
+            return;
+        };
+
+        let (path, title) = (track.path.clone(), track.title.clone());
+        self.enqueue(&path);
+        self.show_toast(format!("Added {title} to the queue"));
+    }
+
+    /// Station name and live title of the radio stream playing.
+    pub fn icy_info(&self) -> Option<IcyInfo> {
+        self.player_mut().icy_info()
+    }
+
+    pub fn toggle_bookmark_list(&mut self) {
+        if self.bookmark_list.take().is_some() {
+            return;
+        }
+
+        if let Some(track) = &self.current_track {
+            self.bookmark_list = Some(ListSelector::new(track.bookmarks.clone()));
+        }
+    }
+
+    pub fn jump_to_selected_bookmark(&mut self) {
+        let bookmark = self
+            .bookmark_list
+            .take()
+            .and_then(|list| list.selected_item().cloned());
+
+        let Some(bookmark) = bookmark else {
+            return;
+        };
+        let position = Duration::from_secs(bookmark.position);
+
+        // Bookmarks are listed for the current track, so unless playback has
+        // stopped this only seeks
+        if self.player_mut().current_path.is_some() {
+            self.seek_to(position);
+            self.show_toast(format!("Bookmark: {}", bookmark.name));
+        } else if let Some(track) = self.current_track.clone() {
+            let path = track.path.clone();
+            self.play_path_at(&path, Some(track), position);
+        }
+    }
+
+    pub fn delete_selected_bookmark(&mut self) {
+        let Some(index) = self.bookmark_list.as_ref().map(|list| list.selected) else {
+            return;
+        };
+        let Some(path) = self.current_track.as_ref().map(|t| t.path.clone()) else {
+            return;
+        };
+
+        let updated = self.library_mut().remove_bookmark(&path, index);
+        if let Some(track) = updated {
+            self.bookmark_list = Some(ListSelector::new(track.bookmarks.clone()));
+            self.current_track = Some(track);
+        }
+    }
+
+    fn fire_alarm(&mut self) {
+        let Some(alarm) = &self.alarm else {
+            return;
+        };
+        let alarm_config = alarm.config.clone();
+
+        let paths = match playlist::load_playlist(&alarm_config.playlist) {
+            Ok(paths) => paths,
+            Err(err) => {
+                log::error!(
+                    "Alarm playlist {:?} failed to load: {err}",
+                    alarm_config.playlist
+                );
+                return;
+            }
+        };
+
+        let Some(first) = paths.first().cloned() else {
+            log::warn!("Alarm playlist {:?} is empty", alarm_config.playlist);
+            return;
+        };
+
+        log::debug!("Alarm fired, playing {:?}", alarm_config.playlist);
+
+        self.player_mut().set_volume(0.0);
+        self.volume_ramp = Some(VolumeRamp::new(
+            Duration::from_secs(alarm_config.ramp_secs),
+            alarm_config.volume,
+        ));
+
+        self.autoplay_enabled = true;
+        self.set_play_queue(paths, 0);
+        self.play_path(&first);
+    }
+
+    /// Plays whatever autoplay has next after `current`: the next queue
+    /// entry, an auto-DJ pick or the next visible library track.
+    pub fn autoplay_next(&mut self, current: Option<PathBuf>) {
+        if !self.autoplay_enabled {
+            return;
+        }
+
+        // An explicit queue (e.g. an alarm playlist) takes precedence over
+        // the library's visible track order.
+        if !self.play_queue.is_empty() {
+            self.play_next_track();
+            return;
+        }
+
+        if self.auto_dj && self.play_auto_dj_track() {
+            return;
+        }
+
+        let Some(next_path) = current.and_then(|path| self.library_next_path(&path)) else {
+            return;
+        };
+        self.library_mut().select_track_by_path(&next_path);
+        self.play_path(&next_path);
+
+        if let Some(next_track) = &self.current_track {
+            log::debug!(
+                "Autoplay switched to: {} – {}",
+                next_track.album_artist,
+                next_track.title
+            );
+            log::debug!("playback_start: {:?}", self.playback_start);
+        }
+    }
+
+    pub fn play_next_track(&mut self) {
+        if let Some(index) = self.queue_next_index() {
+            self.queue_index = index;
+            let next_path = self.play_queue[self.queue_index].clone();
+
+            self.library_mut().select_track_by_path(&next_path);
+            self.play_path(&next_path);
+
+            if self.current_track.is_none() {
+                log::warn!("Could not find LibraryTrack for path: {:?}", next_path);
+            }
+        } else {
+            log::debug!("Reached end of queue");
+            self.queue_index = 0;
+            self.play_queue.clear();
+            self.current_track = None;
+            self.playback_start = None;
+        }
+    }
+
+    /// Index of the queue entry after the current one, going back to the
+    /// start when repeating everything.
+    fn queue_next_index(&self) -> Option<usize> {
+        let next = self.queue_index + 1;
+        match next < self.play_queue.len() {
+            true => Some(next),
+            false if self.repeat == RepeatMode::All && !self.play_queue.is_empty() => Some(0),
+            false => None,
+        }
+    }
+
+    /// The visible library track after `current`, going back to the first
+    /// when repeating everything.
+    pub fn library_next_path(&mut self, current: &Path) -> Option<PathBuf> {
+        if self.shuffle {
+            return self.shuffle_next_path(current);
+        }
+
+        let lib = self.library_mut();
+        match self.repeat {
+            RepeatMode::All => lib.next_track_path_wrapping(current),
+            _ => lib.next_track_path(current),
+        }
+    }
+
+    /// The next track of the shuffled order of the visible tracks. A new
+    /// order is made whenever the visible tracks change, and a new round
+    /// starts after the last track when repeating everything.
+    fn shuffle_next_path(&mut self, current: &Path) -> Option<PathBuf> {
+        let pool: Vec<PathBuf> = self
+            .library_mut()
+            .visible_tracks()
+            .into_iter()
+            .map(|track| track.path)
+            .collect();
+
+        let order = match &mut self.shuffle_order {
+            Some(order) if order.covers(&pool) => order,
+            slot => slot.insert(ShuffleOrder::new(pool)),
+        };
+
+        order.played(current);
+        if order.is_finished() && self.repeat == RepeatMode::All {
+            order.restart();
+            order.played(current);
+        }
+
+        order.next().cloned()
+    }
+
+    pub fn toggle_shuffle(&mut self) {
+        self.shuffle = !self.shuffle;
+        self.shuffle_order = None;
+        self.save_state();
+    }
+
+    /// Starts the current track over, for repeat-one. Returns false if
+    /// nothing is playing.
+    pub fn repeat_track(&mut self) -> bool {
+        let Some(path) = self.playing_path() else {
+            return false;
+        };
+
+        self.play_path(&path);
+        true
+    }
+
+    pub fn cycle_repeat(&mut self) {
+        self.repeat = self.repeat.next();
+        self.save_state();
+        self.show_toast(format!("Repeat: {}", self.repeat.label()));
+    }
+
+    /// Plays a random track from the whole library, or from the tracks with
+    /// the filtered label when a filter is active.
+    pub fn play_random_track(&mut self) {
+        let current = self.current_track.as_ref().map(|t| t.path.clone());
+
+        let path = {
+            let lib = self.library_mut();
+            let mut candidates = lib.filtered_tracks();
+
+            // Don't "surprise" with the track that's already playing
+            if candidates.len() > 1 {
+                candidates.retain(|t| Some(&t.path) != current.as_ref());
+            }
+
+            candidates.choose(&mut rand::rng()).map(|t| t.path.clone())
+        };
+
+        if let Some(path) = path {
+            self.library_mut().select_track_by_path(&path);
+            self.play_path(&path);
+        }
+    }
+
+    /// Plays the auto-DJ's pick to follow the current track. Returns false
+    /// if there is nothing to pick from.
+    pub fn play_auto_dj_track(&mut self) -> bool {
+        // Use the pick already queued to the player, which may have opened it
+        let Some(next) = self
+            .gapless_next
+            .take()
+            .or_else(|| self.pick_auto_dj_track())
+        else {
+            return false;
+        };
+
+        self.library_mut().select_track_by_path(&next);
+        self.play_path(&next);
+        true
+    }
+
+    /// Picks the auto-DJ's follow-up to the current track.
+    fn pick_auto_dj_track(&self) -> Option<PathBuf> {
+        let current = self.current_track.as_ref()?;
+
+        let recent: Vec<PathBuf> = self
+            .history
+            .entries
+            .iter()
+            .take(AUTO_DJ_RECENT)
+            .map(|entry| entry.path.clone())
+            .collect();
+
+        let lib = self.library_mut();
+        autodj::pick_next(current, &lib.filtered_tracks(), &recent).map(|t| t.path.clone())
+    }
+
+    /// Adds finished downloads from the incoming folder to the library and
+    /// the queue.
+    fn poll_incoming(&mut self) {
+        let Some(incoming) = &mut self.incoming else {
+            return;
+        };
+
+        let paths = incoming.poll();
+        if paths.is_empty() {
+            return;
+        }
+
+        // New downloads are held to the same filter as the library folders
+        let filter = self.library_mut().scan_filter.clone();
+        for path in paths {
+            let tracks = scan_path_with_progress(&path, &filter, &ScanProgress::default());
+            let Some(track) = tracks.first().cloned() else {
+                continue;
+            };
+
+            self.library_mut().add_tracks(tracks);
+            self.analyze_loudness();
+            self.enqueue(&path);
+            self.show_toast(format!("Queued {} – {}", track.artist, track.title));
+        }
+    }
+
+    /// Starts rescanning the library folders in the background, reading
+    /// only files that are new or changed since they were last scanned.
+    pub fn refresh_library(&mut self) {
+        if self.library_mut().roots.is_empty() {
+            self.show_toast("No library folders yet, add one from the browser");
+            return;
+        }
+        if self.scan_running() {
+            return;
+        }
+        let check = self.library_mut().check_roots();
+        self.library_scan = Some(LibraryScan::refresh(check));
+    }
+
+    /// Starts adding `path` to the library, reading its tags in the
+    /// background.
+    pub fn add_to_library(&mut self, path: PathBuf) {
+        if self.scan_running() {
+            return;
+        }
+        let filter = self.library_mut().scan_filter.clone();
+        self.library_scan = Some(LibraryScan::add(path, filter));
+    }
+
+    /// Whether a background scan is still running, saying so if it is.
+    fn scan_running(&mut self) -> bool {
+        let message = match self.library_scan.as_ref().map(|scan| &scan.job) {
+            Some(ScanJob::Add(_)) => "Still adding the last folder to the library",
+            Some(ScanJob::Refresh) => "Still refreshing the library",
+            None => return false,
+        };
+        self.show_toast(message);
+        true
+    }
+
+    /// Files the results of a finished background scan into the library.
+    fn poll_library_scan(&mut self) {
+        let Some(scan) = &self.library_scan else {
+            return;
+        };
+        let Some(result) = scan.finished() else {
+            return;
+        };
+        self.library_scan = None;
+
+        let (root, tracks) = match result {
+            ScanResult::Added { root, tracks } => (root, tracks),
+            ScanResult::Refreshed(refresh) => {
+                let summary = self.library_mut().apply_refresh(refresh);
+                if summary.added + summary.updated > 0 {
+                    self.analyze_loudness();
+                }
+                self.show_toast(format!("Library refreshed: {summary}"));
+                return;
+            }
+        };
+
+        let count = tracks.len();
+        let mut lib = self.library_mut();
+        lib.tracks = tracks.clone();
+        lib.add_tracks(tracks);
+        drop(lib);
+
+        self.add_library_root(&root);
+        self.analyze_loudness();
+        self.show_toast(format!("Added {count} tracks to the library"));
+    }
+
+    /// Remembers `dir` as a library folder and starts watching it.
+    pub fn add_library_root(&mut self, dir: &Path) {
+        if !self.library_mut().add_root(dir) {
+            return;
+        }
+        if let Some(watcher) = &mut self.library_watcher {
+            watcher.watch(dir);
+        }
+    }
+
+    /// Brings the library up to date with files added, changed or removed
+    /// in its folders while running.
+    fn poll_library_watcher(&mut self) {
+        let Some(watcher) = &mut self.library_watcher else {
+            return;
+        };
+        let paths = watcher.poll();
+        if paths.is_empty() {
+            return;
+        }
+
+        let summary = self.library_mut().refresh_paths(&paths);
+        if summary.is_empty() {
+            return;
+        }
+        log::info!("Library folders changed: {summary}");
+        if summary.added + summary.updated > 0 {
+            self.analyze_loudness();
+        }
+        self.show_toast(format!("Library updated: {summary}"));
+    }
+
+    /// Queues library tracks that haven't been measured yet for the loudness
+    /// scanner. Cue sheet tracks share a file and are left to their tags.
+    pub fn analyze_loudness(&mut self) {
+        let Some(scanner) = &mut self.loudness_scanner else {
+            return;
+        };
+
+        let lib = self.library.lock().unwrap();
+        let tracks = lib
+            .artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks);
+        for track in tracks {
+            if track.loudness.is_none() && track.cue.is_none() {
+                scanner.queue(&track.path);
+            }
+        }
+    }
+
+    /// Saves the loudness scanner's latest results to the library.
+    fn store_loudness(&mut self) {
+        let Some(scanner) = &self.loudness_scanner else {
+            return;
+        };
+
+        let measured = scanner.finished();
+        if measured.is_empty() {
+            return;
+        }
+
+        self.library_mut().set_loudness(&measured);
+        self.reload_current_track();
+    }
+
+    /// Measures the selected album, or every album of the selected artist,
+    /// and writes ReplayGain tags to their MP3 and FLAC files.
+    pub fn scan_replay_gain(&mut self) {
+        if self.screen != AppScreen::Library {
+            return;
+        }
+        if self.replay_gain_scan.is_some() {
+            self.show_toast("Still writing ReplayGain tags");
+            return;
+        }
+
+        // Cue sheet tracks share one file, which can only carry one gain
+        let albums: Vec<Vec<PathBuf>> = self
+            .library_mut()
+            .selected_albums()
+            .into_iter()
+            .map(|album| {
+                album
+                    .tracks
+                    .iter()
+                    .filter(|t| t.cue.is_none() && tag_writer::can_write_replay_gain(&t.path))
+                    .map(|t| t.path.clone())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|paths| !paths.is_empty())
+            .collect();
+
+        if albums.is_empty() {
+            self.show_toast("No MP3 or FLAC files to tag");
+            return;
+        }
+
+        self.show_toast(format!(
+            "Writing ReplayGain tags for {} albums",
+            albums.len()
+        ));
+        self.replay_gain_scan = Some(ReplayGainScan::spawn(albums));
+    }
+
+    /// Saves ReplayGain values written since the last update to the library.
+    fn store_replay_gain(&mut self) {
+        let Some(scan) = &mut self.replay_gain_scan else {
+            return;
+        };
+
+        let done = scan.is_done();
+        let tagged = scan.finished();
+        let written = scan.written;
+        if done {
+            self.replay_gain_scan = None;
+            self.show_toast(format!("Wrote ReplayGain tags to {written} tracks"));
+        }
+
+        if !tagged.is_empty() {
+            self.library_mut().set_replay_gain(&tagged);
+            self.reload_current_track();
+        }
+    }
+
+    /// Looks up covers online for the selected album, or every album of the
+    /// selected artist, or with `all` for every album in the library, that
+    /// has no art cached yet.
+    pub fn fetch_cover_art(&mut self, all: bool) {
+        if self.cover_fetch.is_some() {
+            self.show_toast("Still fetching cover art");
+            return;
+        }
+
+        let lib = self.library_mut();
+        let albums: Vec<&AlbumNode> = match all {
+            true => lib.artists.iter().flat_map(|a| &a.albums).collect(),
+            false if self.screen == AppScreen::Library => lib.selected_albums(),
+            false => Vec::new(),
+        };
+        let mut seen = HashSet::new();
+        let requests: Vec<CoverRequest> = albums
+            .into_iter()
+            .filter_map(|album| album.tracks.first())
+            .filter(|track| artwork::has_album(track))
+            .map(|track| CoverRequest {
+                artist: track.artist_entry().to_string(),
+                album: track.album.clone(),
+                thumbnail: artwork::thumbnail_path(track),
+            })
+            .filter(|request| seen.insert(request.thumbnail.clone()))
+            .filter(|request| !request.thumbnail.exists())
+            .collect();
+        drop(lib);
+
+        if requests.is_empty() {
+            self.show_toast("No albums without cover art");
+            return;
+        }
+
+        self.show_toast(format!("Fetching cover art for {} albums", requests.len()));
+        self.cover_fetch = Some(CoverArtFetch::spawn(requests));
+    }
+
+    fn poll_cover_fetch(&mut self) {
+        let Some(fetch) = &mut self.cover_fetch else {
+            return;
+        };
+
+        let done = fetch.is_done();
+        fetch.poll();
+        if done {
+            let message = format!(
+                "Found cover art for {} of {} albums",
+                fetch.found, fetch.total
+            );
+            self.cover_fetch = None;
+            self.show_toast(message);
+        }
+    }
+
+    /// Picks up changes to the current track's library entry, so new gain
+    /// values apply right away.
+    fn reload_current_track(&mut self) {
+        let Some(path) = self.current_track.as_ref().map(|t| t.path.clone()) else {
+            return;
+        };
+
+        let reloaded = self.library_mut().track_by_path(&path).cloned();
+        if let Some(track) = reloaded {
+            self.current_track = Some(track);
+            if self.volume_ramp.is_none() {
+                self.apply_volume();
+            }
+        }
+    }
+
+    /// Appends `path` to the play queue, or plays it right away if nothing
+    /// is playing.
+    pub fn enqueue(&mut self, path: &Path) {
+        let idle = {
+            let player = self.player_mut();
+            !player.is_loaded()
+                || (player.is_done() && player.is_decoder_done.load(Ordering::SeqCst))
+        };
+
+        if idle {
+            self.set_play_queue(vec![path.to_path_buf()], 0);
+            self.play_path(path);
+            return;
+        }
+
+        // Start a queue after whatever is playing now
+        if self.play_queue.is_empty() {
+            let current = self.playing_path();
+            self.play_queue.extend(current);
+            self.queue_index = 0;
+        }
+
+        self.play_queue.push(path.to_path_buf());
+    }
+
+    /// Replaces the queue with `paths` and plays the one at `index`.
+    pub fn play_all(&mut self, paths: Vec<PathBuf>, index: usize) {
+        let Some(path) = paths.get(index).cloned() else {
+            return;
+        };
+        self.set_play_queue(paths, index);
+        self.play_path(&path);
+    }
+
+    /// Locks party mode, or asks for the code to unlock it.
+    pub fn toggle_party(&mut self) {
+        if self.config.party_code.is_none() {
+            self.show_toast("Set party_code in config.json to use party mode");
+            return;
+        }
+
+        if self.party_locked {
+            self.prompt = Some(Prompt::new("Unlock code", "", PromptAction::UnlockParty).masked());
+        } else {
+            self.party_locked = true;
+            self.show_toast("Party mode locked");
+        }
+    }
+
+    pub fn show_toast(&mut self, message: impl Into<String>) {
+        self.toast = Some((message.into(), Instant::now()));
+    }
+
+    /// The toast to show, until it expires.
+    pub fn toast(&self) -> Option<&str> {
+        self.toast
+            .as_ref()
+            .filter(|(_, shown)| shown.elapsed() < TOAST_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+
+    pub fn set_play_queue(&mut self, tracks: Vec<PathBuf>, start_index: usize) {
+        self.play_queue = tracks;
+        self.queue_index = start_index;
+    }
+
+    pub fn pause(&mut self) {
+        let mut player = self.player.borrow_mut();
+        player.set_paused(true);
+        self.paused_at = Some(Instant::now());
+    }
+
+    pub fn resume(&mut self) {
+        let mut player = self.player.borrow_mut();
+        player.set_paused(false);
         self.paused_at = None;
     }
 
     pub fn toggle_pause(&mut self) {
         let is_paused = {
-            let player = self.player.lock().unwrap();
+            let player = self.player.borrow_mut();
             player.is_paused
         };
 
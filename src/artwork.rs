@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use id3::Tag as Id3Tag;
+use id3::frame::PictureType;
+use image::ImageFormat;
+use rayon::prelude::*;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardVisualKey};
+use symphonia::core::probe::Hint;
+use symphonia::default::get_probe;
+
+use crate::library::{LibraryTrack, UNKNOWN_ALBUM};
+use crate::persistence;
+
+/// Thumbnails fit in a square this many pixels wide.
+const THUMBNAIL_SIZE: u32 = 300;
+
+/// Image files next to the audio that are taken as the album cover, by
+/// name without the extension, most likely first.
+const COVER_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Whether `track`'s album has art that could be cached.
+pub fn has_album(track: &LibraryTrack) -> bool {
+    track.album != UNKNOWN_ALBUM
+}
+
+/// Where the thumbnail for the album `track` is on goes, whether or not
+/// it's there yet.
+pub fn thumbnail_path(track: &LibraryTrack) -> PathBuf {
+    let key = format!("{}\0{}", track.artist_entry(), track.album);
+    persistence::artwork_dir().join(format!("{:016x}.jpg", fnv1a(key.as_bytes())))
+}
+
+/// The cached thumbnail for the album `track` is on, if it has one.
+pub fn cached_thumbnail(track: &LibraryTrack) -> Option<PathBuf> {
+    Some(thumbnail_path(track)).filter(|path| path.exists())
+}
+
+/// Makes thumbnails for the albums among `tracks` that don't have one yet,
+/// from the first track's embedded cover or else a cover image in its
+/// folder. Albums without either are left alone.
+pub fn cache_album_art(tracks: &[LibraryTrack]) {
+    let mut seen = HashSet::new();
+    let albums: Vec<(&LibraryTrack, PathBuf)> = tracks
+        .iter()
+        .filter(|track| has_album(track))
+        .map(|track| (track, thumbnail_path(track)))
+        .filter(|(_, thumbnail)| seen.insert(thumbnail.clone()) && !thumbnail.exists())
+        .collect();
+    albums.par_iter().for_each(|(track, thumbnail)| {
+        // Cue sheet tracks keep their art in the file the sheet points to
+        let audio = track.cue.as_ref().map_or(&track.path, |cue| &cue.file);
+        let Some(cover) = embedded_cover(audio).or_else(|| folder_cover(audio)) else {
+            return;
+        };
+        if let Err(err) = save_thumbnail(&cover, thumbnail) {
+            log::warn!("Failed to cache album art for {:?}: {err}", track.path);
+        }
+    });
+}
+
+/// The front cover stored in the file's tags, or else the first picture.
+fn embedded_cover(path: &Path) -> Option<Vec<u8>> {
+    let is_mp3 = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+    if is_mp3 {
+        let tag = Id3Tag::read_from_path(path).ok()?;
+        let front = tag
+            .pictures()
+            .find(|p| p.picture_type == PictureType::CoverFront);
+        return front
+            .or_else(|| tag.pictures().next())
+            .map(|p| p.data.clone());
+    }
+
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut probed = get_probe()
+        .format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let metadata = probed.format.metadata();
+    let visuals = metadata.current()?.visuals();
+    let front = visuals
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover));
+    front.or(visuals.first()).map(|v| v.data.to_vec())
+}
+
+/// A cover image such as `cover.jpg` or `Folder.png` next to the file.
+fn folder_cover(path: &Path) -> Option<Vec<u8>> {
+    let dir = path.parent()?;
+    let images: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| COVER_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect();
+
+    let stem = |path: &Path| {
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default()
+    };
+    let cover = COVER_NAMES
+        .iter()
+        .find_map(|name| images.iter().find(|image| stem(image) == *name))?;
+    fs::read(cover).ok()
+}
+
+/// Scales `image` down to fit `THUMBNAIL_SIZE` and saves it as a JPEG.
+pub fn save_thumbnail(image: &[u8], path: &Path) -> anyhow::Result<()> {
+    let image = image::load_from_memory(image)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8();
+
+    // Write next to the cache entry and swap it in, so a half-written file
+    // is never taken for a thumbnail
+    let tmp = path.with_extension("jpg.tmp");
+    thumbnail.save_with_format(&tmp, ImageFormat::Jpeg)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// A hash that stays the same between builds, for naming cache files.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use rand::seq::IndexedRandom;
+
+use crate::library::LibraryTrack;
+
+/// Tempos within this fraction of each other count as a close match.
+const CLOSE_BPM: f32 = 0.06;
+
+/// Tempos within this fraction of each other still blend reasonably.
+const NEAR_BPM: f32 = 0.12;
+
+/// Picks a track to follow `current`, preferring the same genre and a similar
+/// tempo, and skipping anything in `recent`.
+///
+/// Candidates are scored and one of the best is picked at random, so the flow
+/// stays coherent without always taking the same path through the library.
+/// When nothing matches at all, any track that wasn't played recently will do.
+pub fn pick_next<'a>(
+    current: &LibraryTrack,
+    candidates: &[&'a LibraryTrack],
+    recent: &[PathBuf],
+) -> Option<&'a LibraryTrack> {
+    let fresh: Vec<&LibraryTrack> = candidates
+        .iter()
+        .copied()
+        .filter(|t| t.path != current.path && !recent.contains(&t.path))
+        .collect();
+
+    let best = fresh.iter().map(|t| score(current, t)).max()?;
+    let matches: Vec<&LibraryTrack> = fresh
+        .into_iter()
+        .filter(|t| score(current, t) == best)
+        .collect();
+
+    matches.choose(&mut rand::rng()).copied()
+}
+
+fn score(current: &LibraryTrack, candidate: &LibraryTrack) -> u32 {
+    let mut score = 0;
+
+    let same_genre = current
+        .genre
+        .as_ref()
+        .zip(candidate.genre.as_ref())
+        .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b));
+    if same_genre {
+        score += 2;
+    }
+
+    if let (Some(a), Some(b)) = (current.bpm, candidate.bpm) {
+        let diff = a.abs_diff(b) as f32 / a.max(b) as f32;
+        if diff <= CLOSE_BPM {
+            score += 2;
+        } else if diff <= NEAR_BPM {
+            score += 1;
+        }
+    }
+
+    score
+}
@@ -1,5 +1,7 @@
+use crate::library::is_audio_file;
 use crate::list::ListSelector;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 #[derive(Debug, Clone)]
 pub enum BrowserItem {
@@ -57,6 +59,47 @@ impl BrowserState {
             _ => None,
         }
     }
+
+    /// The audio files in the current folder, with the position of the
+    /// selected one, when an audio file is selected.
+    pub fn selected_in_folder(&self) -> Option<(Vec<PathBuf>, usize)> {
+        let selected = self.selected_path().filter(|path| is_playable(path))?;
+        let files: Vec<PathBuf> = self
+            .list
+            .entries
+            .iter()
+            .filter_map(|item| match item {
+                BrowserItem::Entry(path) if is_playable(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        let index = files.iter().position(|path| path == selected)?;
+        Some((files, index))
+    }
+
+    /// The selected file, or every audio file below the selected folder.
+    pub fn selected_audio_files(&self) -> Vec<PathBuf> {
+        self.selected_path()
+            .map_or_else(Vec::new, |path| audio_files(path))
+    }
+}
+
+/// Whether `path` is an audio file rather than a folder or something else.
+pub fn is_playable(path: &Path) -> bool {
+    path.is_file() && is_audio_file(path)
+}
+
+/// `path` if it's an audio file, or the audio files below it in the order
+/// the browser lists them, skipping hidden files and folders.
+fn audio_files(path: &Path) -> Vec<PathBuf> {
+    WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(Result::ok)
+        .filter(|e| is_playable(e.path()))
+        .map(|e| e.into_path())
+        .collect()
 }
 
 fn read_dir_items(dir: &PathBuf) -> Vec<BrowserItem> {
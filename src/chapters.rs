@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// Chapter tracks longer than this are taken to be corrupt.
+const MAX_CHAPTERS: usize = 10_000;
+
+/// A named point in an audiobook.
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub title: String,
+    /// Where the chapter starts in the file
+    pub start: Duration,
+}
+
+/// Whether `path` is an MP4 file that can carry chapters.
+pub fn can_have_chapters(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["m4a", "m4b", "mp4"]
+                .iter()
+                .any(|e| ext.eq_ignore_ascii_case(e))
+        })
+}
+
+/// The chapters embedded in an M4B or M4A file, in order. Empty for other
+/// files and files without chapters.
+pub fn read(path: &Path) -> Vec<Chapter> {
+    if !can_have_chapters(path) {
+        return Vec::new();
+    }
+
+    match read_chapters(path) {
+        Ok(mut chapters) => {
+            chapters.sort_by_key(|chapter| chapter.start);
+            chapters
+        }
+        Err(err) => {
+            log::warn!("Failed to read chapters of {:?}: {err}", path);
+            Vec::new()
+        }
+    }
+}
+
+/// Reads Nero chapters (`udta/chpl`), which most taggers write, or else the
+/// QuickTime chapter track that iTunes audiobooks use.
+fn read_chapters(path: &Path) -> io::Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+    let Some(moov) = read_top_level(&mut file, b"moov")? else {
+        return Ok(Vec::new());
+    };
+
+    let nero = child(&moov, &[b"udta", b"chpl"]).and_then(nero_chapters);
+    match nero {
+        Some(chapters) if !chapters.is_empty() => Ok(chapters),
+        _ => text_track_chapters(&mut file, &moov),
+    }
+}
+
+/// Finds the top level atom `kind` and reads its body, skipping over the
+/// others without reading them; the media data can be hundreds of MB.
+fn read_top_level(file: &mut File, kind: &[u8; 4]) -> io::Result<Option<Vec<u8>>> {
+    let len = file.metadata()?.len();
+    let mut pos = 0;
+    let mut header = [0u8; 16];
+
+    while pos + 8 <= len {
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header[..8])?;
+        let (header_len, size) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            0 => (8, len - pos),
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                (16, u64::from_be_bytes(header[8..].try_into().unwrap()))
+            }
+            size => (8, size as u64),
+        };
+        if size < header_len {
+            break;
+        }
+
+        if header[4..8] == kind[..] {
+            let mut body = vec![0; (size.min(len - pos) - header_len) as usize];
+            file.read_exact(&mut body)?;
+            return Ok(Some(body));
+        }
+        pos += size;
+    }
+
+    Ok(None)
+}
+
+/// The atoms in `data` as type and body, up to the first malformed one.
+fn atoms(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+        let (header_len, size) = match be_u32(data, 0)? {
+            0 => (8, data.len()),
+            1 => (16, be_u64(data, 8)? as usize),
+            size => (8, size as usize),
+        };
+        if size < header_len || size > data.len() {
+            return None;
+        }
+
+        let body = &data[header_len..size];
+        data = &data[size..];
+        Some((kind, body))
+    })
+}
+
+/// The body of the first atom at `path` below `data`.
+fn child<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+    let (_, body) = atoms(data).find(|(kind, _)| kind == *first)?;
+    match rest.is_empty() {
+        true => Some(body),
+        false => child(body, rest),
+    }
+}
+
+/// Parses a `chpl` atom: start times in 100 ns units and a title each.
+fn nero_chapters(chpl: &[u8]) -> Option<Vec<Chapter>> {
+    let version = *chpl.first()?;
+    let mut at = match version {
+        0 => 4,
+        _ => 8,
+    };
+    let count = *chpl.get(at)?;
+    at += 1;
+
+    let mut chapters = Vec::new();
+    for _ in 0..count {
+        let start = be_u64(chpl, at)?;
+        let len = *chpl.get(at + 8)? as usize;
+        let title = chpl.get(at + 9..at + 9 + len)?;
+        chapters.push(Chapter {
+            title: String::from_utf8_lossy(title).into_owned(),
+            start: Duration::from_nanos(start.saturating_mul(100)),
+        });
+        at += 9 + len;
+    }
+    Some(chapters)
+}
+
+/// Reads the titles of the text track the audio track names as its
+/// chapter track (`tref/chap`). Each sample of it is one chapter.
+fn text_track_chapters(file: &mut File, moov: &[u8]) -> io::Result<Vec<Chapter>> {
+    let tracks: Vec<&[u8]> = atoms(moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .map(|(_, body)| body)
+        .collect();
+
+    let ids: Vec<u32> = tracks
+        .iter()
+        .filter_map(|track| child(track, &[b"tref", b"chap"]))
+        .flat_map(|chap| chap.chunks_exact(4))
+        .map(|id| u32::from_be_bytes(id.try_into().unwrap()))
+        .collect();
+
+    let chapter_track = tracks.iter().find(|track| {
+        child(track, &[b"tkhd"])
+            .and_then(|tkhd| be_u32(tkhd, versioned_offset(tkhd)?))
+            .is_some_and(|id| ids.contains(&id))
+    });
+    let Some(samples) = chapter_track.and_then(|track| samples(track)) else {
+        return Ok(Vec::new());
+    };
+
+    let mut chapters = Vec::new();
+    for (start, offset, size) in samples {
+        // Text samples start with the length of the text
+        let mut len = [0u8; 2];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut len)?;
+        let len = (u16::from_be_bytes(len) as u32).min(size.saturating_sub(2));
+
+        let mut text = vec![0; len as usize];
+        file.read_exact(&mut text)?;
+        chapters.push(Chapter {
+            title: decode_text(&text),
+            start,
+        });
+    }
+    Ok(chapters)
+}
+
+/// Start time, file offset and size of each sample of `track`, from its
+/// sample table.
+fn samples(track: &[u8]) -> Option<Vec<(Duration, u64, u32)>> {
+    let mdia = child(track, &[b"mdia"])?;
+    let mdhd = child(mdia, &[b"mdhd"])?;
+    let timescale = be_u32(mdhd, versioned_offset(mdhd)?).filter(|scale| *scale > 0)?;
+    let stbl = child(mdia, &[b"minf", b"stbl"])?;
+
+    let stsz = child(stbl, &[b"stsz"])?;
+    let fixed_size = be_u32(stsz, 4)?;
+    let count = (be_u32(stsz, 8)? as usize).min(MAX_CHAPTERS);
+    let sizes = (0..count)
+        .map(|i| match fixed_size {
+            0 => be_u32(stsz, 12 + i * 4),
+            size => Some(size),
+        })
+        .collect::<Option<Vec<u32>>>()?;
+
+    let stts = child(stbl, &[b"stts"])?;
+    let mut starts = Vec::with_capacity(count);
+    let mut time = 0u64;
+    for i in 0..be_u32(stts, 4)? as usize {
+        let samples = be_u32(stts, 8 + i * 8)?;
+        let delta = be_u32(stts, 12 + i * 8)?;
+        for _ in 0..samples {
+            if starts.len() == count {
+                break;
+            }
+            starts.push(Duration::from_secs_f64(time as f64 / timescale as f64));
+            time += delta as u64;
+        }
+    }
+
+    let chunks = chunk_offsets(stbl)?;
+    let stsc = child(stbl, &[b"stsc"])?;
+    let runs = (0..be_u32(stsc, 4)? as usize)
+        .map(|i| Some((be_u32(stsc, 8 + i * 12)?, be_u32(stsc, 12 + i * 12)?)))
+        .collect::<Option<Vec<(u32, u32)>>>()?;
+
+    // Samples follow each other within a chunk
+    let mut offsets = Vec::with_capacity(count);
+    for (chunk, offset) in chunks.iter().enumerate() {
+        let per_chunk = runs
+            .iter()
+            .rev()
+            .find(|(first, _)| *first as usize <= chunk + 1)
+            .map_or(0, |(_, samples)| *samples);
+
+        let mut at = *offset;
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.get(offsets.len()) else {
+                break;
+            };
+            offsets.push(at);
+            at += *size as u64;
+        }
+    }
+
+    Some(
+        starts
+            .into_iter()
+            .zip(offsets)
+            .zip(sizes)
+            .map(|((start, offset), size)| (start, offset, size))
+            .collect(),
+    )
+}
+
+/// File offsets of the chunks of a sample table, from `stco` or `co64`.
+fn chunk_offsets(stbl: &[u8]) -> Option<Vec<u64>> {
+    if let Some(stco) = child(stbl, &[b"stco"]) {
+        return (0..be_u32(stco, 4)? as usize)
+            .map(|i| be_u32(stco, 8 + i * 4).map(u64::from))
+            .collect();
+    }
+
+    let co64 = child(stbl, &[b"co64"])?;
+    (0..be_u32(co64, 4)? as usize)
+        .map(|i| be_u64(co64, 8 + i * 8))
+        .collect()
+}
+
+/// Where the field after the creation and modification times of a `tkhd`
+/// or `mdhd` atom is; version 1 stores the times in 64 bits.
+fn versioned_offset(atom: &[u8]) -> Option<usize> {
+    match atom.first()? {
+        0 => Some(12),
+        _ => Some(20),
+    }
+}
+
+/// Text samples are UTF-8, or UTF-16 when they start with a byte order mark.
+fn decode_text(text: &[u8]) -> String {
+    if let Some(utf16) = text.strip_prefix(&[0xfe, 0xff]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    String::from_utf8_lossy(text).into_owned()
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
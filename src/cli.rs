@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+use crate::history::ExportFormat;
+use crate::import::Source;
+
+/// A terminal music player.
+#[derive(Debug, Parser)]
+#[command(
+    name = "shelltrax",
+    version,
+    after_help = "Config keys can also be set with SHELLTRAX_* environment variables, e.g.\n\
+                  SHELLTRAX_KEY_TIMEOUT_MS=500 or SHELLTRAX_ALARM__TIME=07:00."
+)]
+pub struct Cli {
+    /// Override a config key, e.g. --set key_timeout_ms=500 (repeatable;
+    /// nested keys use dots: alarm.time=07:00)
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_assignment)]
+    pub overrides: Vec<(String, String)>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands that run instead of the TUI.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Merge key bindings and settings from another player's config files
+    /// into config.json (cmus: rc; ncmpcpp: bindings, config)
+    Import {
+        #[arg(value_enum)]
+        source: Source,
+
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Print the track playing in a running shelltrax, or exit 1 if nothing
+    /// is playing
+    NowPlaying {
+        /// Print all fields as JSON instead of using FORMAT
+        #[arg(long)]
+        json: bool,
+
+        /// Template such as "%a - %t [%p/%d]"; see the README for placeholders
+        format: Option<String>,
+    },
+
+    /// Export the listening history of completed plays
+    History {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print the man page in roff format
+    Man,
+}
+
+fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {arg:?}"))?;
+
+    Ok((key.trim().to_string(), value.to_string()))
+}
+
+pub fn print_completions(shell: Shell) {
+    let mut command = Cli::command();
+    clap_complete::generate(shell, &mut command, "shelltrax", &mut std::io::stdout());
+}
+
+pub fn print_man_page() -> std::io::Result<()> {
+    let man = clap_mangen::Man::new(Cli::command());
+    man.render(&mut std::io::stdout())
+}
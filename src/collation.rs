@@ -0,0 +1,65 @@
+use std::cmp::Ordering;
+
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+
+/// How artist and genre names are put in order.
+#[derive(Default)]
+pub struct NameOrder {
+    /// Compares by Unicode collation rules; `None` compares code points
+    collator: Option<Collator>,
+    /// Lowercased leading words, like "the", skipped when comparing
+    articles: Vec<String>,
+}
+
+impl NameOrder {
+    /// Orders names by the collation rules of `locale`, or those most
+    /// languages share when it's `None`, unless `collate` is off. Names
+    /// starting with one of `articles` are sorted by what follows it.
+    pub fn new(collate: bool, locale: Option<&str>, articles: &[String]) -> Self {
+        let locale = locale
+            .and_then(|locale| {
+                locale
+                    .parse::<Locale>()
+                    .map_err(|err| log::warn!("Unknown sort_locale {locale:?}: {err}"))
+                    .ok()
+            })
+            .unwrap_or_default();
+        let collator = collate
+            .then(|| Collator::try_new(&(&locale).into(), CollatorOptions::new()))
+            .and_then(|collator| {
+                collator
+                    .map_err(|err| log::warn!("No collation rules for {locale}: {err}"))
+                    .ok()
+            });
+
+        Self {
+            collator,
+            articles: articles
+                .iter()
+                .map(|article| article.to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        let (a, b) = (self.strip_article(a), self.strip_article(b));
+        match &self.collator {
+            Some(collator) => collator.compare(a, b),
+            None => a.cmp(b),
+        }
+    }
+
+    /// `name` without a leading article and the space after it, unless
+    /// the article is all there is.
+    fn strip_article<'a>(&self, name: &'a str) -> &'a str {
+        let Some((first, rest)) = name.split_once(' ') else {
+            return name;
+        };
+        let rest = rest.trim_start();
+        match !rest.is_empty() && self.articles.contains(&first.to_lowercase()) {
+            true => rest,
+            false => name,
+        }
+    }
+}
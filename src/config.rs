@@ -0,0 +1,447 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::keymap::Action;
+use crate::library::{AlbumSort, TrackSort};
+use crate::player::dsp::CrossfeedLevel;
+use crate::replaygain::ReplayGainMode;
+
+const CONFIG_PATH: &str = "config.json";
+
+/// Environment variables starting with this override config keys.
+///
+/// `SHELLTRAX_KEY_TIMEOUT_MS=500` sets `key_timeout_ms`; a double underscore
+/// descends into a table, so `SHELLTRAX_ALARM__TIME=07:00` sets `alarm.time`.
+const ENV_PREFIX: &str = "SHELLTRAX_";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub alarm: Option<AlarmConfig>,
+
+    /// Playback overrides for tracks below specific directories
+    pub folders: Vec<FolderPreset>,
+
+    /// Extra or replacement key bindings, e.g. `"g a": "focus_artists"`
+    pub keys: HashMap<String, Action>,
+
+    /// Key that `<leader>` stands for in bindings
+    pub leader: String,
+
+    /// How long to wait for the next key of a multi-key binding
+    pub key_timeout_ms: u64,
+
+    /// Volume change per key press, in percent
+    pub volume_step: u32,
+
+    /// Preferences for specific output devices
+    pub devices: Vec<DevicePreset>,
+
+    /// Store ratings and play counts in the files' own tags
+    pub write_tags: bool,
+
+    /// Remember the position in any track at least this many minutes long,
+    /// regardless of folder presets; `null` turns it off
+    pub auto_resume_mins: Option<u64>,
+
+    /// Start tracks with a saved position from the beginning and offer to
+    /// resume instead of resuming right away
+    pub ask_to_resume: bool,
+
+    /// How strongly crossfeed blends the channels: light, medium or strong
+    pub crossfeed_level: CrossfeedLevel,
+
+    /// Highest volume (0.0 - 1.0) while night mode is on
+    pub night_volume_ceiling: f32,
+
+    /// Level in dB below which skip-silence mode counts audio as silent
+    pub silence_threshold_db: f32,
+
+    /// Seconds of a silence played before skip-silence mode skips the rest
+    pub silence_min_secs: f32,
+
+    /// Where unwanted duplicates are moved
+    pub trash_dir: PathBuf,
+
+    /// Where recordings of the output are saved
+    pub recordings_dir: PathBuf,
+
+    /// End cue sheet tracks where the next track's pregap starts, instead of
+    /// playing the gap like a CD would
+    pub skip_pregaps: bool,
+
+    /// Downloads folder to watch; new audio files are added to the library
+    /// and queued
+    pub incoming_dir: Option<PathBuf>,
+
+    /// Pick up files added to, changed in or removed from the library
+    /// folders while running
+    pub watch_library: bool,
+
+    /// Glob patterns for the files in library folders to scan; all of them
+    /// when empty. Patterns without a `/` match a name at any depth
+    pub scan_include: Vec<String>,
+
+    /// Glob patterns for files and folders to leave out of the library,
+    /// e.g. `**/ringtones/**`, `.stversions` or `*.txt`
+    pub scan_exclude: Vec<String>,
+
+    /// How albums are ordered under an artist: year, title or added
+    pub album_sort: AlbumSort,
+
+    /// How tracks are ordered within an album: number, title or duration
+    pub track_sort: TrackSort,
+
+    /// Sort artist and genre names by Unicode collation rules, so accented
+    /// and non-Latin names land where readers expect them, rather than by
+    /// code point
+    pub sort_collate: bool,
+
+    /// Language whose collation rules to sort by, e.g. `sv` or `de`; the
+    /// rules most languages share when unset
+    pub sort_locale: Option<String>,
+
+    /// Leading words skipped when sorting artist names, e.g. `["The", "A"]`
+    /// to file "The Beatles" under B
+    pub sort_articles: Vec<String>,
+
+    /// How much of a track, in percent, has to be heard for it to count as
+    /// played
+    pub play_count_percent: u32,
+
+    /// Append completed plays to this Rockbox style `.scrobbler.log`
+    pub scrobbler_log: Option<PathBuf>,
+
+    /// Code that unlocks party mode; party mode is unavailable without one
+    pub party_code: Option<String>,
+
+    /// Devices that mirror everything played on the main output
+    pub mirror_outputs: Vec<MirrorOutput>,
+
+    /// Stereo balance from -1.0 (left only) to 1.0 (right only)
+    pub balance: f32,
+
+    /// Milliseconds to fade in or out on pause, resume, seek and stop
+    pub fade_ms: u64,
+
+    /// Frames per audio callback; larger values avoid dropouts on slow
+    /// outputs such as Bluetooth, smaller ones lower the latency. Unset
+    /// leaves it to the driver
+    pub output_buffer_frames: Option<u32>,
+
+    /// Audio system to play through, e.g. `jack` (needs the `jack` build
+    /// feature) or `alsa`; unset uses the platform's default
+    pub audio_host: Option<String>,
+
+    /// What plays the audio: `cpal` for a sound device, or `pipe` to feed
+    /// it to `pipe_command`
+    pub audio_backend: AudioBackend,
+
+    /// Command the `pipe` backend writes raw 32-bit float samples to;
+    /// `{rate}` and `{channels}` are filled in
+    pub pipe_command: String,
+
+    /// Part of the name of the device to play on, e.g. `hw:CARD=DAC` for
+    /// direct hardware access on ALSA; unset uses the default device
+    pub output_device: Option<String>,
+
+    /// Open the device at each file's own rate and bit depth and send its
+    /// samples unchanged, skipping volume, effects and resampling
+    pub bit_perfect: bool,
+
+    /// Measure the loudness of library tracks in the background, for the
+    /// `r128` ReplayGain mode
+    pub analyze_loudness: bool,
+
+    /// Loudness the `r128` mode brings tracks to, in LUFS
+    pub loudness_target: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            alarm: None,
+            folders: Vec::new(),
+            keys: HashMap::new(),
+            leader: "\\".to_string(),
+            key_timeout_ms: 1000,
+            volume_step: 5,
+            devices: Vec::new(),
+            write_tags: false,
+            auto_resume_mins: Some(20),
+            ask_to_resume: true,
+            crossfeed_level: CrossfeedLevel::Light,
+            night_volume_ceiling: 0.5,
+            silence_threshold_db: -50.0,
+            silence_min_secs: 2.0,
+            trash_dir: PathBuf::from("trash"),
+            recordings_dir: PathBuf::from("recordings"),
+            skip_pregaps: false,
+            incoming_dir: None,
+            watch_library: true,
+            scan_include: Vec::new(),
+            scan_exclude: Vec::new(),
+            album_sort: AlbumSort::default(),
+            track_sort: TrackSort::default(),
+            sort_collate: true,
+            sort_locale: None,
+            sort_articles: Vec::new(),
+            play_count_percent: 50,
+            scrobbler_log: None,
+            party_code: None,
+            mirror_outputs: Vec::new(),
+            balance: 0.0,
+            fade_ms: 150,
+            output_buffer_frames: None,
+            audio_host: None,
+            audio_backend: AudioBackend::Cpal,
+            pipe_command: "pw-cat --playback --format f32 --rate {rate} --channels {channels} -"
+                .to_string(),
+            output_device: None,
+            bit_perfect: false,
+            analyze_loudness: false,
+            loudness_target: -18.0,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the most specific folder preset containing `path`.
+    pub fn folder_preset(&self, path: &Path) -> Option<&FolderPreset> {
+        self.folders
+            .iter()
+            .filter(|preset| path.starts_with(&preset.path))
+            .max_by_key(|preset| preset.path.components().count())
+    }
+
+    /// Returns the first device preset whose `name` appears in `device`.
+    pub fn device_preset(&self, device: &str) -> Option<&DevicePreset> {
+        DevicePreset::find(&self.devices, device)
+    }
+}
+
+/// Where the player sends its audio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackend {
+    /// A sound device, through the audio host cpal picks or `audio_host`
+    #[default]
+    Cpal,
+    /// The standard input of `pipe_command`
+    Pipe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DevicePreset {
+    /// Matched case-insensitively against the output device name
+    pub name: String,
+
+    /// Gain applied on top of the volume for this device, in dB
+    #[serde(default)]
+    pub volume_offset_db: f32,
+
+    /// Play bit-perfect on this device, or not, whatever `bit_perfect` says
+    #[serde(default)]
+    pub exclusive: Option<bool>,
+
+    /// Which rate to open this device at
+    #[serde(default)]
+    pub sample_rate: SampleRatePolicy,
+}
+
+impl DevicePreset {
+    /// Returns the first of `presets` whose `name` appears in `device`.
+    pub fn find<'a>(presets: &'a [DevicePreset], device: &str) -> Option<&'a DevicePreset> {
+        let device = device.to_lowercase();
+        presets
+            .iter()
+            .find(|preset| device.contains(&preset.name.to_lowercase()))
+    }
+}
+
+/// The rate an output device is opened at, relative to the track's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleRatePolicy {
+    /// The track's own rate, or the closest one the device supports
+    #[default]
+    Match,
+    /// Always this rate, resampling every track to it
+    Fixed(u32),
+    /// The track's rate up to this one; faster tracks are resampled down
+    Max(u32),
+}
+
+impl SampleRatePolicy {
+    /// The rate to ask for when playing audio at `sample_rate`.
+    pub fn rate_for(self, sample_rate: u32) -> u32 {
+        match self {
+            Self::Match => sample_rate,
+            Self::Fixed(rate) => rate,
+            Self::Max(rate) => sample_rate.min(rate),
+        }
+    }
+}
+
+/// A second output device that plays along with the main one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorOutput {
+    /// Matched case-insensitively against the output device name
+    pub name: String,
+
+    /// Fixed volume (0.0 - 1.0) on this device, independent of the main one
+    #[serde(default = "default_mirror_volume")]
+    pub volume: f32,
+}
+
+fn default_mirror_volume() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderPreset {
+    pub path: PathBuf,
+
+    /// Save the playback position when leaving a track and resume from it
+    #[serde(default)]
+    pub remember_position: bool,
+
+    /// Playback speed for tracks in this folder, instead of the usual one
+    #[serde(default)]
+    pub speed: Option<f32>,
+
+    /// ReplayGain mode for tracks in this folder, instead of the usual one
+    #[serde(default)]
+    pub replay_gain: Option<ReplayGainMode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmConfig {
+    /// Local time to start playing, as `HH:MM`
+    pub time: String,
+
+    /// An `.m3u` playlist or a directory of audio files
+    pub playlist: PathBuf,
+
+    /// Seconds to ramp the volume up from silence
+    #[serde(default = "default_ramp_secs")]
+    pub ramp_secs: u64,
+
+    /// Volume reached at the end of the ramp (0.0 - 1.0)
+    #[serde(default = "default_alarm_volume")]
+    pub volume: f32,
+}
+
+fn default_ramp_secs() -> u64 {
+    60
+}
+
+fn default_alarm_volume() -> f32 {
+    1.0
+}
+
+/// Loads `config.json` and applies overrides on top of it.
+///
+/// Environment variables are applied first, then `overrides` (from `--set`),
+/// so the command line wins. Their values are parsed as JSON when possible
+/// (`500`, `true`, `[...]`) and are otherwise taken as plain strings.
+pub fn load_config(overrides: &[(String, String)]) -> std::io::Result<Config> {
+    let mut value = if Path::new(CONFIG_PATH).exists() {
+        let data = fs::read_to_string(CONFIG_PATH)?;
+        serde_json::from_str(&data)?
+    } else {
+        serde_json::Value::Object(Default::default())
+    };
+
+    for (key, raw) in env_overrides().iter().chain(overrides) {
+        let parsed = serde_json::from_str(raw).unwrap_or_else(|_| raw_string(raw));
+        let is_string = parsed.is_string();
+        apply_override(&mut value, key, parsed)?;
+
+        // `1234` parses as a number, which a text setting such as
+        // `party_code` won't take; those get it as written instead
+        if !is_string && serde_json::from_value::<Config>(value.clone()).is_err() {
+            apply_override(&mut value, key, raw_string(raw))?;
+        }
+    }
+
+    let config = serde_json::from_value(value)?;
+    Ok(config)
+}
+
+/// Edits the top-level table of `config.json` in place as raw JSON,
+/// creating the file if needed.
+///
+/// Used by tools that write settings (e.g. importers) so keys they don't know
+/// about are preserved untouched.
+pub fn update_config_file(
+    edit: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+) -> anyhow::Result<()> {
+    let mut value = if Path::new(CONFIG_PATH).exists() {
+        let data = fs::read_to_string(CONFIG_PATH)?;
+        serde_json::from_str(&data)?
+    } else {
+        serde_json::Value::Object(Default::default())
+    };
+
+    let Some(config) = value.as_object_mut() else {
+        anyhow::bail!("{CONFIG_PATH} has to hold a JSON object");
+    };
+    edit(config);
+
+    let data = serde_json::to_string_pretty(&value)?;
+    fs::write(CONFIG_PATH, data)?;
+    Ok(())
+}
+
+fn env_overrides() -> Vec<(String, String)> {
+    let mut overrides: Vec<(String, String)> = std::env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            Some((key.to_ascii_lowercase().replace("__", "."), value))
+        })
+        .collect();
+
+    overrides.sort();
+    overrides
+}
+
+fn raw_string(raw: &str) -> serde_json::Value {
+    serde_json::Value::String(raw.to_string())
+}
+
+/// Sets the dotted `key` in `value` to `parsed`, creating intermediate tables
+/// as needed.
+fn apply_override(
+    value: &mut serde_json::Value,
+    key: &str,
+    parsed: serde_json::Value,
+) -> std::io::Result<()> {
+    use serde_json::Value;
+
+    let known_keys = serde_json::to_value(Config::default())?;
+    let top = key.split('.').next().unwrap_or(key);
+    if known_keys.get(top).is_none() {
+        log::warn!("Overriding unknown config key {key:?}");
+    }
+
+    let mut target = value;
+    for part in key.split('.') {
+        if !target.is_object() {
+            *target = Value::Object(Default::default());
+        }
+
+        target = target
+            .as_object_mut()
+            .expect("just made an object")
+            .entry(part.to_string())
+            .or_insert(Value::Null);
+    }
+
+    *target = parsed;
+    Ok(())
+}
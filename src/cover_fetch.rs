@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use crate::artwork;
+use crate::musicbrainz;
+
+/// Releases scoring below this are a different album that happens to share
+/// words with it.
+const MIN_SCORE: u32 = 90;
+
+/// How many of the best matching releases are tried for a cover.
+const MAX_CANDIDATES: usize = 3;
+
+/// An album to find a cover for.
+#[derive(Debug, Clone)]
+pub struct CoverRequest {
+    pub artist: String,
+    pub album: String,
+    /// Where its thumbnail goes
+    pub thumbnail: PathBuf,
+}
+
+/// Looks albums up on MusicBrainz and caches their covers from the Cover
+/// Art Archive, one at a time on a background thread.
+pub struct CoverArtFetch {
+    /// Whether each album got a cover
+    results: Receiver<bool>,
+    handle: JoinHandle<()>,
+    pub total: usize,
+    pub found: usize,
+    pub done: usize,
+}
+
+impl CoverArtFetch {
+    pub fn spawn(albums: Vec<CoverRequest>) -> Self {
+        let (sender, results) = mpsc::channel();
+        let total = albums.len();
+
+        let handle = thread::spawn(move || {
+            for (i, album) in albums.into_iter().enumerate() {
+                if i > 0 {
+                    thread::sleep(musicbrainz::REQUEST_INTERVAL);
+                }
+
+                let found = match fetch(&album) {
+                    Ok(found) => found,
+                    Err(err) => {
+                        log::warn!("Failed to fetch cover art for {:?}: {err}", album.album);
+                        false
+                    }
+                };
+                if sender.send(found).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            results,
+            handle,
+            total,
+            found: 0,
+            done: 0,
+        }
+    }
+
+    /// Counts the albums looked up since the last call.
+    pub fn poll(&mut self) {
+        for found in self.results.try_iter() {
+            self.done += 1;
+            self.found += usize::from(found);
+        }
+    }
+
+    /// Whether every album has been looked up. Check this before the last
+    /// `poll`.
+    pub fn is_done(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+/// Caches the cover of the best matching release that has one. Returns
+/// whether one was found.
+fn fetch(album: &CoverRequest) -> anyhow::Result<bool> {
+    let releases = musicbrainz::search_releases(&album.artist, &album.album)?;
+
+    for release in releases
+        .iter()
+        .filter(|r| r.score >= MIN_SCORE)
+        .take(MAX_CANDIDATES)
+    {
+        let Some(image) = musicbrainz::front_cover(&release.id)? else {
+            continue;
+        };
+        log::debug!(
+            "Cover for {:?} from release {} ({})",
+            album.album,
+            release.id,
+            release.title
+        );
+        artwork::save_thumbnail(&image, &album.thumbnail)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Audio before track 1's `INDEX 01` shorter than this is just a gap, not a
+/// hidden track.
+const MIN_HIDDEN_TRACK: Duration = Duration::from_secs(2);
+
+/// A parsed `.cue` sheet describing several tracks in one audio file.
+#[derive(Debug, Clone, Default)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// From `REM GENRE`
+    pub genre: Option<String>,
+    /// From `REM DATE`, usually just the year
+    pub date: Option<String>,
+    /// The first audio file, resolved relative to the sheet
+    pub file: Option<PathBuf>,
+    pub tracks: Vec<CueTrack>,
+}
+
+impl CueSheet {
+    /// Plays every track from `file`, for a sheet embedded in it.
+    pub fn use_file(&mut self, file: &Path) {
+        self.file = Some(file.to_path_buf());
+        for track in &mut self.tracks {
+            track.file = None;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// The `FILE` its `INDEX 01` is in, when the sheet lists several;
+    /// otherwise the sheet's file
+    pub file: Option<PathBuf>,
+    /// `INDEX 00`: where the pregap before the track starts
+    pub pregap: Option<Duration>,
+    /// `INDEX 01`: where the track itself starts
+    pub start: Duration,
+}
+
+/// Where a library entry's audio lives when it is part of a larger file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CueRange {
+    pub file: PathBuf,
+    pub start_ms: u64,
+    /// `None` plays to the end of the file
+    pub end_ms: Option<u64>,
+    /// Length of the next track's pregap, which sits at the end of this range
+    #[serde(default)]
+    pub pregap_ms: u64,
+}
+
+impl CueRange {
+    /// Start and end of the range, leaving out the trailing pregap if asked.
+    pub fn bounds(&self, skip_pregaps: bool) -> (Duration, Option<Duration>) {
+        let end = self.end_ms.map(|end| match skip_pregaps {
+            true => end.saturating_sub(self.pregap_ms).max(self.start_ms),
+            false => end,
+        });
+
+        (
+            Duration::from_millis(self.start_ms),
+            end.map(Duration::from_millis),
+        )
+    }
+}
+
+/// One playable entry produced from a sheet.
+#[derive(Debug, Clone)]
+pub struct CueEntry {
+    /// 0 for a hidden track in the first track's pregap
+    pub number: u32,
+    pub title: String,
+    pub performer: Option<String>,
+    pub range: CueRange,
+}
+
+pub fn load(path: &Path) -> std::io::Result<CueSheet> {
+    let data = fs::read(path)?;
+    let text = String::from_utf8_lossy(&data);
+    let mut sheet = parse(&text);
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    sheet.file = sheet.file.map(|file| base.join(file));
+    for track in &mut sheet.tracks {
+        track.file = track.file.take().map(|file| base.join(file));
+    }
+    Ok(sheet)
+}
+
+pub fn parse(text: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+    // The latest `FILE`, and whether the current track's `INDEX 00` came
+    // before it
+    let mut file: Option<PathBuf> = None;
+    let mut pregap_in_other_file = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                let path = PathBuf::from(quoted(rest));
+                if sheet.file.is_none() {
+                    sheet.file = Some(path.clone());
+                }
+                pregap_in_other_file = sheet.tracks.last().is_some_and(|t| t.pregap.is_some());
+                file = Some(path);
+            }
+            "TRACK" => {
+                pregap_in_other_file = false;
+                let number = rest.split_whitespace().next().and_then(|n| n.parse().ok());
+                sheet.tracks.push(CueTrack {
+                    number: number.unwrap_or(sheet.tracks.len() as u32 + 1),
+                    ..Default::default()
+                });
+            }
+            "TITLE" => match sheet.tracks.last_mut() {
+                Some(track) => track.title = Some(quoted(rest)),
+                None => sheet.title = Some(quoted(rest)),
+            },
+            "PERFORMER" => match sheet.tracks.last_mut() {
+                Some(track) => track.performer = Some(quoted(rest)),
+                None => sheet.performer = Some(quoted(rest)),
+            },
+            "REM" => {
+                let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if sheet.tracks.is_empty() {
+                    match key.to_ascii_uppercase().as_str() {
+                        "GENRE" => sheet.genre = Some(quoted_value(value)),
+                        "DATE" => sheet.date = Some(quoted_value(value)),
+                        _ => {}
+                    }
+                }
+            }
+            "INDEX" => {
+                let mut parts = rest.split_whitespace();
+                let index = parts.next().and_then(|n| n.parse::<u32>().ok());
+                let time = parts.next().and_then(parse_time);
+                // Tracks in the first file go by the sheet's
+                let track_file = file
+                    .clone()
+                    .filter(|file| Some(file) != sheet.file.as_ref());
+
+                if let (Some(track), Some(index), Some(time)) =
+                    (sheet.tracks.last_mut(), index, time)
+                {
+                    match index {
+                        0 => track.pregap = Some(time),
+                        1 => {
+                            track.start = time;
+                            track.file = track_file;
+                            // A pregap at the end of the previous file plays
+                            // with the previous track
+                            if pregap_in_other_file {
+                                track.pregap = None;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    sheet
+}
+
+/// Turns the sheet's tracks into playable ranges of its audio files.
+///
+/// A track runs until the next track's `INDEX 01`, so the pregap plays at the
+/// end of the previous track like on a CD; [`CueRange::bounds`] can cut it
+/// off. The last track in each file plays to the end of it. Audio before
+/// track 1 long enough to be a hidden track becomes its own entry, numbered 0.
+pub fn entries(sheet: &CueSheet) -> Vec<CueEntry> {
+    let Some(file) = &sheet.file else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+
+    if let Some(first) = sheet.tracks.first() {
+        let hidden_start = first.pregap.unwrap_or(Duration::ZERO);
+        if first.start.saturating_sub(hidden_start) >= MIN_HIDDEN_TRACK {
+            entries.push(CueEntry {
+                number: 0,
+                title: "Hidden track".to_string(),
+                performer: first.performer.clone(),
+                range: CueRange {
+                    file: track_file(first, file).clone(),
+                    start_ms: hidden_start.as_millis() as u64,
+                    end_ms: Some(first.start.as_millis() as u64),
+                    pregap_ms: 0,
+                },
+            });
+        }
+    }
+
+    for (i, track) in sheet.tracks.iter().enumerate() {
+        let next = sheet
+            .tracks
+            .get(i + 1)
+            .filter(|next| track_file(next, file) == track_file(track, file));
+        let pregap = next
+            .and_then(|next| next.pregap.map(|pregap| next.start.saturating_sub(pregap)))
+            .unwrap_or_default();
+
+        entries.push(CueEntry {
+            number: track.number,
+            title: track
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Track {:02}", track.number)),
+            performer: track.performer.clone(),
+            range: CueRange {
+                file: track_file(track, file).clone(),
+                start_ms: track.start.as_millis() as u64,
+                end_ms: next.map(|next| next.start.as_millis() as u64),
+                pregap_ms: pregap.as_millis() as u64,
+            },
+        });
+    }
+
+    entries
+}
+
+/// The file `track` is in: `file`, the sheet's first, unless it names another.
+fn track_file<'a>(track: &'a CueTrack, file: &'a PathBuf) -> &'a PathBuf {
+    track.file.as_ref().unwrap_or(file)
+}
+
+/// Library path for one track of a sheet, e.g. `album.cue#03`, or
+/// `album.flac#03` for a sheet embedded in the audio file.
+///
+/// Cue tracks share their audio file, so they are keyed by the sheet and
+/// track number instead.
+pub fn track_path(cue_path: &Path, number: u32) -> PathBuf {
+    PathBuf::from(format!("{}#{:02}", cue_path.display(), number))
+}
+
+/// Splits a path made by [`track_path`] back into the sheet and track number.
+pub fn split_track_path(path: &Path) -> Option<(PathBuf, u32)> {
+    let (sheet, number) = path.to_str()?.rsplit_once('#')?;
+    let sheet = PathBuf::from(sheet);
+
+    let is_sheet = sheet
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue") || ext.eq_ignore_ascii_case("flac"));
+    if !is_sheet {
+        return None;
+    }
+
+    Some((sheet, number.parse().ok()?))
+}
+
+fn quoted(value: &str) -> String {
+    let value = value.trim();
+    match value.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or(rest).to_string(),
+        // Unquoted FILE lines end with the file type
+        None => value.split_whitespace().next().unwrap_or(value).to_string(),
+    }
+}
+
+/// A value that may be quoted and may contain spaces, like `REM GENRE Hip Hop`.
+fn quoted_value(value: &str) -> String {
+    let value = value.trim();
+    match value.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or(rest).to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Parses `mm:ss:ff`, where frames are 1/75 of a second.
+fn parse_time(value: &str) -> Option<Duration> {
+    let mut parts = value.split(':').map(|p| p.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+
+    let millis = (minutes * 60 + seconds) * 1000 + frames * 1000 / 75;
+    Some(Duration::from_millis(millis))
+}
@@ -0,0 +1,199 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::library::{ArtistNode, LibraryTrack};
+use crate::list::ListSelector;
+
+/// Tracks whose lengths differ by more than this are different recordings.
+const MAX_DURATION_DIFF_SECS: u64 = 3;
+
+/// One copy of a duplicated recording, with what's needed to compare quality.
+#[derive(Debug, Clone)]
+pub struct DuplicateFile {
+    pub track: LibraryTrack,
+    pub format: String,
+    pub size: u64,
+    pub bitrate_kbps: Option<u64>,
+}
+
+impl DuplicateFile {
+    fn new(track: LibraryTrack) -> Self {
+        let format = track
+            .path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+        let size = fs::metadata(&track.path).map(|m| m.len()).unwrap_or(0);
+        let bitrate_kbps = track
+            .duration
+            .filter(|secs| *secs > 0)
+            .map(|secs| size * 8 / secs / 1000);
+
+        Self {
+            track,
+            format,
+            size,
+            bitrate_kbps,
+        }
+    }
+
+    fn is_lossless(&self) -> bool {
+        matches!(self.format.as_str(), "flac" | "wav" | "alac" | "ape")
+    }
+}
+
+/// Copies of the same recording, best quality first.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub name: String,
+    pub files: Vec<DuplicateFile>,
+}
+
+/// Groups library tracks with the same artist and title and similar length.
+pub fn find_duplicates(artists: &[ArtistNode]) -> Vec<DuplicateGroup> {
+    let mut by_name: HashMap<(String, String), Vec<&LibraryTrack>> = HashMap::new();
+
+    for track in artists
+        .iter()
+        .flat_map(|a| &a.albums)
+        .flat_map(|alb| &alb.tracks)
+    {
+        let key = (normalize(&track.artist), normalize(&track.title));
+        by_name.entry(key).or_default().push(track);
+    }
+
+    let mut groups = Vec::new();
+    for tracks in by_name.into_values().filter(|tracks| tracks.len() > 1) {
+        // Split same-named tracks into clusters of similar length, so a live
+        // version doesn't count as a copy of the studio one
+        let mut clusters: Vec<Vec<&LibraryTrack>> = Vec::new();
+        for track in tracks {
+            let cluster = clusters.iter_mut().find(|cluster| {
+                match (cluster[0].duration, track.duration) {
+                    (Some(a), Some(b)) => a.abs_diff(b) <= MAX_DURATION_DIFF_SECS,
+                    // Without both lengths there's no telling a copy from
+                    // another recording of the same name
+                    _ => false,
+                }
+            });
+
+            match cluster {
+                Some(cluster) => cluster.push(track),
+                None => clusters.push(vec![track]),
+            }
+        }
+
+        groups.extend(clusters.into_iter().filter(|c| c.len() > 1).map(group));
+    }
+
+    groups.sort_by_key(|group| group.name.to_lowercase());
+    groups
+}
+
+/// The library tracks at each of `paths`, a list of files found to sound the
+/// same, as groups. Files that have left the library since are dropped.
+pub fn recording_groups(artists: &[ArtistNode], paths: &[Vec<PathBuf>]) -> Vec<DuplicateGroup> {
+    let tracks: HashMap<&Path, &LibraryTrack> = artists
+        .iter()
+        .flat_map(|a| &a.albums)
+        .flat_map(|alb| &alb.tracks)
+        .map(|track| (track.path.as_path(), track))
+        .collect();
+
+    let mut groups: Vec<DuplicateGroup> = paths
+        .iter()
+        .map(|paths| -> Vec<&LibraryTrack> {
+            paths
+                .iter()
+                .filter_map(|path| tracks.get(path.as_path()).copied())
+                .collect()
+        })
+        .filter(|cluster| cluster.len() > 1)
+        .map(group)
+        .collect();
+    groups.sort_by_key(|group| group.name.to_lowercase());
+    groups
+}
+
+/// Copies of one recording, sorted best first and named after the best.
+fn group(tracks: Vec<&LibraryTrack>) -> DuplicateGroup {
+    let mut files: Vec<DuplicateFile> = tracks
+        .into_iter()
+        .map(|track| DuplicateFile::new(track.clone()))
+        .collect();
+    files.sort_by_key(|f| {
+        (
+            !f.is_lossless(),
+            Reverse(f.bitrate_kbps),
+            f.track.path.clone(),
+        )
+    });
+
+    let first = &files[0].track;
+    DuplicateGroup {
+        name: format!("{} – {}", first.artist, first.title),
+        files,
+    }
+}
+
+/// The duplicates screen: groups plus a selection over every file in them.
+pub struct DuplicateView {
+    pub groups: Vec<DuplicateGroup>,
+    /// `(group, file)` indices, in display order
+    pub list: ListSelector<(usize, usize)>,
+}
+
+impl DuplicateView {
+    pub fn new(groups: Vec<DuplicateGroup>) -> Self {
+        let rows = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(g, group)| (0..group.files.len()).map(move |f| (g, f)))
+            .collect();
+
+        Self {
+            groups,
+            list: ListSelector::new(rows),
+        }
+    }
+
+    pub fn selected_file(&self) -> Option<&DuplicateFile> {
+        let (group, file) = *self.list.selected_item()?;
+        self.groups.get(group)?.files.get(file)
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Moves `path` into `trash_dir`, keeping the file name unique there.
+pub fn move_to_trash(path: &Path, trash_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(trash_dir)?;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut target = trash_dir.join(name);
+    let mut n = 1;
+    while target.exists() {
+        target = trash_dir.join(format!("{n}-{}", name.to_string_lossy()));
+        n += 1;
+    }
+
+    // Renaming fails across filesystems; fall back to copying
+    if fs::rename(path, &target).is_err() {
+        fs::copy(path, &target)?;
+        fs::remove_file(path)?;
+    }
+
+    Ok(target)
+}
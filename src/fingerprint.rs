@@ -0,0 +1,457 @@
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use rayon::prelude::*;
+use realfft::RealFftPlanner;
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::default::{get_codecs, get_probe};
+
+use crate::library::FileStamp;
+use crate::persistence;
+
+/// Audio is mixed down to mono at this rate before it's analysed.
+const SAMPLE_RATE: u32 = 11025;
+
+/// Samples per analysed frame; frames overlap by two thirds.
+const FRAME_SIZE: usize = 4096;
+const FRAME_STEP: usize = FRAME_SIZE / 3;
+
+/// How much of each file is fingerprinted, from where the sound starts.
+const FINGERPRINT_SECS: usize = 30;
+
+/// Pitches outside this range, in Hz, are left out of the chroma features.
+const MIN_FREQ: f32 = 28.0;
+const MAX_FREQ: f32 = 3520.0;
+
+/// Leading samples quieter than this are skipped, so copies with more or
+/// less silence before the music still line up.
+const SILENCE: f32 = 1.0 / 1024.0;
+
+/// Bits used in each frame's sub-fingerprint.
+const BITS: u32 = 24;
+
+/// Fingerprints are lined up to within this many frames either way, about
+/// a second.
+const MAX_OFFSET: isize = 8;
+
+/// Fingerprints with at most this share of their bits differing are the
+/// same recording; unrelated audio differs in about half.
+const MAX_DISTANCE: f32 = 0.2;
+
+/// Files whose lengths differ by more than this aren't compared.
+const MAX_DURATION_DIFF_SECS: u64 = 5;
+
+/// What the start of a file sounds like, in a form that survives
+/// re-encoding at another bitrate or in another format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// The file as it was when fingerprinted
+    pub stamp: FileStamp,
+    /// Length of the whole file in seconds, when the container says
+    pub duration: Option<u64>,
+    pub frames: Vec<u32>,
+}
+
+/// Fingerprints by path, kept between runs.
+pub type Fingerprints = HashMap<PathBuf, Fingerprint>;
+
+/// A library file to fingerprint.
+#[derive(Debug, Clone)]
+pub struct FingerprintRequest {
+    pub path: PathBuf,
+    pub stamp: Option<FileStamp>,
+    pub duration: Option<u64>,
+}
+
+/// Fingerprints files on the rayon pool, from a background thread, then
+/// groups the ones that sound the same.
+pub struct FingerprintScan {
+    /// One message per file looked at
+    progress: Receiver<()>,
+    handle: JoinHandle<Vec<Vec<PathBuf>>>,
+    pub total: usize,
+    pub done: usize,
+}
+
+impl FingerprintScan {
+    /// Starts on `files`, reusing the saved fingerprints of files that
+    /// haven't changed since the last time.
+    pub fn spawn(files: Vec<FingerprintRequest>) -> Self {
+        let (sender, progress) = mpsc::channel();
+        let total = files.len();
+
+        let handle = thread::spawn(move || {
+            let mut cache = persistence::load_fingerprints().unwrap_or_else(|err| {
+                log::warn!("Failed to load fingerprints, starting over: {err}");
+                Fingerprints::new()
+            });
+            let fresh: Vec<(PathBuf, Fingerprint)> = files
+                .par_iter()
+                .filter_map(|file| {
+                    let found = fingerprint_unless_cached(file, &cache);
+                    sender.send(()).ok();
+                    found
+                })
+                .collect();
+            cache.extend(fresh);
+
+            // Files no longer in the library are forgotten
+            let paths: HashSet<&PathBuf> = files.iter().map(|file| &file.path).collect();
+            cache.retain(|path, _| paths.contains(path));
+            if let Err(err) = persistence::save_fingerprints(&cache) {
+                log::error!("Failed to save fingerprints: {err}");
+            }
+
+            same_recordings(&files, &cache)
+        });
+
+        Self {
+            progress,
+            handle,
+            total,
+            done: 0,
+        }
+    }
+
+    /// Counts the files looked at since the last call.
+    pub fn poll(&mut self) {
+        self.done += self.progress.try_iter().count();
+    }
+
+    /// Whether every file has been looked at and grouped. Check this before
+    /// the last `poll`.
+    pub fn is_done(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// The paths of each group of files that sound the same, once done.
+    pub fn finish(self) -> Option<Vec<Vec<PathBuf>>> {
+        self.handle.join().ok()
+    }
+}
+
+/// A new fingerprint of `file`, or `None` when `cache` has an up to date
+/// one or the file can't be fingerprinted.
+fn fingerprint_unless_cached(
+    file: &FingerprintRequest,
+    cache: &Fingerprints,
+) -> Option<(PathBuf, Fingerprint)> {
+    let stamp = file.stamp.or_else(|| FileStamp::of(&file.path))?;
+    if cache
+        .get(&file.path)
+        .is_some_and(|cached| cached.stamp == stamp)
+    {
+        return None;
+    }
+
+    match fingerprint(&file.path) {
+        Ok(Some((frames, duration))) => Some((
+            file.path.clone(),
+            Fingerprint {
+                stamp,
+                duration,
+                frames,
+            },
+        )),
+        Ok(None) => {
+            log::debug!("{:?} is too short or quiet to fingerprint", file.path);
+            None
+        }
+        Err(err) => {
+            log::warn!("Failed to fingerprint {:?}: {err}", file.path);
+            None
+        }
+    }
+}
+
+/// Decodes the start of the file and works out its fingerprint, along with
+/// the file's length if the container gives it. `None` when there's less
+/// than a frame of sound.
+fn fingerprint(path: &Path) -> anyhow::Result<Option<(Vec<u32>, Option<u64>)>> {
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut format = get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow::anyhow!("No supported audio track found"))?
+        .clone();
+    let params = &track.codec_params;
+    let duration = params
+        .time_base
+        .zip(params.n_frames)
+        .map(|(tb, frames)| frames * u64::from(tb.numer) / u64::from(tb.denom));
+    let mut decoder = get_codecs().make(params, &DecoderOptions::default())?;
+
+    let wanted = FINGERPRINT_SECS * SAMPLE_RATE as usize;
+    let mut mono = Vec::with_capacity(wanted);
+    let mut resampler = None;
+    let mut buffer = None;
+
+    while mono.len() < wanted {
+        let Ok(packet) = format.next_packet() else {
+            break;
+        };
+        if packet.track_id() != track.id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                log::debug!("Skipping undecodable packet in {:?}: {err}", path);
+                continue;
+            }
+        };
+
+        let spec = *decoded.spec();
+        let resampler = resampler.get_or_insert_with(|| Resampler::new(spec.rate));
+        let samples =
+            buffer.get_or_insert_with(|| SampleBuffer::<f32>::new(decoded.capacity() as u64, spec));
+        if samples.capacity() < decoded.capacity() * spec.channels.count() {
+            *samples = SampleBuffer::new(decoded.capacity() as u64, spec);
+        }
+        samples.copy_interleaved_ref(decoded);
+
+        let channels = spec.channels.count().max(1);
+        for frame in samples.samples().chunks(channels) {
+            let sample = frame.iter().sum::<f32>() / channels as f32;
+            if !resampler.started && sample.abs() < SILENCE {
+                continue;
+            }
+            resampler.started = true;
+            resampler.push(sample, &mut mono);
+        }
+    }
+
+    mono.truncate(wanted);
+    let frames = fingerprint_samples(&mono);
+    Ok((!frames.is_empty()).then_some((frames, duration)))
+}
+
+/// Brings audio down to `SAMPLE_RATE` by averaging the samples that fall
+/// into each output sample. Rates below it come out slower.
+struct Resampler {
+    rate: u32,
+    /// Progress towards the next output sample, in units of 1 / `rate`
+    position: u32,
+    sum: f32,
+    count: u32,
+    /// Set once the leading silence is over
+    started: bool,
+}
+
+impl Resampler {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            position: 0,
+            sum: 0.0,
+            count: 0,
+            started: false,
+        }
+    }
+
+    fn push(&mut self, sample: f32, out: &mut Vec<f32>) {
+        self.sum += sample;
+        self.count += 1;
+        self.position += SAMPLE_RATE;
+        if self.position >= self.rate {
+            self.position -= self.rate;
+            out.push(self.sum / self.count as f32);
+            self.sum = 0.0;
+            self.count = 0;
+        }
+    }
+}
+
+/// One sub-fingerprint per frame after the first, from how the energy of
+/// each of the twelve pitch classes compares with its neighbour's, and how
+/// that changed since the frame before.
+fn fingerprint_samples(samples: &[f32]) -> Vec<u32> {
+    let mut planner = RealFftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(FRAME_SIZE);
+    let mut input = fft.make_input_vec();
+    let mut spectrum = fft.make_output_vec();
+
+    let window: Vec<f32> = (0..FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_SIZE as f32).cos())
+        .collect();
+    let classes: Vec<Option<usize>> = (0..spectrum.len())
+        .map(|bin| {
+            let freq = bin as f32 * SAMPLE_RATE as f32 / FRAME_SIZE as f32;
+            let semitones = 12.0 * (freq / 440.0).log2();
+            (MIN_FREQ..=MAX_FREQ)
+                .contains(&freq)
+                .then(|| (semitones.round() as i32).rem_euclid(12) as usize)
+        })
+        .collect();
+
+    let mut chroma: Vec<[f32; 12]> = Vec::new();
+    for start in (0..samples.len().saturating_sub(FRAME_SIZE - 1)).step_by(FRAME_STEP) {
+        for (i, value) in input.iter_mut().enumerate() {
+            *value = samples[start + i] * window[i];
+        }
+        if fft.process(&mut input, &mut spectrum).is_err() {
+            continue;
+        }
+
+        let mut frame = [0f32; 12];
+        for (bin, class) in classes.iter().enumerate() {
+            if let Some(class) = class {
+                frame[*class] += spectrum[bin].norm_sqr();
+            }
+        }
+        let norm = frame.iter().map(|e| e * e).sum::<f32>().sqrt();
+        if norm > f32::EPSILON {
+            frame.iter_mut().for_each(|e| *e /= norm);
+        }
+        chroma.push(frame);
+    }
+
+    // Averaged with the frames either side, to even out note onsets
+    let smoothed: Vec<[f32; 12]> = (0..chroma.len())
+        .map(|i| {
+            let around = &chroma[i.saturating_sub(1)..(i + 2).min(chroma.len())];
+            let mut frame = [0f32; 12];
+            for (class, value) in frame.iter_mut().enumerate() {
+                *value = around.iter().map(|f| f[class]).sum::<f32>() / around.len() as f32;
+            }
+            frame
+        })
+        .collect();
+
+    smoothed
+        .windows(2)
+        .map(|pair| {
+            let (before, now) = (&pair[0], &pair[1]);
+            (0..12).fold(0u32, |bits, class| {
+                let next = (class + 1) % 12;
+                let contrast = now[class] - now[next];
+                let change = contrast - (before[class] - before[next]);
+                bits | (u32::from(contrast > 0.0) << class)
+                    | (u32::from(change > 0.0) << (class + 12))
+            })
+        })
+        .collect()
+}
+
+/// The share of bits that differ between two fingerprints lined up as well
+/// as they can be, 0 for the same audio and around 0.5 for unrelated audio.
+fn distance(a: &[u32], b: &[u32]) -> f32 {
+    // At least half the shorter one has to overlap
+    let min_overlap = (a.len().min(b.len()) / 2).max(1);
+
+    (-MAX_OFFSET..=MAX_OFFSET)
+        .filter_map(|offset| {
+            let (a, b) = match offset < 0 {
+                true => (a.get(offset.unsigned_abs()..)?, b),
+                false => (a, b.get(offset as usize..)?),
+            };
+            let overlap = a.len().min(b.len());
+            if overlap < min_overlap {
+                return None;
+            }
+            let differing: u32 = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum();
+            Some(differing as f32 / (overlap as u32 * BITS) as f32)
+        })
+        .fold(1.0, f32::min)
+}
+
+/// The paths of each group of `files` whose fingerprints are close enough
+/// to be the same recording. Only files of about the same length are
+/// compared, unless a file's length isn't known.
+fn same_recordings(files: &[FingerprintRequest], cache: &Fingerprints) -> Vec<Vec<PathBuf>> {
+    let mut known: Vec<(&PathBuf, u64, &[u32])> = Vec::new();
+    let mut unknown: Vec<(&PathBuf, &[u32])> = Vec::new();
+    for file in files {
+        let Some(fingerprint) = cache.get(&file.path) else {
+            continue;
+        };
+        match file.duration.or(fingerprint.duration) {
+            Some(duration) => known.push((&file.path, duration, &fingerprint.frames)),
+            None => unknown.push((&file.path, &fingerprint.frames)),
+        }
+    }
+    known.sort_by_key(|(_, duration, _)| *duration);
+
+    let all: Vec<(&PathBuf, &[u32])> = known
+        .iter()
+        .map(|(path, _, frames)| (*path, *frames))
+        .chain(unknown.iter().copied())
+        .collect();
+    let mut groups = Groups::new(all.len());
+
+    for i in 0..known.len() {
+        for j in i + 1..known.len() {
+            if known[j].1 - known[i].1 > MAX_DURATION_DIFF_SECS {
+                break;
+            }
+            if distance(known[i].2, known[j].2) <= MAX_DISTANCE {
+                groups.join(i, j);
+            }
+        }
+    }
+    for i in known.len()..all.len() {
+        for j in 0..i {
+            if distance(all[i].1, all[j].1) <= MAX_DISTANCE {
+                groups.join(i, j);
+            }
+        }
+    }
+
+    let mut by_root: HashMap<usize, Vec<PathBuf>> = HashMap::new();
+    for (i, (path, _)) in all.iter().enumerate() {
+        by_root
+            .entry(groups.root(i))
+            .or_default()
+            .push(path.to_path_buf());
+    }
+    by_root
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// Disjoint sets of indices, merged as matches turn up.
+struct Groups {
+    parents: Vec<usize>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.root(a), self.root(b));
+        self.parents[a] = b;
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::persistence;
+
+/// One completed play.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix time the track finished
+    pub played_at: u64,
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    /// Seconds listened
+    pub listened: u64,
+}
+
+impl HistoryEntry {
+    /// `played_at` as local `YYYY-MM-DD HH:MM`.
+    pub fn local_time(&self) -> String {
+        format_local(self.played_at)
+    }
+
+    /// `played_at` relative to today: `today 14:02`, `yesterday 15:31`,
+    /// `Mon 09:12` within the last week, otherwise the date.
+    pub fn relative_time(&self) -> String {
+        let Some(time) = Local.timestamp_opt(self.played_at as i64, 0).single() else {
+            return String::new();
+        };
+
+        let days_ago = (Local::now().date_naive() - time.date_naive()).num_days();
+        match days_ago {
+            0 => time.format("today %H:%M").to_string(),
+            1 => time.format("yesterday %H:%M").to_string(),
+            2..=6 => time.format("%a %H:%M").to_string(),
+            _ => time.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// A Unix time as local `YYYY-MM-DD HH:MM`.
+pub fn format_local(unix: u64) -> String {
+    Local
+        .timestamp_opt(unix as i64, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// How long ago a Unix time was, as briefly as fits a column: `today`,
+/// `3d`, `2w`, `5mo` or `1y`.
+pub fn format_ago(unix: u64) -> String {
+    let Some(time) = Local.timestamp_opt(unix as i64, 0).single() else {
+        return String::new();
+    };
+
+    let days_ago = (Local::now().date_naive() - time.date_naive())
+        .num_days()
+        .max(0);
+    match days_ago {
+        0 => "today".to_string(),
+        1..=13 => format!("{days_ago}d"),
+        14..=59 => format!("{}w", days_ago / 7),
+        60..=364 => format!("{}mo", days_ago / 30),
+        _ => format!("{}y", days_ago / 365),
+    }
+}
+
+/// The last `limit` distinct tracks played, newest first, from `entries`
+/// (also newest first).
+pub fn recent(entries: &[HistoryEntry], limit: usize) -> Vec<HistoryEntry> {
+    let mut seen = HashSet::new();
+
+    entries
+        .iter()
+        .filter(|entry| seen.insert(&entry.path))
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Writes `entries` oldest first.
+pub fn export(
+    entries: &[HistoryEntry],
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut entries = entries.to_vec();
+    entries.sort_by_key(|entry| entry.played_at);
+
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &entries)?;
+            writeln!(out)
+        }
+        ExportFormat::Csv => {
+            writeln!(
+                out,
+                "played_at,artist,album_artist,album,title,listened,path"
+            )?;
+            for entry in &entries {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{}",
+                    entry.played_at,
+                    csv_field(&entry.artist),
+                    csv_field(&entry.album_artist),
+                    csv_field(&entry.album),
+                    csv_field(&entry.title),
+                    entry.listened,
+                    csv_field(&entry.path.to_string_lossy()),
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Entry point for `shelltrax history`.
+pub fn run(format: ExportFormat, output: Option<&Path>) -> io::Result<()> {
+    let entries = persistence::load_history()?;
+
+    match output {
+        Some(path) => export(&entries, format, &mut File::create(path)?),
+        None => export(&entries, format, &mut io::stdout().lock()),
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config;
+use crate::keymap::Action;
+use crate::persistence;
+
+/// Player whose configuration is being imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    Cmus,
+    Ncmpcpp,
+}
+
+/// Everything recognised in a foreign config, plus what had to be skipped.
+#[derive(Debug, Default)]
+pub struct Import {
+    pub keys: Vec<(String, Action)>,
+    pub volume_step: Option<u32>,
+    pub autoplay: Option<bool>,
+    pub skipped: Vec<String>,
+}
+
+/// Reads the given files, merges what was recognised into `config.json` (and
+/// `state.json` for playback flags) and prints a report.
+pub fn run(source: Source, paths: &[PathBuf]) -> anyhow::Result<()> {
+    let mut import = Import::default();
+
+    for path in paths {
+        let data = fs::read_to_string(path)?;
+        match source {
+            Source::Cmus => import_cmus(&data, &mut import),
+            Source::Ncmpcpp => import_ncmpcpp(&data, &mut import),
+        }
+    }
+
+    config::update_config_file(|config| {
+        let keys = config
+            .entry("keys")
+            .or_insert_with(|| serde_json::json!({}));
+
+        if let Some(keys) = keys.as_object_mut() {
+            for (spec, action) in &import.keys {
+                keys.insert(
+                    spec.clone(),
+                    serde_json::to_value(action).expect("action serializes"),
+                );
+            }
+        }
+
+        if let Some(step) = import.volume_step {
+            config.insert("volume_step".to_string(), step.into());
+        }
+    })?;
+
+    if let Some(autoplay) = import.autoplay {
+        let mut state = persistence::load_state().unwrap_or_default();
+        state.autoplay = autoplay;
+        persistence::save_state(&state)?;
+    }
+
+    println!("Imported {} key bindings:", import.keys.len());
+    for (spec, action) in &import.keys {
+        println!("  {spec:<12} {}", action.label());
+    }
+
+    if let Some(step) = import.volume_step {
+        println!("Volume step: {step}%");
+    }
+    if let Some(autoplay) = import.autoplay {
+        println!("Autoplay: {autoplay}");
+    }
+
+    if !import.skipped.is_empty() {
+        println!("Skipped {} unrecognised entries:", import.skipped.len());
+        for line in &import.skipped {
+            println!("  {line}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a cmus `rc` file (`bind <context> <key> <command>`, `set opt=val`).
+pub fn import_cmus(data: &str, import: &mut Import) {
+    for line in data.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("bind") => {
+                let mut rest: Vec<&str> = words.collect();
+                if rest.first() == Some(&"-f") {
+                    rest.remove(0);
+                }
+
+                let mapped = match rest.as_slice() {
+                    [_context, key, command @ ..] => cmus_key(key).zip(cmus_action(command)),
+                    _ => None,
+                };
+
+                match mapped {
+                    Some(binding) => import.keys.push(binding),
+                    None => import.skipped.push(line.to_string()),
+                }
+            }
+            Some("set") => {
+                let option = words.collect::<Vec<_>>().join(" ");
+                match option.split_once('=') {
+                    Some(("continue", value)) => import.autoplay = Some(value.trim() == "true"),
+                    _ => import.skipped.push(line.to_string()),
+                }
+            }
+            _ => import.skipped.push(line.to_string()),
+        }
+    }
+}
+
+fn cmus_key(key: &str) -> Option<String> {
+    let spec = match key {
+        "space" => "<space>".to_string(),
+        "enter" => "<enter>".to_string(),
+        "tab" => "<tab>".to_string(),
+        "backspace" => "<bs>".to_string(),
+        "delete" => "<del>".to_string(),
+        "escape" => "<esc>".to_string(),
+        "up" | "down" | "left" | "right" | "home" | "end" => format!("<{key}>"),
+        "page_up" => "<pgup>".to_string(),
+        "page_down" => "<pgdn>".to_string(),
+        _ if key.starts_with('^') && key.len() == 2 => format!("C-{}", key[1..].to_lowercase()),
+        _ if key.starts_with("M-") && key.len() == 3 => key.to_string(),
+        _ if key.starts_with('F') && key[1..].parse::<u8>().is_ok() => {
+            format!("<f{}>", &key[1..])
+        }
+        _ if key.chars().count() == 1 => key.to_string(),
+        _ => return None,
+    };
+
+    Some(spec)
+}
+
+fn cmus_action(command: &[&str]) -> Option<Action> {
+    let action = match command {
+        ["quit", ..] => Action::Quit,
+        ["view", "1" | "tree"] => Action::GotoLibrary,
+        ["view", "5" | "browser"] => Action::GotoBrowser,
+        ["win-up"] => Action::MoveUp,
+        ["win-down"] => Action::MoveDown,
+        ["win-activate"] => Action::Activate,
+        ["win-toggle"] => Action::ToggleExpanded,
+        ["win-next"] => Action::TabFocus,
+        ["win-add-l"] => Action::AddToLibrary,
+        ["browser-up"] => Action::GoUp,
+        ["player-pause"] | ["player-pause-playback"] => Action::TogglePause,
+        ["player-next"] => Action::NextTrack,
+        ["toggle", "continue"] => Action::ToggleAutoplay,
+        ["vol", amount, ..] if amount.starts_with('+') => Action::VolumeUp,
+        ["vol", amount, ..] if amount.starts_with('-') => Action::VolumeDown,
+        _ => return None,
+    };
+
+    Some(action)
+}
+
+/// Parses an ncmpcpp `bindings` file (`def_key "k"` followed by indented
+/// actions) or its main `config` file (`option = value`).
+pub fn import_ncmpcpp(data: &str, import: &mut Import) {
+    let mut current_key: Option<(String, Option<String>)> = None;
+    let mut current_action: Option<Action> = None;
+
+    for line in data.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("def_key") {
+            finish(current_key.take(), current_action.take(), import);
+            let raw = rest.trim().trim_matches('"').to_string();
+            let spec = ncmpcpp_key(&raw);
+            current_key = Some((raw, spec));
+            continue;
+        }
+
+        if trimmed.starts_with("def_command") {
+            finish(current_key.take(), current_action.take(), import);
+            import.skipped.push(trimmed.to_string());
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            // Action line of the current binding; keep the first one we know
+            if current_key.is_some() && current_action.is_none() {
+                current_action = ncmpcpp_action(trimmed);
+            }
+            continue;
+        }
+
+        match trimmed.split_once('=') {
+            Some((option, value)) if option.trim() == "volume_change_step" => {
+                match value.trim().trim_matches('"').parse() {
+                    Ok(step) => import.volume_step = Some(step),
+                    Err(_) => import.skipped.push(trimmed.to_string()),
+                }
+            }
+            _ => import.skipped.push(trimmed.to_string()),
+        }
+    }
+
+    finish(current_key.take(), current_action.take(), import);
+}
+
+/// Records a finished `def_key` block.
+fn finish(key: Option<(String, Option<String>)>, action: Option<Action>, import: &mut Import) {
+    if let Some((raw, spec)) = key {
+        match spec.zip(action) {
+            Some(binding) => import.keys.push(binding),
+            None => import.skipped.push(format!("def_key \"{raw}\"")),
+        }
+    }
+}
+
+fn ncmpcpp_key(key: &str) -> Option<String> {
+    let spec = match key {
+        "space" => "<space>".to_string(),
+        "enter" => "<enter>".to_string(),
+        "tab" => "<tab>".to_string(),
+        "backspace" => "<bs>".to_string(),
+        "delete" => "<del>".to_string(),
+        "escape" => "<esc>".to_string(),
+        "up" | "down" | "left" | "right" | "home" | "end" => format!("<{key}>"),
+        "page_up" => "<pgup>".to_string(),
+        "page_down" => "<pgdn>".to_string(),
+        _ if key.starts_with("ctrl-") && key.len() == 6 => format!("C-{}", &key[5..]),
+        _ if key.starts_with("alt-") && key.len() == 5 => format!("M-{}", &key[4..]),
+        _ if key.starts_with('f') && key[1..].parse::<u8>().is_ok() => format!("<{key}>"),
+        _ if key.chars().count() == 1 => key.to_string(),
+        _ => return None,
+    };
+
+    Some(spec)
+}
+
+fn ncmpcpp_action(action: &str) -> Option<Action> {
+    let action = match action {
+        "quit" => Action::Quit,
+        "scroll_up" => Action::MoveUp,
+        "scroll_down" => Action::MoveDown,
+        "enter_directory" | "play_item" | "run_action" => Action::Activate,
+        "jump_to_parent_directory" => Action::GoUp,
+        "next_column" | "previous_column" => Action::TabFocus,
+        "pause" => Action::TogglePause,
+        "next" => Action::NextTrack,
+        "volume_up" => Action::VolumeUp,
+        "volume_down" => Action::VolumeDown,
+        "show_browser" => Action::GotoBrowser,
+        "show_media_library" => Action::GotoLibrary,
+        _ => return None,
+    };
+
+    Some(action)
+}
@@ -0,0 +1,82 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use walkdir::WalkDir;
+
+use crate::library::is_audio_file;
+
+/// How often the incoming folder is checked for new files.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches a downloads folder for audio files that have finished arriving.
+///
+/// A file counts as finished once its size stayed the same across two polls,
+/// so files still being written aren't picked up half done. Files already in
+/// the folder when shelltrax starts are ignored.
+pub struct IncomingWatcher {
+    dir: PathBuf,
+    /// Size seen at the last poll, for files still arriving
+    sizes: HashMap<PathBuf, u64>,
+    /// Files already reported (or present at startup)
+    seen: HashSet<PathBuf>,
+    last_poll: Instant,
+}
+
+impl IncomingWatcher {
+    pub fn new(dir: PathBuf) -> Self {
+        let seen = audio_files(&dir)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+
+        Self {
+            dir,
+            sizes: HashMap::new(),
+            seen,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns the files that finished arriving since the last call.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut finished = Vec::new();
+
+        for (path, size) in audio_files(&self.dir) {
+            if self.seen.contains(&path) {
+                continue;
+            }
+
+            match self.sizes.insert(path.clone(), size) {
+                Some(previous) if previous == size && size > 0 => {
+                    self.sizes.remove(&path);
+                    self.seen.insert(path.clone());
+                    finished.push(path);
+                }
+                _ => {}
+            }
+        }
+
+        finished.sort();
+        finished
+    }
+}
+
+/// Audio files below `dir` with their current sizes.
+fn audio_files(dir: &Path) -> Vec<(PathBuf, u64)> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file() && is_audio_file(e.path()))
+        .filter_map(|e| {
+            let size = fs::metadata(e.path()).ok()?.len();
+            Some((e.into_path(), size))
+        })
+        .collect()
+}
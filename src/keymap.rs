@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+/// Everything a key binding can trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    GotoLibrary,
+    GotoBrowser,
+    GotoHistory,
+    GotoStats,
+    FocusArtists,
+    FocusTracks,
+    AddToLibrary,
+    RefreshLibrary,
+    FindMissing,
+    CycleBrowse,
+    MoveUp,
+    MoveDown,
+    Activate,
+    GoUp,
+    ToggleExpanded,
+    TabFocus,
+    TogglePause,
+    NextTrack,
+    ToggleAutoplay,
+    VolumeUp,
+    VolumeDown,
+    AddBookmark,
+    ToggleBookmarks,
+    RateUp,
+    RateDown,
+    Rate1,
+    Rate2,
+    Rate3,
+    Rate4,
+    Rate5,
+    ClearRating,
+    ToggleKaraoke,
+    ToggleCrossfeed,
+    ToggleNightMode,
+    ToggleSkipSilence,
+    TrackGainUp,
+    TrackGainDown,
+    EditLabels,
+    ToggleLabels,
+    ToggleSmartPlaylists,
+    ToggleMark,
+    Retag,
+    FetchCoverArt,
+    FetchAllCoverArt,
+    LookupAlbum,
+    GotoDuplicates,
+    TrashFile,
+    DeleteFile,
+    KeepDuplicate,
+    FindSoundDuplicates,
+    PlayRandom,
+    ToggleAutoDj,
+    GotoRecent,
+    Enqueue,
+    PlayAll,
+    Search,
+    FilterPane,
+    CycleSort,
+    ToggleParty,
+    SaveSession,
+    ToggleSessions,
+    SeekForward,
+    SeekBackward,
+    SeekForwardLong,
+    SeekBackwardLong,
+    CycleReplayGain,
+    ToggleEqualizer,
+    SpeedUp,
+    SpeedDown,
+    MarkLoop,
+    CycleRepeat,
+    ToggleShuffle,
+    ToggleMute,
+    ToggleSettings,
+    StopAfterCurrent,
+    WriteReplayGain,
+    ToggleDiagnostics,
+    ToggleRecording,
+    NextChapter,
+    PreviousChapter,
+    ToggleChapters,
+    PlayUrl,
+    ToggleStations,
+}
+
+impl Action {
+    /// Short description shown in the key hint popup.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::GotoLibrary => "library",
+            Action::GotoBrowser => "browser",
+            Action::GotoHistory => "history",
+            Action::GotoStats => "stats",
+            Action::FocusArtists => "artists",
+            Action::FocusTracks => "tracks",
+            Action::AddToLibrary => "add to library",
+            Action::RefreshLibrary => "refresh library",
+            Action::FindMissing => "missing files",
+            Action::CycleBrowse => "browse by",
+            Action::MoveUp => "up",
+            Action::MoveDown => "down",
+            Action::Activate => "play / open",
+            Action::GoUp => "parent directory",
+            Action::ToggleExpanded => "expand artist",
+            Action::TabFocus => "switch pane",
+            Action::TogglePause => "pause / resume",
+            Action::NextTrack => "next track",
+            Action::ToggleAutoplay => "autoplay",
+            Action::VolumeUp => "volume up",
+            Action::VolumeDown => "volume down",
+            Action::AddBookmark => "add bookmark",
+            Action::ToggleBookmarks => "bookmarks",
+            Action::RateUp => "rate up",
+            Action::RateDown => "rate down",
+            Action::Rate1 => "rate 1 star",
+            Action::Rate2 => "rate 2 stars",
+            Action::Rate3 => "rate 3 stars",
+            Action::Rate4 => "rate 4 stars",
+            Action::Rate5 => "rate 5 stars",
+            Action::ClearRating => "clear rating",
+            Action::ToggleKaraoke => "karaoke",
+            Action::ToggleCrossfeed => "crossfeed",
+            Action::ToggleNightMode => "night mode",
+            Action::ToggleSkipSilence => "skip silence",
+            Action::TrackGainUp => "track gain +1 dB",
+            Action::TrackGainDown => "track gain -1 dB",
+            Action::EditLabels => "edit labels",
+            Action::ToggleLabels => "labels",
+            Action::ToggleSmartPlaylists => "smart playlists",
+            Action::ToggleMark => "mark track",
+            Action::Retag => "retag tracks",
+            Action::FetchCoverArt => "fetch cover art",
+            Action::FetchAllCoverArt => "fetch all missing cover art",
+            Action::LookupAlbum => "look up album",
+            Action::GotoDuplicates => "duplicates",
+            Action::TrashFile => "move file to trash",
+            Action::DeleteFile => "delete file",
+            Action::KeepDuplicate => "keep only this copy",
+            Action::FindSoundDuplicates => "find duplicates by sound",
+            Action::PlayRandom => "surprise me",
+            Action::ToggleAutoDj => "auto-DJ",
+            Action::GotoRecent => "recently played",
+            Action::Enqueue => "add to queue",
+            Action::PlayAll => "play all",
+            Action::Search => "search",
+            Action::FilterPane => "filter pane",
+            Action::CycleSort => "sort pane",
+            Action::ToggleParty => "party mode",
+            Action::SaveSession => "save session",
+            Action::ToggleSessions => "sessions",
+            Action::SeekForward => "forward 5s",
+            Action::SeekBackward => "back 5s",
+            Action::SeekForwardLong => "forward 30s",
+            Action::SeekBackwardLong => "back 30s",
+            Action::CycleReplayGain => "ReplayGain mode",
+            Action::ToggleEqualizer => "equalizer",
+            Action::SpeedUp => "faster",
+            Action::SpeedDown => "slower",
+            Action::MarkLoop => "A-B loop",
+            Action::CycleRepeat => "repeat mode",
+            Action::ToggleShuffle => "shuffle",
+            Action::ToggleMute => "mute",
+            Action::ToggleSettings => "settings",
+            Action::StopAfterCurrent => "stop after this track",
+            Action::WriteReplayGain => "write ReplayGain tags",
+            Action::ToggleDiagnostics => "output diagnostics",
+            Action::ToggleRecording => "record output",
+            Action::NextChapter => "next chapter",
+            Action::PreviousChapter => "previous chapter",
+            Action::ToggleChapters => "chapters",
+            Action::PlayUrl => "play URL",
+            Action::ToggleStations => "radio stations",
+        }
+    }
+
+    /// Actions guests can't run while party mode is locked: quitting and
+    /// anything that changes the library or files.
+    pub fn is_restricted(&self) -> bool {
+        matches!(
+            self,
+            Action::Quit
+                | Action::AddToLibrary
+                | Action::RefreshLibrary
+                | Action::AddBookmark
+                | Action::RateUp
+                | Action::RateDown
+                | Action::Rate1
+                | Action::Rate2
+                | Action::Rate3
+                | Action::Rate4
+                | Action::Rate5
+                | Action::ClearRating
+                | Action::TrackGainUp
+                | Action::TrackGainDown
+                | Action::EditLabels
+                | Action::Retag
+                | Action::LookupAlbum
+                | Action::TrashFile
+                | Action::DeleteFile
+                | Action::KeepDuplicate
+                | Action::ToggleSettings
+                | Action::WriteReplayGain
+                | Action::ToggleRecording
+                | Action::SaveSession
+                | Action::ToggleSessions
+        )
+    }
+}
+
+/// A single key press, with shift folded into the character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl KeyPress {
+    fn plain(code: KeyCode) -> Self {
+        Self {
+            code,
+            ctrl: false,
+            alt: false,
+        }
+    }
+}
+
+impl From<KeyEvent> for KeyPress {
+    fn from(event: KeyEvent) -> Self {
+        Self {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+            alt: event.modifiers.contains(KeyModifiers::ALT),
+        }
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::Char(' ') => "<space>".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Enter => "<enter>".to_string(),
+            KeyCode::Tab => "<tab>".to_string(),
+            KeyCode::Esc => "<esc>".to_string(),
+            KeyCode::Backspace => "<bs>".to_string(),
+            KeyCode::Up => "<up>".to_string(),
+            KeyCode::Down => "<down>".to_string(),
+            KeyCode::Left => "<left>".to_string(),
+            KeyCode::Right => "<right>".to_string(),
+            KeyCode::PageUp => "<pgup>".to_string(),
+            KeyCode::PageDown => "<pgdn>".to_string(),
+            KeyCode::Home => "<home>".to_string(),
+            KeyCode::End => "<end>".to_string(),
+            KeyCode::Delete => "<del>".to_string(),
+            KeyCode::F(n) => format!("<f{n}>"),
+            other => format!("{other:?}"),
+        };
+
+        match (self.ctrl, self.alt) {
+            (true, _) => write!(f, "C-{name}"),
+            (_, true) => write!(f, "M-{name}"),
+            _ => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Parses one key token: `a`, `<space>`, `<enter>`, `<f5>`, `C-x`, `M-x`.
+fn parse_key(token: &str, leader: &[KeyPress]) -> Option<Vec<KeyPress>> {
+    if token == "<leader>" {
+        return Some(leader.to_vec());
+    }
+
+    if let Some(rest) = token.strip_prefix("C-") {
+        let mut key = parse_key(rest, leader)?.into_iter().next()?;
+        key.ctrl = true;
+        return Some(vec![key]);
+    }
+
+    if let Some(rest) = token.strip_prefix("M-") {
+        let mut key = parse_key(rest, leader)?.into_iter().next()?;
+        key.alt = true;
+        return Some(vec![key]);
+    }
+
+    let mut chars = token.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(vec![KeyPress::plain(KeyCode::Char(c))]);
+    }
+
+    let code = match token.to_ascii_lowercase().as_str() {
+        "<space>" => KeyCode::Char(' '),
+        "<enter>" | "<cr>" => KeyCode::Enter,
+        "<tab>" => KeyCode::Tab,
+        "<esc>" => KeyCode::Esc,
+        "<bs>" | "<backspace>" => KeyCode::Backspace,
+        "<up>" => KeyCode::Up,
+        "<down>" => KeyCode::Down,
+        "<left>" => KeyCode::Left,
+        "<right>" => KeyCode::Right,
+        "<pgup>" => KeyCode::PageUp,
+        "<pgdn>" => KeyCode::PageDown,
+        "<home>" => KeyCode::Home,
+        "<end>" => KeyCode::End,
+        "<del>" => KeyCode::Delete,
+        "<lt>" => KeyCode::Char('<'),
+        other => {
+            let n = other.strip_prefix("<f")?.strip_suffix('>')?.parse().ok()?;
+            KeyCode::F(n)
+        }
+    };
+
+    Some(vec![KeyPress::plain(code)])
+}
+
+/// Parses a whitespace separated key sequence such as `g a` or `<leader> q`.
+pub fn parse_sequence(spec: &str, leader: &[KeyPress]) -> Option<Vec<KeyPress>> {
+    let mut keys = Vec::new();
+    for token in spec.split_whitespace() {
+        keys.extend(parse_key(token, leader)?);
+    }
+
+    (!keys.is_empty()).then_some(keys)
+}
+
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("q", Action::Quit),
+    ("1", Action::GotoLibrary),
+    ("3", Action::GotoHistory),
+    ("4", Action::GotoStats),
+    ("5", Action::GotoBrowser),
+    ("g l", Action::GotoLibrary),
+    ("g b", Action::GotoBrowser),
+    ("g h", Action::GotoHistory),
+    ("g r", Action::GotoRecent),
+    ("g s", Action::GotoStats),
+    ("g d", Action::GotoDuplicates),
+    ("g a", Action::FocusArtists),
+    ("g t", Action::FocusTracks),
+    ("a", Action::AddToLibrary),
+    ("<leader> u", Action::RefreshLibrary),
+    ("<leader> m", Action::FindMissing),
+    ("v", Action::CycleBrowse),
+    ("<up>", Action::MoveUp),
+    ("<down>", Action::MoveDown),
+    ("<enter>", Action::Activate),
+    ("<bs>", Action::GoUp),
+    ("<space>", Action::ToggleExpanded),
+    ("<tab>", Action::TabFocus),
+    ("c", Action::TogglePause),
+    ("n", Action::NextTrack),
+    ("<right>", Action::SeekForward),
+    ("<left>", Action::SeekBackward),
+    (">", Action::SeekForwardLong),
+    ("<lt>", Action::SeekBackwardLong),
+    ("]", Action::SpeedUp),
+    ("[", Action::SpeedDown),
+    ("l", Action::MarkLoop),
+    ("r", Action::CycleRepeat),
+    ("s", Action::ToggleShuffle),
+    ("S", Action::StopAfterCurrent),
+    ("R", Action::PlayRandom),
+    ("o", Action::PlayUrl),
+    ("O", Action::ToggleStations),
+    ("e", Action::Enqueue),
+    ("P", Action::PlayAll),
+    ("/", Action::Search),
+    ("f", Action::FilterPane),
+    ("t", Action::CycleSort),
+    ("p", Action::ToggleAutoplay),
+    ("+", Action::VolumeUp),
+    ("=", Action::VolumeUp),
+    ("-", Action::VolumeDown),
+    ("m", Action::ToggleMute),
+    ("b", Action::AddBookmark),
+    ("B", Action::ToggleBookmarks),
+    ("<leader> b", Action::ToggleBookmarks),
+    ("}", Action::NextChapter),
+    ("{", Action::PreviousChapter),
+    ("C", Action::ToggleChapters),
+    ("<leader> a", Action::ToggleAutoplay),
+    ("<leader> d", Action::ToggleAutoDj),
+    ("<leader> P", Action::ToggleParty),
+    ("<leader> s", Action::SaveSession),
+    ("<leader> S", Action::ToggleSessions),
+    ("<leader> +", Action::RateUp),
+    ("<leader> -", Action::RateDown),
+    ("<leader> 1", Action::Rate1),
+    ("<leader> 2", Action::Rate2),
+    ("<leader> 3", Action::Rate3),
+    ("<leader> 4", Action::Rate4),
+    ("<leader> 5", Action::Rate5),
+    ("<leader> 0", Action::ClearRating),
+    ("<leader> k", Action::ToggleKaraoke),
+    ("<leader> x", Action::ToggleCrossfeed),
+    ("<leader> n", Action::ToggleNightMode),
+    ("<leader> z", Action::ToggleSkipSilence),
+    ("<leader> ]", Action::TrackGainUp),
+    ("<leader> [", Action::TrackGainDown),
+    ("<leader> g", Action::CycleReplayGain),
+    ("<leader> G", Action::WriteReplayGain),
+    ("<leader> e", Action::ToggleEqualizer),
+    ("<leader> o", Action::ToggleSettings),
+    ("<leader> i", Action::ToggleDiagnostics),
+    ("<leader> r", Action::ToggleRecording),
+    ("L", Action::EditLabels),
+    ("<leader> l", Action::ToggleLabels),
+    ("<leader> p", Action::ToggleSmartPlaylists),
+    ("x", Action::ToggleMark),
+    ("<leader> t", Action::Retag),
+    ("<leader> c", Action::FetchCoverArt),
+    ("<leader> C", Action::FetchAllCoverArt),
+    ("<leader> M", Action::LookupAlbum),
+    ("d", Action::TrashFile),
+    ("D", Action::DeleteFile),
+    ("K", Action::KeepDuplicate),
+    ("<leader> f", Action::FindSoundDuplicates),
+];
+
+/// Maps key sequences to actions, buffering partial multi-key sequences.
+///
+/// When a sequence is both a complete binding and the prefix of a longer one
+/// (e.g. `g` and `g a`), the shorter binding fires once the timeout expires.
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyPress>, Action)>,
+    pending: Vec<KeyPress>,
+    pending_since: Option<Instant>,
+    timeout: Duration,
+}
+
+impl Keymap {
+    /// Builds the default keymap, then applies `overrides` on top of it.
+    pub fn new(overrides: &HashMap<String, Action>, leader: &str, timeout: Duration) -> Self {
+        let leader = parse_sequence(leader, &[]).unwrap_or_else(|| {
+            log::warn!("Invalid leader key {leader:?}, using \\");
+            vec![KeyPress::plain(KeyCode::Char('\\'))]
+        });
+
+        let mut keymap = Self {
+            bindings: Vec::new(),
+            pending: Vec::new(),
+            pending_since: None,
+            timeout,
+        };
+
+        for (spec, action) in DEFAULT_BINDINGS {
+            keymap.bind(spec, *action, &leader);
+        }
+
+        for (spec, action) in overrides {
+            keymap.bind(spec, *action, &leader);
+        }
+
+        keymap
+    }
+
+    /// Adds a binding, replacing any existing binding for the same sequence.
+    fn bind(&mut self, spec: &str, action: Action, leader: &[KeyPress]) {
+        let Some(keys) = parse_sequence(spec, leader) else {
+            log::warn!("Ignoring invalid key binding {spec:?}");
+            return;
+        };
+
+        self.bindings.retain(|(existing, _)| *existing != keys);
+        self.bindings.push((keys, action));
+    }
+
+    /// Feeds a key press; returns an action once a binding is complete.
+    pub fn feed(&mut self, event: KeyEvent) -> Option<Action> {
+        let key = KeyPress::from(event);
+
+        if key.code == KeyCode::Esc && !self.pending.is_empty() {
+            self.clear();
+            return None;
+        }
+
+        self.pending.push(key);
+
+        let exact = self.exact_match();
+        let has_longer = self
+            .bindings
+            .iter()
+            .any(|(keys, _)| keys.len() > self.pending.len() && keys.starts_with(&self.pending));
+
+        if has_longer {
+            self.pending_since = Some(Instant::now());
+            return None;
+        }
+
+        let was_sequence = self.pending.len() > 1;
+        self.clear();
+
+        if exact.is_some() {
+            return exact;
+        }
+
+        // An unknown continuation starts over with the key on its own
+        if was_sequence {
+            return self.feed(event);
+        }
+
+        None
+    }
+
+    /// Fires or drops a pending sequence once the timeout has passed.
+    pub fn tick(&mut self) -> Option<Action> {
+        let since = self.pending_since?;
+        if since.elapsed() < self.timeout {
+            return None;
+        }
+
+        let action = self.exact_match();
+        self.clear();
+        action
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.pending_since = None;
+    }
+
+    pub fn pending(&self) -> &[KeyPress] {
+        &self.pending
+    }
+
+    /// Possible next keys for the pending sequence, for the hint popup.
+    pub fn hints(&self) -> Vec<(String, &'static str)> {
+        let mut hints: Vec<(String, &'static str)> = self
+            .bindings
+            .iter()
+            .filter(|(keys, _)| keys.len() > self.pending.len() && keys.starts_with(&self.pending))
+            .map(|(keys, action)| {
+                let rest: Vec<String> = keys[self.pending.len()..]
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect();
+                (rest.join(" "), action.label())
+            })
+            .collect();
+
+        hints.sort();
+        hints
+    }
+
+    fn exact_match(&self) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(keys, _)| *keys == self.pending)
+            .map(|(_, action)| *action)
+    }
+}
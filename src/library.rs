@@ -1,12 +1,20 @@
-use std::fs::File;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
 use ratatui::widgets::{ListItem, ListState};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 
-use id3::Tag as Id3Tag;
+use id3::{Tag as Id3Tag, TagLike};
 use symphonia::core::{
     formats::FormatOptions,
     io::MediaSourceStream,
@@ -16,7 +24,18 @@ use symphonia::core::{
 };
 use symphonia::default::get_probe;
 
+use crate::artwork;
+use crate::collation::NameOrder;
+use crate::cue::{self, CueRange, CueSheet};
+use crate::history;
+use crate::loudness::Loudness;
+use crate::path_tags;
 use crate::persistence;
+use crate::query::Query;
+use crate::replaygain::ReplayGain;
+use crate::scan::{ScanFilter, ScanProgress};
+use crate::search;
+use crate::tag_writer;
 
 #[derive(Debug, Clone)]
 pub enum VisibleRow {
@@ -48,7 +67,301 @@ pub struct LibraryState {
     pub focus: LibraryFocus,
     pub track_index: usize,
     pub visible_rows: Vec<VisibleRow>,
-    pub tracks: Vec<LibraryTrack>
+    pub tracks: Vec<LibraryTrack>,
+
+    /// Only show tracks carrying this label
+    pub label_filter: Option<String>,
+    /// Only show tracks matching this smart playlist, kept with its name
+    pub smart_filter: Option<(String, Query)>,
+
+    /// Folders added to the library, walked again on refresh
+    pub roots: Vec<PathBuf>,
+
+    /// Which files under `roots` belong in the library
+    pub scan_filter: ScanFilter,
+
+    /// Typed to narrow the left pane to matching names
+    pub artist_filter: String,
+    /// Typed to narrow the track pane to matching titles
+    pub track_filter: String,
+    /// Keys go to the focused pane's filter rather than the keymap
+    pub typing_filter: bool,
+
+    /// Tracks picked out to retag together
+    pub marked: HashSet<PathBuf>,
+
+    /// How albums are ordered under each artist
+    pub album_sort: AlbumSort,
+    /// How tracks are ordered within each album
+    pub track_sort: TrackSort,
+    /// How artist and group names are ordered
+    pub name_order: NameOrder,
+
+    /// What the left pane groups albums by
+    pub browse: BrowseBy,
+    /// The albums grouped that way, unless that's by artist
+    groups: Vec<ArtistNode>,
+}
+
+/// What the left pane of the library groups albums by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BrowseBy {
+    #[default]
+    Artist,
+    Genre,
+    Year,
+    Decade,
+}
+
+impl BrowseBy {
+    pub fn next(self) -> Self {
+        match self {
+            BrowseBy::Artist => BrowseBy::Genre,
+            BrowseBy::Genre => BrowseBy::Year,
+            BrowseBy::Year => BrowseBy::Decade,
+            BrowseBy::Decade => BrowseBy::Artist,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BrowseBy::Artist => "artists",
+            BrowseBy::Genre => "genres",
+            BrowseBy::Year => "years",
+            BrowseBy::Decade => "decades",
+        }
+    }
+
+    /// The group `track` is listed under.
+    fn group_of(self, track: &LibraryTrack) -> String {
+        match self {
+            BrowseBy::Artist => track.artist_entry().to_string(),
+            BrowseBy::Genre => track
+                .genre
+                .as_deref()
+                .map(str::trim)
+                .filter(|genre| !genre.is_empty())
+                .unwrap_or("Unknown Genre")
+                .to_string(),
+            BrowseBy::Year => track
+                .year
+                .map_or_else(|| UNKNOWN_YEAR.to_string(), |year| year.to_string()),
+            BrowseBy::Decade => track.year.map_or_else(
+                || UNKNOWN_YEAR.to_string(),
+                |year| format!("{}s", year / 10 * 10),
+            ),
+        }
+    }
+}
+
+/// The order albums are listed in under an artist, genre and such.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlbumSort {
+    /// Oldest release first
+    #[default]
+    Year,
+    Title,
+    /// Most recently added to the library first
+    Added,
+}
+
+impl AlbumSort {
+    pub fn next(self) -> Self {
+        match self {
+            AlbumSort::Year => AlbumSort::Title,
+            AlbumSort::Title => AlbumSort::Added,
+            AlbumSort::Added => AlbumSort::Year,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlbumSort::Year => "year",
+            AlbumSort::Title => "title",
+            AlbumSort::Added => "date added",
+        }
+    }
+
+    fn sort(self, albums: &mut [AlbumNode]) {
+        match self {
+            // Albums without a year go last
+            AlbumSort::Year => albums.sort_by_cached_key(|album| {
+                let year = album.year();
+                (year.is_none(), year, album.name.to_lowercase())
+            }),
+            AlbumSort::Title => albums.sort_by_cached_key(|album| album.name.to_lowercase()),
+            AlbumSort::Added => albums.sort_by_cached_key(|album| Reverse(album.added())),
+        }
+    }
+}
+
+/// The order tracks are listed in within an album.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackSort {
+    /// By disc, then track number
+    #[default]
+    Number,
+    Title,
+    /// Shortest first
+    Duration,
+    /// Most played first
+    Plays,
+    /// Most recently played first
+    LastPlayed,
+}
+
+impl TrackSort {
+    pub fn next(self) -> Self {
+        match self {
+            TrackSort::Number => TrackSort::Title,
+            TrackSort::Title => TrackSort::Duration,
+            TrackSort::Duration => TrackSort::Plays,
+            TrackSort::Plays => TrackSort::LastPlayed,
+            TrackSort::LastPlayed => TrackSort::Number,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TrackSort::Number => "track number",
+            TrackSort::Title => "title",
+            TrackSort::Duration => "duration",
+            TrackSort::Plays => "play count",
+            TrackSort::LastPlayed => "last played",
+        }
+    }
+
+    fn sort(self, tracks: &mut [LibraryTrack]) {
+        match self {
+            TrackSort::Number => {
+                tracks.sort_by_key(|t| (t.disc_number.unwrap_or(1), t.track_number.unwrap_or(999)))
+            }
+            TrackSort::Title => tracks.sort_by_cached_key(|t| t.title.to_lowercase()),
+            TrackSort::Duration => tracks.sort_by_key(|t| (t.duration.is_none(), t.duration)),
+            TrackSort::Plays => tracks.sort_by_key(|t| Reverse(t.play_count)),
+            TrackSort::LastPlayed => tracks.sort_by_key(|t| Reverse(t.last_played)),
+        }
+    }
+}
+
+/// What a library refresh changed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RefreshSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl RefreshSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added + self.updated + self.removed == 0
+    }
+}
+
+impl fmt::Display for RefreshSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} changed, {} removed",
+            self.added, self.updated, self.removed
+        )
+    }
+}
+
+/// A listed track, as much of it as a refresh compares with the disk.
+struct ListedTrack {
+    path: PathBuf,
+    cue_file: Option<PathBuf>,
+    stamp: Option<FileStamp>,
+}
+
+/// What a refresh compares the files on disk against.
+pub struct RefreshCheck {
+    paths: Vec<PathBuf>,
+    filter: ScanFilter,
+    listed: Vec<ListedTrack>,
+    /// Whether to look for tracks listed by a path that isn't canonical
+    dedupe: bool,
+}
+
+/// What a [`RefreshCheck`] found, to apply with
+/// [`LibraryState::apply_refresh`].
+#[derive(Default)]
+pub struct RefreshScan {
+    /// Listed paths that resolve to another path
+    renamed: HashMap<PathBuf, PathBuf>,
+    /// Tracks whose files are gone or changed
+    stale: HashSet<PathBuf>,
+    /// Tracks read from new and changed files
+    scanned: Vec<LibraryTrack>,
+}
+
+impl RefreshCheck {
+    /// Walks the paths, and reads the files that are new or changed since
+    /// they were scanned, counting them in `progress`.
+    pub fn run(self, progress: &ScanProgress) -> RefreshScan {
+        let renamed: HashMap<PathBuf, PathBuf> = match self.dedupe {
+            true => self
+                .listed
+                .iter()
+                .map(|track| (track.path.clone(), canonical_path(&track.path)))
+                .filter(|(path, canonical)| path != canonical)
+                .collect(),
+            false => HashMap::new(),
+        };
+
+        let paths: Vec<PathBuf> = self.paths.iter().map(|path| canonical_path(path)).collect();
+        let on_disk: HashSet<PathBuf> = paths
+            .iter()
+            .flat_map(|path| walk_files(path, &self.filter))
+            .collect();
+
+        // Checked once per file, however many cue tracks it holds
+        let mut unchanged: HashMap<PathBuf, bool> = HashMap::new();
+        let mut stale = HashSet::new();
+        // Files already listed, directly or as the audio of a cue sheet
+        let mut known = HashSet::new();
+
+        for track in &self.listed {
+            let path = renamed.get(&track.path).unwrap_or(&track.path);
+            let source = source_file(path);
+            let in_scope = paths.iter().any(|path| source.starts_with(path));
+            let fresh = !in_scope
+                || *unchanged.entry(source.clone()).or_insert_with(|| {
+                    on_disk.contains(&source)
+                        && track.stamp.is_some()
+                        && FileStamp::of(&source) == track.stamp
+                });
+
+            if !fresh {
+                stale.insert(path.clone());
+                continue;
+            }
+            if let Some(file) = &track.cue_file {
+                known.insert(file.clone());
+            }
+            known.insert(source);
+        }
+
+        let mut changed: Vec<PathBuf> = on_disk
+            .into_iter()
+            .filter(|path| !known.contains(path))
+            .filter(|path| is_audio_file(path) || has_extension(path, "cue"))
+            .collect();
+        changed.sort();
+        let scanned = match stale.is_empty() && changed.is_empty() {
+            true => Vec::new(),
+            false => scan_files(&changed, progress),
+        };
+
+        RefreshScan {
+            renamed,
+            stale,
+            scanned,
+        }
+    }
 }
 
 impl LibraryState {
@@ -65,40 +378,255 @@ impl LibraryState {
             track_index: 0,
             visible_rows: Vec::new(),
             tracks: Vec::new(),
+            label_filter: None,
+            smart_filter: None,
+            artist_filter: String::new(),
+            track_filter: String::new(),
+            typing_filter: false,
+            marked: HashSet::new(),
+            roots: Vec::new(),
+            scan_filter: ScanFilter::default(),
+            album_sort: AlbumSort::default(),
+            track_sort: TrackSort::default(),
+            name_order: NameOrder::default(),
+            browse: BrowseBy::Artist,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Remembers `dir` as a library folder, unless a folder above it already
+    /// is. Folders below it are dropped in favour of it.
+    ///
+    /// Returns whether `dir` was added.
+    pub fn add_root(&mut self, dir: &Path) -> bool {
+        let dir = &canonical_path(dir);
+        if !dir.is_dir() || self.roots.iter().any(|root| dir.starts_with(root)) {
+            return false;
+        }
+
+        self.roots.retain(|root| !root.starts_with(dir));
+        self.roots.push(dir.to_path_buf());
+        persistence::save_library_roots(&self.roots).ok();
+        true
+    }
+
+    /// What refreshing the whole library checks: every library folder, and
+    /// whether tracks are listed by their canonical paths. See
+    /// [`Self::refresh_paths`].
+    pub fn check_roots(&self) -> RefreshCheck {
+        let mut check = self.check_paths(&self.roots);
+        check.dedupe = true;
+        check
+    }
+
+    /// What refreshing `paths` checks, taken from the library so the files
+    /// can be compared and read off the UI thread with [`RefreshCheck::run`].
+    pub fn check_paths(&self, paths: &[PathBuf]) -> RefreshCheck {
+        let listed = self
+            .artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|a| &a.tracks)
+            .map(|track| ListedTrack {
+                path: track.path.clone(),
+                cue_file: track.cue.as_ref().map(|range| range.file.clone()),
+                stamp: track.stamp,
+            })
+            .collect();
+
+        RefreshCheck {
+            paths: paths.to_vec(),
+            filter: self.scan_filter.clone(),
+            listed,
+            dedupe: false,
         }
     }
 
-    pub fn add_tracks(&mut self, tracks: Vec<LibraryTrack>) {
-        for track in tracks {
-            // Check if artist exists
-            if let Some(artist) = self.artists.iter_mut().find(|a| a.name == track.album_artist) {
-                // Check if album exists
-                if let Some(album) = artist.albums.iter_mut().find(|a| a.name == track.album) {
-                    // Check for duplicate by path
-                    let already_exists = album.tracks.iter().any(|t| t.path == track.path);
-                    if !already_exists {
-                        album.tracks.push(track);
-                        album.tracks.sort_by_key(|t| t.track_number.unwrap_or(999));
+    /// Lists every track by its canonical path, as found by the check,
+    /// dropping entries that turn out to be another way to reach a file
+    /// that's already listed, e.g. through a symlink. Returns how many were
+    /// dropped.
+    fn dedupe_paths(&mut self, renamed: &HashMap<PathBuf, PathBuf>) -> usize {
+        let mut seen = HashSet::new();
+        let mut changed = false;
+        let mut dropped = 0;
+
+        for artist in &mut self.artists {
+            for album in &mut artist.albums {
+                album.tracks.retain_mut(|track| {
+                    if let Some(canonical) = renamed.get(&track.path) {
+                        track.path = canonical.clone();
+                        changed = true;
                     }
-                } else {
-                    artist.albums.push(AlbumNode {
-                        name: track.album.clone(),
-                        tracks: vec![track],
+
+                    let unique = seen.insert(track.path.clone());
+                    dropped += usize::from(!unique);
+                    unique
+                });
+            }
+            artist.albums.retain(|album| !album.tracks.is_empty());
+        }
+        self.artists.retain(|artist| !artist.albums.is_empty());
+
+        if changed {
+            self.tracks.retain(|t| seen.contains(&t.path));
+            self.track_index = 0;
+            self.rebuild_visible_rows();
+            persistence::save_library(&self.artists).ok();
+        }
+        dropped
+    }
+
+    /// Brings the library in line with the files at and below `paths`,
+    /// comparing each file's size and modification time with the ones it was
+    /// scanned with. Only new and changed files are read; tracks whose files
+    /// are gone are dropped. Ratings, labels and the like survive a file
+    /// changing.
+    pub fn refresh_paths(&mut self, paths: &[PathBuf]) -> RefreshSummary {
+        let scan = self.check_paths(paths).run(&ScanProgress::default());
+        self.apply_refresh(scan)
+    }
+
+    /// Files what a [`RefreshCheck`] found into the library.
+    pub fn apply_refresh(&mut self, scan: RefreshScan) -> RefreshSummary {
+        let duplicates = self.dedupe_paths(&scan.renamed);
+        let RefreshScan {
+            stale, mut scanned, ..
+        } = scan;
+        if stale.is_empty() && scanned.is_empty() {
+            return RefreshSummary {
+                removed: duplicates,
+                ..RefreshSummary::default()
+            };
+        }
+
+        let mut dropped: HashMap<PathBuf, LibraryTrack> = HashMap::new();
+        for artist in &mut self.artists {
+            for album in &mut artist.albums {
+                let (gone, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut album.tracks)
+                    .into_iter()
+                    .partition(|t| stale.contains(&t.path));
+                album.tracks = kept;
+                // Entries cached by a symlinked path hand their ratings and
+                // such to the canonical one
+                dropped.extend(gone.into_iter().map(|t| (canonical_path(&t.path), t)));
+            }
+            artist.albums.retain(|album| !album.tracks.is_empty());
+        }
+        self.artists.retain(|artist| !artist.albums.is_empty());
+
+        let mut summary = RefreshSummary::default();
+        for track in &mut scanned {
+            let Some(old) = dropped.remove(&track.path) else {
+                summary.added += 1;
+                continue;
+            };
+
+            track.bookmarks = old.bookmarks;
+            track.rating = old.rating;
+            track.play_count = old.play_count;
+            track.last_played = old.last_played;
+            track.gain_db = old.gain_db;
+            track.labels = old.labels;
+            track.added = old.added;
+            // Most changes are tag edits, like the ones written for ratings,
+            // which leave the audio and its loudness as they were
+            if track.duration == old.duration {
+                track.loudness = old.loudness;
+            }
+            summary.updated += 1;
+        }
+        summary.removed = dropped.len() + duplicates;
+
+        self.tracks.retain(|t| !stale.contains(&t.path));
+        self.track_index = 0;
+        self.add_tracks(scanned);
+
+        summary
+    }
+
+    /// Files the tracks under their album artist and album, skipping paths
+    /// the library already has.
+    ///
+    /// Artists and albums are looked up by name in maps and everything is
+    /// sorted once at the end, so adding a big folder takes time in
+    /// proportion to its size.
+    pub fn add_tracks(&mut self, mut tracks: Vec<LibraryTrack>) {
+        self.join_compilations(&mut tracks);
+
+        let mut known: HashSet<PathBuf> = self
+            .artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .map(|t| t.path.clone())
+            .collect();
+        let mut artist_index: HashMap<String, usize> = self
+            .artists
+            .iter()
+            .enumerate()
+            .map(|(index, artist)| (artist.name.clone(), index))
+            .collect();
+        let mut album_index: HashMap<(usize, String), usize> = self
+            .artists
+            .iter()
+            .enumerate()
+            .flat_map(|(artist, a)| {
+                a.albums
+                    .iter()
+                    .enumerate()
+                    .map(move |(album, alb)| ((artist, alb.name.clone()), album))
+            })
+            .collect();
+        // Albums that got new tracks, which need sorting again
+        let mut changed = HashSet::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        for mut track in tracks {
+            if !known.insert(track.path.clone()) {
+                continue;
+            }
+            track.added.get_or_insert(now);
+
+            let artists = &mut self.artists;
+            let artist = *artist_index
+                .entry(track.artist_entry().to_string())
+                .or_insert_with(|| {
+                    artists.push(ArtistNode {
+                        name: track.artist_entry().to_string(),
+                        albums: Vec::new(),
+                        expanded: false,
                     });
-                }
-            } else {
-                self.artists.push(ArtistNode {
-                    name: track.album_artist.clone(),
-                    albums: vec![AlbumNode {
+                    artists.len() - 1
+                });
+
+            let albums = &mut self.artists[artist].albums;
+            let album = *album_index
+                .entry((artist, track.album.clone()))
+                .or_insert_with(|| {
+                    albums.push(AlbumNode {
                         name: track.album.clone(),
-                        tracks: vec![track],
-                    }],
-                    expanded: false,
+                        tracks: Vec::new(),
+                    });
+                    albums.len() - 1
                 });
-            }
+
+            albums[album].tracks.push(track);
+            changed.insert((artist, album));
         }
 
-        self.artists.sort_by_key(|a| a.name.clone());
+        let mut changed_artists = HashSet::new();
+        for (artist, album) in changed {
+            self.track_sort
+                .sort(&mut self.artists[artist].albums[album].tracks);
+            changed_artists.insert(artist);
+        }
+        for artist in changed_artists {
+            self.album_sort.sort(&mut self.artists[artist].albums);
+        }
+        self.sort_artists();
 
         self.rebuild_visible_rows(); // <-- Important
 
@@ -111,8 +639,31 @@ impl LibraryState {
         persistence::save_library(&self.artists).ok();
     }
 
+    /// Files tracks scanned on their own, e.g. one file that changed, with
+    /// the compilation already in the library that they're part of.
+    fn join_compilations(&self, tracks: &mut [LibraryTrack]) {
+        let compilations: HashMap<(PathBuf, String), &str> = self
+            .artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .filter(|t| t.compilation)
+            .map(|t| (album_key(t), t.album_artist.as_str()))
+            .collect();
+        if compilations.is_empty() {
+            return;
+        }
+
+        for track in tracks.iter_mut().filter(|t| !t.compilation) {
+            if let Some(album_artist) = compilations.get(&album_key(track)) {
+                track.compilation = true;
+                track.album_artist = album_artist.to_string();
+            }
+        }
+    }
+
     pub fn move_down(&mut self) {
-        let visual_rows = Self::build_visible_rows(&self.artists);
+        let visual_rows = self.build_visible_rows();
 
         let current_index = Self::selected_index(&visual_rows, self.selection);
         let next_index = (current_index + 1).min(visual_rows.len().saturating_sub(1));
@@ -120,7 +671,7 @@ impl LibraryState {
     }
 
     pub fn move_up(&mut self) {
-        let visual_rows = Self::build_visible_rows(&self.artists);
+        let visual_rows = self.build_visible_rows();
 
         let current_index = Self::selected_index(&visual_rows, self.selection);
         let next_index = current_index.saturating_sub(1);
@@ -129,7 +680,7 @@ impl LibraryState {
 
     pub fn toggle_expanded(&mut self) {
         if let Some(LibrarySelection::Artist { artist_index }) = self.selection {
-            if let Some(artist) = self.artists.get_mut(artist_index) {
+            if let Some(artist) = self.tree_mut().get_mut(artist_index) {
                 artist.expanded = !artist.expanded;
                 self.rebuild_visible_rows();
             }
@@ -138,8 +689,8 @@ impl LibraryState {
 
     pub fn selected_artist(&self) -> Option<&ArtistNode> {
         match self.selection {
-            Some(LibrarySelection::Artist { artist_index }) => self.artists.get(artist_index),
-            Some(LibrarySelection::Album { artist_index, .. }) => self.artists.get(artist_index),
+            Some(LibrarySelection::Artist { artist_index }) => self.tree().get(artist_index),
+            Some(LibrarySelection::Album { artist_index, .. }) => self.tree().get(artist_index),
             None => None,
         }
     }
@@ -149,17 +700,33 @@ impl LibraryState {
             Some(LibrarySelection::Album {
                 artist_index,
                 album_index,
-            }) => self.artists.get(artist_index)?.albums.get(album_index),
+            }) => self.tree().get(artist_index)?.albums.get(album_index),
             _ => None,
         }
     }
 
-    fn build_visible_rows<'a>(artists: &'a [ArtistNode]) -> Vec<VisibleRow> {
+    /// The rows of the left pane, leaving out albums without tracks that
+    /// pass the label and smart playlist filters and names that don't match
+    /// the typed filter.
+    fn build_visible_rows(&self) -> Vec<VisibleRow> {
         let mut rows = Vec::new();
-        for (artist_index, artist) in artists.iter().enumerate() {
+        for (artist_index, artist) in self.tree().iter().enumerate() {
+            // A matching artist shows all its albums, else only matching ones
+            let named = search::matches(&self.artist_filter, &artist.name);
+            let shown = |album: &AlbumNode| {
+                album.tracks.iter().any(|t| self.passes_filters(t))
+                    && (named || search::matches(&self.artist_filter, &album.name))
+            };
+            if !artist.albums.iter().any(shown) {
+                continue;
+            }
+
             rows.push(VisibleRow::Artist { artist_index });
             if artist.expanded {
                 for (album_index, album) in artist.albums.iter().enumerate() {
+                    if !shown(album) {
+                        continue;
+                    }
                     rows.push(VisibleRow::Album {
                         artist_index,
                         album_index,
@@ -228,17 +795,132 @@ impl LibraryState {
     }
 
     pub fn visible_tracks(&self) -> Vec<LibraryTrack> {
+        let mut tracks = self.selected_tracks();
+        tracks.retain(|track| self.passes_filters(track));
+        if !self.track_filter.is_empty() {
+            let filter = &self.track_filter;
+            tracks.retain(|t| search::matches(filter, &format!("{} {}", t.artist, t.title)));
+        }
+        tracks
+    }
+
+    /// What's been typed to narrow the focused pane.
+    pub fn pane_filter(&self) -> &str {
+        match self.focus {
+            LibraryFocus::Left => &self.artist_filter,
+            LibraryFocus::Right => &self.track_filter,
+        }
+    }
+
+    /// Sends typed keys to the focused pane's filter until [`Self::stop_typing`].
+    pub fn start_typing(&mut self) {
+        self.typing_filter = true;
+    }
+
+    /// Keeps the filter, giving keys back to the keymap.
+    pub fn stop_typing(&mut self) {
+        self.typing_filter = false;
+    }
+
+    pub fn push_filter(&mut self, c: char) {
+        self.edit_filter(|filter| filter.push(c));
+    }
+
+    pub fn pop_filter(&mut self) {
+        self.edit_filter(|filter| {
+            filter.pop();
+        });
+    }
+
+    /// Drops the focused pane's filter and stops typing into it.
+    pub fn clear_filter(&mut self) {
+        self.typing_filter = false;
+        self.edit_filter(String::clear);
+    }
+
+    fn edit_filter(&mut self, edit: impl FnOnce(&mut String)) {
+        match self.focus {
+            LibraryFocus::Left => {
+                edit(&mut self.artist_filter);
+                self.rebuild_visible_rows();
+            }
+            LibraryFocus::Right => {
+                edit(&mut self.track_filter);
+                self.track_index = 0;
+            }
+        }
+    }
+
+    /// Marks the selected track in the track pane, or unmarks it, and moves
+    /// on to the next one.
+    pub fn toggle_mark(&mut self) {
+        let tracks = self.visible_tracks();
+        let Some(track) = tracks.get(self.track_index) else {
+            return;
+        };
+        if !self.marked.remove(&track.path) {
+            self.marked.insert(track.path.clone());
+        }
+        self.move_track_down(tracks.len());
+    }
+
+    /// The marked tracks, in library order.
+    pub fn marked_tracks(&self) -> Vec<LibraryTrack> {
+        self.artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .filter(|t| self.marked.contains(&t.path))
+            .cloned()
+            .collect()
+    }
+
+    /// Every track in the library that passes the label and smart playlist
+    /// filters.
+    pub fn filtered_tracks(&self) -> Vec<&LibraryTrack> {
+        self.artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .filter(|t| self.passes_filters(t))
+            .collect()
+    }
+
+    /// Every track in the library matching `query`, in library order.
+    pub fn matching_tracks(&self, query: &Query) -> Vec<&LibraryTrack> {
+        self.artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .filter(|t| query.matches(t))
+            .collect()
+    }
+
+    /// Whether `track` carries the label filter's label and matches the
+    /// smart playlist's query, when those are set.
+    pub fn passes_filters(&self, track: &LibraryTrack) -> bool {
+        self.label_filter
+            .as_deref()
+            .is_none_or(|label| track.has_label(label))
+            && self
+                .smart_filter
+                .as_ref()
+                .is_none_or(|(_, query)| query.matches(track))
+    }
+
+    /// Every track under the selected artist or album, ignoring the filter.
+    pub fn selected_tracks(&self) -> Vec<LibraryTrack> {
         match self.selection {
             Some(LibrarySelection::Artist { artist_index }) => self
-                .artists
+                .tree()
                 .get(artist_index)
                 .map(|a| a.albums.iter().flat_map(|alb| alb.tracks.clone()).collect())
                 .unwrap_or_default(),
-                Some(LibrarySelection::Album {
-                    artist_index,
-                    album_index,
-                }) => self
-            .artists
+            Some(LibrarySelection::Album {
+                artist_index,
+                album_index,
+            }) => self
+                .tree()
                 .get(artist_index)
                 .and_then(|a| a.albums.get(album_index))
                 .map(|alb| alb.tracks.clone())
@@ -247,21 +929,30 @@ impl LibraryState {
         }
     }
 
-    pub fn rebuild_visible_rows(&mut self) {
-        self.visible_rows.clear();
-
-        for (artist_index, artist) in self.artists.iter().enumerate() {
-            self.visible_rows.push(VisibleRow::Artist { artist_index });
-
-            if artist.expanded {
-                for (album_index, _) in artist.albums.iter().enumerate() {
-                    self.visible_rows.push(VisibleRow::Album {
-                        artist_index,
-                        album_index,
-                    });
-                }
-            }
+    /// The albums under the selected artist, or just the selected album.
+    pub fn selected_albums(&self) -> Vec<&AlbumNode> {
+        match self.selection {
+            Some(LibrarySelection::Artist { artist_index }) => self
+                .tree()
+                .get(artist_index)
+                .map(|a| a.albums.iter().collect())
+                .unwrap_or_default(),
+            Some(LibrarySelection::Album {
+                artist_index,
+                album_index,
+            }) => self
+                .tree()
+                .get(artist_index)
+                .and_then(|a| a.albums.get(album_index))
+                .into_iter()
+                .collect(),
+            None => vec![],
         }
+    }
+
+    pub fn rebuild_visible_rows(&mut self) {
+        self.rebuild_groups();
+        self.visible_rows = self.build_visible_rows();
 
         // Restore selection if it was valid
         if self.visible_rows.is_empty() {
@@ -286,19 +977,67 @@ impl LibraryState {
         let mut playable_indices = Vec::new();
         let mut last_album: Option<String> = None;
 
-        for track in tracks {
+        // Albums spread over several discs get a heading per disc
+        let mut discs: HashMap<&str, HashSet<u32>> = HashMap::new();
+        for track in &tracks {
+            discs
+                .entry(&track.album)
+                .or_default()
+                .insert(track.disc_number.unwrap_or(1));
+        }
+        let mut last_disc = None;
+
+        for track in &tracks {
             let album = track.album.clone();
 
             if last_album.as_deref() != Some(album.as_str()) {
                 items.push(ListItem::new(format!("{}:", album)));
                 last_album = Some(album);
+                last_disc = None;
+            }
+
+            // Only when in disc order, as other orders mix the discs
+            let disc = track.disc_number.unwrap_or(1);
+            let by_disc = self.track_sort == TrackSort::Number;
+            if by_disc && discs[track.album.as_str()].len() > 1 && last_disc != Some(disc) {
+                items.push(ListItem::new(format!(" Disc {disc}")).style(Color::DarkGray));
+                last_disc = Some(disc);
             }
 
             playable_indices.push(items.len()); // index where this track will be
             let number = track
                 .track_number
                 .map_or("--".to_string(), |n| format!("{:02}", n));
-            items.push(ListItem::new(format!("  {}. {}", number, track.title)));
+            // A column of five, so titles line up whatever the rating
+            let stars = track.rating.unwrap_or(0).min(5) as usize;
+            let stars = format!("{}{}", "★".repeat(stars), "☆".repeat(5 - stars));
+            let plays = match track.play_count {
+                0 => String::new(),
+                count => format!("{count}×"),
+            };
+            let last_played = track
+                .last_played
+                .map(history::format_ago)
+                .unwrap_or_default();
+            let mark = if self.marked.contains(&track.path) {
+                "*"
+            } else {
+                " "
+            };
+            // Names guessed from the path are shown as provisional
+            let title = match track.guessed {
+                true => Span::styled(
+                    format!("{} ?", track.list_title()),
+                    Style::default().add_modifier(Modifier::ITALIC),
+                ),
+                false => Span::raw(track.list_title()),
+            };
+            items.push(ListItem::new(Line::from(vec![
+                Span::raw(format!("{mark} {number}. ")),
+                Span::styled(stars, Style::default().fg(Color::Yellow)),
+                Span::styled(format!(" {plays:>5} {last_played:>5} "), Color::DarkGray),
+                title,
+            ])));
         }
 
         (items, playable_indices)
@@ -316,6 +1055,16 @@ impl LibraryState {
         None
     }
 
+    /// Like [`LibraryState::next_track_path`], but goes from the last visible
+    /// track back to the first.
+    pub fn next_track_path_wrapping(&self, current: &Path) -> Option<PathBuf> {
+        self.next_track_path(current).or_else(|| {
+            let tracks = self.visible_tracks();
+            let listed = tracks.iter().any(|track| track.path == current);
+            listed.then(|| tracks[0].path.clone())
+        })
+    }
+
     pub fn select_track_by_path(&mut self, path: &Path) {
         let tracks = self.visible_tracks();
         if let Some(i) = tracks.iter().position(|t| &t.path == path) {
@@ -324,8 +1073,338 @@ impl LibraryState {
         }
     }
 
+    /// The library entry for `path`, also when it was reached through a
+    /// symlink while the library lists it by its canonical path.
     pub fn track_by_path(&self, path: &Path) -> Option<&LibraryTrack> {
-        self.tracks.iter().find(|t| t.path == path)
+        self.find_track(path).or_else(|| {
+            let canonical = canonical_path(path);
+            (canonical != path).then(|| self.find_track(&canonical))?
+        })
+    }
+
+    pub fn track_by_path_mut(&mut self, path: &Path) -> Option<&mut LibraryTrack> {
+        let path = match self.find_track(path) {
+            Some(_) => path.to_path_buf(),
+            None => canonical_path(path),
+        };
+        self.artists
+            .iter_mut()
+            .flat_map(|a| &mut a.albums)
+            .flat_map(|alb| &mut alb.tracks)
+            .find(|t| t.path == path)
+    }
+
+    fn find_track(&self, path: &Path) -> Option<&LibraryTrack> {
+        self.tracks.iter().find(|t| t.path == path).or_else(|| {
+            self.artists
+                .iter()
+                .flat_map(|a| &a.albums)
+                .flat_map(|alb| &alb.tracks)
+                .find(|t| t.path == path)
+        })
+    }
+
+    /// Edits the library copy of a track and saves the library.
+    ///
+    /// Returns the updated track, or `None` if `path` is not in the library.
+    pub fn update_track(
+        &mut self,
+        path: &Path,
+        edit: impl FnOnce(&mut LibraryTrack),
+    ) -> Option<LibraryTrack> {
+        let track = self.track_by_path_mut(path)?;
+        edit(track);
+        let track = track.clone();
+
+        self.sync_scanned_track(&track);
+        persistence::save_library(&self.artists).ok();
+        Some(track)
+    }
+
+    pub fn add_bookmark(&mut self, path: &Path, bookmark: Bookmark) -> Option<LibraryTrack> {
+        self.update_track(path, |track| {
+            track.bookmarks.push(bookmark);
+            track.bookmarks.sort_by_key(|b| b.position);
+        })
+    }
+
+    pub fn remove_bookmark(&mut self, path: &Path, index: usize) -> Option<LibraryTrack> {
+        if self.track_by_path(path)?.bookmarks.len() <= index {
+            return None;
+        }
+
+        self.update_track(path, |track| {
+            track.bookmarks.remove(index);
+        })
+    }
+
+    /// Drops a track from the library, along with albums and artists left empty.
+    pub fn remove_track(&mut self, path: &Path) {
+        self.remove_tracks(&[path.to_path_buf()]);
+    }
+
+    /// Drops several tracks at once, saving the library once.
+    pub fn remove_tracks(&mut self, paths: &[PathBuf]) {
+        let paths: HashSet<&PathBuf> = paths.iter().collect();
+        for artist in &mut self.artists {
+            for album in &mut artist.albums {
+                album.tracks.retain(|t| !paths.contains(&t.path));
+            }
+            artist.albums.retain(|album| !album.tracks.is_empty());
+        }
+        self.artists.retain(|artist| !artist.albums.is_empty());
+        self.tracks.retain(|t| !paths.contains(&t.path));
+
+        self.track_index = 0;
+        self.rebuild_visible_rows();
+        persistence::save_library(&self.artists).ok();
+    }
+
+    /// Tracks whose files no longer exist, e.g. after folders were moved,
+    /// by artist and album. Checks every file, so it can take a while on a
+    /// network mount.
+    pub fn missing_tracks(&self) -> Vec<LibraryTrack> {
+        let mut exists: HashMap<PathBuf, bool> = HashMap::new();
+        self.artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+            .filter(|track| {
+                let source = source_file(&track.path);
+                !*exists
+                    .entry(source)
+                    .or_insert_with_key(|source| source.exists())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Adds and removes labels on several tracks, saving the library once.
+    ///
+    /// Returns how many of `paths` were found in the library.
+    pub fn edit_labels(&mut self, paths: &[PathBuf], add: &[String], remove: &[String]) -> usize {
+        let mut updated = Vec::new();
+
+        for path in paths {
+            let Some(track) = self.track_by_path_mut(path) else {
+                continue;
+            };
+
+            track
+                .labels
+                .retain(|l| !remove.iter().any(|r| r.eq_ignore_ascii_case(l)));
+            for label in add {
+                if !track.has_label(label) {
+                    track.labels.push(label.clone());
+                }
+            }
+            track.labels.sort();
+            updated.push(track.clone());
+        }
+
+        for track in &updated {
+            self.sync_scanned_track(track);
+        }
+        persistence::save_library(&self.artists).ok();
+        updated.len()
+    }
+
+    /// Stores loudness measurements, saving the library once.
+    pub fn set_loudness(&mut self, measured: &[(PathBuf, Loudness)]) {
+        let mut updated = Vec::new();
+
+        for (path, loudness) in measured {
+            if let Some(track) = self.track_by_path_mut(path) {
+                track.loudness = Some(*loudness);
+                updated.push(track.clone());
+            }
+        }
+
+        for track in &updated {
+            self.sync_scanned_track(track);
+        }
+        persistence::save_library(&self.artists).ok();
+    }
+
+    /// Stores freshly written ReplayGain values, saving the library once.
+    pub fn set_replay_gain(&mut self, tagged: &[(PathBuf, ReplayGain)]) {
+        let mut updated = Vec::new();
+
+        for (path, gain) in tagged {
+            if let Some(track) = self.track_by_path_mut(path) {
+                track.replay_gain = *gain;
+                updated.push(track.clone());
+            }
+        }
+
+        for track in &updated {
+            self.sync_scanned_track(track);
+        }
+        persistence::save_library(&self.artists).ok();
+    }
+
+    /// All labels in the library with the number of tracks carrying each.
+    pub fn label_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+
+        for track in self
+            .artists
+            .iter()
+            .flat_map(|a| &a.albums)
+            .flat_map(|alb| &alb.tracks)
+        {
+            for label in &track.labels {
+                match counts
+                    .iter_mut()
+                    .find(|(l, _)| l.eq_ignore_ascii_case(label))
+                {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((label.clone(), 1)),
+                }
+            }
+        }
+
+        counts.sort();
+        counts
+    }
+
+    /// Shows only tracks labelled `label`, or everything for `None`.
+    pub fn set_label_filter(&mut self, label: Option<String>) {
+        self.label_filter = label;
+        self.track_index = 0;
+        self.rebuild_visible_rows();
+    }
+
+    /// Shows only tracks matching the smart playlist `name`'s query, or
+    /// everything for `None`.
+    pub fn set_smart_filter(&mut self, filter: Option<(String, Query)>) {
+        self.smart_filter = filter;
+        self.track_index = 0;
+        self.rebuild_visible_rows();
+    }
+
+    /// Orders the artists the way `name_order` says, and every artist's
+    /// albums and every album's tracks the way `album_sort` and `track_sort`
+    /// say.
+    pub fn sort_tree(&mut self) {
+        self.sort_artists();
+        for artist in &mut self.artists {
+            for album in &mut artist.albums {
+                self.track_sort.sort(&mut album.tracks);
+            }
+            self.album_sort.sort(&mut artist.albums);
+        }
+
+        // Album indices no longer point where they did
+        if let Some(LibrarySelection::Album { artist_index, .. }) = self.selection {
+            self.selection = Some(LibrarySelection::Artist { artist_index });
+        }
+        self.track_index = 0;
+        self.rebuild_visible_rows();
+    }
+
+    fn sort_artists(&mut self) {
+        let order = &self.name_order;
+        self.artists.sort_by(|a, b| order.compare(&a.name, &b.name));
+    }
+
+    /// Switches the left pane to grouping albums the next way.
+    pub fn cycle_browse(&mut self) {
+        self.browse = self.browse.next();
+        self.selection = None;
+        self.track_index = 0;
+        self.rebuild_visible_rows();
+    }
+
+    /// The tree the left pane shows: the artists, or when browsing another
+    /// way the same structure with genres and such in place of artists.
+    pub fn tree(&self) -> &[ArtistNode] {
+        match self.browse {
+            BrowseBy::Artist => &self.artists,
+            _ => &self.groups,
+        }
+    }
+
+    fn tree_mut(&mut self) -> &mut [ArtistNode] {
+        match self.browse {
+            BrowseBy::Artist => &mut self.artists,
+            _ => &mut self.groups,
+        }
+    }
+
+    /// Regroups the albums for browse modes other than by artist, keeping
+    /// groups that were expanded open.
+    fn rebuild_groups(&mut self) {
+        if self.browse == BrowseBy::Artist {
+            self.groups.clear();
+            return;
+        }
+
+        let expanded: HashSet<String> = self
+            .groups
+            .iter()
+            .filter(|group| group.expanded)
+            .map(|group| group.name.clone())
+            .collect();
+
+        // By lowercased name, so differently cased tags end up together
+        let mut groups: BTreeMap<String, (String, BTreeMap<String, Vec<LibraryTrack>>)> =
+            BTreeMap::new();
+        for artist in &self.artists {
+            for album in &artist.albums {
+                // Albums of the same name by different artists stay apart
+                let album_name = format!("{} – {}", album.name, artist.name);
+                for track in &album.tracks {
+                    let name = self.browse.group_of(track);
+                    groups
+                        .entry(name.to_lowercase())
+                        .or_insert_with(|| (name, BTreeMap::new()))
+                        .1
+                        .entry(album_name.clone())
+                        .or_default()
+                        .push(track.clone());
+                }
+            }
+        }
+
+        let album_sort = self.album_sort;
+        let mut groups: Vec<ArtistNode> = groups
+            .into_values()
+            .map(|(name, albums)| {
+                let mut albums: Vec<AlbumNode> = albums
+                    .into_iter()
+                    .map(|(name, tracks)| AlbumNode { name, tracks })
+                    .collect();
+                album_sort.sort(&mut albums);
+                ArtistNode {
+                    expanded: expanded.contains(&name),
+                    name,
+                    albums,
+                }
+            })
+            .collect();
+
+        let order = &self.name_order;
+        groups.sort_by(|a, b| order.compare(&a.name, &b.name));
+        self.groups = groups;
+    }
+
+    /// Keeps the last scan's and the browse groups' copies of a track in
+    /// step with the artist tree.
+    fn sync_scanned_track(&mut self, track: &LibraryTrack) {
+        if let Some(scanned) = self.tracks.iter_mut().find(|t| t.path == track.path) {
+            *scanned = track.clone();
+        }
+
+        let grouped = self
+            .groups
+            .iter_mut()
+            .flat_map(|g| &mut g.albums)
+            .flat_map(|alb| &mut alb.tracks)
+            .find(|t| t.path == track.path);
+        if let Some(grouped) = grouped {
+            *grouped = track.clone();
+        }
     }
 }
 
@@ -336,8 +1415,150 @@ pub struct LibraryTrack {
     pub artist: String,
     pub album: String,
     pub track_number: Option<u32>,
+    /// Which disc of a multi-disc album the track is on
+    #[serde(default)]
+    pub disc_number: Option<u32>,
+    /// Release year, from the date tag
+    #[serde(default)]
+    pub year: Option<u32>,
     pub album_artist: String,
+    /// Part of an album by various artists
+    #[serde(default)]
+    pub compilation: bool,
     pub duration: Option<u64>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    /// Stars, 1 - 5
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// Number of times the track was played, counted once enough of it was
+    /// heard
+    #[serde(default)]
+    pub play_count: u32,
+    /// When it was last counted as played, in seconds since the Unix epoch
+    #[serde(default)]
+    pub last_played: Option<u64>,
+    /// Manual volume offset applied whenever the track plays, in dB
+    #[serde(default)]
+    pub gain_db: f32,
+    /// Free-form labels such as `mood:chill` or `workout`
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Set for tracks that are part of a single-file album with a cue sheet
+    #[serde(default)]
+    pub cue: Option<CueRange>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    /// Tempo from the file's tags
+    #[serde(default)]
+    pub bpm: Option<u32>,
+    #[serde(default)]
+    pub replay_gain: ReplayGain,
+    /// Measured by the loudness scanner, when enabled
+    #[serde(default)]
+    pub loudness: Option<Loudness>,
+    /// The file as it was when scanned
+    #[serde(default)]
+    pub stamp: Option<FileStamp>,
+    /// When the track was first added to the library, in seconds since the
+    /// Unix epoch
+    #[serde(default)]
+    pub added: Option<u64>,
+    /// The file has no tags and its names were guessed from its path
+    #[serde(default)]
+    pub guessed: bool,
+}
+
+impl LibraryTrack {
+    /// Stand-in entry for a stream URL, titled after the file it names and
+    /// credited to its host.
+    pub fn stream(url: &Path) -> Self {
+        let url = url.to_string_lossy();
+        let address = url.split("://").nth(1).unwrap_or(&url);
+        let address = address.split(['?', '#']).next().unwrap_or(address);
+        let host = address.split('/').next().unwrap_or(address).to_string();
+        let title = address
+            .rsplit('/')
+            .find(|segment| !segment.is_empty())
+            .unwrap_or(&host)
+            .to_string();
+
+        Self {
+            path: PathBuf::from(url.as_ref()),
+            title,
+            artist: host.clone(),
+            album: "Stream".to_string(),
+            track_number: None,
+            disc_number: None,
+            year: None,
+            album_artist: host,
+            compilation: false,
+            duration: None,
+            bookmarks: Vec::new(),
+            rating: None,
+            play_count: 0,
+            last_played: None,
+            gain_db: 0.0,
+            labels: Vec::new(),
+            cue: None,
+            genre: None,
+            bpm: None,
+            replay_gain: ReplayGain::default(),
+            loudness: None,
+            stamp: None,
+            added: None,
+            guessed: false,
+        }
+    }
+
+    /// The artist pane entry the track is filed under: its album artist,
+    /// or one shared Various Artists entry for every compilation.
+    pub fn artist_entry(&self) -> &str {
+        match self.compilation {
+            true => VARIOUS_ARTISTS,
+            false => &self.album_artist,
+        }
+    }
+
+    /// Title as listed in the track pane, with the track's own artist for
+    /// compilations, where the album artist doesn't say who it's by.
+    pub fn list_title(&self) -> String {
+        match self.compilation {
+            true => format!("{} – {}", self.artist, self.title),
+            false => self.title.clone(),
+        }
+    }
+
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|l| l.eq_ignore_ascii_case(label))
+    }
+}
+
+/// Size and modification time of a file, to tell whether it changed since
+/// it was scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub modified: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: meta.len(),
+            modified: modified.as_secs(),
+        })
+    }
+}
+
+/// A named position (in seconds) within a track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -346,6 +1567,22 @@ pub struct AlbumNode {
     pub tracks: Vec<LibraryTrack>,
 }
 
+impl AlbumNode {
+    /// The earliest release year of its tracks.
+    fn year(&self) -> Option<u32> {
+        self.tracks.iter().filter_map(|t| t.year).min()
+    }
+
+    /// When its latest track was added to the library.
+    fn added(&self) -> u64 {
+        self.tracks
+            .iter()
+            .filter_map(|t| t.added)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArtistNode {
     pub name: String,
@@ -353,89 +1590,533 @@ pub struct ArtistNode {
     pub expanded: bool,
 }
 
+/// Title of files without a title tag.
+const UNKNOWN_TITLE: &str = "Unknown Title";
+
+/// Artist of files without an artist tag.
+pub const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+/// Album of files without an album tag.
+pub const UNKNOWN_ALBUM: &str = "Unknown Album";
+
+/// Where tracks without a date tag are listed when browsing by year.
+const UNKNOWN_YEAR: &str = "Unknown Year";
+
+/// Album artist of compilations that don't name one.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// What a file's tags say about it, with placeholders for missing names.
+struct TrackTags {
+    title: String,
+    artist: String,
+    album: String,
+    track_number: Option<u32>,
+    disc_number: Option<u32>,
+    year: Option<u32>,
+    album_artist: Option<String>,
+    /// Stars from a rating tag
+    rating: Option<u8>,
+    /// Tagged as part of a compilation
+    compilation: bool,
+    duration: Option<u64>,
+    genre: Option<String>,
+    bpm: Option<u32>,
+    replay_gain: ReplayGain,
+    /// A cue sheet embedded in a single-file rip, as text
+    cue_sheet: Option<String>,
+}
+
+impl TrackTags {
+    /// The album artist tag, else Various Artists for compilations and the
+    /// track's artist for everything else.
+    fn album_artist(&self) -> String {
+        match (&self.album_artist, self.compilation) {
+            (Some(album_artist), _) => album_artist.clone(),
+            (None, true) => VARIOUS_ARTISTS.to_string(),
+            (None, false) => self.artist.clone(),
+        }
+    }
+
+    /// Whether the file named neither its title, artist nor album.
+    fn is_untagged(&self) -> bool {
+        self.title == UNKNOWN_TITLE && self.artist == UNKNOWN_ARTIST && self.album == UNKNOWN_ALBUM
+    }
+
+    /// Fills in names from a path like `Artist - Album/01 - Title.mp3`,
+    /// keeping the placeholders for what it doesn't say. Returns whether the
+    /// path could be read.
+    fn guess_from_path(&mut self, path: &Path) -> bool {
+        let Some(guess) = path_tags::guess(path) else {
+            return false;
+        };
+
+        self.title = guess.title;
+        self.album_artist = guess.album_artist.clone().or(self.album_artist.take());
+        self.artist = guess
+            .artist
+            .or(guess.album_artist)
+            .unwrap_or(UNKNOWN_ARTIST.into());
+        self.album = guess.album.unwrap_or(UNKNOWN_ALBUM.into());
+        self.track_number = self.track_number.or(guess.track_number);
+        self.disc_number = self.disc_number.or(guess.disc_number);
+        self.year = self.year.or(guess.year);
+        true
+    }
+}
+
+impl Default for TrackTags {
+    fn default() -> Self {
+        Self {
+            title: UNKNOWN_TITLE.into(),
+            artist: UNKNOWN_ARTIST.into(),
+            album: UNKNOWN_ALBUM.into(),
+            track_number: None,
+            disc_number: None,
+            year: None,
+            album_artist: None,
+            rating: None,
+            compilation: false,
+            duration: None,
+            genre: None,
+            bpm: None,
+            replay_gain: ReplayGain::default(),
+            cue_sheet: None,
+        }
+    }
+}
+
 /// Scans a path recursively and parses audio files into LibraryTrack entries.
+///
+/// Audio files with a cue sheet next to them, or embedded in their tags, are
+/// listed as the sheet's tracks.
 pub fn scan_path_for_tracks(path: &Path) -> Vec<LibraryTrack> {
-    let mut tracks = Vec::new();
+    scan_path_with_progress(path, &ScanFilter::default(), &ScanProgress::default())
+}
+
+/// [`scan_path_for_tracks`] for a library folder: only files `filter` lets
+/// through are read, and they're counted in `progress`.
+pub fn scan_path_with_progress(
+    path: &Path,
+    filter: &ScanFilter,
+    progress: &ScanProgress,
+) -> Vec<LibraryTrack> {
+    // A single cue track, e.g. from the queue or a playlist
+    if let Some((sheet, number)) = cue::split_track_path(path) {
+        let tracks = match has_extension(&sheet, "cue") {
+            true => scan_cue_sheet(&sheet),
+            false => scan_embedded_cue_sheet(&sheet),
+        };
+        return tracks
+            .into_iter()
+            .filter(|t| t.track_number == Some(number))
+            .collect();
+    }
+
+    let mut seen = HashSet::new();
+    let files: Vec<PathBuf> = walk_files(path, filter)
+        .filter(|file| seen.insert(file.clone()))
+        .collect();
+    scan_files(&files, progress)
+}
 
-    for entry in WalkDir::new(path)
+/// The canonical paths of the files at and below `path`, leaving out what
+/// `filter` excludes and not going into excluded folders at all. A file
+/// reached through several symlinks comes up more than once.
+fn walk_files(path: &Path, filter: &ScanFilter) -> impl Iterator<Item = PathBuf> {
+    WalkDir::new(path)
         .into_iter()
+        .filter_entry(|e| !filter.excludes(e.path()))
         .filter_map(Result::ok)
-        .filter(|e| e.path().is_file())
-    {
-        let path = entry.path();
-
-        let ext = path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|s| s.to_ascii_lowercase());
-
-        let (title, artist, album, track_number, album_artist, duration) = match ext.as_deref() {
-            Some("mp3") => extract_id3_tags(path),
-            Some("flac") => extract_symphonia_tags(path),
-            _ => continue,
-        };
+        .filter(|e| e.path().is_file() && filter.includes(e.path()))
+        .map(|e| canonical_path(e.path()))
+}
 
-        tracks.push(LibraryTrack {
-            path: path.to_path_buf(),
-            title,
-            artist,
-            album,
-            track_number,
-            album_artist,
-            duration,
-        });
+/// `path` with symlinks and `..` resolved, so a file reached several ways is
+/// listed once. Cue track paths keep their track number; paths that can't be
+/// resolved, like those of files that are gone, come back as they are.
+pub fn canonical_path(path: &Path) -> PathBuf {
+    if let Some((sheet, number)) = cue::split_track_path(path) {
+        return cue::track_path(&canonical_path(&sheet), number);
     }
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
 
+/// Parses `files` into library entries, listing audio files that one of the
+/// cue sheets among them points to as the sheet's tracks.
+///
+/// Tags are read on the rayon pool, one file per thread at a time, with
+/// each thread's share counted in `progress`. Tracks come out in the order
+/// of `files` either way. Album art found along the way is cached.
+fn scan_files(files: &[PathBuf], progress: &ScanProgress) -> Vec<LibraryTrack> {
+    let sheets: Vec<&PathBuf> = files.iter().filter(|p| has_extension(p, "cue")).collect();
+    progress.total.fetch_add(sheets.len(), Ordering::Relaxed);
+
+    let mut tracks: Vec<LibraryTrack> = sheets
+        .par_iter()
+        .flat_map_iter(|sheet| progress.track(sheet, || scan_cue_sheet(sheet)))
+        .collect();
+    let covered: HashSet<&PathBuf> = tracks
+        .iter()
+        .filter_map(|t| Some(&t.cue.as_ref()?.file))
+        .collect();
+
+    let audio: Vec<&PathBuf> = files
+        .iter()
+        .filter(|p| !covered.contains(p) && is_audio_file(p))
+        .collect();
+    progress.total.fetch_add(audio.len(), Ordering::Relaxed);
+
+    let scanned: Vec<LibraryTrack> = audio
+        .par_iter()
+        .flat_map_iter(|path| progress.track(path, || scan_audio_file(path)))
+        .collect();
+    tracks.extend(scanned);
+    mark_compilations(&mut tracks);
+    artwork::cache_album_art(&tracks);
     tracks
 }
 
-fn extract_id3_tags(path: &Path) -> (String, String, String, Option<u32>, String, Option<u64>) {
-    let tag = Id3Tag::read_from_path(path).ok();
+/// Flags albums by various artists and files all their tracks under one
+/// album artist, so they aren't shredded into an artist entry per track.
+///
+/// An album counts as a compilation when its tags say so, or when the
+/// tracks of one album in one folder have different album artists, which
+/// is what happens when the tags only name each track's artist.
+fn mark_compilations(tracks: &mut [LibraryTrack]) {
+    let mut albums: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, track) in tracks.iter().enumerate() {
+        // Loose files without tags aren't an album
+        if track.album != UNKNOWN_ALBUM {
+            albums.entry(album_key(track)).or_default().push(index);
+        }
+    }
+
+    for indices in albums.values() {
+        let album_artists: HashSet<&str> = indices
+            .iter()
+            .map(|&i| tracks[i].album_artist.as_str())
+            .collect();
+        let tagged = indices.iter().any(|&i| tracks[i].compilation);
+        if album_artists.len() < 2 && !tagged {
+            continue;
+        }
+
+        let album_artist = match album_artists.len() {
+            1 => album_artists
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            _ => VARIOUS_ARTISTS.to_string(),
+        };
+        for &i in indices {
+            tracks[i].compilation = true;
+            tracks[i].album_artist = album_artist.clone();
+        }
+    }
+}
+
+/// The folder a track's file is in and its album, which together tell
+/// albums of the same name apart.
+fn album_key(track: &LibraryTrack) -> (PathBuf, String) {
+    let source = source_file(&track.path);
+    let folder = source.parent().map(Path::to_path_buf).unwrap_or_default();
+    (folder, track.album.clone())
+}
 
-    let title = tag
-        .as_ref()
-        .and_then(|t| t.title())
-        .unwrap_or("Unknown Title")
-        .to_string();
-    let artist = tag
-        .as_ref()
-        .and_then(|t| t.artist())
-        .unwrap_or("Unknown Artist")
-        .to_string();
-    let album = tag
-        .as_ref()
-        .and_then(|t| t.album())
-        .unwrap_or("Unknown Album")
-        .to_string();
+/// The library entry for an audio file, or the tracks of the cue sheet
+/// embedded in it.
+fn scan_audio_file(path: &Path) -> Vec<LibraryTrack> {
+    let mut tags = if has_extension(path, "mp3") {
+        extract_id3_tags(path)
+    } else {
+        extract_symphonia_tags(path)
+    };
+    let guessed = tags.is_untagged() && tags.guess_from_path(path);
+
+    let embedded = tags.cue_sheet.as_deref().map(|text| {
+        let mut sheet = cue::parse(text);
+        sheet.use_file(path);
+        sheet_tracks(path, &sheet, &tags)
+    });
+    if let Some(entries) = embedded.filter(|entries| !entries.is_empty()) {
+        return entries;
+    }
 
-    let album_artist = tag
-        .as_ref()
-        .and_then(|t| t.album_artist())
-        .unwrap_or("Unknown Album Artist")
-        .to_string();
+    let album_artist = tags.album_artist();
+    vec![LibraryTrack {
+        path: path.to_path_buf(),
+        title: tags.title,
+        artist: tags.artist,
+        album: tags.album,
+        track_number: tags.track_number,
+        disc_number: tags.disc_number,
+        year: tags.year,
+        album_artist,
+        compilation: tags.compilation,
+        duration: tags.duration,
+        bookmarks: Vec::new(),
+        rating: tags.rating,
+        play_count: 0,
+        last_played: None,
+        gain_db: 0.0,
+        labels: Vec::new(),
+        cue: None,
+        genre: tags.genre,
+        bpm: tags.bpm,
+        replay_gain: tags.replay_gain,
+        loudness: None,
+        stamp: FileStamp::of(path),
+        added: None,
+        guessed,
+    }]
+}
 
-    let track_number = tag.and_then(|t| t.track()).map(|n| n as u32);
+/// The file a library entry was read from: the cue sheet or single-file rip
+/// for cue tracks, else the track's own path.
+fn source_file(path: &Path) -> PathBuf {
+    match cue::split_track_path(path) {
+        Some((sheet, _)) => sheet,
+        None => path.to_path_buf(),
+    }
+}
+
+/// Lists the tracks of a cue sheet, including a hidden track before track 1.
+///
+/// The sheet's titles and performers win; the audio file's tags fill the gaps.
+fn scan_cue_sheet(sheet_path: &Path) -> Vec<LibraryTrack> {
+    let sheet = match cue::load(sheet_path) {
+        Ok(sheet) => sheet,
+        Err(err) => {
+            log::warn!("Failed to read cue sheet {}: {err}", sheet_path.display());
+            return Vec::new();
+        }
+    };
 
+    let Some(file) = sheet.file.as_deref().filter(|file| file.is_file()) else {
+        return Vec::new();
+    };
 
+    let tags = if has_extension(file, "mp3") {
+        extract_id3_tags(file)
+    } else {
+        extract_symphonia_tags(file)
+    };
 
-    (title, artist, album, track_number, album_artist, None)
+    sheet_tracks(sheet_path, &sheet, &tags)
 }
 
-fn extract_symphonia_tags(path: &Path) -> (String, String, String, Option<u32>, String, Option<u64>) {
+/// Lists the tracks of a cue sheet stored in the `CUESHEET` tag of a
+/// single-file rip, keyed like `album.flac#03`.
+fn scan_embedded_cue_sheet(path: &Path) -> Vec<LibraryTrack> {
+    let tags = extract_symphonia_tags(path);
+    let Some(text) = &tags.cue_sheet else {
+        return Vec::new();
+    };
+
+    let mut sheet = cue::parse(text);
+    sheet.use_file(path);
+    sheet_tracks(path, &sheet, &tags)
+}
+
+/// Library entries for the tracks of `sheet`, keyed by `sheet_path`, with
+/// the audio file's `tags` filling in what the sheet leaves out.
+fn sheet_tracks(sheet_path: &Path, sheet: &CueSheet, tags: &TrackTags) -> Vec<LibraryTrack> {
+    let album = sheet.title.clone().unwrap_or_else(|| tags.album.clone());
+    let album_artist = sheet
+        .performer
+        .clone()
+        .unwrap_or_else(|| tags.album_artist());
+    let genre = sheet.genre.clone().or_else(|| tags.genre.clone());
+    let year = sheet.date.as_deref().and_then(parse_year).or(tags.year);
+    let stamp = FileStamp::of(sheet_path);
+
+    cue::entries(sheet)
+        .into_iter()
+        .map(|entry| {
+            let start_secs = entry.range.start_ms / 1000;
+            // The tags are the first file's; tracks in the sheet's other
+            // files run to the end of theirs
+            let file_length = match Some(&entry.range.file) == sheet.file.as_ref() {
+                true => tags.duration,
+                false => probe_duration(&entry.range.file),
+            };
+            let duration = match entry.range.end_ms {
+                Some(end_ms) => Some(end_ms.saturating_sub(entry.range.start_ms) / 1000),
+                None => file_length.map(|secs| secs.saturating_sub(start_secs)),
+            };
+
+            LibraryTrack {
+                path: cue::track_path(sheet_path, entry.number),
+                title: entry.title,
+                artist: entry
+                    .performer
+                    .or_else(|| sheet.performer.clone())
+                    .unwrap_or_else(|| tags.artist.clone()),
+                album: album.clone(),
+                track_number: Some(entry.number),
+                disc_number: tags.disc_number,
+                year,
+                album_artist: album_artist.clone(),
+                compilation: tags.compilation,
+                duration,
+                bookmarks: Vec::new(),
+                rating: None,
+                play_count: 0,
+                last_played: None,
+                gain_db: 0.0,
+                labels: Vec::new(),
+                cue: Some(entry.range),
+                genre: genre.clone(),
+                bpm: None,
+                replay_gain: tags.replay_gain,
+                loudness: None,
+                stamp,
+                added: None,
+                guessed: false,
+            }
+        })
+        .collect()
+}
+
+/// Whether `path` is a format the library can scan on its own.
+pub fn is_audio_file(path: &Path) -> bool {
+    [
+        "mp3", "flac", "m4a", "m4b", "ogg", "oga", "opus", "wav", "aiff", "aif", "caf", "mka",
+    ]
+    .iter()
+    .any(|ext| has_extension(path, ext))
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+/// Whether a flag tag like `TCMP` or `COMPILATION` is set.
+fn is_set_flag(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes"
+    )
+}
+
+/// The number in a track or disc tag, which may come with the total as in
+/// `2/3`.
+fn parse_position(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+/// The year a date tag starts with, as in `1997`, `1997-03-14` or
+/// `1997-03-14T00:00:00`.
+fn parse_year(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let year = value.get(..4)?.parse().ok()?;
+    let rest = &value[4..];
+    (rest.is_empty() || rest.starts_with(['-', '/', 'T', ' '])).then_some(year)
+}
+
+/// Stars from a `POPM` rating, using the ranges around the values most
+/// players write for 1 - 5 stars (1, 64, 128, 196, 255).
+fn popm_stars(rating: u8) -> Option<u8> {
+    match rating {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// Stars from an `FMPS_RATING` tag, a fraction between 0.0 and 1.0.
+fn parse_fmps_rating(value: &str) -> Option<u8> {
+    let stars = (value.trim().parse::<f32>().ok()? * 5.0)
+        .round()
+        .clamp(0.0, 5.0) as u8;
+    (stars > 0).then_some(stars)
+}
+
+/// Stars from a `RATING` comment, which is either 1 - 5 or out of 100.
+fn parse_rating(value: &str) -> Option<u8> {
+    let value = value.trim().parse::<f32>().ok()?;
+    let stars = match value <= 5.0 {
+        true => value.round(),
+        false => (value / 20.0).round().min(5.0),
+    } as u8;
+    (stars > 0).then_some(stars)
+}
+
+/// Parses a BPM tag, which some taggers write with decimals.
+fn parse_bpm(value: &str) -> Option<u32> {
+    let bpm = value.trim().parse::<f32>().ok()?.round();
+    (bpm > 0.0).then_some(bpm as u32)
+}
+
+fn extract_id3_tags(path: &Path) -> TrackTags {
+    let Ok(tag) = Id3Tag::read_from_path(path) else {
+        return TrackTags::default();
+    };
+    let defaults = TrackTags::default();
+
+    let mut replay_gain = ReplayGain::default();
+    let mut fmps_rating = None;
+    for text in tag.extended_texts() {
+        if text.description.eq_ignore_ascii_case("FMPS_Rating") {
+            fmps_rating = parse_fmps_rating(&text.value);
+        }
+        replay_gain.set(&text.description, &text.value);
+    }
+
+    // Each player keeps its own POPM frame; ours wins if there is one
+    let popms: Vec<_> = tag
+        .frames()
+        .filter_map(|f| f.content().popularimeter())
+        .collect();
+    let popm = popms
+        .iter()
+        .find(|popm| popm.user == tag_writer::POPM_USER)
+        .or_else(|| popms.iter().find(|popm| popm.rating > 0));
+
+    TrackTags {
+        title: tag.title().map_or(defaults.title, str::to_string),
+        artist: tag.artist().map_or(defaults.artist, str::to_string),
+        album: tag.album().map_or(defaults.album, str::to_string),
+        track_number: tag.track(),
+        disc_number: tag.disc(),
+        year: tag
+            .date_released()
+            .or_else(|| tag.date_recorded())
+            .map(|date| date.year as u32)
+            .or_else(|| tag.year().map(|year| year as u32)),
+        album_artist: tag.album_artist().map(str::to_string),
+        rating: popm
+            .and_then(|popm| popm_stars(popm.rating))
+            .or(fmps_rating),
+        compilation: tag
+            .get("TCMP")
+            .and_then(|frame| frame.content().text())
+            .is_some_and(is_set_flag),
+        duration: probe_duration(path),
+        genre: tag.genre_parsed().map(|genre| genre.into_owned()),
+        bpm: tag
+            .get("TBPM")
+            .and_then(|frame| frame.content().text())
+            .and_then(parse_bpm),
+        replay_gain,
+        cue_sheet: None,
+    }
+}
+
+fn extract_symphonia_tags(path: &Path) -> TrackTags {
     use symphonia::core::meta::StandardTagKey;
 
+    let mut tags = TrackTags::default();
+
     let file = match File::open(path) {
         Ok(f) => f,
-        Err(_) => {
-            return (
-                "Unknown Title".into(),
-                "Unknown Artist".into(),
-                "Unknown Album".into(),
-                None,
-                "Unknown Album Artist".into(),
-                None,
-            );
-        }
+        Err(_) => return tags,
     };
 
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
@@ -447,53 +2128,74 @@ fn extract_symphonia_tags(path: &Path) -> (String, String, String, Option<u32>,
         &MetadataOptions::default(),
     ) {
         Ok(p) => p,
-        Err(_) => {
-            return (
-                "Unknown Title".into(),
-                "Unknown Artist".into(),
-                "Unknown Album".into(),
-                None,
-                "Unknown Album Artist".into(),
-                None,
-            );
-        }
+        Err(_) => return tags,
     };
 
     let binding = probed.format.metadata();
     let meta = binding.current();
 
-    let mut title = "Unknown Title".to_string();
-    let mut artist = "Unknown Artist".to_string();
-    let mut album_artist = "Unknown Album Artist".to_string();
-    let mut album = "Unknown Album".to_string();
-    let mut track_number = None;
-
     if let Some(m) = meta {
         for tag in m.tags() {
             match tag.std_key {
-                Some(StandardTagKey::TrackTitle) => title = tag.value.to_string(),
-                Some(StandardTagKey::Artist) => artist = tag.value.to_string(),
-                Some(StandardTagKey::AlbumArtist) => album_artist = tag.value.to_string(),
-                Some(StandardTagKey::Album) => album = tag.value.to_string(),
+                Some(StandardTagKey::TrackTitle) => tags.title = tag.value.to_string(),
+                Some(StandardTagKey::Artist) => tags.artist = tag.value.to_string(),
+                Some(StandardTagKey::AlbumArtist) => {
+                    tags.album_artist = Some(tag.value.to_string());
+                }
+                Some(StandardTagKey::Compilation) => {
+                    tags.compilation = is_set_flag(&tag.value.to_string());
+                }
+                Some(StandardTagKey::Album) => tags.album = tag.value.to_string(),
                 Some(StandardTagKey::TrackNumber) => {
-                    track_number = tag.value.to_string().parse::<u32>().ok();
+                    tags.track_number = parse_position(&tag.value.to_string());
+                }
+                Some(StandardTagKey::DiscNumber) => {
+                    tags.disc_number = parse_position(&tag.value.to_string());
+                }
+                Some(StandardTagKey::Date | StandardTagKey::ReleaseDate) => {
+                    tags.year = tags.year.or(parse_year(&tag.value.to_string()));
+                }
+                Some(StandardTagKey::Genre) => tags.genre = Some(tag.value.to_string()),
+                Some(StandardTagKey::Bpm) => tags.bpm = parse_bpm(&tag.value.to_string()),
+                Some(StandardTagKey::Rating) => {
+                    tags.rating = tags.rating.or(parse_rating(&tag.value.to_string()));
+                }
+                _ if tag.key.eq_ignore_ascii_case("FMPS_RATING") => {
+                    tags.rating = parse_fmps_rating(&tag.value.to_string()).or(tags.rating);
+                }
+                _ if tag.key.eq_ignore_ascii_case("CUESHEET") => {
+                    tags.cue_sheet = Some(tag.value.to_string());
                 }
-                _ => {}
+                _ => tags.replay_gain.set(&tag.key, &tag.value.to_string()),
             }
         }
     }
 
-    let mut duration = None;
+    tags.duration = track_duration(&probed);
+    tags
+}
 
-    if let Some(track) = probed.format.default_track() {
-        if let Some(tb) = track.codec_params.time_base {
-            if let Some(n_frames) = track.codec_params.n_frames {
-                duration = Some((n_frames as u64 * tb.numer as u64) / tb.denom as u64);
-            }
-        }
-    }
+/// Length of the file's audio in seconds, read from its headers without
+/// decoding it.
+fn probe_duration(path: &Path) -> Option<u64> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let probed = get_probe()
+        .format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    track_duration(&probed)
+}
 
-    (title, artist, album, track_number, album_artist, duration)
+/// Length of the default track in seconds, when the container says.
+fn track_duration(probed: &ProbeResult) -> Option<u64> {
+    let params = &probed.format.default_track()?.codec_params;
+    let time_base = params.time_base?;
+    Some(params.n_frames? * u64::from(time_base.numer) / u64::from(time_base.denom))
 }
 
 #[derive(PartialEq)]
@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::{Channels, SampleBuffer};
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::default::{get_codecs, get_probe};
+
+/// Blocks quieter than this are left out entirely, in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks this far below the ungated loudness are left out, in LU.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Gating blocks are 400 ms long and start every 100 ms.
+const BLOCK_STEPS: usize = 4;
+
+/// A track's loudness as measured per EBU R128.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness, in LUFS
+    pub integrated: f32,
+    /// Loudest sample, where 1.0 is full scale
+    pub peak: f32,
+}
+
+impl Loudness {
+    /// Linear gain that brings the track to `target` LUFS, lowered when it
+    /// would push the peak past full scale.
+    pub fn factor(&self, target: f32) -> f32 {
+        let factor = 10f32.powf((target - self.integrated) / 20.0);
+        match self.peak > 0.0 {
+            true => factor.min(1.0 / self.peak),
+            false => factor,
+        }
+    }
+}
+
+/// Measures tracks on a background thread, one at a time, so scanning a big
+/// library doesn't hold up the UI.
+pub struct LoudnessScanner {
+    sender: Sender<PathBuf>,
+    results: Receiver<(PathBuf, Loudness)>,
+    /// Everything queued this run, so failed files aren't tried again
+    queued: HashSet<PathBuf>,
+}
+
+impl LoudnessScanner {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel::<PathBuf>();
+        let (result_sender, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            for path in receiver {
+                match measure(&path) {
+                    Ok(Some(loudness)) => {
+                        if result_sender.send((path, loudness)).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => log::debug!("{:?} is silent, not normalizing it", path),
+                    Err(err) => log::warn!("Failed to measure loudness of {:?}: {err}", path),
+                }
+            }
+        });
+
+        Self {
+            sender,
+            results,
+            queued: HashSet::new(),
+        }
+    }
+
+    /// Queues `path` unless it was queued before.
+    pub fn queue(&mut self, path: &Path) {
+        if !self.queued.insert(path.to_path_buf()) {
+            return;
+        }
+
+        if self.sender.send(path.to_path_buf()).is_err() {
+            log::error!("Loudness scanner has stopped, not measuring {:?}", path);
+        }
+    }
+
+    /// Measurements finished since the last call.
+    pub fn finished(&self) -> Vec<(PathBuf, Loudness)> {
+        self.results.try_iter().collect()
+    }
+}
+
+/// Decodes the whole file and measures its integrated loudness (ITU-R
+/// BS.1770). Returns `None` for files with nothing above the absolute gate.
+pub fn measure(path: &Path) -> anyhow::Result<Option<Loudness>> {
+    Ok(scan(path)?.and_then(|meter| gate(&meter.blocks, meter.peak)))
+}
+
+/// Measures each of `paths` and the album they make up together, gating
+/// over the blocks of every track as if they were one file. Files that fail
+/// to decode are logged and left out.
+pub fn measure_album(paths: &[PathBuf]) -> (Vec<(PathBuf, Loudness)>, Option<Loudness>) {
+    let mut tracks = Vec::new();
+    let mut blocks = Vec::new();
+    let mut peak = 0f32;
+
+    for path in paths {
+        let meter = match scan(path) {
+            Ok(Some(meter)) => meter,
+            Ok(None) => continue,
+            Err(err) => {
+                log::warn!("Failed to measure loudness of {:?}: {err}", path);
+                continue;
+            }
+        };
+
+        if let Some(loudness) = gate(&meter.blocks, meter.peak) {
+            tracks.push((path.clone(), loudness));
+        }
+        blocks.extend(meter.blocks);
+        peak = peak.max(meter.peak);
+    }
+
+    (tracks, gate(&blocks, peak))
+}
+
+/// Runs the whole file through a meter; `None` if nothing was decoded.
+fn scan(path: &Path) -> anyhow::Result<Option<Meter>> {
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut format = get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow::anyhow!("No supported audio track found"))?
+        .clone();
+    let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut meter = None;
+    let mut buffer = None;
+
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track.id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                log::debug!("Skipping undecodable packet in {:?}: {err}", path);
+                continue;
+            }
+        };
+
+        let spec = *decoded.spec();
+        let meter = meter.get_or_insert_with(|| Meter::new(spec.channels, spec.rate));
+        let samples = buffer.get_or_insert_with(|| {
+            SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec())
+        });
+        if samples.capacity() < decoded.capacity() * decoded.spec().channels.count() {
+            *samples = SampleBuffer::new(decoded.capacity() as u64, *decoded.spec());
+        }
+        samples.copy_interleaved_ref(decoded);
+        meter.process(samples.samples());
+    }
+
+    Ok(meter)
+}
+
+/// Second order IIR filter, transposed direct form II.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// The K-weighting filter of BS.1770 for `sample_rate`: a high shelf for the
+/// head's acoustic effect followed by a high pass.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let k = (PI * 1681.974450955533 / rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    let k = (PI * 38.13547087602444 / rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// How much a channel counts towards the loudness: surround channels a bit
+/// more than the front, the LFE channel not at all.
+fn channel_weight(channel: Channels) -> f64 {
+    const SURROUND: Channels = Channels::REAR_LEFT
+        .union(Channels::REAR_RIGHT)
+        .union(Channels::SIDE_LEFT)
+        .union(Channels::SIDE_RIGHT);
+    const LFE: Channels = Channels::LFE1.union(Channels::LFE2);
+
+    if LFE.contains(channel) {
+        0.0
+    } else if SURROUND.contains(channel) {
+        1.41
+    } else {
+        1.0
+    }
+}
+
+/// Gated loudness meter over interleaved samples.
+struct Meter {
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    /// Frames per 100 ms step
+    step_frames: usize,
+    /// Frames and weighted sum of squares of the step being filled
+    frames: usize,
+    sum: f64,
+    /// Mean square of the last steps, newest last
+    steps: Vec<f64>,
+    /// Mean square of every 400 ms block
+    blocks: Vec<f64>,
+    peak: f32,
+}
+
+impl Meter {
+    fn new(layout: Channels, sample_rate: u32) -> Self {
+        let mut weights: Vec<f64> = layout.iter().map(channel_weight).collect();
+        if weights.is_empty() {
+            weights.push(1.0);
+        }
+
+        Self {
+            filters: vec![k_weighting(sample_rate); weights.len()],
+            weights,
+            step_frames: (sample_rate as usize / 10).max(1),
+            frames: 0,
+            sum: 0.0,
+            steps: Vec::new(),
+            blocks: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.weights.len()) {
+            for ((sample, filters), weight) in
+                frame.iter().zip(&mut self.filters).zip(&self.weights)
+            {
+                self.peak = self.peak.max(sample.abs());
+                let filtered = filters
+                    .iter_mut()
+                    .fold(*sample as f64, |value, filter| filter.process(value));
+                self.sum += weight * filtered * filtered;
+            }
+
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                self.end_step();
+            }
+        }
+    }
+
+    fn end_step(&mut self) {
+        self.steps.push(self.sum / self.frames as f64);
+        self.frames = 0;
+        self.sum = 0.0;
+
+        if self.steps.len() > BLOCK_STEPS {
+            self.steps.remove(0);
+        }
+        if self.steps.len() == BLOCK_STEPS {
+            self.blocks
+                .push(self.steps.iter().sum::<f64>() / BLOCK_STEPS as f64);
+        }
+    }
+}
+
+/// Integrated loudness of 400 ms blocks given by their mean square, or `None`
+/// when none of them is above the absolute gate.
+fn gate(blocks: &[f64], peak: f32) -> Option<Loudness> {
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10();
+    let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+
+    let audible: Vec<f64> = blocks
+        .iter()
+        .copied()
+        .filter(|power| loudness(*power) > ABSOLUTE_GATE)
+        .collect();
+    if audible.is_empty() {
+        return None;
+    }
+
+    let threshold = loudness(mean(&audible)) + RELATIVE_GATE;
+    let gated: Vec<f64> = audible
+        .into_iter()
+        .filter(|power| loudness(*power) > threshold)
+        .collect();
+
+    Some(Loudness {
+        integrated: loudness(mean(&gated)) as f32,
+        peak,
+    })
+}
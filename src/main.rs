@@ -1,21 +1,61 @@
+mod alarm;
+mod album_lookup;
 mod app;
+mod artwork;
+mod autodj;
 mod browser;
+mod chapters;
+mod cli;
+mod collation;
+mod config;
+mod cover_fetch;
+mod cue;
+mod duplicates;
+mod fingerprint;
+mod history;
+mod import;
+mod incoming;
+mod keymap;
 mod library;
 mod list;
+mod loudness;
+mod musicbrainz;
+mod now_playing;
+mod path_tags;
 mod persistence;
 mod player;
+mod playlist;
+mod prompt;
+mod query;
+mod radio;
+mod replaygain;
+mod replaygain_scan;
+mod retag;
+mod scan;
 mod screens;
+mod scrobble_log;
+mod search;
+mod session;
+mod shuffle;
+mod stats;
+mod tag_writer;
 mod ui;
+mod watcher;
 
-use app::{App, AppScreen};
+use app::{App, AppScreen, RepeatMode};
 
-use std::time::Instant;
+use crate::album_lookup::LookupStep;
 
 use crate::browser::BrowserItem;
 
-use crate::library::{LibraryFocus, scan_path_for_tracks};
+use crate::cli::{Cli, Command};
+
+use clap::Parser;
+
+use crate::keymap::Action;
+
+use crate::library::LibraryFocus;
 
-use std::sync::Arc;
 use std::sync::atomic::Ordering;
 
 use crossterm::{
@@ -30,6 +70,14 @@ use simplelog::*;
 use std::fs::File;
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if let Some(command) = cli.command {
+        return run_command(command);
+    }
+
+    // Only the player logs, so a subcommand run alongside it doesn't
+    // truncate its log
     CombinedLogger::init(vec![WriteLogger::new(
         LevelFilter::Trace,
         Config::default(),
@@ -37,165 +85,154 @@ fn main() -> Result<()> {
     )])
     .unwrap();
 
+    let config = match config::load_config(&cli.overrides) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Failed to load config: {err}");
+            std::process::exit(1);
+        }
+    };
+
     enable_raw_mode()?;
     execute!(stdout(), EnterAlternateScreen)?;
 
     let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new();
+    let mut app = App::new(config);
+    app.offer_resume();
+    app.analyze_loudness();
 
     loop {
         app.update();
+        app.skip_unplayable();
 
         if app
             .player
-            .lock()
-            .unwrap()
+            .borrow()
             .autoplay_trigger
             .swap(false, Ordering::SeqCst)
         {
-            let mut plyr = app.player.lock().unwrap();
-            let mut lib = app.library.lock().unwrap();
-
-            if let Some(current_path) = &plyr.current_path {
-                if app.autoplay_enabled {
-                    if let Some(next_path) = lib.next_track_path(current_path) {
-                        lib.select_track_by_path(&next_path);
-                        plyr.play(&next_path);
-
-                        if let Some(next_track) = lib.track_by_path(&next_path) {
-                            app.current_track = Some(next_track.clone());
-                            app.playback_start = Some(Instant::now());
-
-                            log::debug!(
-                                "Autoplay switched to: {} – {}",
-                                next_track.album_artist,
-                                next_track.title
-                            );
-                            log::debug!("playback_start: {:?}", app.playback_start);
-                        }
-                    }
-                }
+            app.record_play();
+
+            if app.stop_after_current {
+                app.stop_after_current = false;
+                continue;
             }
+
+            if app.repeat == RepeatMode::Track && app.repeat_track() {
+                continue;
+            }
+
+            let current_path = app.playing_path();
+            app.autoplay_next(current_path);
         }
 
         log::debug!("Drawing track: {:?}", app.current_track.as_ref().map(|t| &t.title));
         terminal.draw(|f| ui::draw_ui(f, &mut app))?;
 
-        if event::poll(std::time::Duration::from_millis(200))? {
-
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => break,
-                    KeyCode::Char('1') => app.goto_screen(app::AppScreen::Library),
-                    KeyCode::Char('5') => app.goto_screen(app::AppScreen::Browser),
-                    KeyCode::Char('a') => {
-                        let mut lib = app.library_mut();
-
-                        if app.screen == AppScreen::Browser {
-                            if let Some(BrowserItem::Entry(path)) = app.browser.list.selected_item()
-                            {
-                                let tracks = scan_path_for_tracks(path);
-                                lib.tracks = tracks.clone();
-
-                                lib.add_tracks(tracks);
-                            }
-                        }
-                    }
+        if let Some(action) = app.keymap.tick()
+            && handle_action(&mut app, action)
+        {
+            break;
+        }
 
-                    KeyCode::Down => match app.screen {
-                        AppScreen::Browser => app.browser.move_down(),
-
-                        AppScreen::Library => {
-                            let mut lib = app.library_mut();
-
-                            match lib.focus {
-                                LibraryFocus::Left => lib.move_down(),
-                                LibraryFocus::Right => {
-                                    let count = lib.visible_tracks().len();
-                                    lib.move_track_down(count);
-                                }
-                            }
-                        },
-                    },
-
-                    KeyCode::Up => match app.screen {
-                        AppScreen::Browser => app.browser.move_up(),
-
-                        AppScreen::Library => {
-                            let mut lib = app.library_mut();
-
-                            match lib.focus {
-                                LibraryFocus::Left => lib.move_up(),
-                                LibraryFocus::Right => lib.move_track_up(),
-                            }
-                        },
-                    },
-
-                    KeyCode::Enter => {
-                        if app.screen == AppScreen::Browser {
-                            app.browser.open_selected();
-                        }
-
-                        let mut lib = app.library_mut();
-
-                        if app.screen == AppScreen::Library
-                            && lib.focus == LibraryFocus::Right
-                        {
-                            let selected = {
-                                let lib = lib;
-                                lib.visible_tracks().get(lib.track_index).cloned()
-                            };
-
-                            if let Some(track) = selected {
-                                let player = Arc::clone(&app.player);
-
-                                // Stop current playback and play selected track
-                                {
-                                    let mut plyr = player.lock().unwrap();
-                                    plyr.stop();
-                                    plyr.play(&track.path);
-
-                                    app.current_track = Some(track.clone());
-                                    app.playback_duration = track.duration.unwrap_or(0);
-                                    app.playback_start = Some(Instant::now());
-                                }
-                            }
-                        }
-                    }
+        if event::poll(std::time::Duration::from_millis(200))?
+            && let Event::Key(key) = event::read()?
+        {
+            if app.prompt.is_some() {
+                handle_prompt_key(&mut app, key.code);
+                continue;
+            }
 
-                    KeyCode::Char('p') => {
-                        app.autoplay_enabled = !app.autoplay_enabled;
-                    }
+            if app.bookmark_list.is_some() {
+                handle_bookmark_list_key(&mut app, key.code);
+                continue;
+            }
 
-                    KeyCode::Char('c') => {
-                        app.toggle_pause();
-                    }
+            if app.chapter_list.is_some() {
+                handle_chapter_list_key(&mut app, key.code);
+                continue;
+            }
 
-                    KeyCode::Char('n') => {
-                        app.play_next_track();
-                    }
+            if app.label_list.is_some() {
+                handle_label_list_key(&mut app, key.code);
+                continue;
+            }
 
-                    KeyCode::Backspace => {
-                        if app.screen == AppScreen::Browser {
-                            app.browser.go_up();
-                        }
-                    }
-                    KeyCode::Char(' ') => {
-                        let mut lib = app.library_mut();
+            if app.session_list.is_some() {
+                handle_session_list_key(&mut app, key.code);
+                continue;
+            }
 
-                        if app.screen == AppScreen::Library {
-                            lib.toggle_expanded();
-                        }
-                    }
-                    KeyCode::Tab => {
-                        let mut lib = app.library_mut();
-                        lib.tab_focus();
-                    }
-                    _ => {}
+            if app.station_list.is_some() {
+                handle_station_list_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.album_lookup.is_some() {
+                handle_album_lookup_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.retag_menu.is_some() {
+                handle_retag_menu_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.retag_preview.is_some() {
+                handle_retag_preview_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.smart_list.is_some() {
+                handle_smart_list_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.missing_list.is_some() {
+                handle_missing_list_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.search.is_some() {
+                handle_search_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.equalizer_band.is_some() {
+                handle_equalizer_key(&mut app, key.code);
+                continue;
+            }
+
+            if app.settings_row.is_some() {
+                handle_settings_key(&mut app, key.code);
+                continue;
+            }
+
+            // Diagnostics stay up while playing, so only Esc is taken
+            if app.show_diagnostics && key.code == KeyCode::Esc {
+                app.show_diagnostics = false;
+                continue;
+            }
+
+            if app.screen == AppScreen::Library {
+                let filtering = {
+                    let lib = app.library_mut();
+                    let clearing = key.code == KeyCode::Esc && !lib.pane_filter().is_empty();
+                    lib.typing_filter || clearing
+                };
+                if filtering {
+                    handle_filter_key(&mut app, key.code);
+                    continue;
                 }
             }
+
+            if let Some(action) = app.keymap.feed(key)
+                && handle_action(&mut app, action)
+            {
+                break;
+            }
         }
     }
 
@@ -203,3 +240,542 @@ fn main() -> Result<()> {
     execute!(stdout(), LeaveAlternateScreen)?;
     Ok(())
 }
+
+/// Runs a non-interactive subcommand instead of the TUI.
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Import { source, paths } => {
+            import::run(source, &paths).map_err(std::io::Error::other)
+        }
+        Command::NowPlaying { format, json } => {
+            std::process::exit(now_playing::run(format.as_deref(), json))
+        }
+        Command::History { format, output } => history::run(format, output.as_deref()),
+        Command::Completions { shell } => {
+            cli::print_completions(shell);
+            Ok(())
+        }
+        Command::Man => cli::print_man_page(),
+    }
+}
+
+/// Runs a bound action. Returns true when the app should quit.
+fn handle_action(app: &mut App, action: Action) -> bool {
+    if app.party_locked && action.is_restricted() {
+        app.show_toast("Locked in party mode");
+        return false;
+    }
+
+    match action {
+        Action::Quit => {
+            app.save_last_session();
+            app.remember_position();
+            app.clear_now_playing();
+            app.stop_recording();
+            if let Some(writer) = app.tag_writer.take() {
+                writer.finish();
+            }
+            return true;
+        }
+        Action::GotoLibrary => app.goto_screen(AppScreen::Library),
+        Action::GotoBrowser => app.goto_screen(AppScreen::Browser),
+        Action::GotoHistory => app.goto_screen(AppScreen::History),
+        Action::GotoRecent => app.goto_screen(AppScreen::Recent),
+        Action::GotoStats => app.goto_screen(AppScreen::Stats),
+        Action::GotoDuplicates => app.goto_screen(AppScreen::Duplicates),
+        Action::FocusArtists => {
+            app.goto_screen(AppScreen::Library);
+            app.library_mut().focus = LibraryFocus::Left;
+        }
+        Action::FocusTracks => {
+            app.goto_screen(AppScreen::Library);
+            app.library_mut().focus = LibraryFocus::Right;
+        }
+        Action::AddToLibrary => {
+            if app.screen == AppScreen::Browser
+                && let Some(BrowserItem::Entry(path)) = app.browser.list.selected_item()
+            {
+                let path = path.clone();
+                app.add_to_library(path);
+            }
+        }
+
+        Action::MoveDown => match app.screen {
+            AppScreen::Browser => app.browser.move_down(),
+            AppScreen::History => app.history.move_down(),
+            AppScreen::Recent => app.recent.move_down(),
+            AppScreen::Duplicates => app.duplicates.list.move_down(),
+            AppScreen::Stats => {}
+
+            AppScreen::Library => {
+                let mut lib = app.library_mut();
+
+                match lib.focus {
+                    LibraryFocus::Left => lib.move_down(),
+                    LibraryFocus::Right => {
+                        let count = lib.visible_tracks().len();
+                        lib.move_track_down(count);
+                    }
+                }
+            }
+        },
+
+        Action::MoveUp => match app.screen {
+            AppScreen::Browser => app.browser.move_up(),
+            AppScreen::History => app.history.move_up(),
+            AppScreen::Recent => app.recent.move_up(),
+            AppScreen::Duplicates => app.duplicates.list.move_up(),
+            AppScreen::Stats => {}
+
+            AppScreen::Library => {
+                let mut lib = app.library_mut();
+
+                match lib.focus {
+                    LibraryFocus::Left => lib.move_up(),
+                    LibraryFocus::Right => lib.move_track_up(),
+                }
+            }
+        },
+
+        Action::Activate => {
+            if app.screen == AppScreen::Browser {
+                // Files play on through the rest of their folder
+                match app.browser.selected_in_folder() {
+                    Some((files, index)) => app.play_all(files, index),
+                    None => app.browser.open_selected(),
+                }
+            }
+
+            if app.screen == AppScreen::Duplicates {
+                let selected = app.duplicates.selected_file().map(|f| f.track.path.clone());
+                if let Some(path) = selected {
+                    app.play_path(&path);
+                }
+            }
+
+            if app.screen == AppScreen::History {
+                let selected = app.history.selected_item().map(|entry| entry.path.clone());
+                if let Some(path) = selected {
+                    app.play_path(&path);
+                }
+            }
+
+            if app.screen == AppScreen::Recent {
+                let selected = app.recent.selected_item().map(|entry| entry.path.clone());
+                if let Some(path) = selected {
+                    app.play_path(&path);
+                }
+            }
+
+            let lib = app.library_mut();
+
+            if app.screen == AppScreen::Library && lib.focus == LibraryFocus::Right {
+                let selected = lib.visible_tracks().get(lib.track_index).cloned();
+                drop(lib);
+
+                if let Some(track) = selected {
+                    // Stop current playback and play selected track
+                    app.play_path(&track.path);
+                }
+            }
+        }
+
+        Action::ToggleAutoplay => app.toggle_autoplay(),
+        Action::VolumeUp => app.change_volume(app.config.volume_step as f32 / 100.0),
+        Action::VolumeDown => app.change_volume(-(app.config.volume_step as f32) / 100.0),
+        Action::TogglePause => app.toggle_pause(),
+        Action::NextTrack => app.play_next_track(),
+        Action::SeekForward => app.seek_by(5),
+        Action::SeekBackward => app.seek_by(-5),
+        Action::SeekForwardLong => app.seek_by(30),
+        Action::SeekBackwardLong => app.seek_by(-30),
+        Action::PlayRandom => app.play_random_track(),
+        Action::ToggleAutoDj => app.toggle_auto_dj(),
+        Action::ToggleParty => app.toggle_party(),
+        Action::SaveSession => app.prompt_save_session(),
+        Action::ToggleSessions => app.toggle_session_list(),
+        Action::CycleReplayGain => app.cycle_replay_gain(),
+        Action::WriteReplayGain => app.scan_replay_gain(),
+        Action::ToggleEqualizer => app.toggle_equalizer(),
+        Action::SpeedUp => app.change_speed(0.1),
+        Action::SpeedDown => app.change_speed(-0.1),
+        Action::MarkLoop => app.mark_loop(),
+        Action::CycleRepeat => app.cycle_repeat(),
+        Action::ToggleShuffle => app.toggle_shuffle(),
+        Action::ToggleMute => app.toggle_mute(),
+        Action::ToggleSettings => app.toggle_settings(),
+        Action::ToggleDiagnostics => app.toggle_diagnostics(),
+        Action::ToggleRecording => app.toggle_recording(),
+        Action::StopAfterCurrent => app.toggle_stop_after_current(),
+        Action::Enqueue => {
+            let selected: Vec<_> = match app.screen {
+                AppScreen::Recent => app
+                    .recent
+                    .selected_item()
+                    .map(|e| e.path.clone())
+                    .into_iter()
+                    .collect(),
+                AppScreen::History => app
+                    .history
+                    .selected_item()
+                    .map(|e| e.path.clone())
+                    .into_iter()
+                    .collect(),
+                AppScreen::Browser => app.browser.selected_audio_files(),
+                AppScreen::Library => {
+                    // The whole artist, album, genre or decade from the left pane
+                    let lib = app.library_mut();
+                    let tracks = lib.visible_tracks().into_iter().map(|t| t.path);
+                    match lib.focus {
+                        LibraryFocus::Left => tracks.collect(),
+                        LibraryFocus::Right => tracks.skip(lib.track_index).take(1).collect(),
+                    }
+                }
+                _ => Vec::new(),
+            };
+
+            for path in &selected {
+                app.enqueue(path);
+            }
+            match selected.len() {
+                0 => {}
+                1 => app.show_toast("Added to queue"),
+                n => app.show_toast(format!("Added {n} tracks to the queue")),
+            }
+        }
+        Action::Search => app.toggle_search(),
+        Action::CycleSort => {
+            if app.screen == AppScreen::Library {
+                app.cycle_sort();
+            }
+        }
+        Action::FilterPane => {
+            if app.screen == AppScreen::Library {
+                app.library_mut().start_typing();
+            }
+        }
+        Action::PlayAll => {
+            let (paths, index) = match app.screen {
+                AppScreen::Browser => app
+                    .browser
+                    .selected_in_folder()
+                    .unwrap_or_else(|| (app.browser.selected_audio_files(), 0)),
+                AppScreen::Library => {
+                    let lib = app.library_mut();
+                    let paths = lib.visible_tracks().into_iter().map(|t| t.path).collect();
+                    match lib.focus {
+                        LibraryFocus::Left => (paths, 0),
+                        LibraryFocus::Right => (paths, lib.track_index),
+                    }
+                }
+                _ => (Vec::new(), 0),
+            };
+            app.play_all(paths, index);
+        }
+
+        Action::GoUp => {
+            if app.screen == AppScreen::Browser {
+                app.browser.go_up();
+            }
+        }
+        Action::ToggleExpanded => {
+            if app.screen == AppScreen::Library {
+                app.library_mut().toggle_expanded();
+            }
+        }
+        Action::TabFocus => match app.screen {
+            AppScreen::Stats => app.stats_period = app.stats_period.next(),
+            _ => app.library_mut().tab_focus(),
+        },
+        Action::AddBookmark => app.prompt_bookmark(),
+        Action::ToggleBookmarks => app.toggle_bookmark_list(),
+        Action::NextChapter => app.next_chapter(),
+        Action::PreviousChapter => app.previous_chapter(),
+        Action::ToggleChapters => app.toggle_chapter_list(),
+        Action::PlayUrl => app.prompt_url(),
+        Action::ToggleStations => app.toggle_station_list(),
+        Action::RateUp => app.change_rating(1),
+        Action::RateDown => app.change_rating(-1),
+        Action::Rate1 => app.set_rating(Some(1)),
+        Action::Rate2 => app.set_rating(Some(2)),
+        Action::Rate3 => app.set_rating(Some(3)),
+        Action::Rate4 => app.set_rating(Some(4)),
+        Action::Rate5 => app.set_rating(Some(5)),
+        Action::ClearRating => app.set_rating(None),
+        Action::ToggleKaraoke => app.toggle_karaoke(),
+        Action::ToggleCrossfeed => app.toggle_crossfeed(),
+        Action::ToggleNightMode => app.toggle_night_mode(),
+        Action::ToggleSkipSilence => app.toggle_skip_silence(),
+        Action::RefreshLibrary => app.refresh_library(),
+        Action::FindMissing => app.toggle_missing_list(),
+        Action::CycleBrowse if app.screen == AppScreen::Stats => app.toggle_library_stats(),
+        Action::CycleBrowse => {
+            let browse = {
+                let mut lib = app.library_mut();
+                lib.cycle_browse();
+                lib.browse
+            };
+            app.goto_screen(AppScreen::Library);
+            app.show_toast(format!("Browsing by {}", browse.label()));
+        }
+        Action::TrackGainUp => app.change_track_gain(1.0),
+        Action::TrackGainDown => app.change_track_gain(-1.0),
+        Action::EditLabels => app.prompt_labels(),
+        Action::ToggleLabels => app.toggle_label_list(),
+        Action::ToggleSmartPlaylists => app.toggle_smart_list(),
+        Action::ToggleMark => {
+            let mut lib = app.library_mut();
+            if app.screen == AppScreen::Library && lib.focus == LibraryFocus::Right {
+                lib.toggle_mark();
+            }
+        }
+        Action::Retag => app.open_retag(),
+        Action::FetchCoverArt => app.fetch_cover_art(false),
+        Action::FetchAllCoverArt => app.fetch_cover_art(true),
+        Action::LookupAlbum => app.lookup_album(),
+        Action::TrashFile => {
+            if app.screen == AppScreen::Duplicates {
+                app.trash_selected_duplicate();
+            }
+        }
+        Action::DeleteFile => {
+            if app.screen == AppScreen::Duplicates {
+                app.prompt_delete_selected_duplicate();
+            }
+        }
+        Action::KeepDuplicate => {
+            if app.screen == AppScreen::Duplicates {
+                app.keep_selected_duplicate();
+            }
+        }
+        Action::FindSoundDuplicates => app.find_sound_duplicates(),
+    }
+
+    false
+}
+
+fn handle_prompt_key(app: &mut App, code: KeyCode) {
+    let Some(prompt) = app.prompt.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Enter => app.submit_prompt(),
+        KeyCode::Esc => app.prompt = None,
+        KeyCode::Backspace => prompt.pop(),
+        KeyCode::Char(c) => prompt.push(c),
+        _ => {}
+    }
+}
+
+fn handle_bookmark_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.bookmark_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Enter => app.jump_to_selected_bookmark(),
+        KeyCode::Char('d') if !app.party_locked => app.delete_selected_bookmark(),
+        KeyCode::Esc | KeyCode::Char('B') => app.bookmark_list = None,
+        _ => {}
+    }
+}
+
+fn handle_chapter_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.chapter_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Enter => app.jump_to_selected_chapter(),
+        KeyCode::Esc | KeyCode::Char('C') => app.chapter_list = None,
+        _ => {}
+    }
+}
+
+fn handle_label_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.label_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Enter => app.apply_selected_label(),
+        KeyCode::Esc => app.label_list = None,
+        _ => {}
+    }
+}
+
+fn handle_equalizer_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Left => app.select_eq_band(-1),
+        KeyCode::Right => app.select_eq_band(1),
+        KeyCode::Up | KeyCode::Char('+') => app.adjust_eq_band(1.0),
+        KeyCode::Down | KeyCode::Char('-') => app.adjust_eq_band(-1.0),
+        KeyCode::Char('p') => app.cycle_eq_preset(),
+        KeyCode::Esc => app.equalizer_band = None,
+        _ => {}
+    }
+}
+
+fn handle_settings_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up => app.select_setting(-1),
+        KeyCode::Down => app.select_setting(1),
+        KeyCode::Left | KeyCode::Char('-') => app.adjust_setting(-1.0),
+        KeyCode::Right | KeyCode::Char('+') => app.adjust_setting(1.0),
+        KeyCode::Esc => app.settings_row = None,
+        _ => {}
+    }
+}
+
+fn handle_station_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.station_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Enter => app.play_selected_station(),
+        KeyCode::Char('a') if !app.party_locked => app.save_current_station(),
+        KeyCode::Char('d') if !app.party_locked => app.delete_selected_station(),
+        KeyCode::Esc | KeyCode::Char('O') => app.station_list = None,
+        _ => {}
+    }
+}
+
+fn handle_album_lookup_key(app: &mut App, code: KeyCode) {
+    let Some(lookup) = app.album_lookup.as_mut() else {
+        return;
+    };
+
+    match (&mut lookup.step, code) {
+        (_, KeyCode::Esc) => app.album_lookup = None,
+        (LookupStep::Candidates(list), KeyCode::Up) => list.move_up(),
+        (LookupStep::Candidates(list), KeyCode::Down) => list.move_down(),
+        (LookupStep::Candidates(_), KeyCode::Enter) => lookup.choose(),
+        (LookupStep::Preview(list), KeyCode::Up) => list.move_up(),
+        (LookupStep::Preview(list), KeyCode::Down) => list.move_down(),
+        (LookupStep::Preview(_), KeyCode::Enter) if !app.party_locked => app.apply_album_lookup(),
+        _ => {}
+    }
+}
+
+fn handle_retag_menu_key(app: &mut App, code: KeyCode) {
+    let Some(menu) = app.retag_menu.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => menu.fields.move_up(),
+        KeyCode::Down => menu.fields.move_down(),
+        KeyCode::Enter => app.choose_retag_field(),
+        KeyCode::Esc => app.retag_menu = None,
+        _ => {}
+    }
+}
+
+fn handle_retag_preview_key(app: &mut App, code: KeyCode) {
+    let Some(preview) = app.retag_preview.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => preview.changes.move_up(),
+        KeyCode::Down => preview.changes.move_down(),
+        KeyCode::Enter if !app.party_locked => app.apply_retag(),
+        KeyCode::Esc => app.retag_preview = None,
+        _ => {}
+    }
+}
+
+fn handle_smart_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.smart_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Enter => app.apply_selected_smart_playlist(),
+        KeyCode::Char('p') => app.play_selected_smart_playlist(),
+        KeyCode::Char('a') if !app.party_locked => app.prompt_smart_playlist(),
+        KeyCode::Char('d') if !app.party_locked => app.delete_selected_smart_playlist(),
+        KeyCode::Esc => app.smart_list = None,
+        _ => {}
+    }
+}
+
+fn handle_missing_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.missing_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Char('d') if !app.party_locked => app.remove_selected_missing(),
+        KeyCode::Char('D') if !app.party_locked => app.remove_all_missing(),
+        KeyCode::Esc => app.missing_list = None,
+        _ => {}
+    }
+}
+
+fn handle_search_key(app: &mut App, code: KeyCode) {
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => search.results.move_up(),
+        KeyCode::Down => search.results.move_down(),
+        KeyCode::Enter => app.play_search_result(),
+        KeyCode::Tab => app.enqueue_search_result(),
+        KeyCode::Backspace => app.erase_search(),
+        KeyCode::Char(c) => app.type_search(c),
+        KeyCode::Esc => app.search = None,
+        _ => {}
+    }
+}
+
+/// Keys while typing into a library pane's filter: the list narrows as
+/// you type and can still be moved through.
+fn handle_filter_key(app: &mut App, code: KeyCode) {
+    let mut lib = app.library_mut();
+
+    match (code, &lib.focus) {
+        (KeyCode::Up, LibraryFocus::Left) => lib.move_up(),
+        (KeyCode::Down, LibraryFocus::Left) => lib.move_down(),
+        (KeyCode::Up, LibraryFocus::Right) => lib.move_track_up(),
+        (KeyCode::Down, LibraryFocus::Right) => {
+            let count = lib.visible_tracks().len();
+            lib.move_track_down(count);
+        }
+        (KeyCode::Enter, _) => lib.stop_typing(),
+        (KeyCode::Esc, _) => lib.clear_filter(),
+        (KeyCode::Backspace, _) => lib.pop_filter(),
+        (KeyCode::Char(c), _) => lib.push_filter(c),
+        _ => {}
+    }
+}
+
+fn handle_session_list_key(app: &mut App, code: KeyCode) {
+    let Some(list) = app.session_list.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Up => list.move_up(),
+        KeyCode::Down => list.move_down(),
+        KeyCode::Enter if !app.party_locked => app.restore_selected_session(),
+        KeyCode::Char('d') if !app.party_locked => app.delete_selected_session(),
+        KeyCode::Esc => app.session_list = None,
+        _ => {}
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::Read;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+const API_URL: &str = "https://musicbrainz.org/ws/2";
+const COVER_ART_URL: &str = "https://coverartarchive.org";
+
+/// MusicBrainz turns away clients that don't say who they are.
+const USER_AGENT: &str = concat!(
+    "shelltrax/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/ducks/shelltrax )"
+);
+
+/// MusicBrainz allows one request a second; callers wait this long between
+/// lookups.
+pub const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Releases a search by name returns.
+const SEARCH_LIMIT: usize = 5;
+
+/// Cover images larger than this are not album art.
+const MAX_IMAGE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// A release found by a search, best match first.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub id: String,
+    pub title: String,
+    /// How well the release matched the search, out of 100
+    #[serde(default)]
+    pub score: u32,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`
+    pub date: Option<String>,
+    pub country: Option<String>,
+    #[serde(rename = "track-count", default)]
+    pub track_count: usize,
+}
+
+impl Release {
+    pub fn artist(&self) -> String {
+        credit(&self.artist_credit)
+    }
+
+    pub fn year(&self) -> Option<u32> {
+        year(self.date.as_deref())
+    }
+}
+
+/// A release with its tracks, as looked up by id.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleaseDetail {
+    pub title: String,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    pub date: Option<String>,
+    #[serde(default)]
+    pub media: Vec<Medium>,
+}
+
+impl ReleaseDetail {
+    pub fn artist(&self) -> String {
+        credit(&self.artist_credit)
+    }
+
+    pub fn year(&self) -> Option<u32> {
+        year(self.date.as_deref())
+    }
+}
+
+/// One disc of a release.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Medium {
+    pub position: u32,
+    #[serde(default)]
+    pub tracks: Vec<ReleaseTrack>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleaseTrack {
+    pub position: u32,
+    pub title: String,
+    /// In milliseconds
+    pub length: Option<u64>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+}
+
+impl ReleaseTrack {
+    pub fn artist(&self) -> String {
+        credit(&self.artist_credit)
+    }
+}
+
+/// One of the artists a release or track is credited to, with what joins
+/// it to the next, like `" & "`.
+#[derive(Debug, Clone, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Deserialize)]
+struct ReleaseSearch {
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build()
+}
+
+/// Looks up releases named `album` by `artist`.
+pub fn search_releases(artist: &str, album: &str) -> anyhow::Result<Vec<Release>> {
+    let query = format!("release:{} AND artist:{}", phrase(album), phrase(artist));
+    search(&query, SEARCH_LIMIT)
+}
+
+/// Runs a release search such as `release:"x" AND tracks:12`, returning up
+/// to `limit` of the best matches.
+pub fn search(query: &str, limit: usize) -> anyhow::Result<Vec<Release>> {
+    let body = agent()
+        .get(&format!("{API_URL}/release"))
+        .query("query", query)
+        .query("fmt", "json")
+        .query("limit", &limit.to_string())
+        .call()?
+        .into_string()?;
+
+    let search: ReleaseSearch = serde_json::from_str(&body)?;
+    Ok(search.releases)
+}
+
+/// The release with its discs and tracks.
+pub fn release(release_id: &str) -> anyhow::Result<ReleaseDetail> {
+    let body = agent()
+        .get(&format!("{API_URL}/release/{release_id}"))
+        .query("inc", "recordings artist-credits")
+        .query("fmt", "json")
+        .call()?
+        .into_string()?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// The release's front cover from the Cover Art Archive, or `None` when it
+/// has none.
+pub fn front_cover(release_id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let url = format!("{COVER_ART_URL}/release/{release_id}/front-500");
+    let response = match agent().get(&url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut image = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_IMAGE_BYTES)
+        .read_to_end(&mut image)?;
+    Ok(Some(image))
+}
+
+fn credit(credits: &[ArtistCredit]) -> String {
+    credits
+        .iter()
+        .map(|credit| format!("{}{}", credit.name, credit.joinphrase))
+        .collect()
+}
+
+fn year(date: Option<&str>) -> Option<u32> {
+    date?.get(..4)?.parse().ok()
+}
+
+/// Quotes `text` as one search term, escaping what the search syntax would
+/// otherwise read as its own.
+pub fn phrase(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::persistence;
+
+/// Published snapshots older than this are treated as a dead instance.
+const STALE_AFTER_SECS: u64 = 5;
+
+pub const DEFAULT_FORMAT: &str = "%a - %t [%p/%d]";
+
+/// Snapshot of the current track, written by the running player for
+/// `shelltrax now-playing` and other scripts to read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NowPlaying {
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    /// Seconds into the track
+    pub position: u64,
+    /// Track length in seconds, 0 if unknown
+    pub duration: u64,
+    pub paused: bool,
+    pub volume: u32,
+    /// Unix time of the snapshot
+    pub updated_at: u64,
+    /// Cached album art thumbnail, if the album has one
+    #[serde(default)]
+    pub art: Option<PathBuf>,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Writes the snapshot through a copy that's swapped in, so readers never
+/// see half of it, or removes it when nothing is playing.
+pub fn publish(now_playing: Option<&NowPlaying>) -> std::io::Result<()> {
+    let path = persistence::now_playing_file();
+    match now_playing {
+        Some(now_playing) => {
+            let data = serde_json::to_string_pretty(now_playing)?;
+            persistence::write_data_file(&path, data.as_bytes())
+        }
+        None if path.exists() => fs::remove_file(path),
+        None => Ok(()),
+    }
+}
+
+/// Reads the published snapshot, ignoring it if the player stopped updating it.
+pub fn read() -> Option<NowPlaying> {
+    let data = fs::read_to_string(persistence::now_playing_file()).ok()?;
+    let now_playing: NowPlaying = serde_json::from_str(&data).ok()?;
+
+    (unix_now().saturating_sub(now_playing.updated_at) <= STALE_AFTER_SECS).then_some(now_playing)
+}
+
+/// `h:mm:ss`, or `mm:ss` under an hour.
+pub fn format_time(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Expands a template:
+///
+/// `%t` title, `%a` artist, `%A` album artist, `%b` album, `%f` file path,
+/// `%p` position, `%d` duration, `%s` playing/paused, `%v` volume, `%c` album
+/// art, `%%` a `%`.
+pub fn format(now_playing: &NowPlaying, template: &str) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => out.push_str(&now_playing.title),
+            Some('a') => out.push_str(&now_playing.artist),
+            Some('A') => out.push_str(&now_playing.album_artist),
+            Some('b') => out.push_str(&now_playing.album),
+            Some('f') => out.push_str(&now_playing.path.to_string_lossy()),
+            Some('p') => out.push_str(&format_time(now_playing.position)),
+            Some('d') => out.push_str(&format_time(now_playing.duration)),
+            Some('s') => out.push_str(if now_playing.paused {
+                "paused"
+            } else {
+                "playing"
+            }),
+            Some('v') => out.push_str(&now_playing.volume.to_string()),
+            Some('c') => {
+                if let Some(art) = &now_playing.art {
+                    out.push_str(&art.to_string_lossy());
+                }
+            }
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+
+    out
+}
+
+/// Entry point for `shelltrax now-playing`. Returns the process exit code.
+pub fn run(template: Option<&str>, json: bool) -> i32 {
+    let Some(now_playing) = read() else {
+        return 1;
+    };
+
+    if json {
+        match serde_json::to_string(&now_playing) {
+            Ok(data) => println!("{data}"),
+            Err(err) => {
+                eprintln!("{err}");
+                return 2;
+            }
+        }
+    } else {
+        println!(
+            "{}",
+            format(&now_playing, template.unwrap_or(DEFAULT_FORMAT))
+        );
+    }
+
+    0
+}
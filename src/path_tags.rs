@@ -0,0 +1,133 @@
+use std::path::Path;
+
+/// Names read from where a file is and what it's called, for files without
+/// tags.
+#[derive(Debug, Default)]
+pub struct PathTags {
+    pub title: String,
+    /// From the file name, as in `Artist - Title.mp3`
+    pub artist: Option<String>,
+    /// From the folders, as in `Artist - Album/` or `Artist/Album/`
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+}
+
+/// Guesses a file's names from paths laid out like
+/// `Artist - Album/01 - Title.mp3` or `Artist/Album (1999)/CD2/03. Title.flac`.
+/// Numbered files are taken to be in an album folder, named after it.
+pub fn guess(path: &Path) -> Option<PathTags> {
+    let stem = readable(path.file_stem()?.to_str()?);
+    let (track_number, name) = split_number(&stem);
+    let (artist, title) = match name.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+        None => (None, name.trim().to_string()),
+    };
+    // A loose `Artist - Title.mp3` is a single, whatever folder it's in
+    if track_number.is_none() && artist.is_some() {
+        return Some(PathTags {
+            title,
+            album_artist: artist.clone(),
+            artist,
+            ..PathTags::default()
+        });
+    }
+
+    let mut folders = path
+        .ancestors()
+        .skip(1)
+        .filter_map(|dir| dir.file_name()?.to_str());
+    let mut album_folder = folders.next();
+    let disc_number = album_folder.and_then(disc_folder);
+    if disc_number.is_some() {
+        album_folder = folders.next();
+    }
+
+    let (mut year, mut album_artist, mut album) = (None, None, None);
+    if let Some(folder) = album_folder.map(readable) {
+        // The year goes first, so `1999 - Album` isn't taken for an artist
+        let (leading_year, folder) = split_year(&folder);
+        let (artist, name) = match folder.split_once(" - ") {
+            Some((artist, name)) => (Some(artist.trim().to_string()), name.to_string()),
+            None => (folders.next().map(readable), folder.clone()),
+        };
+        let (trailing_year, name) = split_year(&name);
+        year = leading_year.or(trailing_year);
+        album_artist = artist;
+        album = Some(name);
+    }
+
+    Some(PathTags {
+        title,
+        artist,
+        album_artist,
+        album: album.filter(|album| !album.is_empty()),
+        track_number,
+        disc_number,
+        year,
+    })
+}
+
+/// A name with underscores for spaces, as in `01_some_title`, spelled out.
+fn readable(name: &str) -> String {
+    match name.contains(' ') {
+        true => name.to_string(),
+        false => name.replace('_', " "),
+    }
+}
+
+/// A leading track number such as `01 - `, `1. ` or `07 ` and what follows.
+fn split_number(name: &str) -> (Option<u32>, &str) {
+    let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    // Longer runs of digits are more likely a year or part of the title
+    if digits == 0 || digits > 3 {
+        return (None, name);
+    }
+
+    let rest = name[digits..].trim_start_matches([' ', '.', '-', '_', ')']);
+    if rest.is_empty() || rest.len() == name.len() - digits {
+        return (None, name);
+    }
+    (name[..digits].parse().ok(), rest)
+}
+
+/// The disc number of a folder named like `CD1`, `Disc 2` or `disk_3`.
+fn disc_folder(name: &str) -> Option<u32> {
+    let lower = name.to_ascii_lowercase();
+    let number = ["disc", "disk", "cd"]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix))?
+        .trim_start_matches([' ', '_', '-']);
+    number.parse().ok()
+}
+
+/// A year in an album folder's name, as in `1999 - Album`, `Album (1999)` or
+/// `[1999] Album`, and the name without it.
+fn split_year(album: &str) -> (Option<u32>, String) {
+    let year = |text: &str| {
+        let text = text.trim().trim_matches(['(', ')', '[', ']']);
+        Some(text)
+            .filter(|text| text.len() == 4)?
+            .parse::<u32>()
+            .ok()
+    };
+    let bracketed = |text: &str| year(text).filter(|_| text.starts_with(['(', '[']));
+
+    let found = album
+        .split_once(" - ")
+        .and_then(|(before, rest)| Some((year(before)?, rest)))
+        .or_else(|| {
+            let (rest, last) = album.rsplit_once(' ')?;
+            Some((bracketed(last)?, rest))
+        })
+        .or_else(|| {
+            let (first, rest) = album.split_once(' ')?;
+            Some((bracketed(first)?, rest))
+        });
+    match found {
+        Some((year, rest)) => (Some(year), rest.trim().to_string()),
+        None => (None, album.trim().to_string()),
+    }
+}
@@ -1,20 +1,269 @@
+use crate::app::RepeatMode;
+use crate::fingerprint::Fingerprints;
+use crate::history::HistoryEntry;
 use crate::library::ArtistNode;
-use std::{fs, path::Path};
+use crate::player::dsp::EQ_BANDS;
+use crate::query::SmartPlaylist;
+use crate::radio::Station;
+use crate::replaygain::ReplayGainMode;
+use crate::session::Session;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-const SAVE_PATH: &str = "library.json"; // or "library.ron"
+/// The scanned library, kept in the data directory
+const LIBRARY_FILE: &str = "library.json";
+/// Folders added to the library, kept next to it
+const ROOTS_FILE: &str = "library_roots.json";
+/// Audio fingerprints from the last duplicate search, kept next to it
+const FINGERPRINTS_FILE: &str = "fingerprints.json";
+/// What's playing right now, rewritten every second while the player runs
+const NOW_PLAYING_FILE: &str = "now_playing.json";
+/// Where older versions kept the library, read when there's no cache yet
+const LEGACY_LIBRARY_PATH: &str = "library.json";
 
+// Also kept in the data directory. Older versions wrote these to the working
+// directory, where they're still read from until first saved again
+const POSITIONS_FILE: &str = "positions.json";
+const STATE_FILE: &str = "state.json";
+const HISTORY_FILE: &str = "history.json";
+const SESSIONS_FILE: &str = "sessions.json";
+const LAST_SESSION_FILE: &str = "last_session.json";
+const STATIONS_FILE: &str = "stations.json";
+const SMART_PLAYLISTS_FILE: &str = "smart_playlists.json";
+
+/// Playback settings that survive restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaybackState {
+    pub volume: f32,
+    pub autoplay: bool,
+
+    /// Last volume used on each output device, by device name
+    pub device_volumes: HashMap<String, f32>,
+
+    pub crossfeed: bool,
+
+    pub auto_dj: bool,
+
+    pub repeat: RepeatMode,
+
+    pub shuffle: bool,
+
+    pub replay_gain: ReplayGainMode,
+
+    /// Equalizer band gains in dB
+    pub eq_gains: [f32; EQ_BANDS.len()],
+
+    /// Playback speed, 1.0 being normal
+    pub speed: f32,
+}
+
+impl Default for PlaybackState {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            autoplay: true,
+            device_volumes: HashMap::new(),
+            crossfeed: false,
+            auto_dj: false,
+            repeat: RepeatMode::Off,
+            shuffle: false,
+            replay_gain: ReplayGainMode::Off,
+            eq_gains: [0.0; EQ_BANDS.len()],
+            speed: 1.0,
+        }
+    }
+}
+
+/// Where the library cache and saved state go: `$XDG_DATA_HOME/shelltrax`,
+/// or `~/.local/share/shelltrax`.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            let home = std::env::var_os("HOME")?;
+            Some(PathBuf::from(home).join(".local").join("share"))
+        })?;
+    Some(base.join("shelltrax"))
+}
+
+/// A file in the data directory, or in the working directory when there's
+/// no home.
+fn data_file(name: &str) -> PathBuf {
+    match data_dir() {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// The snapshot of what's playing, kept in the data directory so
+/// `shelltrax now-playing` finds it from anywhere.
+pub fn now_playing_file() -> PathBuf {
+    data_file(NOW_PLAYING_FILE)
+}
+
+/// Album art thumbnails, kept in the data directory.
+pub fn artwork_dir() -> PathBuf {
+    data_file("artwork")
+}
+
+/// Writes `data` to `path` through a copy that's swapped in, so a crash
+/// mid-write can't lose what was there.
+pub fn write_data_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, data)?;
+    fs::rename(partial, path)
+}
+
+/// Caches the scanned library, tags and file stamps included, so startup
+/// doesn't have to rescan. Written compactly since it can get big.
 pub fn save_library(artists: &[ArtistNode]) -> std::io::Result<()> {
-    let data = serde_json::to_string_pretty(artists)?;
-    fs::write(SAVE_PATH, data)?;
-    Ok(())
+    let data = serde_json::to_vec(artists)?;
+    write_data_file(&data_file(LIBRARY_FILE), &data)
 }
 
+/// The cached library, falling back to a `library.json` in the working
+/// directory from before the cache moved.
 pub fn load_library() -> std::io::Result<Vec<ArtistNode>> {
-    if Path::new(SAVE_PATH).exists() {
-        let data = fs::read_to_string(SAVE_PATH)?;
-        let artists = serde_json::from_str(&data)?;
-        Ok(artists)
+    let path = [data_file(LIBRARY_FILE), PathBuf::from(LEGACY_LIBRARY_PATH)]
+        .into_iter()
+        .find(|path| path.exists());
+
+    match path {
+        Some(path) => {
+            let data = fs::read_to_string(&path)?;
+            let artists = serde_json::from_str(&data)?;
+            log::info!("Loaded library from {}", path.display());
+            Ok(artists)
+        }
+        None => Ok(vec![]), // start empty if no file
+    }
+}
+
+/// Caches audio fingerprints so only new and changed files are decoded the
+/// next time. Written compactly like the library.
+pub fn save_fingerprints(fingerprints: &Fingerprints) -> std::io::Result<()> {
+    let data = serde_json::to_vec(fingerprints)?;
+    write_data_file(&data_file(FINGERPRINTS_FILE), &data)
+}
+
+pub fn load_fingerprints() -> std::io::Result<Fingerprints> {
+    let path = data_file(FINGERPRINTS_FILE);
+    if path.exists() {
+        let data = fs::read_to_string(path)?;
+        let fingerprints = serde_json::from_str(&data)?;
+        Ok(fingerprints)
     } else {
-        Ok(vec![]) // start empty if no file
+        Ok(Fingerprints::new())
     }
 }
+
+pub fn save_library_roots(roots: &[PathBuf]) -> std::io::Result<()> {
+    let data = serde_json::to_vec_pretty(roots)?;
+    write_data_file(&data_file(ROOTS_FILE), &data)
+}
+
+pub fn load_library_roots() -> std::io::Result<Vec<PathBuf>> {
+    let path = data_file(ROOTS_FILE);
+    if path.exists() {
+        let data = fs::read_to_string(path)?;
+        let roots = serde_json::from_str(&data)?;
+        Ok(roots)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Writes `value` as readable JSON to the data file `name`.
+fn save_json(name: &str, value: &impl Serialize) -> std::io::Result<()> {
+    let data = serde_json::to_vec_pretty(value)?;
+    write_data_file(&data_file(name), &data)
+}
+
+/// Reads the data file `name`, or the one older versions left in the
+/// working directory. `None` if neither exists.
+fn load_json<T: DeserializeOwned>(name: &str) -> std::io::Result<Option<T>> {
+    let path = [data_file(name), PathBuf::from(name)]
+        .into_iter()
+        .find(|path| path.exists());
+
+    match path {
+        Some(path) => {
+            let data = fs::read_to_string(path)?;
+            Ok(Some(serde_json::from_str(&data)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Saved playback positions in seconds, keyed by track path.
+pub fn save_positions(positions: &HashMap<PathBuf, u64>) -> std::io::Result<()> {
+    save_json(POSITIONS_FILE, positions)
+}
+
+pub fn load_positions() -> std::io::Result<HashMap<PathBuf, u64>> {
+    Ok(load_json(POSITIONS_FILE)?.unwrap_or_default())
+}
+
+pub fn save_state(state: &PlaybackState) -> std::io::Result<()> {
+    save_json(STATE_FILE, state)
+}
+
+pub fn load_state() -> std::io::Result<PlaybackState> {
+    Ok(load_json(STATE_FILE)?.unwrap_or_default())
+}
+
+/// Completed plays, newest first.
+pub fn save_history(entries: &[HistoryEntry]) -> std::io::Result<()> {
+    save_json(HISTORY_FILE, &entries)
+}
+
+pub fn load_history() -> std::io::Result<Vec<HistoryEntry>> {
+    Ok(load_json(HISTORY_FILE)?.unwrap_or_default())
+}
+
+/// Named session snapshots, sorted by name.
+pub fn save_sessions(sessions: &[Session]) -> std::io::Result<()> {
+    save_json(SESSIONS_FILE, &sessions)
+}
+
+/// What was playing when the app was last quit.
+pub fn save_last_session(session: &Session) -> std::io::Result<()> {
+    save_json(LAST_SESSION_FILE, session)
+}
+
+pub fn load_last_session() -> std::io::Result<Option<Session>> {
+    load_json(LAST_SESSION_FILE)
+}
+
+pub fn load_sessions() -> std::io::Result<Vec<Session>> {
+    Ok(load_json(SESSIONS_FILE)?.unwrap_or_default())
+}
+
+/// Saved radio stations, in the order they were added.
+pub fn save_stations(stations: &[Station]) -> std::io::Result<()> {
+    save_json(STATIONS_FILE, &stations)
+}
+
+pub fn load_stations() -> std::io::Result<Vec<Station>> {
+    Ok(load_json(STATIONS_FILE)?.unwrap_or_default())
+}
+
+/// Saved smart playlists, in the order they were added.
+pub fn save_smart_playlists(playlists: &[SmartPlaylist]) -> std::io::Result<()> {
+    save_json(SMART_PLAYLISTS_FILE, &playlists)
+}
+
+pub fn load_smart_playlists() -> std::io::Result<Vec<SmartPlaylist>> {
+    Ok(load_json(SMART_PLAYLISTS_FILE)?.unwrap_or_default())
+}
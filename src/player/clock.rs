@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Tells which position in the file the audio callback has played up to.
+///
+/// The decode thread runs ahead of playback, so each jump it makes (a seek,
+/// an A-B loop or the next track) is marked with the sample count where it
+/// lands in the buffer. Positions are counted on from the latest mark that
+/// playback has reached.
+#[derive(Clone, Default)]
+pub struct PlaybackClock {
+    /// Sample count and file position at each jump, oldest first
+    marks: Arc<Mutex<VecDeque<(u64, Duration)>>>,
+    /// Playback speed as `f32` bits, shared with the player
+    speed: Arc<AtomicU32>,
+    /// Samples per second of audio at normal speed, over all channels
+    rate: u32,
+}
+
+impl PlaybackClock {
+    pub fn new(speed: Arc<AtomicU32>, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            marks: Arc::default(),
+            speed,
+            rate: sample_rate * channels as u32,
+        }
+    }
+
+    /// Notes that the sample at `sample` plays `position` of the file.
+    pub fn mark(&self, sample: u64, position: Duration) {
+        self.marks.lock().unwrap().push_back((sample, position));
+    }
+
+    /// Like [`PlaybackClock::mark`], dropping marks for audio that was thrown
+    /// away, e.g. after a seek.
+    pub fn restart(&self, sample: u64, position: Duration) {
+        let mut marks = self.marks.lock().unwrap();
+        marks.clear();
+        marks.push_back((sample, position));
+    }
+
+    /// File position after `played` samples.
+    pub fn position(&self, played: u64) -> Duration {
+        let mut marks = self.marks.lock().unwrap();
+        while marks.get(1).is_some_and(|(sample, _)| *sample <= played) {
+            marks.pop_front();
+        }
+
+        let Some(&(sample, position)) = marks.front() else {
+            return Duration::ZERO;
+        };
+
+        // Speed changes always seek, so it holds since the last mark
+        let speed = f32::from_bits(self.speed.load(Ordering::Relaxed)) as f64;
+        let samples = played.saturating_sub(sample) as f64;
+        position + Duration::from_secs_f64(samples * speed / self.rate.max(1) as f64)
+    }
+}
@@ -1,35 +1,784 @@
+use std::fmt;
 use std::fs::File;
-use std::path::Path;
-use symphonia::core::codecs::{CODEC_TYPE_NULL, Decoder, DecoderOptions};
-use symphonia::core::formats::{FormatOptions, FormatReader, Track};
-use symphonia::core::io::MediaSourceStream;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use symphonia::core::audio::Channels;
+use symphonia::core::codecs::{
+    CODEC_TYPE_AC4, CODEC_TYPE_DCA, CODEC_TYPE_EAC3, CODEC_TYPE_MONKEYS_AUDIO, CODEC_TYPE_MUSEPACK,
+    CODEC_TYPE_NULL, CODEC_TYPE_OPUS, CODEC_TYPE_SPEEX, CODEC_TYPE_TTA, CODEC_TYPE_WAVPACK,
+    CODEC_TYPE_WMA, CodecType, Decoder, DecoderOptions,
+};
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
 use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+use symphonia::default::formats::MpaReader;
 use symphonia::default::{get_codecs, get_probe};
 
-pub fn open_decoder(
-    path: &Path,
-) -> anyhow::Result<(Box<dyn FormatReader>, Box<dyn Decoder>, Track)> {
-    let file = File::open(path)?;
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+use super::seek_index::SeekIndex;
+use super::stream::{self, SharedIcy};
+
+/// Packets read to estimate the length of a file that doesn't state it.
+const ESTIMATE_PACKETS: usize = 200;
+
+/// The audio of a file is in a codec there's no decoder for, such as Opus.
+#[derive(Debug)]
+pub struct UnsupportedCodec(pub String);
+
+impl fmt::Display for UnsupportedCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} audio isn't supported", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedCodec {}
+
+/// A file opened for decoding, positioned at the start of the range to play.
+pub struct Source {
+    pub path: PathBuf,
+    pub format: Box<dyn FormatReader>,
+    pub decoder: Box<dyn Decoder>,
+    pub track_id: u32,
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub layout: Channels,
+    pub time_base: Option<TimeBase>,
+    /// Where in the file the range starts
+    pub start: Duration,
+    /// Frame to stop at, when only part of the file plays
+    pub end_frame: Option<u64>,
+    /// Length of the whole file, from the container or else estimated
+    pub duration: Option<Duration>,
+    /// Bit depth of the stored samples, for lossless formats
+    pub bits_per_sample: Option<u32>,
+    /// Frame positions of an MP3 that doesn't state its length
+    seek_index: Option<Arc<SeekIndex>>,
+    /// Frames before the start of `format`, when it was opened mid-file
+    frame_offset: u64,
+    /// Decoded audio before this frame is dropped, so a seek lands exactly
+    pub skip_to: Option<u64>,
+    /// Live titles of a radio stream
+    pub icy: Option<SharedIcy>,
+}
+
+impl Source {
+    pub fn open(path: &Path, start: Duration, end: Option<Duration>) -> anyhow::Result<Self> {
+        let (mut format, icy) = probe(path)?;
+
+        // The first audio track there's a decoder for, or else the first
+        // audio track, to say what it's in
+        let track = format
+            .tracks()
+            .iter()
+            .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .min_by_key(|t| get_codecs().get_codec(t.codec_params.codec).is_none())
+            .ok_or_else(|| anyhow::anyhow!("No supported audio track found"))?
+            .clone();
+        if get_codecs().get_codec(track.codec_params.codec).is_none() {
+            return Err(UnsupportedCodec(codec_name(track.codec_params.codec)).into());
+        }
+
+        let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+        let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+        let layout = match track.codec_params.channels {
+            Some(layout) => Some(layout),
+            None => decoded_layout(format.as_mut(), decoder.as_mut(), track.id),
+        };
+        let layout = layout.ok_or_else(|| anyhow::anyhow!("Unknown channel layout"))?;
+        let time_base = track.codec_params.time_base;
+        // The index, once built, has the exact length where the container
+        // only guesses
+        let seek_index = match stream::is_url(path) {
+            true => None,
+            false => SeekIndex::for_file(path),
+        };
+        let frames = seek_index.as_ref().map(|index| index.frames);
+        let duration = match frames.or(track.codec_params.n_frames) {
+            Some(frames) => Some(to_duration(time_base, sample_rate, frames)),
+            None => estimate_duration(path, track.id, time_base, sample_rate),
+        };
+
+        let mut source = Self {
+            path: path.to_path_buf(),
+            format,
+            decoder,
+            track_id: track.id,
+            sample_rate,
+            channels: layout.count(),
+            layout,
+            time_base,
+            start,
+            end_frame: None,
+            duration,
+            bits_per_sample: track.codec_params.bits_per_sample,
+            seek_index,
+            frame_offset: 0,
+            skip_to: None,
+            icy,
+        };
+        source.end_frame = end.map(|end| source.frame_at(end));
+
+        let seeked = (!start.is_zero()).then(|| source.seek(start));
+        if let Some(Err(err)) = seeked {
+            log::warn!("Failed to seek to {:?}: {err}", start);
+        }
+
+        Ok(source)
+    }
+
+    /// Moves to `position`. Audio decoded before it is left in `skip_to`
+    /// to be dropped, so playback starts there to the sample.
+    pub fn seek(&mut self, position: Duration) -> anyhow::Result<()> {
+        let frame = self.frame_at(position);
+        // The index may have been built since the file was opened
+        if self.seek_index.is_none() && !stream::is_url(&self.path) {
+            self.seek_index = SeekIndex::for_file(&self.path);
+        }
+
+        match &self.seek_index {
+            // Start reading at the indexed frame, as if the file began there
+            Some(index) => {
+                let (start, byte) = index.before(frame);
+                let mut file = File::open(&self.path)?;
+                file.seek(SeekFrom::Start(byte))?;
+                let mss =
+                    MediaSourceStream::new(Box::new(ReadOnlySource::new(file)), Default::default());
+
+                self.format = Box::new(MpaReader::try_new(mss, &FormatOptions::default())?);
+                self.track_id = self.format.default_track().map_or(0, |track| track.id);
+                self.frame_offset = start;
+            }
+            None => {
+                let seek_to = SeekTo::Time {
+                    time: Time::from(position.as_secs_f64()),
+                    track_id: Some(self.track_id),
+                };
+                self.format.seek(SeekMode::Accurate, seek_to)?;
+            }
+        }
+
+        self.decoder.reset();
+        self.skip_to = Some(frame);
+        Ok(())
+    }
+
+    /// Frame number at `time` into the file.
+    pub fn frame_at(&self, time: Duration) -> u64 {
+        (time.as_secs_f64() * self.sample_rate as f64) as u64
+    }
+
+    /// Whether a packet at `ts` starts beyond the range being played.
+    pub fn is_past_end(&self, ts: u64) -> bool {
+        self.end_frame
+            .is_some_and(|end| self.packet_frame(ts) >= end)
+    }
+
+    /// Frame number of a packet timestamp.
+    pub fn packet_frame(&self, ts: u64) -> u64 {
+        let frame = match self.time_base {
+            Some(tb) => {
+                let time = tb.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * self.sample_rate as f64) as u64
+            }
+            None => ts,
+        };
+        self.frame_offset + frame
+    }
+}
+
+/// Opens the container of `path`, which is either a file or a URL, along
+/// with the metadata of a radio stream.
+fn probe(path: &Path) -> anyhow::Result<(Box<dyn FormatReader>, Option<SharedIcy>)> {
+    let (source, hint, icy) = match path.to_str().filter(|_| stream::is_url(path)) {
+        Some(url) => {
+            let stream = stream::open(url)?;
+            (stream.source, stream.hint, stream.icy)
+        }
+        None => (Box::new(File::open(path)?) as _, Hint::new(), None),
+    };
+    let mss = MediaSourceStream::new(source, Default::default());
 
     let probed = get_probe().format(
-        &Default::default(),
+        &hint,
         mss,
         &FormatOptions::default(),
         &MetadataOptions::default(),
     )?;
 
-    let track = probed
-        .format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .ok_or_else(|| anyhow::anyhow!("No supported audio track found"))?
-        .clone();
+    Ok((probed.format, icy))
+}
+
+/// Channels of the first packet of `track_id`, for containers that leave
+/// them to the codec, such as MP4. Goes back to the start afterwards.
+fn decoded_layout(
+    format: &mut dyn FormatReader,
+    decoder: &mut dyn Decoder,
+    track_id: u32,
+) -> Option<Channels> {
+    let layout = loop {
+        let packet = format.next_packet().ok()?;
+        if packet.track_id() == track_id {
+            break decoder.decode(&packet).ok()?.spec().channels;
+        }
+    };
+
+    let start = SeekTo::TimeStamp { ts: 0, track_id };
+    format.seek(SeekMode::Accurate, start).ok()?;
+    decoder.reset();
+    Some(layout)
+}
+
+/// What to call a codec symphonia can read the container of but not decode.
+fn codec_name(codec: CodecType) -> String {
+    let name = match codec {
+        CODEC_TYPE_OPUS => "Opus",
+        CODEC_TYPE_SPEEX => "Speex",
+        CODEC_TYPE_MUSEPACK => "Musepack",
+        CODEC_TYPE_WAVPACK => "WavPack",
+        CODEC_TYPE_MONKEYS_AUDIO => "Monkey's Audio",
+        CODEC_TYPE_TTA => "TTA",
+        CODEC_TYPE_EAC3 => "E-AC-3",
+        CODEC_TYPE_AC4 => "AC-4",
+        CODEC_TYPE_DCA => "DTS",
+        CODEC_TYPE_WMA => "WMA",
+        other => return format!("Codec {other}"),
+    };
+    name.to_string()
+}
+
+/// Time of a timestamp, counted in frames when the file has no time base.
+fn to_duration(time_base: Option<TimeBase>, sample_rate: u32, ts: u64) -> Duration {
+    match time_base {
+        Some(tb) => {
+            let time = tb.calc_time(ts);
+            Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+        }
+        None => Duration::from_secs_f64(ts as f64 / sample_rate as f64),
+    }
+}
+
+/// Guesses the length of a file that doesn't state its frame count, such as
+/// a VBR MP3 without a Xing header, from the bitrate of its first packets
+/// and the file size. Tags and cover art make it come out a little long.
+fn estimate_duration(
+    path: &Path,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    sample_rate: u32,
+) -> Option<Duration> {
+    let size = std::fs::metadata(path).ok()?.len();
+    let (mut format, _) = probe(path).ok()?;
+
+    let (mut bytes, mut length) = (0, 0);
+    for _ in 0..ESTIMATE_PACKETS {
+        let Ok(packet) = format.next_packet() else {
+            break;
+        };
+        if packet.track_id() == track_id {
+            bytes += packet.data.len() as u64;
+            length += packet.dur;
+        }
+    }
+
+    if bytes == 0 {
+        return None;
+    }
+
+    let sampled = to_duration(time_base, sample_rate, length);
+    Some(sampled.mul_f64(size as f64 / bytes as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use symphonia::core::audio::SampleBuffer;
+
+    use super::*;
+
+    const RATE: u32 = 44100;
+    const FRAMES: usize = 4096;
+
+    /// A path in the temp directory for a fixture, unique to this run.
+    fn fixture_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("shelltrax-{}-{name}", process::id()))
+    }
+
+    /// A stereo sine wave, as 16-bit samples per channel.
+    fn sine() -> [Vec<i16>; 2] {
+        let wave = |freq: f32| {
+            (0..FRAMES)
+                .map(|i| {
+                    let t = i as f32 / RATE as f32;
+                    ((t * freq * std::f32::consts::TAU).sin() * 8000.0) as i16
+                })
+                .collect()
+        };
+        [wave(440.0), wave(660.0)]
+    }
+
+    fn write_wav(path: &Path, channels: &[Vec<i16>; 2]) {
+        let data_len = (FRAMES * 2 * 2) as u32;
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&RATE.to_le_bytes());
+        out.extend_from_slice(&(RATE * 4).to_le_bytes());
+        out.extend_from_slice(&4u16.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for (left, right) in channels[0].iter().zip(&channels[1]) {
+            out.extend_from_slice(&left.to_le_bytes());
+            out.extend_from_slice(&right.to_le_bytes());
+        }
+        fs::write(path, out).unwrap();
+    }
+
+    /// A FLAC file of one frame with the samples stored verbatim.
+    fn write_flac(path: &Path, channels: &[Vec<i16>; 2]) {
+        let mut out = b"fLaC".to_vec();
+        // STREAMINFO, the last metadata block
+        out.extend_from_slice(&[0x80, 0, 0, 34]);
+        out.extend_from_slice(&(FRAMES as u16).to_be_bytes());
+        out.extend_from_slice(&(FRAMES as u16).to_be_bytes());
+        out.extend_from_slice(&[0; 6]);
+        // Rate (20 bits), channels - 1 (3), bits - 1 (5), frame count (36)
+        let packed = (u64::from(RATE) << 44) | (1 << 41) | (15 << 36) | FRAMES as u64;
+        out.extend_from_slice(&packed.to_be_bytes());
+        out.extend_from_slice(&[0; 16]);
+
+        // Fixed block size, 16 bit block size at the end, 44.1 kHz,
+        // independent stereo, 16 bits, frame 0
+        let mut frame = vec![0xff, 0xf8, 0x79, 0x18, 0x00];
+        frame.extend_from_slice(&(FRAMES as u16 - 1).to_be_bytes());
+        frame.push(crc8(&frame));
+        for channel in channels {
+            frame.push(0x02);
+            for sample in channel {
+                frame.extend_from_slice(&sample.to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&crc16(&frame).to_be_bytes());
+        out.extend_from_slice(&frame);
+        fs::write(path, out).unwrap();
+    }
+
+    fn crc8(data: &[u8]) -> u8 {
+        let mut crc = 0u8;
+        for byte in data {
+            crc ^= byte;
+            for _ in 0..8 {
+                crc = match crc & 0x80 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x07,
+                };
+            }
+        }
+        crc
+    }
+
+    fn crc16(data: &[u8]) -> u16 {
+        let mut crc = 0u16;
+        for byte in data {
+            crc ^= u16::from(*byte) << 8;
+            for _ in 0..8 {
+                crc = match crc & 0x8000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x8005,
+                };
+            }
+        }
+        crc
+    }
+
+    /// Packs values into bytes, most significant bit first, or least
+    /// significant first as Vorbis does.
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+        lsb_first: bool,
+    }
+
+    impl BitWriter {
+        fn new(lsb_first: bool) -> Self {
+            Self {
+                bytes: Vec::new(),
+                bits: 0,
+                lsb_first,
+            }
+        }
+
+        fn push(&mut self, value: u32, count: u32) {
+            for i in 0..count {
+                let (bit, shift) = match self.lsb_first {
+                    true => (value >> i & 1, self.bits % 8),
+                    false => (value >> (count - 1 - i) & 1, 7 - self.bits % 8),
+                };
+                if self.bits.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |= (bit as u8) << shift;
+                self.bits += 1;
+            }
+        }
+    }
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// An MP4 audio sample entry for stereo 16-bit audio, with the codec's
+    /// own configuration atom after it.
+    fn sample_entry(kind: &[u8; 4], config: &[u8]) -> Vec<u8> {
+        let mut body = vec![0; 6];
+        body.extend_from_slice(&1u16.to_be_bytes());
+        body.extend_from_slice(&[0; 8]);
+        body.extend_from_slice(&2u16.to_be_bytes());
+        body.extend_from_slice(&16u16.to_be_bytes());
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(&(RATE << 16).to_be_bytes());
+        body.extend_from_slice(config);
+        atom(kind, &body)
+    }
+
+    /// An MP4 file with one audio track of `packets`, each `packet_frames`
+    /// long, stored in a single chunk.
+    fn write_mp4(path: &Path, entry: &[u8], packets: &[Vec<u8>], packet_frames: u32) {
+        let ftyp = atom(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+        let frames = packets.len() as u32 * packet_frames;
+        let full_box = |kind: &[u8; 4], fields: &[u32]| {
+            let mut body = vec![0; 4];
+            for field in fields {
+                body.extend_from_slice(&field.to_be_bytes());
+            }
+            atom(kind, &body)
+        };
+        // The identity matrix, in 16.16 and 2.30 fixed point
+        let matrix = [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x4000_0000];
+
+        let moov = |offset: u32| {
+            let mut mvhd = vec![0, 0, RATE, frames, 0x10000, 0x0100_0000, 0, 0];
+            mvhd.extend_from_slice(&matrix);
+            mvhd.extend_from_slice(&[0; 6]);
+            mvhd.push(2);
+            let mut tkhd = vec![0, 0, 1, 0, frames, 0, 0, 0, 0x0100_0000];
+            tkhd.extend_from_slice(&matrix);
+            tkhd.extend_from_slice(&[0, 0]);
+            let mut tkhd = full_box(b"tkhd", &tkhd);
+            tkhd[11] = 7;
+
+            let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+            stsd.extend_from_slice(entry);
+            let mut stsz = vec![0, packets.len() as u32];
+            stsz.extend(packets.iter().map(|packet| packet.len() as u32));
+            let stbl = [
+                atom(b"stsd", &stsd),
+                full_box(b"stts", &[1, packets.len() as u32, packet_frames]),
+                full_box(b"stsc", &[1, 1, packets.len() as u32, 1]),
+                full_box(b"stsz", &stsz),
+                full_box(b"stco", &[1, offset]),
+            ]
+            .concat();
+            let minf = [full_box(b"smhd", &[0]), atom(b"stbl", &stbl)].concat();
+            let mut hdlr = full_box(b"hdlr", &[0, u32::from_be_bytes(*b"soun"), 0, 0, 0]);
+            hdlr.push(0);
+            hdlr[3] += 1;
+            let mdia = [
+                full_box(b"mdhd", &[0, 0, RATE, frames, 0x55c4_0000]),
+                hdlr,
+                atom(b"minf", &minf),
+            ]
+            .concat();
+            let trak = [tkhd, atom(b"mdia", &mdia)].concat();
+            atom(
+                b"moov",
+                &[full_box(b"mvhd", &mvhd), atom(b"trak", &trak)].concat(),
+            )
+        };
+
+        let moov_len = moov(0).len();
+        let mut out = ftyp.clone();
+        out.extend_from_slice(&moov((ftyp.len() + moov_len + 8) as u32));
+        out.extend_from_slice(&atom(b"mdat", &packets.concat()));
+        fs::write(path, out).unwrap();
+    }
+
+    /// An ALAC file of one frame with the samples stored uncompressed.
+    fn write_alac(path: &Path, channels: &[Vec<i16>; 2]) {
+        // Frame length, version, bit depth, the encoder's tuning
+        // parameters, channels, max run, max frame size, bit rate, rate
+        let mut cookie = vec![0; 4];
+        cookie.extend_from_slice(&(FRAMES as u32).to_be_bytes());
+        cookie.extend_from_slice(&[0, 16, 40, 10, 14, 2, 0, 255]);
+        cookie.extend_from_slice(&[0; 8]);
+        cookie.extend_from_slice(&RATE.to_be_bytes());
+        let entry = sample_entry(b"alac", &atom(b"alac", &cookie));
+
+        // A channel pair element, uncompressed, then the end element
+        let mut frame = BitWriter::new(false);
+        frame.push(1, 3);
+        frame.push(0, 4 + 12 + 1 + 2);
+        frame.push(1, 1);
+        for (left, right) in channels[0].iter().zip(&channels[1]) {
+            frame.push(*left as u16 as u32, 16);
+            frame.push(*right as u16 as u32, 16);
+        }
+        frame.push(7, 3);
+        write_mp4(path, &entry, &[frame.bytes], FRAMES as u32);
+    }
+
+    /// An AAC-LC file of silent frames: each a channel pair element whose
+    /// channels code no bands at all.
+    fn write_aac(path: &Path) {
+        // Descriptor tag and length, then its fields
+        let descriptor = |tag: u8, body: &[u8]| [&[tag, body.len() as u8], body].concat();
+        // AAC-LC, 44.1 kHz, stereo
+        let specific = descriptor(5, &[0x12, 0x10]);
+        let mut config = vec![0x40, 0x15, 0, 0, 0];
+        config.extend_from_slice(&[0; 8]);
+        config.extend_from_slice(&specific);
+        let es = [
+            &[0, 1, 0][..],
+            &descriptor(4, &config),
+            &descriptor(6, &[2]),
+        ]
+        .concat();
+        let esds = atom(b"esds", &[&[0; 4][..], &descriptor(3, &es)].concat());
+        let entry = sample_entry(b"mp4a", &esds);
+
+        let mut frame = BitWriter::new(false);
+        frame.push(1, 3);
+        frame.push(0, 4 + 1);
+        for _ in 0..2 {
+            // Gain, info with no bands, then no pulse, TNS or gain control
+            frame.push(100, 8);
+            frame.push(0, 1 + 2 + 1 + 6 + 1);
+            frame.push(0, 3);
+        }
+        frame.push(7, 3);
+        let packets = vec![frame.bytes; FRAMES / 1024];
+        write_mp4(path, &entry, &packets, 1024);
+    }
+
+    fn ogg_crc(data: &[u8]) -> u32 {
+        let mut crc = 0u32;
+        for byte in data {
+            crc ^= u32::from(*byte) << 24;
+            for _ in 0..8 {
+                crc = match crc & 0x8000_0000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x04c1_1db7,
+                };
+            }
+        }
+        crc
+    }
+
+    /// An Ogg page holding `packets`, each shorter than 255 bytes.
+    fn ogg_page(flags: u8, granule: u64, sequence: u32, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(packets.len() as u8);
+        page.extend(packets.iter().map(|packet| packet.len() as u8));
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    /// An Ogg file of the first header packet on a page of its own, the
+    /// other headers, and the audio packets ending at `granule`.
+    fn write_ogg(path: &Path, headers: &[Vec<u8>], audio: &[Vec<u8>], granule: u64) {
+        let mut out = ogg_page(2, 0, 0, &headers[..1]);
+        out.extend_from_slice(&ogg_page(0, 0, 1, &headers[1..]));
+        out.extend_from_slice(&ogg_page(4, granule, 2, audio));
+        fs::write(path, out).unwrap();
+    }
+
+    /// A Vorbis file of silent short blocks: a setup with one codebook, a
+    /// floor and residue that are never used, and packets marking each
+    /// channel unused.
+    fn write_vorbis(path: &Path) {
+        let header = |kind: u8| [&[kind][..], b"vorbis"].concat();
+        let mut ident = header(1);
+        ident.extend_from_slice(&[0; 4]);
+        ident.push(2);
+        ident.extend_from_slice(&RATE.to_le_bytes());
+        ident.extend_from_slice(&[0; 12]);
+        // Block sizes of 256 and 2048, then the framing bit
+        ident.extend_from_slice(&[0xb8, 1]);
+
+        let mut comment = header(3);
+        comment.extend_from_slice(&[0; 8]);
+        comment.push(1);
+
+        let mut setup = BitWriter::new(true);
+        // A codebook of two one-bit entries
+        setup.push(0, 8);
+        setup.push(0x564342, 24);
+        setup.push(1, 16);
+        setup.push(2, 24);
+        setup.push(0, 1 + 1 + 5 + 5 + 4);
+        // A time domain transform placeholder
+        setup.push(0, 6 + 16);
+        // Floor 1 with no partitions and 8 range bits
+        setup.push(0, 6);
+        setup.push(1, 16);
+        setup.push(0, 5 + 2);
+        setup.push(8, 4);
+        // Residue 0 over no coefficients
+        setup.push(0, 6 + 16);
+        for bits in [24, 24, 24, 6 + 8 + 3 + 1] {
+            setup.push(0, bits);
+        }
+        // A mapping of both channels to them
+        setup.push(0, 6 + 16 + 1 + 1 + 2);
+        setup.push(0, 8 + 8 + 8);
+        // A short block mode, then the framing bit
+        setup.push(0, 6 + 1 + 16);
+        setup.push(0, 16 + 8);
+        setup.push(1, 1);
+        let setup = [header(5), setup.bytes].concat();
+
+        // Each block after the first adds half a block
+        let audio = vec![vec![0]; FRAMES / 128 + 1];
+        write_ogg(path, &[ident, comment, setup], &audio, FRAMES as u64);
+    }
+
+    /// An Ogg Opus file of one packet.
+    fn write_opus(path: &Path) {
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 2]);
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&48000u32.to_le_bytes());
+        head.extend_from_slice(&[0; 3]);
+        let tags = [&b"OpusTags"[..], &[0; 8]].concat();
+        // A single 20 ms packet, which nothing gets to decode
+        write_ogg(path, &[head, tags], &[vec![0xf8]], 960 + 312);
+    }
+
+    /// Decodes all of `source` into interleaved samples.
+    fn decode_all(source: &mut Source) -> Vec<f32> {
+        let mut samples = Vec::new();
+        while let Ok(packet) = source.format.next_packet() {
+            let decoded = source.decoder.decode(&packet).unwrap();
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend_from_slice(buffer.samples());
+        }
+        samples
+    }
+
+    /// Opens and decodes `path`, checking it has the format and samples
+    /// it was written with.
+    fn check_decodes(path: &Path, channels: &[Vec<i16>; 2], bits_per_sample: Option<u32>) {
+        let mut source = Source::open(path, Duration::ZERO, None).unwrap();
+        assert_eq!(source.sample_rate, RATE);
+        assert_eq!(source.channels, 2);
+        assert_eq!(source.bits_per_sample, bits_per_sample);
+        let expected = Duration::from_secs_f64(FRAMES as f64 / RATE as f64);
+        let duration = source.duration.unwrap();
+        assert!(
+            duration.abs_diff(expected) < Duration::from_millis(1),
+            "{duration:?}"
+        );
+
+        let samples = decode_all(&mut source);
+        assert_eq!(samples.len(), FRAMES * 2);
+        for (i, frame) in samples.chunks(2).enumerate() {
+            for (channel, sample) in frame.iter().enumerate() {
+                let original = channels[channel][i] as f32 / 32768.0;
+                assert!(
+                    (sample - original).abs() < 1e-4,
+                    "frame {i}: {sample} != {original}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_wav() {
+        let path = fixture_path("sine.wav");
+        let channels = sine();
+        write_wav(&path, &channels);
+        check_decodes(&path, &channels, Some(16));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decodes_flac() {
+        let path = fixture_path("sine.flac");
+        let channels = sine();
+        write_flac(&path, &channels);
+        check_decodes(&path, &channels, Some(16));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decodes_alac() {
+        let path = fixture_path("sine.m4a");
+        let channels = sine();
+        write_alac(&path, &channels);
+        // The MP4 reader leaves the bit depth to the ALAC decoder
+        check_decodes(&path, &channels, None);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Checks a file opens and decodes to something of the length it states.
+    fn check_plays(path: &Path) {
+        let mut source = Source::open(path, Duration::ZERO, None).unwrap();
+        let channels = source.channels;
+        let duration = source.duration.unwrap();
+        let samples = decode_all(&mut source);
+        let decoded = Duration::from_secs_f64(
+            samples.len() as f64 / channels as f64 / source.sample_rate as f64,
+        );
+        assert!(
+            decoded.abs_diff(duration) < Duration::from_millis(100),
+            "{decoded:?}"
+        );
+    }
 
-    let decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+    #[test]
+    fn decodes_vorbis() {
+        let path = fixture_path("silence.ogg");
+        write_vorbis(&path);
+        check_plays(&path);
+        fs::remove_file(path).unwrap();
+    }
 
-    let format = probed.format;
+    #[test]
+    fn decodes_aac() {
+        let path = fixture_path("silence.m4a");
+        write_aac(&path);
+        check_plays(&path);
+        fs::remove_file(path).unwrap();
+    }
 
-    Ok((format, decoder, track.clone()))
+    #[test]
+    fn reports_opus_as_unsupported() {
+        let path = fixture_path("silence.opus");
+        write_opus(&path);
+        let err = Source::open(&path, Duration::ZERO, None).err().unwrap();
+        assert_eq!(err.downcast::<UnsupportedCodec>().unwrap().0, "Opus");
+        fs::remove_file(path).unwrap();
+    }
 }
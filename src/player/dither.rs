@@ -0,0 +1,36 @@
+/// Converts the output to 16-bit samples for devices that take nothing
+/// else.
+///
+/// Rounding alone turns the lost low bits into distortion that follows the
+/// music, which is audible in quiet passages and fade-outs. Adding
+/// triangular (TPDF) noise of up to one step first turns it into a constant,
+/// much less noticeable hiss.
+pub struct Dither {
+    /// xorshift state; cheap enough for the audio callback
+    state: u32,
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self { state: 0x9e37_79b9 }
+    }
+}
+
+impl Dither {
+    pub fn convert(&mut self, input: &[f32], output: &mut [i16]) {
+        for (sample, out) in input.iter().zip(output.iter_mut()) {
+            // The sum of two uniform values has a triangular distribution
+            let noise = self.uniform() + self.uniform();
+            let scaled = sample * i16::MAX as f32 + noise;
+            *out = scaled.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+
+    /// Uniform random value in -0.5..0.5.
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32 - 0.5
+    }
+}
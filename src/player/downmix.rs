@@ -0,0 +1,83 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use symphonia::core::audio::Channels;
+
+/// Folds surround audio down to stereo.
+///
+/// Channels on one side go to that side. Centre channels go to both sides,
+/// and surround channels to their side, at -3 dB as in ITU-R BS.775. The LFE
+/// channel is left out, like most stereo versions of a surround mix do. The
+/// whole matrix is scaled so that full scale on every channel can't clip.
+pub struct Downmix {
+    /// Left and right gain of each source channel, in interleaved order
+    gains: Vec<(f32, f32)>,
+}
+
+impl Downmix {
+    pub fn new(layout: Channels) -> Self {
+        let mut gains: Vec<(f32, f32)> = layout.iter().map(channel_gains).collect();
+
+        let left: f32 = gains.iter().map(|(left, _)| left).sum();
+        let right: f32 = gains.iter().map(|(_, right)| right).sum();
+        let scale = left.max(right).max(1.0);
+        for (left, right) in &mut gains {
+            *left /= scale;
+            *right /= scale;
+        }
+
+        Self { gains }
+    }
+
+    /// Turns interleaved samples in the source layout into interleaved
+    /// stereo.
+    pub fn process(&self, samples: &[f32]) -> Vec<f32> {
+        let mut stereo = Vec::with_capacity(samples.len() / self.gains.len() * 2);
+        for frame in samples.chunks_exact(self.gains.len()) {
+            let (mut left, mut right) = (0.0, 0.0);
+            for (sample, (to_left, to_right)) in frame.iter().zip(&self.gains) {
+                left += sample * to_left;
+                right += sample * to_right;
+            }
+            stereo.push(left);
+            stereo.push(right);
+        }
+        stereo
+    }
+}
+
+fn channel_gains(channel: Channels) -> (f32, f32) {
+    const FRONT_LEFT: Channels = Channels::FRONT_LEFT
+        .union(Channels::FRONT_LEFT_CENTRE)
+        .union(Channels::FRONT_LEFT_WIDE);
+    const FRONT_RIGHT: Channels = Channels::FRONT_RIGHT
+        .union(Channels::FRONT_RIGHT_CENTRE)
+        .union(Channels::FRONT_RIGHT_WIDE);
+    const LEFT: Channels = Channels::REAR_LEFT
+        .union(Channels::REAR_LEFT_CENTRE)
+        .union(Channels::SIDE_LEFT)
+        .union(Channels::FRONT_LEFT_HIGH)
+        .union(Channels::TOP_FRONT_LEFT)
+        .union(Channels::TOP_REAR_LEFT);
+    const RIGHT: Channels = Channels::REAR_RIGHT
+        .union(Channels::REAR_RIGHT_CENTRE)
+        .union(Channels::SIDE_RIGHT)
+        .union(Channels::FRONT_RIGHT_HIGH)
+        .union(Channels::TOP_FRONT_RIGHT)
+        .union(Channels::TOP_REAR_RIGHT);
+    const LFE: Channels = Channels::LFE1.union(Channels::LFE2);
+
+    if FRONT_LEFT.contains(channel) {
+        (1.0, 0.0)
+    } else if FRONT_RIGHT.contains(channel) {
+        (0.0, 1.0)
+    } else if LEFT.contains(channel) {
+        (FRAC_1_SQRT_2, 0.0)
+    } else if RIGHT.contains(channel) {
+        (0.0, FRAC_1_SQRT_2)
+    } else if LFE.contains(channel) {
+        (0.0, 0.0)
+    } else {
+        // Centre channels, and anything else to be safe
+        (FRAC_1_SQRT_2, FRAC_1_SQRT_2)
+    }
+}
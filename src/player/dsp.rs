@@ -0,0 +1,461 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Center frequencies of the equalizer bands, in Hz.
+pub const EQ_BANDS: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Largest boost or cut of an equalizer band, in dB.
+pub const EQ_MAX_DB: f32 = 12.0;
+
+/// Named equalizer settings, in band order.
+pub const EQ_PRESETS: [(&str, [f32; 10]); 3] = [
+    ("flat", [0.0; 10]),
+    (
+        "bass boost",
+        [6.0, 5.0, 4.0, 2.5, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    ),
+    (
+        "vocal",
+        [-3.0, -2.0, -1.0, 0.0, 2.0, 3.0, 3.5, 2.5, 0.0, -1.0],
+    ),
+];
+
+/// How much of each channel crossfeed blends into the other, after the
+/// feed levels of the bs2b implementation of Bauer's filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrossfeedLevel {
+    /// About -10 dB, a subtle widening
+    #[default]
+    Light,
+    /// -6 dB, the level of Chu Moy's headphone amplifier
+    Medium,
+    /// -4.5 dB, the bs2b default, for the hardest-panned mixes
+    Strong,
+}
+
+impl CrossfeedLevel {
+    /// Level of the crossfed signal relative to the direct one.
+    fn gain(self) -> f32 {
+        match self {
+            CrossfeedLevel::Light => 0.3,
+            CrossfeedLevel::Medium => 0.5,
+            CrossfeedLevel::Strong => 0.6,
+        }
+    }
+}
+
+/// Effect switches, shared between the UI and the audio callback.
+#[derive(Debug)]
+pub struct DspSettings {
+    /// Cancel the center channel to attenuate vocals
+    pub karaoke: AtomicBool,
+
+    /// Blend some of each channel into the other for headphones
+    pub crossfeed: AtomicBool,
+
+    /// `CrossfeedLevel` as its index
+    crossfeed_level: AtomicU8,
+
+    /// Compress the dynamic range and cap the volume
+    pub night_mode: AtomicBool,
+
+    /// Highest output gain in night mode, as `f32` bits
+    night_ceiling: AtomicU32,
+
+    /// Fast-forward through long silences; checked by the decode thread
+    pub skip_silence: AtomicBool,
+
+    /// Level below which audio counts as silence, in dB as `f32` bits
+    silence_threshold: AtomicU32,
+
+    /// How long a silence plays before the rest is skipped, in ms
+    silence_min_ms: AtomicU32,
+
+    /// Equalizer band gains in dB, as `f32` bits
+    eq_gains: [AtomicU32; EQ_BANDS.len()],
+
+    /// Stereo balance from -1.0 (left only) to 1.0 (right only), as `f32`
+    /// bits
+    balance: AtomicU32,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            karaoke: AtomicBool::new(false),
+            crossfeed: AtomicBool::new(false),
+            crossfeed_level: AtomicU8::new(CrossfeedLevel::Light as u8),
+            night_mode: AtomicBool::new(false),
+            night_ceiling: AtomicU32::new(0.5f32.to_bits()),
+            skip_silence: AtomicBool::new(false),
+            silence_threshold: AtomicU32::new((-50.0f32).to_bits()),
+            silence_min_ms: AtomicU32::new(2000),
+            eq_gains: std::array::from_fn(|_| AtomicU32::new(0.0f32.to_bits())),
+            balance: AtomicU32::new(0.0f32.to_bits()),
+        }
+    }
+}
+
+impl DspSettings {
+    pub fn set_crossfeed_level(&self, level: CrossfeedLevel) {
+        self.crossfeed_level.store(level as u8, Ordering::Relaxed);
+    }
+
+    fn crossfeed_level(&self) -> CrossfeedLevel {
+        match self.crossfeed_level.load(Ordering::Relaxed) {
+            1 => CrossfeedLevel::Medium,
+            2 => CrossfeedLevel::Strong,
+            _ => CrossfeedLevel::Light,
+        }
+    }
+
+    pub fn set_night_ceiling(&self, ceiling: f32) {
+        self.night_ceiling
+            .store(ceiling.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn night_ceiling(&self) -> f32 {
+        f32::from_bits(self.night_ceiling.load(Ordering::Relaxed))
+    }
+
+    /// Sets what counts as a silence worth skipping: audio below
+    /// `threshold_db` for longer than `min_length`.
+    pub fn set_silence_detection(&self, threshold_db: f32, min_length: Duration) {
+        self.silence_threshold
+            .store(threshold_db.min(0.0).to_bits(), Ordering::Relaxed);
+        self.silence_min_ms.store(
+            min_length.as_millis().min(u32::MAX as u128) as u32,
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn silence_detection(&self) -> (f32, Duration) {
+        let threshold = f32::from_bits(self.silence_threshold.load(Ordering::Relaxed));
+        let min_ms = self.silence_min_ms.load(Ordering::Relaxed);
+        (threshold, Duration::from_millis(min_ms as u64))
+    }
+
+    pub fn set_balance(&self, balance: f32) {
+        self.balance
+            .store(balance.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn balance(&self) -> f32 {
+        f32::from_bits(self.balance.load(Ordering::Relaxed))
+    }
+
+    /// Sets one equalizer band, in dB; out of range bands are ignored.
+    pub fn set_eq_gain(&self, band: usize, db: f32) {
+        if let Some(gain) = self.eq_gains.get(band) {
+            gain.store(db.clamp(-EQ_MAX_DB, EQ_MAX_DB).to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn eq_gains(&self) -> [f32; EQ_BANDS.len()] {
+        std::array::from_fn(|band| f32::from_bits(self.eq_gains[band].load(Ordering::Relaxed)))
+    }
+
+    /// Whether any equalizer band is boosted or cut.
+    pub fn eq_active(&self) -> bool {
+        self.eq_gains().iter().any(|db| *db != 0.0)
+    }
+}
+
+/// Effect state for one output stream, applied to interleaved samples in
+/// the audio callback so toggles take effect immediately.
+pub struct DspChain {
+    settings: Arc<DspSettings>,
+    channels: usize,
+    equalizer: Equalizer,
+    karaoke: Karaoke,
+    crossfeed: Crossfeed,
+    compressor: Compressor,
+}
+
+impl DspChain {
+    pub fn new(settings: Arc<DspSettings>, channels: usize, sample_rate: u32) -> Self {
+        Self {
+            settings,
+            channels,
+            equalizer: Equalizer::new(channels, sample_rate as f32),
+            karaoke: Karaoke::new(sample_rate as f32),
+            crossfeed: Crossfeed::new(sample_rate as f32),
+            compressor: Compressor::new(sample_rate as f32),
+        }
+    }
+
+    /// Runs the enabled effects, then applies the output `gain`.
+    pub fn process(&mut self, data: &mut [f32], gain: f32) {
+        let eq_gains = self.settings.eq_gains();
+        if eq_gains.iter().any(|db| *db != 0.0) {
+            self.equalizer.process(data, eq_gains);
+        }
+
+        if self.channels == 2 && self.settings.karaoke.load(Ordering::Relaxed) {
+            for frame in data.chunks_exact_mut(2) {
+                self.karaoke.process(frame);
+            }
+        }
+
+        if self.channels == 2 && self.settings.crossfeed.load(Ordering::Relaxed) {
+            let feed = self.settings.crossfeed_level().gain();
+            for frame in data.chunks_exact_mut(2) {
+                self.crossfeed.process(frame, feed);
+            }
+        }
+
+        let mut gain = gain;
+        if self.settings.night_mode.load(Ordering::Relaxed) {
+            for frame in data.chunks_exact_mut(self.channels.max(1)) {
+                self.compressor.process(frame);
+            }
+            gain = gain.min(self.settings.night_ceiling());
+        }
+
+        let balance = self.settings.balance();
+        if self.channels == 2 && balance != 0.0 {
+            // Only the other side is turned down, so nothing gets louder
+            let left = gain * (1.0 - balance).min(1.0);
+            let right = gain * (1.0 + balance).min(1.0);
+            for frame in data.chunks_exact_mut(2) {
+                frame[0] *= left;
+                frame[1] *= right;
+            }
+        } else {
+            for sample in data.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+/// One-pole low-pass coefficient for `cutoff_hz`.
+fn low_pass_alpha(cutoff_hz: f32, sample_rate: f32) -> f32 {
+    1.0 - (-2.0 * PI * cutoff_hz / sample_rate).exp()
+}
+
+/// Ten band graphic equalizer, one peaking filter per band.
+///
+/// Filters are redesigned whenever a band's gain changes, keeping their state
+/// so adjustments are heard right away without clicks. Flat bands are skipped.
+struct Equalizer {
+    channels: usize,
+    sample_rate: f32,
+    /// Gains the filters are currently designed for
+    gains: [f32; EQ_BANDS.len()],
+    filters: [Biquad; EQ_BANDS.len()],
+}
+
+/// Bandwidth of each band, about one octave.
+const EQ_Q: f32 = 1.41;
+
+impl Equalizer {
+    fn new(channels: usize, sample_rate: f32) -> Self {
+        Self {
+            channels: channels.max(1),
+            sample_rate,
+            gains: [0.0; EQ_BANDS.len()],
+            filters: std::array::from_fn(|_| Biquad::new(channels.max(1))),
+        }
+    }
+
+    fn process(&mut self, data: &mut [f32], gains: [f32; EQ_BANDS.len()]) {
+        for (band, &db) in gains.iter().enumerate() {
+            if db != self.gains[band] {
+                self.gains[band] = db;
+                self.filters[band].set_peaking(EQ_BANDS[band], db, EQ_Q, self.sample_rate);
+            }
+        }
+
+        // Bands too close to the Nyquist frequency can't be filtered
+        let max_freq = self.sample_rate * 0.45;
+
+        for (band, filter) in self.filters.iter_mut().enumerate() {
+            if self.gains[band] == 0.0 || EQ_BANDS[band] > max_freq {
+                continue;
+            }
+
+            for frame in data.chunks_exact_mut(self.channels) {
+                for (ch, sample) in frame.iter_mut().enumerate() {
+                    *sample = filter.process(ch, *sample);
+                }
+            }
+        }
+    }
+}
+
+/// Second order IIR filter with separate state per channel.
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    /// Last two inputs and outputs of each channel
+    state: Vec<[f32; 4]>,
+}
+
+impl Biquad {
+    fn new(channels: usize) -> Self {
+        Self {
+            b: [1.0, 0.0, 0.0],
+            a: [0.0, 0.0],
+            state: vec![[0.0; 4]; channels],
+        }
+    }
+
+    /// Peaking EQ from the Audio EQ Cookbook.
+    fn set_peaking(&mut self, freq: f32, db: f32, q: f32, sample_rate: f32) {
+        let amp = 10f32.powf(db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        let a0 = 1.0 + alpha / amp;
+        self.b = [
+            (1.0 + alpha * amp) / a0,
+            -2.0 * cos / a0,
+            (1.0 - alpha * amp) / a0,
+        ];
+        self.a = [-2.0 * cos / a0, (1.0 - alpha / amp) / a0];
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let [x1, x2, y1, y2] = self.state[channel];
+        let output =
+            self.b[0] * input + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+
+        self.state[channel] = [input, x1, output, y1];
+        output
+    }
+}
+
+/// Center channel cancellation.
+///
+/// Vocals are usually mixed dead center, so removing what both channels have
+/// in common removes most of them. The bass is mostly centered too, so the
+/// common part below `KARAOKE_KEEP_BELOW_HZ` is kept.
+struct Karaoke {
+    /// One-pole low-pass coefficient
+    alpha: f32,
+    low: f32,
+}
+
+const KARAOKE_KEEP_BELOW_HZ: f32 = 200.0;
+
+impl Karaoke {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            alpha: low_pass_alpha(KARAOKE_KEEP_BELOW_HZ, sample_rate),
+            low: 0.0,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let (left, right) = (frame[0], frame[1]);
+        let mid = (left + right) * 0.5;
+        let side = (left - right) * 0.5;
+
+        self.low += self.alpha * (mid - self.low);
+
+        frame[0] = self.low + side;
+        frame[1] = self.low - side;
+    }
+}
+
+/// Bauer style crossfeed.
+///
+/// Over speakers each ear hears both channels, the far one a little later and
+/// with less treble. Feeding a low-passed, delayed copy of each channel into
+/// the other recreates that on headphones and softens hard-panned mixes.
+struct Crossfeed {
+    alpha: f32,
+    low: [f32; 2],
+    delay: Vec<[f32; 2]>,
+    delay_pos: usize,
+}
+
+const CROSSFEED_CUTOFF_HZ: f32 = 700.0;
+const CROSSFEED_DELAY_SECS: f32 = 0.0003;
+
+impl Crossfeed {
+    fn new(sample_rate: f32) -> Self {
+        let delay_frames = ((sample_rate * CROSSFEED_DELAY_SECS).round() as usize).max(1);
+
+        Self {
+            alpha: low_pass_alpha(CROSSFEED_CUTOFF_HZ, sample_rate),
+            low: [0.0; 2],
+            delay: vec![[0.0; 2]; delay_frames],
+            delay_pos: 0,
+        }
+    }
+
+    /// Crossfeeds one stereo frame, with the far channel at `feed` times
+    /// the level of the near one.
+    fn process(&mut self, frame: &mut [f32], feed: f32) {
+        let input = [frame[0], frame[1]];
+        for (low, sample) in self.low.iter_mut().zip(input) {
+            *low += self.alpha * (sample - *low);
+        }
+
+        let delayed = self.delay[self.delay_pos];
+        self.delay[self.delay_pos] = self.low;
+        self.delay_pos = (self.delay_pos + 1) % self.delay.len();
+
+        // Normalised so centered content keeps its level at low frequencies
+        frame[0] = (input[0] + feed * delayed[1]) / (1.0 + feed);
+        frame[1] = (input[1] + feed * delayed[0]) / (1.0 + feed);
+    }
+}
+
+/// Feed-forward compressor for night mode.
+///
+/// Peaks above the threshold are turned down by the ratio, then everything is
+/// raised by the makeup gain, so quiet passages come up and loud ones don't
+/// jump out.
+struct Compressor {
+    attack: f32,
+    release: f32,
+    /// Smoothed peak level in dB
+    envelope_db: f32,
+}
+
+const COMPRESSOR_THRESHOLD_DB: f32 = -24.0;
+const COMPRESSOR_RATIO: f32 = 4.0;
+const COMPRESSOR_MAKEUP_DB: f32 = 9.0;
+const COMPRESSOR_ATTACK_SECS: f32 = 0.005;
+const COMPRESSOR_RELEASE_SECS: f32 = 0.25;
+
+impl Compressor {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            attack: (-1.0 / (COMPRESSOR_ATTACK_SECS * sample_rate)).exp(),
+            release: (-1.0 / (COMPRESSOR_RELEASE_SECS * sample_rate)).exp(),
+            envelope_db: -120.0,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let level_db = 20.0 * peak.max(1e-6).log10();
+
+        let coeff = if level_db > self.envelope_db {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope_db = level_db + coeff * (self.envelope_db - level_db);
+
+        let over = (self.envelope_db - COMPRESSOR_THRESHOLD_DB).max(0.0);
+        let gain_db = COMPRESSOR_MAKEUP_DB - over * (1.0 - 1.0 / COMPRESSOR_RATIO);
+        let gain = 10f32.powf(gain_db / 20.0);
+
+        for sample in frame.iter_mut() {
+            *sample = (*sample * gain).clamp(-1.0, 1.0);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::decoder::UnsupportedCodec;
+
+/// Why a track couldn't start playing.
+#[derive(Debug)]
+pub enum PlayerError {
+    /// The file couldn't be read, or isn't audio symphonia can decode
+    Open {
+        path: PathBuf,
+        source: anyhow::Error,
+    },
+    /// The file is fine, but its audio is in a codec there's no decoder for
+    Unsupported { path: PathBuf, codec: String },
+    /// There's no output device to play on
+    NoDevice,
+    /// The device refused the stream
+    BuildStream(anyhow::Error),
+    /// The stream was built but won't start
+    PlayStream(anyhow::Error),
+}
+
+impl PlayerError {
+    /// The error for a file that failed to open, telling unsupported codecs
+    /// apart from broken files.
+    pub fn open(path: &Path, source: anyhow::Error) -> Self {
+        match source.downcast::<UnsupportedCodec>() {
+            Ok(UnsupportedCodec(codec)) => PlayerError::Unsupported {
+                path: path.to_path_buf(),
+                codec,
+            },
+            Err(source) => PlayerError::Open {
+                path: path.to_path_buf(),
+                source,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::Open { path, source } => {
+                write!(f, "failed to open {}: {source}", path.display())
+            }
+            PlayerError::Unsupported { path, codec } => {
+                write!(
+                    f,
+                    "can't play {}: {codec} audio isn't supported",
+                    path.display()
+                )
+            }
+            PlayerError::NoDevice => write!(f, "no output device available"),
+            PlayerError::BuildStream(err) => write!(f, "failed to build output stream: {err}"),
+            PlayerError::PlayStream(err) => write!(f, "failed to start output stream: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlayerError::Open { source, .. } => Some(source.as_ref()),
+            PlayerError::Unsupported { .. } => None,
+            PlayerError::NoDevice => None,
+            PlayerError::BuildStream(err) => Some(err.as_ref()),
+            PlayerError::PlayStream(err) => Some(err.as_ref()),
+        }
+    }
+}
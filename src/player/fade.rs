@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// What the player asks of the audio callback's [`Fader`].
+///
+/// Positions are sample counts taken from the ring buffer, like
+/// `Handoff::played`.
+#[derive(Default)]
+pub struct FadeControl {
+    /// Fade to silence and stay there, e.g. while paused
+    out: AtomicBool,
+    /// Where the last of the old audio kept after a seek or stop ends; it
+    /// is faded out by then, so what comes next doesn't start with a click
+    tail_end: AtomicU64,
+    /// Old audio from `tail_end` up to here is dropped unheard. Left at
+    /// `u64::MAX` until the decode thread knows where new audio starts
+    skip_to: AtomicU64,
+    /// Samples a fade takes, over all channels
+    length: usize,
+}
+
+impl FadeControl {
+    pub fn new(length: usize, out: bool) -> Self {
+        Self {
+            out: AtomicBool::new(out),
+            tail_end: AtomicU64::new(0),
+            skip_to: AtomicU64::new(0),
+            length,
+        }
+    }
+
+    pub fn set_out(&self, out: bool) {
+        self.out.store(out, Ordering::SeqCst);
+    }
+
+    /// Keeps only as much of the audio queued after `played` as fits in a
+    /// fade out and drops the rest, up to where [`FadeControl::resume_at`]
+    /// says new audio starts. Returns where the kept audio ends.
+    pub fn cut(&self, played: u64, pushed: u64) -> u64 {
+        let tail_end = self.tail_end.load(Ordering::SeqCst);
+        let tail_end = match tail_end > played {
+            // Already fading out the audio of an earlier cut
+            true => tail_end,
+            false => (played + self.length as u64).min(pushed),
+        };
+
+        self.skip_to.store(u64::MAX, Ordering::SeqCst);
+        self.tail_end.store(tail_end, Ordering::SeqCst);
+        tail_end
+    }
+
+    /// Called by the decode thread once it starts queueing new audio at
+    /// `sample` after a cut.
+    pub fn resume_at(&self, sample: u64) {
+        self.skip_to.store(sample, Ordering::SeqCst);
+    }
+
+    /// Whether the audio at `sample` was cut. Only meaningful for samples
+    /// already taken from the buffer, which makes sure a new start set
+    /// before they were queued is seen.
+    pub fn drops(&self, sample: u64) -> bool {
+        sample >= self.tail_end.load(Ordering::SeqCst)
+            && sample < self.skip_to.load(Ordering::SeqCst)
+    }
+}
+
+/// Ramps the output gain, so pausing, resuming, seeking and stopping don't
+/// click. Owned by the audio callback.
+pub struct Fader {
+    control: Arc<FadeControl>,
+    channels: usize,
+    /// Current gain, 0.0 - 1.0
+    level: f32,
+    /// Gain change per frame
+    step: f32,
+}
+
+impl Fader {
+    pub fn new(control: Arc<FadeControl>, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let frames = (control.length / channels).max(1);
+        let level = match control.out.load(Ordering::SeqCst) {
+            true => 0.0,
+            false => 1.0,
+        };
+
+        Self {
+            control,
+            channels,
+            level,
+            step: 1.0 / frames as f32,
+        }
+    }
+
+    /// Whether the output has faded out and should stay silent.
+    pub fn is_silent(&self) -> bool {
+        self.level == 0.0 && self.control.out.load(Ordering::SeqCst)
+    }
+
+    /// Applies the ramp to interleaved samples just taken from the buffer,
+    /// the first of which was sample `first`.
+    pub fn process(&mut self, data: &mut [f32], first: u64) {
+        let out = self.control.out.load(Ordering::SeqCst);
+        let tail_end = self.control.tail_end.load(Ordering::SeqCst);
+        let mut tail = tail_end.saturating_sub(first) as usize / self.channels;
+
+        for frame in data.chunks_exact_mut(self.channels) {
+            if tail > 0 {
+                // Reaches silence right at the end of the old audio
+                tail -= 1;
+                self.level = self.level.min(tail as f32 * self.step);
+            } else if out {
+                self.level = (self.level - self.step).max(0.0);
+            } else {
+                self.level = (self.level + self.step).min(1.0);
+            }
+
+            for sample in frame {
+                *sample *= self.level;
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::clock::PlaybackClock;
+use super::decoder::Source;
+
+/// A range of a file queued to play straight after the current one.
+pub struct NextRange {
+    pub path: PathBuf,
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+/// A switch the decode thread made to the queued range, which playback
+/// hasn't reached yet.
+pub struct Transition {
+    pub path: PathBuf,
+    pub end: Option<Duration>,
+    pub duration: Option<Duration>,
+    /// Samples played by the time the new range starts
+    pub at: u64,
+}
+
+/// What the decode thread does after reaching the end of its range.
+pub enum Handover {
+    /// The next range goes on in the same file, from the packet just read
+    Extended,
+    /// Decoding picks up from a newly opened file or a requested seek
+    Reopened,
+    /// Nothing follows, so playback ends
+    Finished,
+}
+
+/// State shared between the player and its decode thread for moving on to
+/// the next track without closing the stream.
+#[derive(Clone, Default)]
+pub struct Handoff {
+    /// Set when the decode thread reached the end and is waiting for a range
+    pub wants_next: Arc<AtomicBool>,
+    pub next: Arc<Mutex<Option<NextRange>>>,
+    pub transition: Arc<Mutex<Option<Transition>>>,
+    /// Samples taken from the buffer by the audio callback
+    pub played: Arc<AtomicU64>,
+    /// Samples handed to the buffer by the decode thread
+    pub pushed: Arc<AtomicU64>,
+    /// File positions of the samples handed to the buffer
+    pub clock: PlaybackClock,
+    /// A queued range that couldn't follow in the same stream, opened
+    /// already so the new stream starts without delay
+    pub prefetched: Arc<Mutex<Option<Source>>>,
+}
+
+impl Handoff {
+    /// Called by the decode thread at the end of `source`.
+    ///
+    /// Waits for the player to queue the next range while there is still
+    /// audio buffered, then moves `source` on to it. Playback finishes when
+    /// nothing was queued in time or the next file needs a different output
+    /// format.
+    pub fn follow(
+        &self,
+        source: &mut Source,
+        pushed: u64,
+        seek_request: &Mutex<Option<Duration>>,
+        stopped: &AtomicBool,
+    ) -> Handover {
+        self.wants_next.store(true, Ordering::SeqCst);
+
+        loop {
+            // Holding the seek lock keeps a seek from landing in the wrong
+            // track while we switch
+            let seek = seek_request.lock().unwrap();
+            if seek.is_some() {
+                return Handover::Reopened;
+            }
+
+            if let Some(next) = self.next.lock().unwrap().take() {
+                self.wants_next.store(false, Ordering::SeqCst);
+                return self.switch(source, next, pushed);
+            }
+
+            let played = self.played.load(Ordering::SeqCst);
+            if stopped.load(Ordering::SeqCst) || played >= pushed {
+                self.wants_next.store(false, Ordering::SeqCst);
+                return Handover::Finished;
+            }
+
+            // Woken as playback goes on, or when a range is queued
+            drop(seek);
+            thread::park();
+        }
+    }
+
+    fn switch(&self, source: &mut Source, next: NextRange, pushed: u64) -> Handover {
+        let continues =
+            next.path == source.path && source.end_frame == Some(source.frame_at(next.start));
+
+        let handover = if continues {
+            // The next cue track picks up right where this one ends
+            source.end_frame = next.end.map(|end| source.frame_at(end));
+            Handover::Extended
+        } else {
+            match Source::open(&next.path, next.start, next.end) {
+                Ok(opened)
+                    if opened.sample_rate == source.sample_rate
+                        && opened.layout == source.layout =>
+                {
+                    *source = opened;
+                    Handover::Reopened
+                }
+                Ok(opened) => {
+                    log::debug!("{:?} needs a different output format", next.path);
+                    *self.prefetched.lock().unwrap() = Some(opened);
+                    return Handover::Finished;
+                }
+                Err(err) => {
+                    log::warn!("Failed to open {:?}: {err}", next.path);
+                    return Handover::Finished;
+                }
+            }
+        };
+
+        self.clock.mark(pushed, next.start);
+        *self.transition.lock().unwrap() = Some(Transition {
+            path: next.path,
+            end: next.end,
+            duration: source.duration,
+            at: pushed,
+        });
+
+        handover
+    }
+}
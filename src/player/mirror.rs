@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use cpal::{
+    Host, SampleFormat, Stream,
+    traits::{DeviceTrait, StreamTrait},
+};
+use rtrb::{Producer, RingBuffer};
+
+use super::DECODE_AHEAD_SECS;
+use super::dsp::{DspChain, DspSettings};
+use super::remap::ChannelMap;
+use super::resample::{self, Resampler};
+use crate::config::MirrorOutput;
+
+/// Seconds of audio a mirror may queue. The decode thread only waits for the
+/// main output, so a mirror that plays slower than it (or not at all) drops
+/// new audio instead of holding more and more.
+const MAX_QUEUED_SECS: usize = DECODE_AHEAD_SECS * 2;
+
+/// What the player shares with every mirror.
+pub struct Controls {
+    pub paused: Arc<AtomicBool>,
+    pub muted: Arc<AtomicBool>,
+    /// Gain that levels tracks, as `f32` bits
+    pub track_gain: Arc<AtomicU32>,
+    pub dsp: Arc<DspSettings>,
+}
+
+/// The decode thread's side of a mirrored output: samples pushed here are
+/// resampled to the device's rate and played through the same effects as the
+/// main output, at the mirror's own volume.
+pub struct MirrorSink {
+    producer: Producer<f32>,
+    /// Samples handed to the callback so far
+    pushed: usize,
+    /// How many of them the callback skips, set when clearing
+    cut: Arc<AtomicUsize>,
+    resampler: Resampler,
+    remap: Option<ChannelMap>,
+}
+
+impl MirrorSink {
+    /// Drops queued samples, e.g. after a seek.
+    pub fn clear(&mut self) {
+        self.cut.store(self.pushed, Ordering::SeqCst);
+    }
+
+    /// Queues `samples`; what doesn't fit is dropped rather than waited for.
+    pub fn push(&mut self, samples: &[f32]) {
+        let mut resampled = self.resampler.process(samples);
+        if let Some(remap) = &self.remap {
+            resampled = remap.process(&resampled);
+        }
+
+        let room = self.producer.slots().min(resampled.len());
+        if let Ok(chunk) = self.producer.write_chunk_uninit(room) {
+            chunk.fill_from_iter(resampled[..room].iter().copied());
+            self.pushed += room;
+        }
+    }
+}
+
+/// Opens a stream on the first device whose name contains `output.name`.
+///
+/// Like the main output, the sink resamples and remaps channels when the
+/// device doesn't support the track's format.
+pub fn open(
+    host: &Host,
+    output: &MirrorOutput,
+    channels: usize,
+    sample_rate: u32,
+    controls: Controls,
+) -> anyhow::Result<(Stream, MirrorSink)> {
+    let device = super::output::find_device(host, &output.name)
+        .ok_or_else(|| anyhow::anyhow!("No output device matching {:?}", output.name))?;
+
+    let format = resample::device_format(&device, channels, sample_rate)?;
+    if format.sample_format != SampleFormat::F32 {
+        anyhow::bail!("Mirror outputs need a device that takes float samples");
+    }
+    let (out_channels, device_rate) = (format.channels, format.rate);
+
+    let config = cpal::StreamConfig {
+        channels: out_channels as u16,
+        sample_rate: cpal::SampleRate(device_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+
+    let limit = device_rate as usize * out_channels * MAX_QUEUED_SECS;
+    let (producer, mut consumer) = RingBuffer::<f32>::new(limit);
+    let cut = Arc::new(AtomicUsize::new(0));
+    let callback_cut = Arc::clone(&cut);
+    let mut taken = 0;
+    let volume = output.volume.clamp(0.0, 1.0);
+    let Controls {
+        paused,
+        muted,
+        track_gain,
+        dsp,
+    } = controls;
+    let mut dsp = DspChain::new(dsp, out_channels, device_rate);
+
+    let stream = device.build_output_stream(
+        &config,
+        move |data: &mut [f32], _| {
+            if paused.load(Ordering::SeqCst) {
+                data.fill(0.0);
+                return;
+            }
+
+            let cut = callback_cut.load(Ordering::SeqCst);
+            for sample in data.iter_mut() {
+                *sample = loop {
+                    match consumer.pop() {
+                        // Left behind by a seek
+                        Ok(_) if taken < cut => taken += 1,
+                        Ok(sample) => {
+                            taken += 1;
+                            break sample;
+                        }
+                        Err(_) => break 0.0,
+                    }
+                };
+            }
+
+            let gain = match muted.load(Ordering::Relaxed) {
+                true => 0.0,
+                false => volume * f32::from_bits(track_gain.load(Ordering::Relaxed)),
+            };
+            dsp.process(data, gain);
+        },
+        move |err| log::error!("Mirror stream error: {err}"),
+        None,
+    )?;
+    stream.play()?;
+
+    let sink = MirrorSink {
+        producer,
+        pushed: 0,
+        cut,
+        resampler: Resampler::new(channels, sample_rate, device_rate),
+        remap: (out_channels != channels).then(|| ChannelMap::new(channels, out_channels)),
+    };
+
+    Ok((stream, sink))
+}
@@ -1,45 +1,173 @@
+mod clock;
 mod decoder;
+mod dither;
+mod downmix;
+pub mod dsp;
+mod error;
+mod fade;
+mod gapless;
+mod mirror;
 mod output;
+mod pipe;
+mod recorder;
+mod remap;
+mod resample;
+mod seek_index;
+mod silence;
+mod stream;
+mod stretch;
 
 use crate::player::thread::JoinHandle;
 
 use std::{
-    fs::File,
+    io,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicBool, Ordering},
-    sync::{Arc, Mutex},
-    thread,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    sync::{Arc, Mutex, OnceLock},
+    thread::{self, Thread},
     time::Duration,
 };
 
-use cpal::{
-    Stream,
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-};
-use symphonia::core::{
-    audio::{AudioBufferRef, Signal},
-    codecs::{CODEC_TYPE_NULL, DecoderOptions},
-    formats::FormatOptions,
-    io::MediaSourceStream,
-    meta::MetadataOptions,
-};
-
-use symphonia::default::{get_codecs, get_probe};
+use cpal::{HostId, SampleFormat};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer, Signal};
+use symphonia::core::errors::Error as SymphoniaError;
 
 use log;
-
-use std::collections::VecDeque;
+use rtrb::RingBuffer;
+
+use self::clock::PlaybackClock;
+use self::decoder::Source;
+use self::downmix::Downmix;
+use self::dsp::{DspChain, DspSettings};
+pub use self::error::PlayerError;
+use self::fade::{FadeControl, Fader};
+use self::gapless::{Handoff, Handover, NextRange};
+use self::mirror::MirrorSink;
+use self::output::{AudioOutput, CpalOutput, OutputStream};
+use self::pipe::PipeOutput;
+use self::recorder::{RecordTap, Recorder};
+use self::remap::ChannelMap;
+pub use self::resample::OutputFormat;
+use self::resample::Resampler;
+use self::silence::SilenceSkipper;
+use self::stream::SharedIcy;
+pub use self::stream::{IcyInfo, is_url};
+use self::stretch::TimeStretch;
+use crate::config::{DevicePreset, MirrorOutput};
+
+/// Seconds of audio decoded ahead of playback, which is all the output
+/// buffer ever holds, however long the track.
+pub const DECODE_AHEAD_SECS: usize = 2;
 
 pub struct Player {
     pub current_path: Option<PathBuf>,
     pub is_playing: bool,
     pub handle: Option<JoinHandle<()>>,
-    stream: Option<Stream>,
+    stream: Option<Box<dyn OutputStream>>,
     buffer: Arc<Mutex<Vec<f32>>>,
     pub autoplay_trigger: Arc<AtomicBool>,
     pub is_decoder_done: Arc<AtomicBool>,
     pub is_paused: bool,
     pub paused_flag: Arc<AtomicBool>,
+
+    /// Output gain as `f32` bits, shared with the audio callback
+    volume: Arc<AtomicU32>,
+
+    /// The part of the gain that levels tracks, which mirrors get too
+    track_gain: Arc<AtomicU32>,
+
+    /// Silences every output without touching the volume
+    muted: Arc<AtomicBool>,
+
+    /// Gain ramps of the current stream
+    fade: Arc<FadeControl>,
+
+    /// How long fading in or out takes
+    fade_length: Duration,
+
+    /// Playback speed as `f32` bits, shared with the decode thread
+    speed: Arc<AtomicU32>,
+
+    /// Name of the device the last stream was opened on
+    pub device_name: Option<String>,
+
+    /// Audio host the last stream was opened through
+    pub host_name: Option<&'static str>,
+
+    /// Format the last stream was opened with
+    pub output_format: Option<OutputFormat>,
+
+    /// Frames per callback to ask the device for; `None` leaves it to the
+    /// driver
+    buffer_frames: Option<u32>,
+
+    /// Audio system to play through, when not the platform's default
+    host_id: Option<HostId>,
+
+    /// Part of the name of the device to play on instead of the default
+    device_match: Option<String>,
+
+    /// Command to pipe the audio into instead of playing it on a device
+    pipe_command: Option<String>,
+
+    /// Whether to open the device at each file's own format and leave the
+    /// samples untouched
+    bit_perfect: bool,
+
+    /// Whether the current stream plays the file's samples unchanged
+    pub bit_perfect_active: bool,
+
+    /// Frames per callback the last stream was opened with; `None` when the
+    /// driver chose
+    pub buffer_size: Option<u32>,
+
+    /// Time from the audio callback to the samples being heard, in
+    /// microseconds, as last reported by the backend; 0 when unknown
+    latency: Arc<AtomicU64>,
+
+    /// Length of the current file, if known
+    duration: Option<Duration>,
+
+    /// Effects applied to every stream
+    pub dsp: Arc<DspSettings>,
+
+    /// Extra devices that play the same audio
+    pub mirrors: Vec<MirrorOutput>,
+
+    /// Per-device bit-perfect and sample rate settings
+    pub device_presets: Vec<DevicePreset>,
+    mirror_streams: Vec<Box<dyn OutputStream>>,
+
+    /// Position the decode thread should jump to, picked up between packets
+    seek_request: Arc<Mutex<Option<Duration>>>,
+
+    /// A-B loop within the file: the decode thread jumps back to the start
+    /// whenever it reaches the end
+    ab_loop: Arc<Mutex<Option<(Duration, Duration)>>>,
+
+    /// Where the current range ends, if it doesn't play to the end of the file
+    range_end: Option<Duration>,
+
+    /// Lets the decode thread carry on into the next track
+    handoff: Handoff,
+
+    /// Tells the decode thread of the current stream to finish
+    stopped: Arc<AtomicBool>,
+
+    /// Set by the stream's error callback, e.g. when the device goes away
+    stream_failed: Arc<AtomicBool>,
+
+    /// Hands the final output to the recorder, shared with every stream
+    record_tap: RecordTap,
+
+    /// Recording of the output, while one runs
+    recorder: Option<Recorder>,
+
+    /// A recording that ended because the output format changed
+    recording_ended: Option<io::Result<PathBuf>>,
+
+    /// Metadata of the radio stream playing, if it sends any
+    icy: Option<SharedIcy>,
 }
 
 impl Player {
@@ -54,102 +182,365 @@ impl Player {
             is_decoder_done: Arc::new(AtomicBool::new(false)),
             is_paused: false,
             paused_flag: Arc::new(AtomicBool::new(false)),
+            volume: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            track_gain: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            muted: Arc::new(AtomicBool::new(false)),
+            fade: Arc::new(FadeControl::default()),
+            fade_length: Duration::ZERO,
+            speed: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            device_name: None,
+            host_name: None,
+            output_format: None,
+            buffer_frames: None,
+            host_id: None,
+            device_match: None,
+            pipe_command: None,
+            bit_perfect: false,
+            bit_perfect_active: false,
+            buffer_size: None,
+            latency: Arc::new(AtomicU64::new(0)),
+            duration: None,
+            dsp: Arc::new(DspSettings::default()),
+            mirrors: Vec::new(),
+            device_presets: Vec::new(),
+            mirror_streams: Vec::new(),
+            seek_request: Arc::new(Mutex::new(None)),
+            ab_loop: Arc::new(Mutex::new(None)),
+            range_end: None,
+            handoff: Handoff::default(),
+            stopped: Arc::new(AtomicBool::new(false)),
+            stream_failed: Arc::new(AtomicBool::new(false)),
+            record_tap: RecordTap::default(),
+            recorder: None,
+            recording_ended: None,
+            icy: None,
         }
     }
 
-    pub fn play(&mut self, path: &Path) {
+    /// Plays `path`, which may also be an HTTP(S) URL to stream, from
+    /// `start`, stopping at `end` if given, as if the file ended there.
+    pub fn play_range(
+        &mut self,
+        path: &Path,
+        start: Duration,
+        end: Option<Duration>,
+    ) -> Result<(), PlayerError> {
         self.stop(); // Stop any current playback
 
         self.autoplay_trigger.store(false, Ordering::SeqCst);
         self.is_decoder_done.store(false, Ordering::SeqCst);
 
-        let file = File::open(path).expect("Failed to open file");
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-        let probed = get_probe()
-            .format(
-                &Default::default(),
-                mss,
-                &FormatOptions::default(),
-                &MetadataOptions::default(),
-            )
-            .expect("Unsupported format");
-
-        let mut format = probed.format;
-
-        let track = format
-            .tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .expect("No supported audio track found");
-
-        let mut decoder = get_codecs()
-            .make(&track.codec_params, &DecoderOptions::default())
-            .expect("Unsupported codec");
-
-        let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
-        let channels = track.codec_params.channels.unwrap().count();
-
-        // Create CPAL output stream
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("No output device available");
-
-        let config = cpal::StreamConfig {
-            channels: channels as u16,
-            sample_rate: cpal::SampleRate(sample_rate),
-            buffer_size: cpal::BufferSize::Default,
+        // The decoder may have opened this range already as the next track
+        let prefetched = self
+            .handoff
+            .prefetched
+            .lock()
+            .unwrap()
+            .take()
+            .filter(|source| source.path == path && source.start == start);
+        let source = match prefetched {
+            Some(mut source) => {
+                source.end_frame = end.map(|end| source.frame_at(end));
+                source
+            }
+            None => {
+                Source::open(path, start, end).map_err(|source| PlayerError::open(path, source))?
+            }
         };
+        let sample_rate = source.sample_rate;
+        self.duration = source.duration;
+        self.icy = source.icy.clone();
+
+        let output = self.output().ok_or(PlayerError::NoDevice)?;
+        self.host_name = Some(output.host_name());
+        self.device_name = output.device_name();
+
+        let preset = self
+            .device_name
+            .as_deref()
+            .and_then(|name| DevicePreset::find(&self.device_presets, name));
+        let rate = preset.map_or(sample_rate, |preset| {
+            preset.sample_rate.rate_for(sample_rate)
+        });
+        let exclusive = preset
+            .and_then(|preset| preset.exclusive)
+            .unwrap_or(self.bit_perfect);
+        // A rate the device is held to leaves nothing to play unchanged
+        let exclusive = exclusive && rate == sample_rate;
+
+        let exact = exclusive.then(|| {
+            let format = output.exact_format(source.channels, sample_rate, source.bits_per_sample);
+            if format.is_none() {
+                log::warn!("Device can't play {:?} bit-perfect, converting it", path);
+            }
+            format
+        });
+        let exact = exact.flatten();
+        let bit_perfect = exact.is_some();
+        self.bit_perfect_active = bit_perfect;
+        let bit_depth = bit_perfect.then_some(source.bits_per_sample).flatten();
+
+        // Surround files are folded down to stereo, which any device plays
+        let downmix = (source.channels > 2 && !bit_perfect).then(|| Downmix::new(source.layout));
+        let channels = match downmix {
+            Some(_) => 2,
+            None => source.channels,
+        };
+
+        // Devices that only run at e.g. 48 kHz get resampled audio, and
+        // ones without a matching channel count get it remapped
+        let format = match exact {
+            Some(format) => Ok(format),
+            None => output.format(channels, rate),
+        };
+        let format = format.unwrap_or_else(|err| {
+            log::warn!("Failed to query output formats: {err}");
+            OutputFormat {
+                channels,
+                rate,
+                sample_format: SampleFormat::F32,
+            }
+        });
+        self.output_format = Some(format);
+        let (out_channels, device_rate) = (format.channels, format.rate);
+
+        // A WAV file has one format throughout
+        let changed = |recorder: &mut Recorder| {
+            recorder.channels != out_channels || recorder.rate != device_rate
+        };
+        if let Some(recorder) = self.recorder.take_if(changed) {
+            log::info!(
+                "Output format changed, ending recording {:?}",
+                recorder.path
+            );
+            self.recording_ended = Some(recorder.finish());
+        }
+        let remap = (out_channels != channels).then(|| ChannelMap::new(channels, out_channels));
+        let mut resampler = Resampler::new(channels, sample_rate, device_rate);
+        let mut stretch = TimeStretch::new(channels, sample_rate);
+        // Skipping silence changes the timing, which bit-perfect playback
+        // leaves alone like the speed
+        let mut silence = (!bit_perfect)
+            .then(|| SilenceSkipper::new(Arc::clone(&self.dsp), sample_rate, source.channels));
+        let speed = Arc::clone(&self.speed);
+
+        // Larger buffers ride out hiccups, e.g. on Bluetooth; smaller ones
+        // cut latency
+        self.buffer_size = output.buffer_frames(self.buffer_frames);
         let buffer = Arc::new(Mutex::new(Vec::<f32>::new()));
         let buffer_clone = Arc::clone(&buffer);
 
-        let sample_buf = Arc::new(Mutex::new(VecDeque::<f32>::new()));
-        let sample_buf_clone = Arc::clone(&sample_buf);
+        // Decoded samples waiting for the audio callback. The callback never
+        // locks or allocates, and holding only this much keeps the decode
+        // thread just ahead of the output, so the next track is only picked
+        // once this one is nearly over
+        let ahead = device_rate as usize * out_channels * DECODE_AHEAD_SECS;
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(ahead);
+        // The decode thread, woken whenever the callback makes room
+        let decoder = Arc::new(OnceLock::<Thread>::new());
+        let decoder_clone = Arc::clone(&decoder);
 
         let autoplay_trigger = Arc::clone(&self.autoplay_trigger);
         let decoder_done = Arc::clone(&self.is_decoder_done);
         let decoder_done_for_thread = Arc::clone(&self.is_decoder_done);
         let paused_flag = Arc::clone(&self.paused_flag);
-
-        let stream = device
-            .build_output_stream(
-                &config,
-                move |data: &mut [f32], _| {
-                    let mut buf = sample_buf_clone.lock().unwrap();
-
-                    if paused_flag.load(Ordering::SeqCst) {
-                        for sample in data.iter_mut() {
-                            *sample = 0.0;
+        let volume = Arc::clone(&self.volume);
+        let muted = Arc::clone(&self.muted);
+        self.handoff = Handoff {
+            clock: PlaybackClock::new(Arc::clone(&self.speed), device_rate, out_channels),
+            ..Handoff::default()
+        };
+        self.handoff.clock.mark(0, start);
+        let played = Arc::clone(&self.handoff.played);
+        self.stream_failed.store(false, Ordering::SeqCst);
+        let stream_failed = Arc::clone(&self.stream_failed);
+        let mut dsp = DspChain::new(Arc::clone(&self.dsp), out_channels, device_rate);
+        let fade_length = match bit_perfect {
+            true => Duration::ZERO,
+            false => self.fade_length,
+        };
+        let fade_samples = (fade_length.as_secs_f32() * device_rate as f32) as usize;
+        self.fade = Arc::new(FadeControl::new(
+            fade_samples * out_channels,
+            self.is_paused,
+        ));
+        let mut fader = Fader::new(Arc::clone(&self.fade), out_channels);
+        let cut = Arc::clone(&self.fade);
+        self.latency.store(0, Ordering::Relaxed);
+        let latency = Arc::clone(&self.latency);
+        let record_tap = Arc::clone(&self.record_tap);
+
+        let fill = move |data: &mut [f32], delay: Option<Duration>| {
+            if let Some(delay) = delay {
+                latency.store(delay.as_micros() as u64, Ordering::Relaxed);
+            }
+
+            // Once faded out, the stream itself is paused where the backend
+            // allows
+            if paused_flag.load(Ordering::SeqCst) && fader.is_silent() {
+                for sample in data.iter_mut() {
+                    *sample = 0.0;
+                }
+                return;
+            }
+
+            let gain = match muted.load(Ordering::Relaxed) {
+                true => 0.0,
+                false => f32::from_bits(volume.load(Ordering::Relaxed)),
+            };
+
+            let first = played.load(Ordering::SeqCst);
+            let mut taken = first;
+            for sample in data.iter_mut() {
+                *sample = loop {
+                    match consumer.pop() {
+                        // Left behind by a seek or stop
+                        Ok(_) if cut.drops(taken) => taken += 1,
+                        Ok(sample) => {
+                            taken += 1;
+                            break sample;
                         }
-                        return;
-                    }
-
-                    for sample in data.iter_mut() {
-                        *sample = buf.pop_front().unwrap_or(0.0); // Pop from front = correct order
-                    }
-
-                    if buf.is_empty() && decoder_done.load(Ordering::SeqCst) {
-                        autoplay_trigger.store(true, Ordering::SeqCst);
+                        Err(_) => break 0.0,
                     }
-                },
-                move |err| log::error!("CPAL stream error: {err}"),
-                None,
-            )
-            .expect("Failed to build output stream");
-
-        stream.play().expect("Failed to play stream");
+                };
+            }
+            played.store(taken, Ordering::SeqCst);
+            if let Some(thread) = decoder_clone.get().filter(|_| taken > first) {
+                thread.unpark();
+            }
+
+            // Bit-perfect output skips volume and effects; muting still works
+            match bit_perfect {
+                true if gain == 0.0 => data.fill(0.0),
+                true => {}
+                false => dsp.process(data, gain),
+            }
+            fader.process(data, first);
+            recorder::feed(&record_tap, data);
+
+            if consumer.is_empty() && decoder_done.load(Ordering::SeqCst) {
+                autoplay_trigger.store(true, Ordering::SeqCst);
+            }
+        };
+        let on_error = move |err: anyhow::Error| {
+            log::error!("Output stream error: {err}");
+            stream_failed.store(true, Ordering::SeqCst);
+        };
+        let stream = output.open(
+            format,
+            self.buffer_size,
+            bit_depth,
+            Box::new(fill),
+            Box::new(on_error),
+        )?;
+
+        // Mirrors are always devices, on the configured host
+        let host = output::host(self.host_id);
+        let mut mirror_sinks: Vec<MirrorSink> = Vec::new();
+        for output in &self.mirrors {
+            let controls = mirror::Controls {
+                paused: Arc::clone(&self.paused_flag),
+                muted: Arc::clone(&self.muted),
+                track_gain: Arc::clone(&self.track_gain),
+                dsp: Arc::clone(&self.dsp),
+            };
+            match mirror::open(&host, output, channels, sample_rate, controls) {
+                Ok((stream, sink)) => {
+                    self.mirror_streams.push(Box::new(stream));
+                    mirror_sinks.push(sink);
+                }
+                Err(err) => log::warn!("Failed to open mirror output {:?}: {err}", output.name),
+            }
+        }
 
         self.is_playing = true;
         self.current_path = Some(path.to_path_buf());
 
 
+        self.range_end = end;
+        self.seek_request = Arc::new(Mutex::new(None));
+        let seek_request = Arc::clone(&self.seek_request);
+        self.ab_loop = Arc::new(Mutex::new(None));
+        let ab_loop = Arc::clone(&self.ab_loop);
+        self.stopped = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&self.stopped);
+        let handoff = self.handoff.clone();
+        let decode_paused = Arc::clone(&self.paused_flag);
+        let fade = Arc::clone(&self.fade);
+
         // Spawn decoding thread
-        let decode_buffer = Arc::clone(&sample_buf);
         let handle = thread::spawn(move || {
-            while let Ok(packet) = format.next_packet() {
-                let decoded = match decoder.decode(&packet) {
+            let mut source = source;
+            // Samples handed to the buffer, counted like `handoff.played`
+            let mut pushed = 0;
+            // Whether the last packets were dropped as silence
+            let mut skipped_silence = false;
+
+            loop {
+                if stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                if let Some(position) = seek_request.lock().unwrap().take() {
+                    // The player cut the old audio when it asked; new audio
+                    // starts here, even if the seek fails
+                    fade.resume_at(pushed);
+
+                    match source.seek(position) {
+                        Ok(_) => {
+                            handoff.clock.restart(pushed, position);
+                            stretch.reset();
+                            for sink in &mut mirror_sinks {
+                                sink.clear();
+                            }
+                        }
+                        Err(err) => log::warn!("Failed to seek to {:?}: {err}", position),
+                    }
+                }
+
+                // Sleep until resumed, stopped or asked to seek; the buffer
+                // already holds plenty to resume with
+                if decode_paused.load(Ordering::SeqCst) {
+                    thread::park();
+                    continue;
+                }
+
+                let packet = source.format.next_packet().ok();
+
+                if packet
+                    .as_ref()
+                    .is_none_or(|packet| source.is_past_end(packet.ts()))
+                {
+                    match handoff.follow(&mut source, pushed, &seek_request, &stopped) {
+                        Handover::Extended => {}
+                        Handover::Reopened => continue,
+                        Handover::Finished => break,
+                    }
+                }
+
+                let Some(packet) = packet else {
+                    continue;
+                };
+                let packet_frame = source.packet_frame(packet.ts());
+
+                let loop_range = *ab_loop.lock().unwrap();
+                let loop_end = loop_range.map(|(_, b)| source.frame_at(b));
+                let looping_back = loop_end.is_some_and(|end| packet_frame >= end);
+                if let Some((a, _)) = loop_range.filter(|_| looping_back) {
+                    match source.seek(a) {
+                        Ok(_) => handoff.clock.mark(pushed, a),
+                        Err(err) => log::warn!("Failed to loop back to {:?}: {err}", a),
+                    }
+                    continue;
+                }
+
+                let decoded = match source.decoder.decode(&packet) {
                     Ok(decoded) => decoded,
+                    // It won't get any better further on, so end the track
+                    Err(SymphoniaError::Unsupported(feature)) => {
+                        log::error!("Can't decode {:?}: unsupported {feature}", source.path);
+                        break;
+                    }
                     Err(err) => {
                         log::error!("Decode error: {err}");
                         continue;
@@ -163,9 +554,9 @@ impl Player {
                     spec.channels.count()
                 );
                 log::debug!(
-                    "CPAL: sample_rate={}, channels={}",
-                    config.sample_rate.0,
-                    config.channels
+                    "Output: sample_rate={}, channels={}",
+                    device_rate,
+                    out_channels
                 );
 
                 let mut samples = Vec::new();
@@ -191,14 +582,7 @@ impl Player {
                     AudioBufferRef::S16(buf) => {
                         for frame in 0..buf.frames() {
                             for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32 / i16::MAX as f32);
-                            }
-                        }
-                    }
-                    AudioBufferRef::U8(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32 / u8::MAX as f32);
+                                samples.push(buf.chan(ch)[frame] as f32 / (1 << 15) as f32);
                             }
                         }
                     }
@@ -221,21 +605,87 @@ impl Player {
                     AudioBufferRef::S32(buf) => {
                         for frame in 0..buf.frames() {
                             for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32 / i32::MAX as f32);
+                                samples.push(buf.chan(ch)[frame] as f32 / (1u32 << 31) as f32);
                             }
                         }
                     }
-                    _ => {
-                        log::debug!("Unsupported buffer format");
-                        continue;
+                    // 8-bit and unsigned PCM, which symphonia converts itself
+                    other => {
+                        let mut converted =
+                            SampleBuffer::<f32>::new(other.capacity() as u64, *other.spec());
+                        converted.copy_interleaved_ref(other);
+                        samples.extend_from_slice(converted.samples());
                     }
                 }
 
-                decode_buffer.lock().unwrap().extend(samples);
+                if let Some(end) = source.end_frame.into_iter().chain(loop_end).min() {
+                    let frames = end.saturating_sub(packet_frame) as usize;
+                    samples.truncate(frames * source.channels);
+                }
 
-                // simulate streaming rate (may be adjustable)
-                std::thread::sleep(Duration::from_millis(10));
+                if let Some(skip) = source.skip_to {
+                    let skipped = skip.saturating_sub(packet_frame) as usize * source.channels;
+                    // Packets wholly before it are dropped and it's kept
+                    if skipped < samples.len() {
+                        source.skip_to = None;
                     }
+                    samples.drain(..skipped.min(samples.len()));
+                }
+
+                if silence
+                    .as_mut()
+                    .is_some_and(|silence| silence.skip(&samples))
+                {
+                    skipped_silence = true;
+                    continue;
+                }
+                if skipped_silence {
+                    // Playback jumps ahead to here once the silence has played
+                    skipped_silence = false;
+                    let position = silence::frame_position(packet_frame, source.sample_rate);
+                    handoff.clock.mark(pushed, position);
+                }
+
+                if let Some(downmix) = &downmix {
+                    samples = downmix.process(&samples);
+                }
+
+                let rate = match bit_perfect {
+                    true => 1.0,
+                    false => f32::from_bits(speed.load(Ordering::Relaxed)),
+                };
+                let samples = stretch.process(&samples, rate);
+
+                for sink in &mut mirror_sinks {
+                    sink.push(&samples);
+                }
+
+                let mut samples = resampler.process(&samples);
+                if let Some(remap) = &remap {
+                    samples = remap.process(&samples);
+                }
+
+                // Wait for room, giving up on these samples if a seek or
+                // stop comes in meanwhile
+                let mut rest = &samples[..];
+                while !rest.is_empty()
+                    && !stopped.load(Ordering::SeqCst)
+                    && seek_request.lock().unwrap().is_none()
+                {
+                    let room = producer.slots().min(rest.len());
+                    if room == 0 {
+                        thread::park();
+                        continue;
+                    }
+
+                    if let Ok(chunk) = producer.write_chunk_uninit(room) {
+                        chunk.fill_from_iter(rest[..room].iter().copied());
+                    }
+                    rest = &rest[room..];
+                    pushed += room as u64;
+                    handoff.pushed.store(pushed, Ordering::SeqCst);
+                }
+            }
 
             // Decoding is finished!
             log::debug!("Finished decoding, setting decoder_done = true");
@@ -243,15 +693,179 @@ impl Player {
         });
 
 
+        decoder.set(handle.thread().clone()).ok();
         self.handle = Some(handle);
-        self.stream = Some(stream); // store the stream if needed for later stop/resume
+        self.stream = Some(stream);
         self.buffer = buffer;
+
+        if self.is_paused {
+            self.sync_streams();
+        }
+
+        Ok(())
+    }
+
+    /// Jumps to `position` in the current file.
+    ///
+    /// Queued samples are dropped right away so the jump is heard at once.
+    /// If the file has already been decoded to the end, it is reopened there.
+    pub fn seek(&mut self, position: Duration) {
+        let Some(path) = self.current_path.clone() else {
+            return;
+        };
+
+        let queued = {
+            let mut request = self.seek_request.lock().unwrap();
+            // The decoder may already be into the next track
+            let switched = self.handoff.transition.lock().unwrap().is_some();
+            let restart = switched || self.is_decoder_done.load(Ordering::SeqCst);
+            if !restart {
+                // Cut before the decode thread can pick the request up and
+                // start queueing new audio
+                let played = self.handoff.played.load(Ordering::SeqCst);
+                let pushed = self.handoff.pushed.load(Ordering::SeqCst);
+                let kept = self.fade.cut(played, pushed);
+                self.handoff.clock.restart(kept, position);
+                *request = Some(position);
+            }
+            !restart
+        };
+
+        if !queued {
+            if let Err(err) = self.play_range(&path, position, self.range_end) {
+                log::error!("Failed to reopen {:?}: {err}", path);
+            }
+            return;
+        }
+
+        self.handoff.next.lock().unwrap().take();
+        self.autoplay_trigger.store(false, Ordering::SeqCst);
+        self.wake_decoder();
+    }
+
+    /// Loops playback between two positions in the file, jumping to the
+    /// first one right away; `None` plays on normally.
+    pub fn set_loop(&mut self, range: Option<(Duration, Duration)>) {
+        *self.ab_loop.lock().unwrap() = range;
+        if let Some((start, _)) = range {
+            self.seek(start);
+        }
     }
 
+    /// Length of the current file, as stated by the container, or estimated
+    /// from its bitrate when it doesn't say.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Station name and live title of the radio stream playing, for
+    /// streams that send ICY metadata.
+    pub fn icy_info(&self) -> Option<IcyInfo> {
+        self.icy.as_ref().map(|icy| icy.lock().unwrap().clone())
+    }
+
+    /// Position in the current file of the audio played so far. It stands
+    /// still while paused and follows seeks, A-B loops and gapless track
+    /// changes once they are heard.
+    pub fn position(&self) -> Duration {
+        if !self.is_loaded() {
+            return Duration::ZERO;
+        }
+
+        let played = self.handoff.played.load(Ordering::SeqCst);
+        self.handoff.clock.position(played)
+    }
+
+    /// Whether the output stream failed since the last call, e.g. because
+    /// its device was unplugged.
+    pub fn take_stream_failure(&self) -> bool {
+        self.stream_failed.swap(false, Ordering::SeqCst)
+    }
+
+    /// Whether there is an output device that can be opened.
+    pub fn output_available(&self) -> bool {
+        self.output().is_some_and(|output| output.is_ready())
+    }
+
+    /// Where the next stream plays: the pipe command if one is set,
+    /// otherwise the configured device.
+    fn output(&self) -> Option<Box<dyn AudioOutput>> {
+        if let Some(command) = &self.pipe_command {
+            return Some(Box::new(PipeOutput::new(command)));
+        }
+        let output = CpalOutput::find(self.host_id, self.device_match.as_deref())?;
+        Some(Box::new(output))
+    }
+
+    /// Whether the decoder reached the end of the current track and is
+    /// waiting for [`Player::set_next`]. Reports each wait once.
+    pub fn wants_next(&self) -> bool {
+        self.handoff.wants_next.swap(false, Ordering::SeqCst)
+    }
+
+    /// Queues a range to follow the current one in the same stream, so
+    /// there's no gap between them. Files with a different sample rate or
+    /// channel count still start a new stream.
+    pub fn set_next(&mut self, path: &Path, start: Duration, end: Option<Duration>) {
+        *self.handoff.next.lock().unwrap() = Some(NextRange {
+            path: path.to_path_buf(),
+            start,
+            end,
+        });
+        self.wake_decoder();
+    }
+
+    /// Drops the range queued with [`Player::set_next`], unless the decoder
+    /// already moved on to it.
+    pub fn cancel_next(&mut self) {
+        self.handoff.next.lock().unwrap().take();
+    }
+
+    /// Checks whether playback reached the track queued with
+    /// [`Player::set_next`], and makes it the current one if so.
+    pub fn take_transition(&mut self) -> bool {
+        let mut transition = self.handoff.transition.lock().unwrap();
+        let played = self.handoff.played.load(Ordering::SeqCst);
+        if transition.as_ref().is_none_or(|t| played < t.at) {
+            return false;
+        }
+
+        let Some(transition) = transition.take() else {
+            return false;
+        };
+        self.current_path = Some(transition.path);
+        self.range_end = transition.end;
+        self.duration = transition.duration;
+        self.icy = None;
+        true
+    }
+
+    /// Stops playback, fading out first, and ends the decode thread.
     pub fn stop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.wake_decoder();
+
+        self.fade.set_out(true);
+        let played = self.handoff.played.load(Ordering::SeqCst);
+        let pushed = self.handoff.pushed.load(Ordering::SeqCst);
+        let fading = !self.is_paused && self.fade.cut(played, pushed) > played;
+        if fading && self.stream.is_some() {
+            // Let the last bit fade out before the stream goes away
+            thread::sleep(self.fade_length);
+        }
+
         self.stream = None;
+        self.mirror_streams.clear();
+
+        // Wait for the decode thread to notice, so it can't touch anything
+        // shared with the next stream
+        if let Some(Err(_)) = self.handle.take().map(JoinHandle::join) {
+            log::error!("Decode thread panicked");
+        }
+
         self.is_playing = false;
         self.current_path = None;
+        self.icy = None;
         self.buffer.lock().unwrap().clear();
     }
 
@@ -263,16 +877,136 @@ impl Player {
         self.buffer.lock().unwrap().is_empty() && self.is_playing
     }
 
+    /// Pauses or resumes the output streams and the decode thread, so
+    /// nothing keeps the CPU or the sound card busy while paused.
     pub fn set_paused(&mut self, paused: bool) {
         self.is_paused = paused;
         self.paused_flag.store(paused, Ordering::SeqCst);
+        self.fade.set_out(paused);
+        if paused && self.stream.is_some() {
+            // Give the callback time to fade out before the stream stops
+            thread::sleep(self.fade_length);
+        }
+        self.sync_streams();
+        if !paused {
+            self.wake_decoder();
+        }
+    }
+
+    /// Starts or pauses the streams to match `is_paused`. Where the backend
+    /// can't pause a stream, its callback plays silence instead.
+    fn sync_streams(&self) {
+        for stream in self.stream.iter().chain(&self.mirror_streams) {
+            if let Err(err) = stream.set_paused(self.is_paused) {
+                log::warn!("Failed to pause or resume output: {err}");
+            }
+        }
+    }
+
+    /// Lets a parked decode thread check for work again.
+    fn wake_decoder(&self) {
+        if let Some(handle) = &self.handle {
+            handle.thread().unpark();
+        }
+    }
+
+    /// Sets the output gain; values above 1.0 amplify.
+    pub fn set_volume(&mut self, volume: f32) {
+        let volume = volume.max(0.0);
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Sets the ReplayGain and per-track offset included in the volume.
+    /// Mirrors play at their own volume, so they apply only this.
+    pub fn set_track_gain(&mut self, gain: f32) {
+        self.track_gain
+            .store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Sets how long pausing, resuming, seeking and stopping take to fade.
+    pub fn set_fade_length(&mut self, length: Duration) {
+        self.fade_length = length;
+    }
+
+    /// Sets the buffer size streams are opened with from now on.
+    pub fn set_buffer_frames(&mut self, frames: Option<u32>) {
+        self.buffer_frames = frames;
+    }
+
+    /// Plays through the audio host called `name`, e.g. "jack", instead of
+    /// the platform's default.
+    pub fn set_audio_host(&mut self, name: Option<&str>) {
+        self.host_id = name.and_then(|name| {
+            let id = output::host_id(name);
+            if id.is_none() {
+                log::warn!("Audio host {name:?} isn't available in this build");
+            }
+            id
+        });
+    }
+
+    /// Plays on the first device whose name contains `device` rather than
+    /// the default one, and with `bit_perfect` at each file's own format
+    /// without volume, effects or resampling where the device allows it.
+    pub fn set_output(&mut self, device: Option<String>, bit_perfect: bool) {
+        self.device_match = device;
+        self.bit_perfect = bit_perfect;
+    }
+
+    /// Pipes the audio into `command` instead of playing it on a device;
+    /// see [`PipeOutput`].
+    pub fn set_pipe_command(&mut self, command: Option<String>) {
+        self.pipe_command = command;
+    }
+
+    /// Starts writing everything played, as heard, to a WAV file at `path`.
+    pub fn start_recording(&mut self, path: &Path) -> anyhow::Result<()> {
+        if self.recorder.is_some() {
+            anyhow::bail!("Already recording");
+        }
+        let Some(format) = self.output_format.filter(|_| self.is_loaded()) else {
+            anyhow::bail!("Nothing is playing");
+        };
+
+        let recorder = Recorder::start(path, format.channels, format.rate, &self.record_tap)?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Ends the recording, returning where it was saved. `None` if there
+    /// wasn't one.
+    pub fn stop_recording(&mut self) -> Option<io::Result<PathBuf>> {
+        self.recorder.take().map(Recorder::finish)
+    }
+
+    /// The file being recorded to, if any.
+    pub fn recording_path(&self) -> Option<&Path> {
+        self.recorder
+            .as_ref()
+            .map(|recorder| recorder.path.as_path())
+    }
+
+    /// A recording ended by a new track playing at a different format,
+    /// reported once.
+    pub fn take_ended_recording(&mut self) -> Option<io::Result<PathBuf>> {
+        self.recording_ended.take()
+    }
+
+    /// Output latency the backend reported for the current stream, if any.
+    pub fn output_latency(&self) -> Option<Duration> {
+        match self.latency.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
     }
 
-    pub fn pause(&mut self) {
-        self.set_paused(true);
+    /// Silences the output while playback carries on.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
     }
 
-    pub fn resume(&mut self) {
-        self.set_paused(false);
+    /// Sets the playback speed; the pitch stays the same.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
     }
 }
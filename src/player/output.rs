@@ -1,59 +1,257 @@
+use std::time::Duration;
+
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::sync::{Arc, Mutex};
-
-#[derive(Default)]
-pub struct AudioOutput {
-    pub sample_rate: u32,
-    pub channels: u16,
-    stream: Option<cpal::Stream>,
-    pub buffer: Arc<Mutex<Vec<f32>>>, // Interleaved stereo samples
-}
-
-impl AudioOutput {
-    pub fn new(sample_rate: u32, channels: u16) -> Self {
-        Self {
-            sample_rate,
-            channels,
-            stream: None,
-            buffer: Arc::new(Mutex::new(Vec::new())),
+use cpal::{BufferSize, Device, Host, HostId, OutputCallbackInfo, SampleFormat, Stream};
+
+use super::dither::Dither;
+use super::error::PlayerError;
+use super::resample::{self, OutputFormat};
+
+/// Renders the next samples into the buffer it's given, interleaved. Also
+/// told how long until they're heard, when the output knows.
+pub type Fill = Box<dyn FnMut(&mut [f32], Option<Duration>) + Send>;
+
+/// Called when a running stream fails, e.g. because its device went away.
+pub type OnError = Box<dyn FnMut(anyhow::Error) + Send>;
+
+/// A place to play audio: a device on one of cpal's audio hosts, or a
+/// command audio is piped into. Looked up afresh for each stream, so a
+/// device plugged in meanwhile is picked up.
+pub trait AudioOutput {
+    /// The audio system, as shown in the diagnostics popup
+    fn host_name(&self) -> &'static str;
+
+    fn device_name(&self) -> Option<String>;
+
+    /// Whether a stream can be opened right now.
+    fn is_ready(&self) -> bool;
+
+    /// Format to open the output at for audio with `channels` at `rate`:
+    /// the same when it's supported, otherwise the closest that is.
+    fn format(&self, channels: usize, rate: u32) -> anyhow::Result<OutputFormat>;
+
+    /// Format that plays audio with `channels` at `rate` and
+    /// `bits_per_sample` unchanged, if there is one.
+    fn exact_format(
+        &self,
+        channels: usize,
+        rate: u32,
+        bits_per_sample: Option<u32>,
+    ) -> Option<OutputFormat>;
+
+    /// Frames per callback a stream asking for `frames` gets; `None` leaves
+    /// it to the driver.
+    fn buffer_frames(&self, frames: Option<u32>) -> Option<u32>;
+
+    /// Starts a stream playing whatever `fill` renders. With `bit_depth`, the
+    /// bits per sample of a file played bit-perfect, integer formats get the
+    /// file's samples back exactly rather than dithered.
+    fn open(
+        &self,
+        format: OutputFormat,
+        buffer_frames: Option<u32>,
+        bit_depth: Option<u32>,
+        fill: Fill,
+        on_error: OnError,
+    ) -> Result<Box<dyn OutputStream>, PlayerError>;
+}
+
+/// A running stream; it stops when dropped.
+pub trait OutputStream {
+    /// Pauses or resumes the stream. Where that isn't possible the stream
+    /// keeps running, and the player fills it with silence.
+    fn set_paused(&self, paused: bool) -> anyhow::Result<()>;
+}
+
+/// Looks up an audio host by name, ignoring case: e.g. "alsa" or "jack" on
+/// Linux, "wasapi" or "asio" on Windows. `None` if this build doesn't
+/// include it.
+pub fn host_id(name: &str) -> Option<HostId> {
+    cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+}
+
+/// Connects to the host `id`, or to the system default when it's unset or
+/// can't be reached right now, e.g. because the JACK server isn't running.
+pub fn host(id: Option<HostId>) -> Host {
+    let Some(id) = id else {
+        return cpal::default_host();
+    };
+
+    cpal::host_from_id(id).unwrap_or_else(|err| {
+        log::debug!(
+            "Audio host {} is unavailable, using the default: {err}",
+            id.name()
+        );
+        cpal::default_host()
+    })
+}
+
+/// The first device whose name contains `name`, ignoring case.
+pub fn find_device(host: &Host, name: &str) -> Option<Device> {
+    let wanted = name.to_lowercase();
+    host.output_devices().ok()?.find(|device| {
+        device
+            .name()
+            .is_ok_and(|name| name.to_lowercase().contains(&wanted))
+    })
+}
+
+/// A device on one of cpal's audio hosts.
+pub struct CpalOutput {
+    host: Host,
+    device: Device,
+}
+
+impl CpalOutput {
+    /// The device matching `name` on the host `id`, or the default one when
+    /// `name` is unset or no device matches it.
+    pub fn find(id: Option<HostId>, name: Option<&str>) -> Option<Self> {
+        let host = host(id);
+        let found = name.and_then(|name| {
+            let device = find_device(&host, name);
+            if device.is_none() {
+                log::warn!("No output device matching {:?}, using the default", name);
+            }
+            device
+        });
+        let device = found.or_else(|| host.default_output_device())?;
+        Some(Self { host, device })
+    }
+}
+
+impl AudioOutput for CpalOutput {
+    fn host_name(&self) -> &'static str {
+        self.host.id().name()
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.device.name().ok()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.device.default_output_config().is_ok()
+    }
+
+    fn format(&self, channels: usize, rate: u32) -> anyhow::Result<OutputFormat> {
+        resample::device_format(&self.device, channels, rate)
+    }
+
+    fn exact_format(
+        &self,
+        channels: usize,
+        rate: u32,
+        bits_per_sample: Option<u32>,
+    ) -> Option<OutputFormat> {
+        resample::exact_format(&self.device, channels, rate, bits_per_sample)
+    }
+
+    fn buffer_frames(&self, frames: Option<u32>) -> Option<u32> {
+        match resample::buffer_size(&self.device, frames) {
+            BufferSize::Fixed(frames) => Some(frames),
+            BufferSize::Default => None,
         }
     }
 
-    pub fn start(&mut self) -> anyhow::Result<()> {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .ok_or_else(|| anyhow::anyhow!("No output device found"))?;
-
-        let config = device.default_output_config()?.config();
-
-        let channels = self.channels as usize;
-        let buffer = self.buffer.clone();
-
-        let stream = device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _| {
-                let mut buf = buffer.lock().unwrap();
-                for frame in data.chunks_mut(channels) {
-                    for sample in frame.iter_mut() {
-                        *sample = buf.pop().unwrap_or(0.0);
-                    }
-                }
-            },
-            move |err| {
-                log::error!("CPAL stream error: {err}");
-            },
-            None,
-        )?;
-
-        stream.play()?;
-        self.stream = Some(stream);
-        Ok(())
+    fn open(
+        &self,
+        format: OutputFormat,
+        buffer_frames: Option<u32>,
+        bit_depth: Option<u32>,
+        mut fill: Fill,
+        mut on_error: OnError,
+    ) -> Result<Box<dyn OutputStream>, PlayerError> {
+        let config = cpal::StreamConfig {
+            channels: format.channels as u16,
+            sample_rate: cpal::SampleRate(format.rate),
+            buffer_size: buffer_frames.map_or(BufferSize::Default, BufferSize::Fixed),
+        };
+        let latency = |info: &OutputCallbackInfo| {
+            let timestamp = info.timestamp();
+            timestamp.playback.duration_since(&timestamp.callback)
+        };
+        let on_error = move |err: cpal::StreamError| on_error(err.into());
+
+        let device = &self.device;
+        let stream = match format.sample_format {
+            // Scaled back the way the decoder scaled the file's samples
+            SampleFormat::I16 if bit_depth.is_some() => {
+                let mut output = Vec::new();
+                device.build_output_stream(
+                    &config,
+                    move |data: &mut [i16], info: &OutputCallbackInfo| {
+                        output.resize(data.len(), 0.0);
+                        fill(&mut output, latency(info));
+                        for (out, sample) in data.iter_mut().zip(&output) {
+                            *out = to_int(*sample, 16) as i16;
+                        }
+                    },
+                    on_error,
+                    None,
+                )
+            }
+            // Floats only carry 24 bits, so converted audio gets those
+            SampleFormat::I32 => {
+                let bits = bit_depth.unwrap_or(24);
+                let mut output = Vec::new();
+                device.build_output_stream(
+                    &config,
+                    move |data: &mut [i32], info: &OutputCallbackInfo| {
+                        output.resize(data.len(), 0.0);
+                        fill(&mut output, latency(info));
+                        for (out, sample) in data.iter_mut().zip(&output) {
+                            *out = to_int(*sample, bits) << (32 - bits);
+                        }
+                    },
+                    on_error,
+                    None,
+                )
+            }
+            SampleFormat::I16 => {
+                let mut dither = Dither::default();
+                // Sized on the first callback, so only that one allocates
+                let mut output = Vec::new();
+                device.build_output_stream(
+                    &config,
+                    move |data: &mut [i16], info: &OutputCallbackInfo| {
+                        output.resize(data.len(), 0.0);
+                        fill(&mut output, latency(info));
+                        dither.convert(&output, data);
+                    },
+                    on_error,
+                    None,
+                )
+            }
+            _ => device.build_output_stream(
+                &config,
+                move |data: &mut [f32], info: &OutputCallbackInfo| fill(data, latency(info)),
+                on_error,
+                None,
+            ),
+        }
+        .map_err(|err| PlayerError::BuildStream(err.into()))?;
+
+        stream
+            .play()
+            .map_err(|err| PlayerError::PlayStream(err.into()))?;
+        Ok(Box::new(stream))
     }
+}
+
+/// `sample` as a `bits`-bit integer, scaled by the power of two the decoder
+/// divided by so each of the file's samples comes back unchanged.
+fn to_int(sample: f32, bits: u32) -> i32 {
+    let scale = (1u32 << (bits - 1)) as f32;
+    (sample * scale).round().clamp(-scale, scale - 1.0) as i32
+}
 
-    /// Append decoded PCM samples to buffer.
-    pub fn push_samples(&mut self, samples: &[f32]) {
-        let mut buf = self.buffer.lock().unwrap();
-        buf.extend_from_slice(samples);
+impl OutputStream for Stream {
+    fn set_paused(&self, paused: bool) -> anyhow::Result<()> {
+        match paused {
+            true => self.pause()?,
+            false => self.play()?,
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,140 @@
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use anyhow::anyhow;
+use cpal::SampleFormat;
+
+use super::error::PlayerError;
+use super::output::{AudioOutput, Fill, OnError, OutputStream};
+use super::resample::OutputFormat;
+
+/// Frames written per chunk when no buffer size is configured.
+const DEFAULT_FRAMES: u32 = 1024;
+
+/// Pipes the audio, as raw 32-bit little-endian floats, into a command such
+/// as `pw-cat` or a snapcast FIFO writer. The command reads it at its own
+/// pace, which is what keeps playback in time.
+pub struct PipeOutput {
+    command: String,
+}
+
+impl PipeOutput {
+    /// `command` is run through the shell, with `{rate}` and `{channels}`
+    /// replaced by the stream's format.
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl AudioOutput for PipeOutput {
+    fn host_name(&self) -> &'static str {
+        "pipe"
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.command.split_whitespace().next().map(str::to_string)
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    fn format(&self, channels: usize, rate: u32) -> anyhow::Result<OutputFormat> {
+        Ok(OutputFormat {
+            channels,
+            rate,
+            sample_format: SampleFormat::F32,
+        })
+    }
+
+    // Floats hold up to 24 bits exactly
+    fn exact_format(
+        &self,
+        channels: usize,
+        rate: u32,
+        bits_per_sample: Option<u32>,
+    ) -> Option<OutputFormat> {
+        match bits_per_sample {
+            Some(bits) if bits > 24 => None,
+            _ => self.format(channels, rate).ok(),
+        }
+    }
+
+    fn buffer_frames(&self, frames: Option<u32>) -> Option<u32> {
+        Some(frames.unwrap_or(DEFAULT_FRAMES))
+    }
+
+    fn open(
+        &self,
+        format: OutputFormat,
+        buffer_frames: Option<u32>,
+        _bit_depth: Option<u32>,
+        mut fill: Fill,
+        mut on_error: OnError,
+    ) -> Result<Box<dyn OutputStream>, PlayerError> {
+        let command = self
+            .command
+            .replace("{rate}", &format.rate.to_string())
+            .replace("{channels}", &format.channels.to_string());
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| PlayerError::BuildStream(anyhow!("failed to run {command:?}: {err}")))?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| PlayerError::PlayStream(anyhow!("{command:?} has no standard input")))?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = Arc::clone(&stopped);
+        let frames = buffer_frames.unwrap_or(DEFAULT_FRAMES) as usize;
+        let mut samples = vec![0.0; frames * format.channels];
+        let mut bytes = Vec::with_capacity(samples.len() * 4);
+        thread::spawn(move || {
+            while !thread_stopped.load(Ordering::SeqCst) {
+                fill(&mut samples, None);
+                bytes.clear();
+                bytes.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+
+                if let Err(err) = stdin.write_all(&bytes) {
+                    // Stopping kills the command, which breaks the pipe
+                    if !thread_stopped.load(Ordering::SeqCst) {
+                        on_error(anyhow!("{command:?} stopped reading: {err}"));
+                    }
+                    return;
+                }
+            }
+        });
+
+        Ok(Box::new(PipeStream { child, stopped }))
+    }
+}
+
+struct PipeStream {
+    child: Child,
+    stopped: Arc<AtomicBool>,
+}
+
+impl OutputStream for PipeStream {
+    // A pipe can't be paused; silence is written instead
+    fn set_paused(&self, _paused: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeStream {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rtrb::{Consumer, Producer, RingBuffer};
+
+/// Seconds of output the audio callback can get ahead of the file writer.
+const QUEUE_SECS: usize = 2;
+
+/// How long the writer sleeps when it has caught up with the callback.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// `WAVE_FORMAT_IEEE_FLOAT`
+const WAV_FLOAT: u16 = 3;
+
+/// Where the audio callback hands its output to the recorder, if one is
+/// running. The callback only ever `try_lock`s it, so it never waits.
+pub type RecordTap = Arc<Mutex<Option<Producer<f32>>>>;
+
+/// Writes the final output to a 32-bit float WAV file on a background
+/// thread, exactly as it's sent to the device.
+pub struct Recorder {
+    pub path: PathBuf,
+    pub channels: usize,
+    pub rate: u32,
+    tap: RecordTap,
+    stopped: Arc<AtomicBool>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Recorder {
+    /// Creates `path` and starts taking audio from `tap`.
+    pub fn start(path: &Path, channels: usize, rate: u32, tap: &RecordTap) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_header(&mut file, channels, rate, 0)?;
+
+        let (producer, consumer) = RingBuffer::new(rate as usize * channels * QUEUE_SECS);
+        *tap.lock().unwrap() = Some(producer);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = Arc::clone(&stopped);
+        let handle =
+            thread::spawn(move || write_samples(file, consumer, channels, rate, thread_stopped));
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            channels,
+            rate,
+            tap: Arc::clone(tap),
+            stopped,
+            handle,
+        })
+    }
+
+    /// Detaches from the callback, writes what's left and closes the file.
+    pub fn finish(self) -> io::Result<PathBuf> {
+        self.tap.lock().unwrap().take();
+        self.stopped.store(true, Ordering::SeqCst);
+
+        match self.handle.join() {
+            Ok(result) => result.map(|_| self.path),
+            Err(_) => Err(io::Error::other("recorder thread panicked")),
+        }
+    }
+}
+
+/// Hands `samples` to the recorder; what doesn't fit is dropped rather than
+/// holding up the callback.
+pub fn feed(tap: &RecordTap, samples: &[f32]) {
+    let Ok(mut producer) = tap.try_lock() else {
+        return;
+    };
+    let Some(producer) = producer.as_mut() else {
+        return;
+    };
+
+    let room = producer.slots().min(samples.len());
+    if let Ok(chunk) = producer.write_chunk_uninit(room) {
+        chunk.fill_from_iter(samples[..room].iter().copied());
+    }
+}
+
+fn write_samples(
+    mut file: BufWriter<File>,
+    mut consumer: Consumer<f32>,
+    channels: usize,
+    rate: u32,
+    stopped: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let mut written: u64 = 0;
+
+    loop {
+        // Read the flag first, so nothing pushed before it was set is missed
+        let finishing = stopped.load(Ordering::SeqCst);
+
+        let available = consumer.slots();
+        if available > 0 {
+            let chunk = consumer.read_chunk(available).map_err(io::Error::other)?;
+            let (first, second) = chunk.as_slices();
+            for sample in first.iter().chain(second) {
+                file.write_all(&sample.to_le_bytes())?;
+            }
+            chunk.commit_all();
+            written += available as u64;
+        } else if finishing {
+            break;
+        } else {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // Now that the length is known, fill it in
+    let data_len = (written * 4).min(u32::MAX as u64 - 64) as u32;
+    file.seek(SeekFrom::Start(0))?;
+    write_header(&mut file, channels, rate, data_len)?;
+    file.flush()
+}
+
+/// Writes the RIFF header for `data_len` bytes of float samples.
+fn write_header(out: &mut impl Write, channels: usize, rate: u32, data_len: u32) -> io::Result<()> {
+    let channels = channels as u16;
+    let block_align = channels * 4;
+    let frames = data_len / block_align.max(1) as u32;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(4 + 26 + 12 + 8 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&18u32.to_le_bytes())?;
+    out.write_all(&WAV_FLOAT.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&rate.to_le_bytes())?;
+    out.write_all(&(rate * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&32u16.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+
+    // Non-PCM formats need a frame count
+    out.write_all(b"fact")?;
+    out.write_all(&4u32.to_le_bytes())?;
+    out.write_all(&frames.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
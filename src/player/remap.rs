@@ -0,0 +1,38 @@
+/// Fits interleaved mono or stereo audio to a device with another channel
+/// count.
+///
+/// Mono goes to both front speakers, and stereo is averaged for a mono
+/// device. Channels the audio doesn't have stay silent, so stereo on a
+/// surround-only device plays from the front left and right.
+pub struct ChannelMap {
+    from: usize,
+    to: usize,
+}
+
+impl ChannelMap {
+    pub fn new(from: usize, to: usize) -> Self {
+        Self {
+            from: from.max(1),
+            to: to.max(1),
+        }
+    }
+
+    pub fn process(&self, samples: &[f32]) -> Vec<f32> {
+        let mut output = Vec::with_capacity(samples.len() / self.from * self.to);
+        for frame in samples.chunks_exact(self.from) {
+            if self.to == 1 {
+                output.push(frame.iter().sum::<f32>() / self.from as f32);
+                continue;
+            }
+
+            for ch in 0..self.to {
+                output.push(match self.from {
+                    1 if ch < 2 => frame[0],
+                    _ if ch < self.from => frame[ch],
+                    _ => 0.0,
+                });
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,231 @@
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+
+use cpal::traits::DeviceTrait;
+use cpal::{BufferSize, Device, SampleFormat, SupportedBufferSize, SupportedStreamConfigRange};
+
+/// How to open an output stream on a device.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputFormat {
+    pub channels: usize,
+    pub rate: u32,
+    /// `F32`, or `I16` for devices that only take 16-bit samples
+    pub sample_format: SampleFormat,
+}
+
+/// Format to open `device` at for audio with `channels` at `sample_rate`:
+/// the same when the device supports it, otherwise the closest it does. The
+/// audio is then resampled and its channels remapped to fit.
+pub fn device_format(
+    device: &Device,
+    channels: usize,
+    sample_rate: u32,
+) -> anyhow::Result<OutputFormat> {
+    let ranges: Vec<_> = device.supported_output_configs()?.collect();
+    let rate_in = |range: &SupportedStreamConfigRange| {
+        sample_rate.clamp(range.min_sample_rate().0, range.max_sample_rate().0)
+    };
+
+    // Fewest changes first: the same channel count, then the fewest extra
+    // channels, then as many of ours as there's room for
+    let closest = |format: SampleFormat| {
+        ranges
+            .iter()
+            .filter(|range| range.sample_format() == format)
+            .min_by_key(|range| {
+                let count = range.channels() as usize;
+                let channel_cost = match count.cmp(&channels) {
+                    Ordering::Equal => 0,
+                    Ordering::Greater => count - channels,
+                    Ordering::Less => usize::MAX - count,
+                };
+                (channel_cost, rate_in(range).abs_diff(sample_rate))
+            })
+    };
+
+    match closest(SampleFormat::F32).or_else(|| closest(SampleFormat::I16)) {
+        Some(range) => Ok(OutputFormat {
+            channels: range.channels() as usize,
+            rate: rate_in(range),
+            sample_format: range.sample_format(),
+        }),
+        None => {
+            let config = device.default_output_config()?;
+            Ok(OutputFormat {
+                channels: config.channels() as usize,
+                rate: config.sample_rate().0,
+                sample_format: SampleFormat::F32,
+            })
+        }
+    }
+}
+
+/// Format to open `device` at for bit-perfect playback: exactly the track's
+/// channels and rate, with integer samples of at least its bit depth where
+/// the device takes them. `None` if the device can't play it unchanged.
+pub fn exact_format(
+    device: &Device,
+    channels: usize,
+    sample_rate: u32,
+    bits_per_sample: Option<u32>,
+) -> Option<OutputFormat> {
+    let ranges: Vec<_> = device.supported_output_configs().ok()?.collect();
+
+    // Floats hold up to 24 bits exactly, so deeper files can't pass through
+    // them unchanged; lossy formats decode to floats
+    let preferred: &[SampleFormat] = match bits_per_sample {
+        Some(bits) if bits <= 16 => &[SampleFormat::I16, SampleFormat::I32, SampleFormat::F32],
+        Some(bits) if bits <= 24 => &[SampleFormat::I32, SampleFormat::F32],
+        Some(_) => &[],
+        None => &[SampleFormat::F32],
+    };
+
+    preferred.iter().find_map(|format| {
+        ranges
+            .iter()
+            .find(|range| {
+                range.sample_format() == *format
+                    && range.channels() as usize == channels
+                    && (range.min_sample_rate().0..=range.max_sample_rate().0)
+                        .contains(&sample_rate)
+            })
+            .map(|range| OutputFormat {
+                channels,
+                rate: sample_rate,
+                sample_format: range.sample_format(),
+            })
+    })
+}
+
+/// Buffer size to open `device` with: `frames` per callback, kept within
+/// what the device reports it supports, or the driver's choice when unset.
+pub fn buffer_size(device: &Device, frames: Option<u32>) -> BufferSize {
+    let Some(frames) = frames else {
+        return BufferSize::Default;
+    };
+
+    let supported = device
+        .default_output_config()
+        .map(|config| *config.buffer_size());
+    match supported {
+        Ok(SupportedBufferSize::Range { min, max }) => BufferSize::Fixed(frames.clamp(min, max)),
+        _ => BufferSize::Fixed(frames),
+    }
+}
+
+/// Zero crossings of the filter on each side of an output frame.
+const ZERO_CROSSINGS: usize = 32;
+/// Points per zero crossing in the filter table, interpolated between.
+const TABLE_STEPS: usize = 128;
+
+/// Windowed-sinc resampler for interleaved samples. It low-passes below the
+/// lower rate's Nyquist frequency, so downsampling doesn't alias, and keeps
+/// the input it still needs between calls so packet boundaries don't click.
+pub struct Resampler {
+    channels: usize,
+    /// Input frames per output frame
+    step: f64,
+    /// Cutoff as a fraction of the input's Nyquist frequency
+    cutoff: f64,
+    /// Input frames the filter reaches on either side of an output frame
+    half_width: usize,
+    /// Right half of the filter, `TABLE_STEPS` points per zero crossing
+    table: Vec<f32>,
+    /// Input frames still in reach of the filter
+    buffer: Vec<f32>,
+    /// Position of the next output frame in `buffer`, in frames
+    position: f64,
+    /// Filter weights for the current output frame
+    weights: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(channels: usize, from_rate: u32, to_rate: u32) -> Self {
+        let channels = channels.max(1);
+        let step = from_rate as f64 / to_rate as f64;
+        // A little under Nyquist, so the filter has room to roll off
+        let cutoff = 0.95 * (1.0 / step).min(1.0);
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+
+        // Sinc under a Blackman window, ending in a zero to interpolate to
+        let table = (0..=ZERO_CROSSINGS * TABLE_STEPS + 1)
+            .map(|i| {
+                let x = i as f64 / TABLE_STEPS as f64;
+                if x >= ZERO_CROSSINGS as f64 {
+                    return 0.0;
+                }
+                let sinc = match i {
+                    0 => 1.0,
+                    _ => (PI * x).sin() / (PI * x),
+                };
+                let w = PI * x / ZERO_CROSSINGS as f64;
+                (sinc * (0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos())) as f32
+            })
+            .collect();
+
+        Self {
+            channels,
+            step,
+            cutoff,
+            half_width,
+            table,
+            // Silence before the start, for the first frames' filter to reach
+            buffer: vec![0.0; half_width * channels],
+            position: half_width as f64,
+            weights: Vec::with_capacity(2 * half_width),
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.step == 1.0 {
+            return input.to_vec();
+        }
+
+        let channels = self.channels;
+        self.buffer.extend_from_slice(input);
+        let frames = self.buffer.len() / channels;
+
+        let expected = (input.len() / channels) as f64 / self.step;
+        let mut output = Vec::with_capacity((expected as usize + 1) * channels);
+        while (self.position as usize) + self.half_width < frames {
+            let first = self.position as usize + 1 - self.half_width;
+            let last = self.position as usize + self.half_width;
+
+            self.weights.clear();
+            for i in first..=last {
+                let distance = (i as f64 - self.position).abs();
+                self.weights.push(self.weight(distance));
+            }
+
+            for ch in 0..channels {
+                let sample: f32 = self
+                    .weights
+                    .iter()
+                    .enumerate()
+                    .map(|(j, weight)| self.buffer[(first + j) * channels + ch] * weight)
+                    .sum();
+                output.push(sample);
+            }
+            self.position += self.step;
+        }
+
+        // Drop the input no later output frame reaches back to
+        let done = (self.position as usize + 1).saturating_sub(self.half_width);
+        let done = done.min(frames);
+        self.buffer.drain(..done * channels);
+        self.position -= done as f64;
+        output
+    }
+
+    /// The filter at `distance` input frames from an output frame.
+    fn weight(&self, distance: f64) -> f32 {
+        let x = distance * self.cutoff * TABLE_STEPS as f64;
+        let i = x as usize;
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let t = (x - i as f64) as f32;
+        let value = self.table[i] + (self.table[i + 1] - self.table[i]) * t;
+        value * self.cutoff as f32
+    }
+}
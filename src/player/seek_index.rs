@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// An index entry is kept every this many MPEG frames, about a second.
+const FRAMES_PER_POINT: u64 = 38;
+
+/// Frames decoded before the target after a jump, so the decoder has the
+/// bit reservoir data the target frame refers back to.
+const PREROLL_FRAMES: u64 = 4;
+
+/// Bitrates of MPEG-1 Layer III, in kbit/s, by header index.
+const BITRATES_V1: [u32; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+
+/// Bitrates of MPEG-2 and 2.5 Layer III.
+const BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// Indexes built or being built this run, by path.
+static CACHE: OnceLock<Mutex<HashMap<PathBuf, Entry>>> = OnceLock::new();
+
+enum Entry {
+    Building,
+    /// `None` for files that turned out not to need an index
+    Built(Option<Arc<SeekIndex>>),
+}
+
+/// Where the frames of an MP3 without a Xing or VBRI header start, so it
+/// can be seeked exactly and its length is known. Without such a header
+/// both are otherwise guessed from the bitrate, which is wrong for VBR files.
+pub struct SeekIndex {
+    /// Audio frame and byte offset of every `FRAMES_PER_POINT`th MPEG frame
+    points: Vec<(u64, u64)>,
+    /// Audio frames in the whole file
+    pub frames: u64,
+    /// Audio frames per MPEG frame
+    frame_len: u64,
+}
+
+impl SeekIndex {
+    /// The index for `path`, once it's built. The first call starts building
+    /// it in the background, as that reads the whole file; until it's done
+    /// this is `None`, as it is for files that aren't MP3s or have a header
+    /// that already states their length.
+    pub fn for_file(path: &Path) -> Option<Arc<SeekIndex>> {
+        let is_mp3 = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+        if !is_mp3 {
+            return None;
+        }
+
+        let cache = CACHE.get_or_init(Default::default);
+        let mut entries = cache.lock().unwrap();
+        match entries.get(path) {
+            Some(Entry::Built(index)) => return index.clone(),
+            Some(Entry::Building) => return None,
+            None => {}
+        }
+        entries.insert(path.to_path_buf(), Entry::Building);
+
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            let index = match Self::build(&path) {
+                Ok(index) => index.map(Arc::new),
+                Err(err) => {
+                    log::warn!("Failed to index {:?} for seeking: {err}", path);
+                    None
+                }
+            };
+            cache.lock().unwrap().insert(path, Entry::Built(index));
+        });
+        None
+    }
+
+    /// Walks the frame headers of the file, skipping over the audio data.
+    fn build(path: &Path) -> io::Result<Option<Self>> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut pos = id3v2_len(&mut file)?;
+        file.seek(SeekFrom::Start(pos))?;
+        // Where the reader is; moving relative to it keeps its buffer
+        let mut at = pos;
+
+        let mut points = Vec::new();
+        let mut count = 0u64;
+        let mut frame_len = 0;
+        let mut header = [0u8; 4];
+
+        loop {
+            file.seek_relative(pos as i64 - at as i64)?;
+            if file.read_exact(&mut header).is_err() {
+                break;
+            }
+            at = pos + 4;
+
+            let Some(frame) = FrameHeader::parse(header) else {
+                // Not at a frame; look for the next one a byte further on
+                if count > 0 && header.starts_with(b"TAG") {
+                    break;
+                }
+                pos += 1;
+                continue;
+            };
+
+            if count == 0 {
+                let mut body = vec![0; frame.size.saturating_sub(4)];
+                file.read_exact(&mut body)?;
+                at += body.len() as u64;
+                if [&b"Xing"[..], b"Info", b"VBRI"]
+                    .iter()
+                    .any(|tag| body.windows(4).any(|bytes| bytes == *tag))
+                {
+                    return Ok(None);
+                }
+                frame_len = frame.samples;
+            }
+
+            if count.is_multiple_of(FRAMES_PER_POINT) {
+                points.push((count * frame_len, pos));
+            }
+            count += 1;
+            pos += frame.size as u64;
+        }
+
+        if count == 0 {
+            return Ok(None);
+        }
+
+        log::debug!("Indexed {count} MPEG frames of {:?}", path);
+        Ok(Some(Self {
+            points,
+            frames: count * frame_len,
+            frame_len,
+        }))
+    }
+
+    /// The indexed position to start decoding from to reach `frame`, as an
+    /// audio frame and byte offset, leaving room for the decoder to warm up.
+    pub fn before(&self, frame: u64) -> (u64, u64) {
+        let target = frame.saturating_sub(PREROLL_FRAMES * self.frame_len);
+        let i = self.points.partition_point(|(start, _)| *start <= target);
+        self.points[i.saturating_sub(1)]
+    }
+}
+
+/// The parts of an MPEG audio frame header needed to step over the frame.
+struct FrameHeader {
+    /// Frame length in bytes, header included
+    size: usize,
+    /// Audio frames it decodes to
+    samples: u64,
+}
+
+impl FrameHeader {
+    /// Reads a Layer III header; anything else is `None`.
+    fn parse(bytes: [u8; 4]) -> Option<Self> {
+        if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        let version = (bytes[1] >> 3) & 0b11;
+        let layer = (bytes[1] >> 1) & 0b11;
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let rate_index = (bytes[2] >> 2) & 0b11;
+        let padding = ((bytes[2] >> 1) & 1) as usize;
+
+        // Version 1 is reserved, layer 1 is Layer III
+        if version == 1 || layer != 1 || bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+
+        let base_rate = match rate_index {
+            0 => 44100,
+            1 => 48000,
+            2 => 32000,
+            _ => return None,
+        };
+
+        let (sample_rate, bitrate, samples, factor) = match version {
+            3 => (base_rate, BITRATES_V1[bitrate_index], 1152, 144),
+            2 => (base_rate / 2, BITRATES_V2[bitrate_index], 576, 72),
+            _ => (base_rate / 4, BITRATES_V2[bitrate_index], 576, 72),
+        };
+
+        Some(Self {
+            size: (factor * bitrate * 1000 / sample_rate) as usize + padding,
+            samples,
+        })
+    }
+}
+
+/// Length of the ID3v2 tag at the start of the file, if there is one.
+fn id3v2_len(file: &mut impl Read) -> io::Result<u64> {
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_err() || !header.starts_with(b"ID3") {
+        return Ok(0);
+    }
+
+    // Sizes are stored 7 bits per byte
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |size, byte| (size << 7) | u64::from(byte & 0x7f));
+    let footer = match header[5] & 0x10 {
+        0 => 0,
+        _ => 10,
+    };
+    Ok(10 + size + footer)
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use super::dsp::DspSettings;
+
+/// Fast-forwards through long silences, such as pauses in a podcast or the
+/// gap before a hidden track. Once the audio has stayed below the threshold
+/// for the minimum length, the rest of the silence is dropped until sound
+/// comes back, so short pauses are left alone and long ones are cut short.
+pub struct SilenceSkipper {
+    settings: Arc<DspSettings>,
+    /// Loudest sample still counted as silence, linear
+    threshold: f32,
+    /// Silent samples, over all channels, played before skipping starts
+    min_samples: u64,
+    /// Silent samples in a row so far
+    run: u64,
+}
+
+impl SilenceSkipper {
+    pub fn new(settings: Arc<DspSettings>, sample_rate: u32, channels: usize) -> Self {
+        let (threshold_db, min_length) = settings.silence_detection();
+        let min_samples = min_length.as_secs_f64() * sample_rate as f64 * channels as f64;
+
+        Self {
+            settings,
+            threshold: 10f32.powf(threshold_db / 20.0),
+            min_samples: min_samples as u64,
+            run: 0,
+        }
+    }
+
+    /// Whether to drop `samples`, a decoded packet, as part of a long
+    /// silence. Always false while skipping is turned off.
+    pub fn skip(&mut self, samples: &[f32]) -> bool {
+        let silent = samples.iter().all(|sample| sample.abs() < self.threshold);
+        if !silent || !self.settings.skip_silence.load(Ordering::Relaxed) {
+            self.run = 0;
+            return false;
+        }
+
+        self.run += samples.len() as u64;
+        self.run > self.min_samples
+    }
+}
+
+/// Position of `frame` at `sample_rate`, where playback picks up after a
+/// skipped silence.
+pub fn frame_position(frame: u64, sample_rate: u32) -> Duration {
+    Duration::from_secs_f64(frame as f64 / sample_rate.max(1) as f64)
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use symphonia::core::io::{MediaSource, ReadOnlySource};
+use symphonia::core::probe::Hint;
+
+/// How long to wait for the server to accept the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a stream may stall before it counts as dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What an Icecast or Shoutcast server says about its stream, updated as
+/// the stream plays.
+#[derive(Debug, Clone, Default)]
+pub struct IcyInfo {
+    /// Station name from the `icy-name` header
+    pub station: Option<String>,
+    /// The live "now playing" title from the latest metadata block
+    pub title: Option<String>,
+}
+
+/// ICY metadata shared between the stream reader and the player.
+pub type SharedIcy = Arc<Mutex<IcyInfo>>;
+
+/// Whether `path` is an HTTP(S) URL rather than a file.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// A stream opened for decoding.
+pub struct Stream {
+    pub source: Box<dyn MediaSource>,
+    /// Format hint from the content type and the URL's extension
+    pub hint: Hint,
+    /// Set for radio streams that interleave ICY metadata with the audio
+    pub icy: Option<SharedIcy>,
+}
+
+/// Requests `url` and hands back its body to decode as it downloads.
+///
+/// Radio servers are asked for ICY metadata; when they send it, it's taken
+/// out of the audio and the titles in it are kept in [`Stream::icy`]. The
+/// body can only be read front to back, so streams can't be seeked.
+pub fn open(url: &str) -> anyhow::Result<Stream> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let response = agent.get(url).set("Icy-MetaData", "1").call()?;
+
+    let mut hint = Hint::new();
+    hint.mime_type(response.content_type());
+    if let Some(ext) = extension(url) {
+        hint.with_extension(ext);
+    }
+
+    let interval = response
+        .header("icy-metaint")
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|interval| *interval > 0);
+    let station = response
+        .header("icy-name")
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    log::debug!(
+        "Streaming {url} ({}, ICY interval {interval:?})",
+        response.content_type()
+    );
+
+    let body = response.into_reader();
+    let stream = match interval {
+        Some(interval) => {
+            let icy = Arc::new(Mutex::new(IcyInfo {
+                station,
+                title: None,
+            }));
+            let reader = IcyReader {
+                inner: body,
+                interval,
+                remaining: interval,
+                icy: Arc::clone(&icy),
+            };
+            Stream {
+                source: Box::new(ReadOnlySource::new(reader)),
+                hint,
+                icy: Some(icy),
+            }
+        }
+        None => Stream {
+            source: Box::new(ReadOnlySource::new(body)),
+            hint,
+            icy: None,
+        },
+    };
+    Ok(stream)
+}
+
+/// Passes on the audio of an ICY stream, which has a metadata block after
+/// every `interval` bytes of it, and reads the blocks as they come.
+struct IcyReader {
+    inner: Box<dyn Read + Send + Sync>,
+    interval: usize,
+    /// Audio bytes until the next metadata block
+    remaining: usize,
+    icy: SharedIcy,
+}
+
+impl IcyReader {
+    /// Reads a metadata block: a length byte counting 16 byte units, then
+    /// text like `StreamTitle='Artist - Title';`, padded with zeros.
+    fn read_metadata(&mut self) -> io::Result<()> {
+        let mut len = [0u8];
+        self.inner.read_exact(&mut len)?;
+        let mut block = vec![0; len[0] as usize * 16];
+        self.inner.read_exact(&mut block)?;
+
+        // Most blocks are empty, meaning nothing changed
+        if let Some(title) = stream_title(&block) {
+            log::debug!("Stream title: {title}");
+            self.icy.lock().unwrap().title = Some(title);
+        }
+        Ok(())
+    }
+}
+
+impl Read for IcyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            self.read_metadata()?;
+            self.remaining = self.interval;
+        }
+
+        let len = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// The `StreamTitle` of a metadata block, if it has a non-empty one.
+fn stream_title(block: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(block);
+    let (_, rest) = text.split_once("StreamTitle='")?;
+    let title = rest.split_once("';").map_or(rest, |(title, _)| title);
+    let title = title.trim_end_matches(['\0', '\'']).trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Extension of the last path segment of `url`, ignoring any query.
+fn extension(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, name) = path.rsplit_once('/')?;
+    let (_, ext) = name.rsplit_once('.')?;
+    Some(ext)
+}
@@ -0,0 +1,154 @@
+/// Length of each overlapped segment.
+const SEGMENT_SECS: f32 = 0.04;
+
+/// How far a segment may move from its nominal position to line up with the
+/// previous one.
+const TOLERANCE_SECS: f32 = 0.01;
+
+/// Only every n-th frame is compared when lining segments up, which is
+/// plenty for finding the waveform's phase and much cheaper.
+const SEARCH_STRIDE: usize = 4;
+
+/// Changes the playback speed without changing the pitch (WSOLA).
+///
+/// Audio is cut into overlapping, Hann windowed segments. Faster speeds take
+/// segments from further apart in the input than they are laid down in the
+/// output and slower speeds from closer together, so the tempo changes but
+/// each segment keeps its pitch. Every segment is nudged to where its
+/// waveform best matches the audio it overlaps, which avoids the phasing an
+/// unaligned overlap-add would have on voices.
+pub struct TimeStretch {
+    channels: usize,
+    window: Vec<f32>,
+    /// Output frames per segment, half the segment length
+    hop: usize,
+    tolerance: usize,
+    /// Interleaved input not fully used yet
+    input: Vec<f32>,
+    /// Where the next segment would come from without alignment, in frames
+    /// into `input`
+    position: f64,
+    /// Input that would have followed the last segment, in frames into
+    /// `input`; `None` before the first segment
+    natural: Option<usize>,
+    /// Second, windowed half of the last segment, added to the next one
+    tail: Vec<f32>,
+}
+
+impl TimeStretch {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        let hop = ((sample_rate as f32 * SEGMENT_SECS) as usize / 2).max(1);
+        let length = hop * 2;
+
+        let window = (0..length)
+            .map(|i| {
+                let phase = i as f32 / length as f32;
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * phase).cos()
+            })
+            .collect();
+
+        Self {
+            channels,
+            window,
+            hop,
+            tolerance: (sample_rate as f32 * TOLERANCE_SECS) as usize,
+            input: Vec::new(),
+            position: 0.0,
+            natural: None,
+            tail: vec![0.0; hop * channels],
+        }
+    }
+
+    /// Drops buffered audio, e.g. after a seek.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.natural = None;
+        self.tail.fill(0.0);
+    }
+
+    /// Stretches interleaved `samples` to play at `speed` times the normal
+    /// rate. Output lags the input by about one segment.
+    pub fn process(&mut self, samples: &[f32], speed: f32) -> Vec<f32> {
+        if speed == 1.0 {
+            self.reset();
+            return samples.to_vec();
+        }
+
+        self.input.extend_from_slice(samples);
+
+        let channels = self.channels;
+        let hop = self.hop;
+        let frames = self.input.len() / channels;
+        let mut output = Vec::new();
+
+        loop {
+            let nominal = self.position.round() as usize;
+            let start = match self.natural {
+                None => nominal,
+                Some(_) if nominal + self.tolerance + 2 * hop > frames => break,
+                Some(natural) => self.best_start(natural, nominal),
+            };
+            if start + 2 * hop > frames {
+                break;
+            }
+
+            for i in 0..hop {
+                for ch in 0..channels {
+                    let sample = self.input[(start + i) * channels + ch] * self.window[i];
+                    output.push(self.tail[i * channels + ch] + sample);
+                }
+            }
+            for i in 0..hop {
+                for ch in 0..channels {
+                    self.tail[i * channels + ch] =
+                        self.input[(start + hop + i) * channels + ch] * self.window[hop + i];
+                }
+            }
+
+            self.natural = Some(start + hop);
+            self.position += hop as f64 * speed as f64;
+        }
+
+        // Forget input no later segment can reach
+        if let Some(natural) = self.natural {
+            let used = natural.min((self.position as usize).saturating_sub(self.tolerance));
+            self.input.drain(..used * channels);
+            self.position -= used as f64;
+            self.natural = Some(natural - used);
+        }
+
+        output
+    }
+
+    /// The segment start near `nominal` whose beginning best matches the
+    /// input at `natural`, by normalised cross-correlation of the mono mix.
+    fn best_start(&self, natural: usize, nominal: usize) -> usize {
+        let mono = |frame: usize| -> f32 {
+            self.input[frame * self.channels..(frame + 1) * self.channels]
+                .iter()
+                .sum()
+        };
+
+        let lowest = nominal.saturating_sub(self.tolerance);
+        let highest = nominal + self.tolerance;
+
+        let mut best = (nominal, f32::MIN);
+        for candidate in (lowest..=highest).step_by(2) {
+            let (mut dot, mut energy) = (0.0, 1e-9);
+            for i in (0..self.hop).step_by(SEARCH_STRIDE) {
+                let sample = mono(candidate + i);
+                dot += mono(natural + i) * sample;
+                energy += sample * sample;
+            }
+
+            let score = dot / f32::sqrt(energy);
+            if score > best.1 {
+                best = (candidate, score);
+            }
+        }
+
+        best.0
+    }
+}
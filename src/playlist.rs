@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::library::scan_path_for_tracks;
+
+/// Loads an `.m3u`/`.m3u8` playlist, or every audio file below a directory.
+///
+/// Relative entries in a playlist are resolved against the playlist's folder.
+pub fn load_playlist(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if path.is_dir() {
+        let mut paths: Vec<PathBuf> = scan_path_for_tracks(path)
+            .into_iter()
+            .map(|t| t.path)
+            .collect();
+        paths.sort();
+        return Ok(paths);
+    }
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    let data = fs::read_to_string(path)?;
+
+    let paths = data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let entry = PathBuf::from(line);
+            if entry.is_absolute() {
+                entry
+            } else {
+                base.join(entry)
+            }
+        })
+        .collect();
+
+    Ok(paths)
+}
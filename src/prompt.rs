@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use crate::library::LibraryTrack;
+use crate::retag::RetagField;
+use crate::session::Session;
+
+/// What to do with the text once the prompt is submitted.
+#[derive(Debug, Clone)]
+pub enum PromptAction {
+    AddBookmark {
+        path: PathBuf,
+        position: u64,
+    },
+    /// Comma separated labels to add, or to remove when prefixed with `-`
+    EditLabels {
+        paths: Vec<PathBuf>,
+    },
+    /// Deletes the file if the answer is `y`
+    DeleteFile {
+        path: PathBuf,
+    },
+    /// Leaves party mode if the input matches the configured code
+    UnlockParty,
+    /// Streams the entered HTTP(S) URL
+    PlayUrl,
+    /// Jumps to `position` in `path` if the answer is `y`, as long as it's
+    /// still playing
+    ResumeTrack {
+        path: PathBuf,
+        position: u64,
+    },
+    /// Saves the current state under the entered name
+    SaveSession,
+    /// Picks up where the last run left off if the answer is `y`
+    ResumeLastSession {
+        session: Box<Session>,
+    },
+    /// Sets `field` of `tracks` to the entered value, after a preview
+    Retag {
+        field: RetagField,
+        tracks: Vec<LibraryTrack>,
+    },
+    /// Checks the entered library query, then asks what to call it
+    SmartPlaylistQuery,
+    /// Saves `query` as a smart playlist under the entered name
+    SaveSmartPlaylist {
+        query: String,
+    },
+}
+
+/// Single-line text input shown in place of the footer.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub label: String,
+    pub input: String,
+    pub action: PromptAction,
+    /// Show `*` instead of the typed text
+    pub masked: bool,
+}
+
+impl Prompt {
+    pub fn new(label: impl Into<String>, input: impl Into<String>, action: PromptAction) -> Self {
+        Self {
+            label: label.into(),
+            input: input.into(),
+            action,
+            masked: false,
+        }
+    }
+
+    pub fn masked(mut self) -> Self {
+        self.masked = true;
+        self
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+    }
+}
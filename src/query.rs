@@ -0,0 +1,280 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::library::LibraryTrack;
+
+/// A saved query whose tracks are worked out again from the library each
+/// time it's shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub query: String,
+}
+
+/// A parsed query such as `genre:ambient year:>2015 rating:>=4`. A track
+/// matches when every term does.
+#[derive(Debug, Clone)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    /// Written with a leading `-`
+    negated: bool,
+    test: Test,
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    /// A bare word, found in the artist, album or title
+    Word(String),
+    /// `field:text` finds the text in the field, `field:=text` wants all of it
+    Text {
+        field: TextField,
+        value: String,
+        exact: bool,
+    },
+    /// `field:>n` and such; `field:n` is the same as `field:=n`
+    Number {
+        field: NumberField,
+        comparison: Comparison,
+        value: u64,
+    },
+    /// `is:guessed`, for files whose names came from their path
+    Guessed,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TextField {
+    Artist,
+    Album,
+    Title,
+    Genre,
+    Label,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NumberField {
+    Year,
+    Rating,
+    Plays,
+    Bpm,
+    /// In seconds
+    Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let terms = tokens(text)?
+            .into_iter()
+            .map(|token| parse_term(&token))
+            .collect::<anyhow::Result<Vec<Term>>>()?;
+        if terms.is_empty() {
+            bail!("empty query");
+        }
+        Ok(Self { terms })
+    }
+
+    pub fn matches(&self, track: &LibraryTrack) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.test.matches(track) != term.negated)
+    }
+}
+
+impl Test {
+    fn matches(&self, track: &LibraryTrack) -> bool {
+        match self {
+            Test::Word(word) => [
+                &track.artist,
+                &track.album_artist,
+                &track.album,
+                &track.title,
+            ]
+            .iter()
+            .any(|text| contains(text, word)),
+            Test::Text {
+                field,
+                value,
+                exact,
+            } => {
+                let found = |text: &str| match exact {
+                    true => text.trim().to_lowercase() == *value,
+                    false => contains(text, value),
+                };
+                match field {
+                    TextField::Artist => found(&track.artist) || found(&track.album_artist),
+                    TextField::Album => found(&track.album),
+                    TextField::Title => found(&track.title),
+                    TextField::Genre => track.genre.as_deref().is_some_and(found),
+                    TextField::Label => track.labels.iter().any(|label| found(label)),
+                }
+            }
+            Test::Number {
+                field,
+                comparison,
+                value,
+            } => {
+                // Unrated and never played count as zero; unknown years,
+                // tempos and lengths match nothing
+                let actual = match field {
+                    NumberField::Year => track.year.map(u64::from),
+                    NumberField::Rating => Some(u64::from(track.rating.unwrap_or(0))),
+                    NumberField::Plays => Some(u64::from(track.play_count)),
+                    NumberField::Bpm => track.bpm.map(u64::from),
+                    NumberField::Duration => track.duration,
+                };
+                actual.is_some_and(|actual| comparison.holds(actual, *value))
+            }
+            Test::Guessed => track.guessed,
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, actual: u64, value: u64) -> bool {
+        match self {
+            Comparison::Equal => actual == value,
+            Comparison::Less => actual < value,
+            Comparison::LessOrEqual => actual <= value,
+            Comparison::Greater => actual > value,
+            Comparison::GreaterOrEqual => actual >= value,
+        }
+    }
+}
+
+/// Splits a query on whitespace, keeping double-quoted parts together, as
+/// in `artist:"boards of canada"`.
+fn tokens(text: &str) -> anyhow::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if quoted {
+        bail!("unclosed quote");
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(token: &str) -> anyhow::Result<Term> {
+    let (negated, token) = match token.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, token),
+    };
+
+    let Some((field, value)) = token.split_once(':') else {
+        return Ok(Term {
+            negated,
+            test: Test::Word(token.to_lowercase()),
+        });
+    };
+
+    if field.eq_ignore_ascii_case("is") {
+        if !value.eq_ignore_ascii_case("guessed") {
+            bail!("unknown flag \"is:{value}\"");
+        }
+        return Ok(Term {
+            negated,
+            test: Test::Guessed,
+        });
+    }
+
+    let text_field = match field.to_ascii_lowercase().as_str() {
+        "artist" => Some(TextField::Artist),
+        "album" => Some(TextField::Album),
+        "title" => Some(TextField::Title),
+        "genre" => Some(TextField::Genre),
+        "label" => Some(TextField::Label),
+        _ => None,
+    };
+    if let Some(field) = text_field {
+        let (exact, value) = match value.strip_prefix('=') {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        return Ok(Term {
+            negated,
+            test: Test::Text {
+                field,
+                value: value.to_lowercase(),
+                exact,
+            },
+        });
+    }
+
+    let field = match field.to_ascii_lowercase().as_str() {
+        "year" => NumberField::Year,
+        "rating" => NumberField::Rating,
+        "plays" => NumberField::Plays,
+        "bpm" => NumberField::Bpm,
+        "duration" => NumberField::Duration,
+        _ => bail!("unknown field \"{field}\""),
+    };
+
+    // Longest operators first, so `>=` isn't read as `>`
+    let (comparison, number) = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ]
+    .into_iter()
+    .find_map(|(op, comparison)| value.strip_prefix(op).map(|rest| (comparison, rest)))
+    .unwrap_or((Comparison::Equal, value));
+
+    let value = match field {
+        NumberField::Duration => parse_duration(number),
+        _ => number.parse().ok(),
+    }
+    .ok_or_else(|| anyhow!("\"{number}\" isn't a number"))?;
+
+    Ok(Term {
+        negated,
+        test: Test::Number {
+            field,
+            comparison,
+            value,
+        },
+    })
+}
+
+/// Seconds, or minutes and seconds as `3:30`.
+fn parse_duration(value: &str) -> Option<u64> {
+    match value.split_once(':') {
+        Some((mins, secs)) => {
+            let mins = mins.parse::<u64>().ok()?.checked_mul(60)?;
+            mins.checked_add(secs.parse().ok()?)
+        }
+        None => value.parse().ok(),
+    }
+}
+
+/// Case-insensitive substring match against an already lowercased needle.
+fn contains(text: &str, needle: &str) -> bool {
+    text.to_lowercase().contains(needle)
+}
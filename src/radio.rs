@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// An internet radio stream saved to the stations list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Station {
+    pub name: String,
+    pub url: String,
+}
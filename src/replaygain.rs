@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::loudness::Loudness;
+
+/// ReplayGain 2.0 levels tracks to this loudness, in LUFS.
+pub const REFERENCE_LOUDNESS: f32 = -18.0;
+
+/// ReplayGain values read from a file's tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayGain {
+    /// In dB
+    pub track_gain: Option<f32>,
+    /// Loudest sample, where 1.0 is full scale
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+/// Which of a track's ReplayGain values playback follows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+    /// Measured EBU R128 loudness instead of tags
+    Loudness,
+}
+
+impl ReplayGainMode {
+    /// The mode after this one when cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            ReplayGainMode::Off => ReplayGainMode::Track,
+            ReplayGainMode::Track => ReplayGainMode::Album,
+            ReplayGainMode::Album => ReplayGainMode::Loudness,
+            ReplayGainMode::Loudness => ReplayGainMode::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "off",
+            ReplayGainMode::Track => "track",
+            ReplayGainMode::Album => "album",
+            ReplayGainMode::Loudness => "r128",
+        }
+    }
+}
+
+impl ReplayGain {
+    /// Values for a track measured at `track`, on an album measured at
+    /// `album`.
+    pub fn from_loudness(track: Loudness, album: Option<Loudness>) -> Self {
+        Self {
+            track_gain: Some(REFERENCE_LOUDNESS - track.integrated),
+            track_peak: Some(track.peak),
+            album_gain: album.map(|album| REFERENCE_LOUDNESS - album.integrated),
+            album_peak: album.map(|album| album.peak),
+        }
+    }
+
+    /// Takes the value of a `REPLAYGAIN_*` tag (an ID3 `TXXX` description or
+    /// a Vorbis comment name); other tags are ignored.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let slot = match key.to_ascii_uppercase().as_str() {
+            "REPLAYGAIN_TRACK_GAIN" => &mut self.track_gain,
+            "REPLAYGAIN_TRACK_PEAK" => &mut self.track_peak,
+            "REPLAYGAIN_ALBUM_GAIN" => &mut self.album_gain,
+            "REPLAYGAIN_ALBUM_PEAK" => &mut self.album_peak,
+            _ => return,
+        };
+
+        // Gains are written like "-6.54 dB"
+        let number = value.trim().trim_end_matches(['d', 'D', 'b', 'B']);
+        *slot = number.trim().parse().ok();
+    }
+
+    /// Linear gain to apply in `mode`.
+    ///
+    /// Album mode falls back to the track values and the other way round,
+    /// so files tagged with only one kind still get levelled. The gain is
+    /// lowered when it would push the file's peak past full scale.
+    pub fn factor(&self, mode: ReplayGainMode) -> f32 {
+        let track = (self.track_gain, self.track_peak);
+        let album = (self.album_gain, self.album_peak);
+
+        let order = match mode {
+            ReplayGainMode::Off | ReplayGainMode::Loudness => return 1.0,
+            ReplayGainMode::Track => [track, album],
+            ReplayGainMode::Album => [album, track],
+        };
+
+        let Some((gain, peak)) = order
+            .into_iter()
+            .find_map(|(gain, peak)| gain.map(|gain| (gain, peak)))
+        else {
+            return 1.0;
+        };
+
+        let factor = 10f32.powf(gain / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
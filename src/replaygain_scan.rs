@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use crate::loudness;
+use crate::replaygain::ReplayGain;
+use crate::tag_writer;
+
+/// Measures albums and writes their ReplayGain tags on a background thread.
+pub struct ReplayGainScan {
+    results: Receiver<(PathBuf, ReplayGain)>,
+    handle: JoinHandle<()>,
+    /// Tracks tagged so far
+    pub written: usize,
+}
+
+impl ReplayGainScan {
+    /// Starts on `albums`, each a list of files measured together for the
+    /// album gain.
+    pub fn spawn(albums: Vec<Vec<PathBuf>>) -> Self {
+        let (sender, results) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            for album in albums {
+                let (tracks, album_loudness) = loudness::measure_album(&album);
+
+                for (path, track_loudness) in tracks {
+                    let gain = ReplayGain::from_loudness(track_loudness, album_loudness);
+                    if let Err(err) = tag_writer::write_replay_gain(&path, &gain) {
+                        log::error!("Failed to write ReplayGain tags to {:?}: {err}", path);
+                        continue;
+                    }
+
+                    if sender.send((path, gain)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self {
+            results,
+            handle,
+            written: 0,
+        }
+    }
+
+    /// Tracks tagged since the last call.
+    pub fn finished(&mut self) -> Vec<(PathBuf, ReplayGain)> {
+        let tagged: Vec<_> = self.results.try_iter().collect();
+        self.written += tagged.len();
+        tagged
+    }
+
+    /// Whether every album has been tagged. Check this before collecting the
+    /// last results with `finished`.
+    pub fn is_done(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::library::LibraryTrack;
+use crate::list::ListSelector;
+
+/// A tag that can be changed on many tracks at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetagField {
+    AlbumArtist,
+    Album,
+    /// Numbers the tracks 1, 2, 3... in the order they're listed, starting
+    /// over for each album and disc
+    TrackNumber,
+}
+
+impl RetagField {
+    pub const ALL: [RetagField; 3] = [
+        RetagField::AlbumArtist,
+        RetagField::Album,
+        RetagField::TrackNumber,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RetagField::AlbumArtist => "Set album artist",
+            RetagField::Album => "Set album name",
+            RetagField::TrackNumber => "Renumber tracks",
+        }
+    }
+
+    /// Whether the new value has to be typed in.
+    pub fn takes_value(self) -> bool {
+        self != RetagField::TrackNumber
+    }
+
+    pub fn current(self, track: &LibraryTrack) -> String {
+        match self {
+            RetagField::AlbumArtist => track.album_artist.clone(),
+            RetagField::Album => track.album.clone(),
+            RetagField::TrackNumber => track
+                .track_number
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// What one file's tag is and what it will be.
+#[derive(Debug, Clone)]
+pub struct RetagChange {
+    pub path: PathBuf,
+    pub title: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Changes waiting to be confirmed before anything is written.
+pub struct RetagPreview {
+    pub field: RetagField,
+    pub changes: ListSelector<RetagChange>,
+}
+
+/// Picking which tag to change on `tracks`.
+pub struct RetagMenu {
+    pub tracks: Vec<LibraryTrack>,
+    pub fields: ListSelector<RetagField>,
+}
+
+impl RetagMenu {
+    pub fn new(tracks: Vec<LibraryTrack>) -> Self {
+        Self {
+            tracks,
+            fields: ListSelector::new(RetagField::ALL.to_vec()),
+        }
+    }
+}
+
+/// Works out the new `field` of each of `tracks`, leaving out the ones it
+/// wouldn't change. `value` is ignored when renumbering.
+pub fn preview(field: RetagField, tracks: &[LibraryTrack], value: &str) -> RetagPreview {
+    let mut numbers: HashMap<(&str, Option<u32>), u32> = HashMap::new();
+
+    let changes = tracks
+        .iter()
+        .filter_map(|track| {
+            let new = match field {
+                RetagField::TrackNumber => {
+                    let number = numbers
+                        .entry((&track.album, track.disc_number))
+                        .or_insert(0);
+                    *number += 1;
+                    number.to_string()
+                }
+                _ => value.to_string(),
+            };
+            let old = field.current(track);
+            (old != new).then(|| RetagChange {
+                path: track.path.clone(),
+                title: track.title.clone(),
+                old,
+                new,
+            })
+        })
+        .collect();
+
+    RetagPreview {
+        field,
+        changes: ListSelector::new(changes),
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::library::{self, LibraryTrack, RefreshCheck, RefreshScan};
+
+/// Which files scanning picks up, from the `scan_include` and `scan_exclude`
+/// patterns.
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    /// When set, only files matching it are scanned
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl ScanFilter {
+    /// Builds the filter, leaving out patterns that don't parse.
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        Self {
+            include: glob_set(include),
+            exclude: glob_set(exclude),
+        }
+    }
+
+    /// Whether `path`, a file or folder, is excluded.
+    pub fn excludes(&self, path: &Path) -> bool {
+        self.exclude.as_ref().is_some_and(|set| set.is_match(path))
+    }
+
+    /// Whether the file `path` passes the include patterns.
+    pub fn includes(&self, path: &Path) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(path))
+    }
+}
+
+/// Patterns are matched against whole paths; ones without a slash, like
+/// `*.txt` or `.stversions`, match a name at any depth.
+fn glob_set(patterns: &[String]) -> Option<GlobSet> {
+    if patterns.is_empty() {
+        return None;
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let full = match pattern.contains('/') {
+            true => pattern.clone(),
+            false => format!("**/{pattern}"),
+        };
+        match GlobBuilder::new(&full).literal_separator(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => log::warn!("Ignoring invalid scan pattern {pattern:?}: {err}"),
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|err| log::warn!("Failed to build scan patterns: {err}"))
+        .ok()
+}
+
+/// How far one scanning thread has got.
+#[derive(Default)]
+pub struct WorkerProgress {
+    /// Files it has read
+    pub done: AtomicUsize,
+    /// The file it's reading now
+    pub current: Mutex<Option<PathBuf>>,
+}
+
+/// How far a scan has got, shared with the threads doing it.
+#[derive(Default)]
+pub struct ScanProgress {
+    /// Files found to read so far
+    pub total: AtomicUsize,
+    /// One per thread of the rayon pool; empty when nobody's watching
+    pub workers: Vec<WorkerProgress>,
+}
+
+impl ScanProgress {
+    /// Progress with a slot for each thread of the rayon pool.
+    pub fn for_pool() -> Self {
+        Self {
+            total: AtomicUsize::new(0),
+            workers: (0..rayon::current_num_threads())
+                .map(|_| WorkerProgress::default())
+                .collect(),
+        }
+    }
+
+    /// Files read so far, by all threads together.
+    pub fn done(&self) -> usize {
+        self.workers
+            .iter()
+            .map(|worker| worker.done.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Runs `read` on `path`, noting it as the calling thread's current
+    /// file while it runs.
+    pub fn track<T>(&self, path: &Path, read: impl FnOnce() -> T) -> T {
+        let worker = rayon::current_thread_index().and_then(|index| self.workers.get(index));
+        if let Some(worker) = worker {
+            *worker.current.lock().unwrap() = Some(path.to_path_buf());
+        }
+
+        let result = read();
+
+        if let Some(worker) = worker {
+            worker.done.fetch_add(1, Ordering::Relaxed);
+            *worker.current.lock().unwrap() = None;
+        }
+        result
+    }
+}
+
+/// What a background scan is for.
+pub enum ScanJob {
+    /// Adding this folder to the library
+    Add(PathBuf),
+    /// Checking the library folders for new, changed and removed files
+    Refresh,
+}
+
+/// What a background scan found.
+pub enum ScanResult {
+    Added {
+        root: PathBuf,
+        tracks: Vec<LibraryTrack>,
+    },
+    Refreshed(RefreshScan),
+}
+
+/// Library files being read on a background thread.
+pub struct LibraryScan {
+    pub job: ScanJob,
+    pub progress: Arc<ScanProgress>,
+    result: Receiver<ScanResult>,
+}
+
+impl LibraryScan {
+    /// Starts reading the files at and below `root`, to add to the library.
+    pub fn add(root: PathBuf, filter: ScanFilter) -> Self {
+        let thread_root = root.clone();
+        Self::start(ScanJob::Add(root), move |progress| {
+            let tracks = library::scan_path_with_progress(&thread_root, &filter, progress);
+            ScanResult::Added {
+                root: thread_root,
+                tracks,
+            }
+        })
+    }
+
+    /// Starts comparing the library folders with what `check` lists.
+    pub fn refresh(check: RefreshCheck) -> Self {
+        Self::start(ScanJob::Refresh, move |progress| {
+            ScanResult::Refreshed(check.run(progress))
+        })
+    }
+
+    fn start(
+        job: ScanJob,
+        scan: impl FnOnce(&ScanProgress) -> ScanResult + Send + 'static,
+    ) -> Self {
+        let progress = Arc::new(ScanProgress::for_pool());
+        let (sender, result) = mpsc::channel();
+
+        let thread_progress = Arc::clone(&progress);
+        thread::spawn(move || {
+            sender.send(scan(&thread_progress)).ok();
+        });
+
+        Self {
+            job,
+            progress,
+            result,
+        }
+    }
+
+    /// What the scan found, once it's done.
+    pub fn finished(&self) -> Option<ScanResult> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                log::error!("Scanning the library stopped without finishing");
+                Some(match &self.job {
+                    ScanJob::Add(root) => ScanResult::Added {
+                        root: root.clone(),
+                        tracks: Vec::new(),
+                    },
+                    ScanJob::Refresh => ScanResult::Refreshed(RefreshScan::default()),
+                })
+            }
+        }
+    }
+}
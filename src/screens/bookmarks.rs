@@ -0,0 +1,51 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popup listing the current track's bookmarks, drawn over `area`.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let title = app
+        .current_track
+        .as_ref()
+        .map(|t| format!("Bookmarks – {}", t.title))
+        .unwrap_or_else(|| "Bookmarks".to_string());
+
+    let Some(list) = app.bookmark_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = if list.entries.is_empty() {
+        vec![ListItem::new(
+            "No bookmarks (press b while playing to add one)",
+        )]
+    } else {
+        list.entries
+            .iter()
+            .map(|b| {
+                let pos = b.position;
+                let hours = pos / 3600;
+                let label = if hours > 0 {
+                    format!("{}:{:02}:{:02}", hours, (pos % 3600) / 60, pos % 60)
+                } else {
+                    format!("{:02}:{:02}", pos / 60, pos % 60)
+                };
+                ListItem::new(format!("{label}  {}", b.name))
+            })
+            .collect()
+    };
+
+    let popup = crate::ui::centered_rect(60, 50, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title(title)
+                .title_bottom("Enter: jump  d: delete  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
@@ -1,5 +1,5 @@
 use crate::app::App;
-use crate::browser::BrowserItem;
+use crate::browser::{self, BrowserItem};
 use ratatui::{prelude::*, widgets::*};
 
 pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
@@ -13,13 +13,22 @@ pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
             BrowserItem::Entry(path) => {
                 let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("???");
                 let prefix = if path.is_dir() { "[DIR] " } else { "      " };
-                ListItem::new(format!("{prefix}{name}"))
+                let item = ListItem::new(format!("{prefix}{name}"));
+                // Files that can't be played are only there for orientation
+                match path.is_dir() || browser::is_playable(path) {
+                    true => item,
+                    false => item.style(Style::default().fg(Color::DarkGray)),
+                }
             }
         })
         .collect();
 
     let list = List::new(items)
-        .block(Block::default().title("Browser").borders(Borders::ALL))
+        .block(
+            Block::default()
+                .title(format!("Browser – {}", app.browser.current_dir.display()))
+                .borders(Borders::ALL),
+        )
         .highlight_style(Style::default().bg(Color::Blue).fg(Color::White))
         .highlight_symbol("➤ ");
 
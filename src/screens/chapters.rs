@@ -0,0 +1,52 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popup listing the chapters of the playing file, drawn over `area`.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let title = app
+        .current_track
+        .as_ref()
+        .map(|t| format!("Chapters – {}", t.title))
+        .unwrap_or_else(|| "Chapters".to_string());
+    let current = app.current_chapter();
+
+    let Some(list) = app.chapter_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = list
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            let pos = chapter.start.as_secs();
+            let hours = pos / 3600;
+            let label = if hours > 0 {
+                format!("{}:{:02}:{:02}", hours, (pos % 3600) / 60, pos % 60)
+            } else {
+                format!("{:02}:{:02}", pos / 60, pos % 60)
+            };
+            let item = ListItem::new(format!("{label}  {}", chapter.title));
+            match current == Some(index) {
+                true => item.style(Style::default().fg(Color::LightGreen)),
+                false => item,
+            }
+        })
+        .collect();
+
+    let popup = crate::ui::centered_rect(60, 50, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title(title)
+                .title_bottom("Enter: jump  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
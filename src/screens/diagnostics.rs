@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::player::DECODE_AHEAD_SECS;
+
+/// Popup showing how the output device was opened and its latency.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let player = app.player_mut();
+    let millis = |duration: Duration| format!("{:.1} ms", duration.as_secs_f64() * 1000.0);
+
+    let host = player.host_name.unwrap_or("-");
+    let device = player
+        .device_name
+        .clone()
+        .unwrap_or_else(|| "-".to_string());
+    let format = match player.output_format {
+        Some(format) => format!(
+            "{} Hz, {} channels, {:?}",
+            format.rate, format.channels, format.sample_format
+        ),
+        None => "-".to_string(),
+    };
+    let buffer = match (player.buffer_size, player.output_format) {
+        (Some(frames), Some(format)) => format!(
+            "{frames} frames ({})",
+            millis(Duration::from_secs_f64(frames as f64 / format.rate as f64))
+        ),
+        (Some(frames), None) => format!("{frames} frames"),
+        (None, _) => "driver default".to_string(),
+    };
+    let bit_perfect = match player.bit_perfect_active {
+        true => "yes",
+        false => "no",
+    };
+    let latency = match player.output_latency() {
+        Some(latency) => millis(latency),
+        None => "unknown".to_string(),
+    };
+    drop(player);
+
+    let rows = [
+        ("Host", host.to_string()),
+        ("Device", device),
+        ("Format", format),
+        ("Buffer", buffer),
+        ("Latency", latency),
+        ("Bit-perfect", bit_perfect.to_string()),
+        ("Decoded ahead", format!("{DECODE_AHEAD_SECS} s")),
+    ];
+    let lines: Vec<Line> = rows
+        .into_iter()
+        .map(|(name, value)| Line::from(format!("{name:<14} {value}")))
+        .collect();
+
+    let popup = crate::ui::centered_rect(50, 30, area);
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .title("Output")
+            .title_bottom("Esc: close")
+            .borders(Borders::ALL),
+    );
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(widget, popup);
+}
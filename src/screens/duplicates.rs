@@ -0,0 +1,66 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let matched = match app.sound_duplicates {
+        Some(_) => "by sound",
+        None => "by tags",
+    };
+    let mut title = format!(
+        "Duplicates {matched} ({} groups)",
+        app.duplicates.groups.len()
+    );
+    if let Some((done, total)) = app.fingerprint_progress() {
+        title.push_str(&format!(" – fingerprinting {done}/{total}"));
+    }
+
+    let view = &mut app.duplicates;
+
+    let items: Vec<ListItem> = if view.list.entries.is_empty() {
+        vec![ListItem::new("No duplicates found")]
+    } else {
+        view.list
+            .entries
+            .iter()
+            .map(|&(g, f)| {
+                let group = &view.groups[g];
+                let file = &group.files[f];
+
+                let bitrate = file
+                    .bitrate_kbps
+                    .map_or("? kbps".to_string(), |kbps| format!("{kbps} kbps"));
+                let quality = format!(
+                    "{:<4} {:>9} {:>6.1} MB",
+                    file.format,
+                    bitrate,
+                    file.size as f64 / 1_000_000.0
+                );
+                let best = if f == 0 { "best" } else { "    " };
+                let line = format!("  {best} {quality}  {}", file.track.path.display());
+
+                if f == 0 {
+                    let heading = Style::default().add_modifier(Modifier::BOLD);
+                    ListItem::new(vec![
+                        Line::styled(group.name.clone(), heading),
+                        Line::from(line),
+                    ])
+                } else {
+                    ListItem::new(line)
+                }
+            })
+            .collect()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(title)
+                .title_bottom("Enter: play  d: move to trash  D: delete  K: keep only this")
+                .borders(Borders::ALL),
+        )
+        .highlight_style(Style::default().bg(Color::Red).fg(Color::White))
+        .highlight_symbol("➤ ");
+
+    frame.render_stateful_widget(list, area, &mut view.list.state);
+}
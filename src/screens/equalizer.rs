@@ -0,0 +1,68 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::player::dsp::{EQ_BANDS, EQ_MAX_DB};
+
+/// Popup with a horizontal slider per equalizer band.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(selected) = app.equalizer_band else {
+        return;
+    };
+
+    let gains = app.player_mut().dsp.eq_gains();
+    let half = EQ_MAX_DB as usize;
+
+    let lines: Vec<Line> = EQ_BANDS
+        .iter()
+        .zip(gains)
+        .enumerate()
+        .map(|(band, (freq, db))| {
+            // One cell per dB either side of the center line
+            let steps = db.round().abs() as usize;
+            let (cut, boost) = match db < 0.0 {
+                true => (steps, 0),
+                false => (0, steps),
+            };
+            let slider = format!(
+                "{}{}│{}{}",
+                "─".repeat(half - cut),
+                "█".repeat(cut),
+                "█".repeat(boost),
+                "─".repeat(half - boost),
+            );
+
+            let style = match band == selected {
+                true => Style::default().bg(Color::Yellow).fg(Color::Black),
+                false => Style::default(),
+            };
+
+            Line::styled(
+                format!("{:>7}  {slider}  {db:+5.1} dB", band_label(*freq)),
+                style,
+            )
+        })
+        .collect();
+
+    let title = match app.eq_preset() {
+        Some(preset) => format!("Equalizer ({preset})"),
+        None => "Equalizer".to_string(),
+    };
+
+    let popup = crate::ui::centered_rect(60, 60, area);
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .title_bottom("←/→: band  ↑/↓: gain  p: preset  Esc: close")
+            .borders(Borders::ALL),
+    );
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(widget, popup);
+}
+
+fn band_label(freq: f32) -> String {
+    match freq >= 1000.0 {
+        true => format!("{} kHz", freq / 1000.0),
+        false => format!("{freq} Hz"),
+    }
+}
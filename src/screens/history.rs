@@ -0,0 +1,33 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = if app.history.entries.is_empty() {
+        vec![ListItem::new("Nothing played yet")]
+    } else {
+        app.history
+            .entries
+            .iter()
+            .map(|entry| {
+                ListItem::new(format!(
+                    "{}  {:02}:{:02}  {} – {}",
+                    entry.local_time(),
+                    entry.listened / 60,
+                    entry.listened % 60,
+                    entry.artist,
+                    entry.title,
+                ))
+            })
+            .collect()
+    };
+
+    let title = format!("History ({} plays)", app.history.entries.len());
+
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .highlight_style(Style::default().bg(Color::Magenta).fg(Color::Black))
+        .highlight_symbol("➤ ");
+
+    frame.render_stateful_widget(list, area, &mut app.history.state);
+}
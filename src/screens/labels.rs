@@ -0,0 +1,39 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popup listing every label in the library, for filtering the library view.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let active = app.library_mut().label_filter.clone();
+
+    let Some(list) = app.label_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = list
+        .entries
+        .iter()
+        .map(|(label, count)| {
+            let marker = if *label == active { "● " } else { "  " };
+            match label {
+                Some(label) => ListItem::new(format!("{marker}{label} ({count})")),
+                None => ListItem::new(format!("{marker}All tracks")),
+            }
+        })
+        .collect();
+
+    let popup = crate::ui::centered_rect(40, 50, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title("Labels")
+                .title_bottom("Enter: filter  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
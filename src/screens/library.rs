@@ -2,7 +2,7 @@ use ratatui::{prelude::*, widgets::*};
 
 use crate::app::App;
 
-use crate::library::{LibraryFocus, LibrarySelection};
+use crate::library::{BrowseBy, LibraryFocus, LibrarySelection};
 
 use crate::library::VisibleRow;
 
@@ -23,7 +23,7 @@ pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
 
         let label = match row {
             VisibleRow::Artist { artist_index } => {
-                let artist = &library.artists[*artist_index];
+                let artist = &library.tree()[*artist_index];
                 let marker = if artist.expanded { "▾" } else { "▸" };
                 format!("{marker} {}", artist.name)
             }
@@ -31,7 +31,7 @@ pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
                 artist_index,
                 album_index,
             } => {
-                let album = &library.artists[*artist_index].albums[*album_index];
+                let album = &library.tree()[*artist_index].albums[*album_index];
                 format!("  {}", album.name)
             }
         };
@@ -52,8 +52,24 @@ pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
     let mut left_state = ListState::default();
     left_state.select(Some(selected_idx));
 
+    let right_focus = library.focus == LibraryFocus::Right;
+    let mut title = match library.browse {
+        BrowseBy::Artist => "Library".to_string(),
+        browse => format!("Library by {}", browse.label()),
+    };
+    if let Some(label) = &library.label_filter {
+        title.push_str(&format!(" [{label}]"));
+    }
+    if let Some((name, _)) = &library.smart_filter {
+        title.push_str(&format!(" [{name}]"));
+    }
+    title.push_str(&filter_title(
+        &library.artist_filter,
+        library.typing_filter && !right_focus,
+    ));
+
     let left_list = List::new(left_items)
-        .block(Block::default().title("Library").borders(Borders::ALL))
+        .block(Block::default().title(title).borders(Borders::ALL))
         .highlight_symbol("➤ ")
         .highlight_style(Style::default().bg(Color::Green).fg(Color::Black));
 
@@ -73,7 +89,14 @@ pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
     }
 
     let right_list = List::new(right_items)
-        .block(Block::default().title("Tracks").borders(Borders::ALL))
+        .block(
+            Block::default()
+                .title(format!(
+                    "Tracks{}",
+                    filter_title(&library.track_filter, library.typing_filter && right_focus)
+                ))
+                .borders(Borders::ALL),
+        )
         .highlight_symbol("➤ ")
         .highlight_style(Style::default().bg(Color::Blue).fg(Color::Black));
 
@@ -84,6 +107,15 @@ pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
     }
 }
 
+/// The typed filter of a pane, for its title, with a cursor while typing.
+fn filter_title(filter: &str, typing: bool) -> String {
+    match (filter.is_empty(), typing) {
+        (true, false) => String::new(),
+        (_, true) => format!(" /{filter}▏"),
+        (false, false) => format!(" /{filter}"),
+    }
+}
+
 fn row_to_selection(row: &VisibleRow) -> LibrarySelection {
     match row {
         VisibleRow::Artist { artist_index } => LibrarySelection::Artist {
@@ -0,0 +1,113 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::album_lookup::LookupStep;
+use crate::app::App;
+
+/// Popup for looking an album up on MusicBrainz: the matching releases,
+/// then the tags the chosen one would give each file.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(lookup) = app.album_lookup.as_mut() else {
+        return;
+    };
+
+    let popup = crate::ui::centered_rect(70, 60, area);
+    let album = lookup
+        .tracks
+        .first()
+        .map(|t| t.album.clone())
+        .unwrap_or_default();
+    let count = lookup.tracks.len();
+    let block = Block::default().borders(Borders::ALL);
+    frame.render_widget(Clear, popup);
+
+    match &mut lookup.step {
+        LookupStep::Searching | LookupStep::Loading => {
+            let text = match lookup.step {
+                LookupStep::Searching => "Searching MusicBrainz…",
+                _ => "Fetching the release's tracks…",
+            };
+            let block = block
+                .title(format!("Look up {album}"))
+                .title_bottom("Esc: cancel");
+            frame.render_widget(Paragraph::new(text).block(block), popup);
+        }
+        LookupStep::Failed(message) => {
+            let block = block
+                .title(format!("Look up {album}"))
+                .title_bottom("Esc: close");
+            frame.render_widget(Paragraph::new(message.as_str()).block(block), popup);
+        }
+        LookupStep::Candidates(releases) => {
+            let items: Vec<ListItem> = releases
+                .entries
+                .iter()
+                .map(|release| {
+                    let details = format!(
+                        "  {} · {} tracks · {} · {}%",
+                        release
+                            .year()
+                            .map_or("----".to_string(), |year| year.to_string()),
+                        release.track_count,
+                        release.country.as_deref().unwrap_or("--"),
+                        release.score,
+                    );
+                    ListItem::new(Line::from(vec![
+                        Span::raw(format!("{} – {}", release.artist(), release.title)),
+                        Span::styled(details, Style::default().fg(Color::DarkGray)),
+                    ]))
+                })
+                .collect();
+
+            let widget = List::new(items)
+                .block(
+                    block
+                        .title(format!("Releases matching {album} ({count} files)"))
+                        .title_bottom("Enter: choose  Esc: cancel"),
+                )
+                .highlight_symbol("➤ ")
+                .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+            frame.render_stateful_widget(widget, popup, &mut releases.state);
+        }
+        LookupStep::Preview(changes) => {
+            let items: Vec<ListItem> = changes
+                .entries
+                .iter()
+                .map(|change| {
+                    let old = format!(
+                        "{} {} – {}",
+                        change
+                            .track
+                            .track_number
+                            .map_or("--".to_string(), |n| format!("{n:02}")),
+                        change.track.artist,
+                        change.track.title,
+                    );
+                    let new = format!(
+                        "{:02} {} – {}",
+                        change.tags.track_number, change.tags.artist, change.tags.title
+                    );
+                    ListItem::new(Line::from(vec![
+                        Span::styled(old, Style::default().fg(Color::DarkGray)),
+                        Span::raw(" → "),
+                        Span::styled(new, Style::default().fg(Color::Green)),
+                    ]))
+                })
+                .collect();
+
+            let tags = &changes.entries[0].tags;
+            let title = match tags.year {
+                Some(year) => format!("{} – {} ({year})", tags.album_artist, tags.album),
+                None => format!("{} – {}", tags.album_artist, tags.album),
+            };
+            let widget = List::new(items)
+                .block(
+                    block
+                        .title(title)
+                        .title_bottom("Enter: write tags  Esc: cancel"),
+                )
+                .highlight_symbol("➤ ")
+                .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+            frame.render_stateful_widget(widget, popup, &mut changes.state);
+        }
+    }
+}
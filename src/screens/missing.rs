@@ -0,0 +1,37 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popup listing library tracks whose files are gone.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(list) = app.missing_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = list
+        .entries
+        .iter()
+        .map(|track| {
+            let path = Line::from(track.path.display().to_string()).style(Color::DarkGray);
+            ListItem::new(vec![
+                Line::from(format!("{} – {}", track.artist, track.title)),
+                path,
+            ])
+        })
+        .collect();
+
+    let popup = crate::ui::centered_rect(70, 60, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title(format!("Missing files ({})", list.entries.len()))
+                .title_bottom("d: remove  D: remove all  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
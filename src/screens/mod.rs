@@ -1,2 +1,20 @@
+pub mod bookmarks;
 pub mod browser;
+pub mod chapters;
+pub mod diagnostics;
+pub mod duplicates;
+pub mod equalizer;
+pub mod history;
+pub mod labels;
 pub mod library;
+pub mod lookup;
+pub mod missing;
+pub mod recent;
+pub mod retag;
+pub mod scan;
+pub mod search;
+pub mod sessions;
+pub mod settings;
+pub mod smartplaylists;
+pub mod stations;
+pub mod stats;
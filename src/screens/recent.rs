@@ -0,0 +1,35 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = if app.recent.entries.is_empty() {
+        vec![ListItem::new("Nothing played yet")]
+    } else {
+        app.recent
+            .entries
+            .iter()
+            .map(|entry| {
+                ListItem::new(format!(
+                    "{:<16}  {} – {}  ({})",
+                    entry.relative_time(),
+                    entry.artist,
+                    entry.title,
+                    entry.album,
+                ))
+            })
+            .collect()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title("Recently played")
+                .title_bottom("Enter: play  e: add to queue")
+                .borders(Borders::ALL),
+        )
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black))
+        .highlight_symbol("➤ ");
+
+    frame.render_stateful_widget(list, area, &mut app.recent.state);
+}
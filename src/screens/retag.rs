@@ -0,0 +1,69 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popups for retagging several tracks: picking the tag to change, then
+/// looking over the changes before they're written.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    if let Some(menu) = app.retag_menu.as_mut() {
+        let items: Vec<ListItem> = menu
+            .fields
+            .entries
+            .iter()
+            .map(|field| ListItem::new(field.label()))
+            .collect();
+
+        let popup = crate::ui::centered_rect(40, 30, area);
+        let widget = List::new(items)
+            .block(
+                Block::default()
+                    .title(format!("Retag {} tracks", menu.tracks.len()))
+                    .title_bottom("Enter: choose  Esc: cancel")
+                    .borders(Borders::ALL),
+            )
+            .highlight_symbol("➤ ")
+            .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+        frame.render_widget(Clear, popup);
+        frame.render_stateful_widget(widget, popup, &mut menu.fields.state);
+    }
+
+    if let Some(preview) = app.retag_preview.as_mut() {
+        let items: Vec<ListItem> = preview
+            .changes
+            .entries
+            .iter()
+            .map(|change| {
+                let old = match change.old.as_str() {
+                    "" => "(none)",
+                    old => old,
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{}: ", change.title)),
+                    Span::styled(old.to_string(), Style::default().fg(Color::DarkGray)),
+                    Span::raw(" → "),
+                    Span::styled(change.new.clone(), Style::default().fg(Color::Green)),
+                ]))
+            })
+            .collect();
+
+        let popup = crate::ui::centered_rect(70, 60, area);
+        let title = format!(
+            "{}: {} tracks change",
+            preview.field.label(),
+            preview.changes.entries.len()
+        );
+        let widget = List::new(items)
+            .block(
+                Block::default()
+                    .title(title)
+                    .title_bottom("Enter: write tags  Esc: cancel")
+                    .borders(Borders::ALL),
+            )
+            .highlight_symbol("➤ ")
+            .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+        frame.render_widget(Clear, popup);
+        frame.render_stateful_widget(widget, popup, &mut preview.changes.state);
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::atomic::Ordering;
+
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::scan::ScanJob;
+
+/// Corner panel showing how far adding a folder to the library, or
+/// refreshing it, has got, with what each scanning thread is reading. Keys
+/// go to the screen below.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(scan) = &app.library_scan else {
+        return;
+    };
+    let progress = &scan.progress;
+
+    let mut lines = vec![Line::from(format!(
+        "{} of {} files",
+        progress.done(),
+        progress.total.load(Ordering::Relaxed)
+    ))];
+    for (index, worker) in progress.workers.iter().enumerate() {
+        let current = worker.current.lock().unwrap();
+        let file = current
+            .as_deref()
+            .and_then(|path| path.file_name())
+            .map_or("idle".into(), |name| name.to_string_lossy());
+        lines.push(Line::from(format!(
+            "#{:<2} {:>6}  {file}",
+            index + 1,
+            worker.done.load(Ordering::Relaxed)
+        )));
+    }
+
+    let width = (area.width / 2).max(30).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let panel = Rect {
+        x: area.x + area.width - width,
+        y: area.y,
+        width,
+        height,
+    };
+
+    let title = match &scan.job {
+        ScanJob::Add(root) => {
+            let name = root
+                .file_name()
+                .map_or(root.to_string_lossy(), |name| name.to_string_lossy());
+            format!("Adding {name}")
+        }
+        ScanJob::Refresh => "Refreshing the library".to_string(),
+    };
+    let widget = Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL));
+
+    frame.render_widget(Clear, panel);
+    frame.render_widget(widget, panel);
+}
@@ -0,0 +1,54 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Overlay searching the whole library as you type.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+
+    let popup = crate::ui::centered_rect(70, 60, area);
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(popup);
+
+    let input = Paragraph::new(format!("{}▏", search.query))
+        .block(Block::default().title("Search").borders(Borders::ALL));
+
+    let items: Vec<ListItem> = search
+        .results
+        .entries
+        .iter()
+        .map(|track| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} – {}", track.artist, track.title)),
+                Span::styled(
+                    format!("  {}", track.album),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+        })
+        .collect();
+
+    let title = match (search.query.trim().is_empty(), items.len()) {
+        (true, _) => "Type to match artists, albums and titles".to_string(),
+        (false, 0) => "No matches".to_string(),
+        (false, count) => format!("{count} matches"),
+    };
+
+    let results = List::new(items)
+        .block(
+            Block::default()
+                .title(title)
+                .title_bottom("Enter: play  Tab: queue  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(input, layout[0]);
+    frame.render_stateful_widget(results, layout[1], &mut search.results.state);
+}
@@ -0,0 +1,38 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::history;
+
+/// Popup listing saved sessions.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(list) = app.session_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = if list.entries.is_empty() {
+        vec![ListItem::new("No saved sessions")]
+    } else {
+        list.entries
+            .iter()
+            .map(|session| {
+                let saved = history::format_local(session.saved_at);
+                ListItem::new(format!("{}  ({saved})", session.name))
+            })
+            .collect()
+    };
+
+    let popup = crate::ui::centered_rect(50, 50, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title("Sessions")
+                .title_bottom("Enter: restore  d: delete  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
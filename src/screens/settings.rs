@@ -0,0 +1,34 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::{App, SETTINGS};
+
+/// Popup listing adjustable settings with their current values.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(selected) = app.settings_row else {
+        return;
+    };
+
+    let lines: Vec<Line> = SETTINGS
+        .iter()
+        .enumerate()
+        .map(|(row, name)| {
+            let style = match row == selected {
+                true => Style::default().bg(Color::Yellow).fg(Color::Black),
+                false => Style::default(),
+            };
+
+            Line::styled(format!("{name:<12} ◀ {} ▶", app.setting_value(row)), style)
+        })
+        .collect();
+
+    let popup = crate::ui::centered_rect(50, 30, area);
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .title("Settings")
+            .title_bottom("↑/↓: setting  ←/→: change  Esc: close")
+            .borders(Borders::ALL),
+    );
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(widget, popup);
+}
@@ -0,0 +1,52 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popup listing saved smart playlists with how many tracks each matches.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let active = app
+        .library_mut()
+        .smart_filter
+        .as_ref()
+        .map(|(name, _)| name.clone());
+
+    let Some(list) = app.smart_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = if list.entries.is_empty() {
+        vec![ListItem::new("No smart playlists (press a to add one)")]
+    } else {
+        list.entries
+            .iter()
+            .map(|(playlist, count)| {
+                let marker = match active.as_deref() == Some(playlist.name.as_str()) {
+                    true => "● ",
+                    false => "  ",
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{marker}{} ({count})", playlist.name)),
+                    Span::styled(
+                        format!("  {}", playlist.query),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]))
+            })
+            .collect()
+    };
+
+    let popup = crate::ui::centered_rect(60, 50, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title("Smart playlists")
+                .title_bottom("Enter: filter library  p: play  a: add  d: delete  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
@@ -0,0 +1,36 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Popup listing saved radio stations.
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(list) = app.station_list.as_mut() else {
+        return;
+    };
+
+    let items: Vec<ListItem> = if list.entries.is_empty() {
+        vec![ListItem::new(
+            "No saved stations (press a while a stream plays to add it)",
+        )]
+    } else {
+        list.entries
+            .iter()
+            .map(|station| ListItem::new(format!("{}  ({})", station.name, station.url)))
+            .collect()
+    };
+
+    let popup = crate::ui::centered_rect(60, 50, area);
+
+    let widget = List::new(items)
+        .block(
+            Block::default()
+                .title("Radio stations")
+                .title_bottom("Enter: play  a: save playing stream  d: delete  Esc: close")
+                .borders(Borders::ALL),
+        )
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(widget, popup, &mut list.state);
+}
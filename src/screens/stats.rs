@@ -0,0 +1,136 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::now_playing;
+use crate::stats::{self, LibraryStats};
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    if let Some(stats) = &app.library_stats {
+        draw_library(frame, stats, area);
+        return;
+    }
+
+    let stats = stats::compute(
+        &app.history.entries,
+        app.stats_period,
+        now_playing::unix_now(),
+    );
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(area);
+
+    let hours = stats.listened_secs / 3600;
+    let minutes = (stats.listened_secs % 3600) / 60;
+    let summary = Paragraph::new(format!(
+        "{} plays, {}h {:02}m listened",
+        stats.plays, hours, minutes
+    ))
+    .block(
+        Block::default()
+            .title(format!("Stats – {}", app.stats_period.label()))
+            .title_bottom("Tab: change period · v: library")
+            .borders(Borders::ALL),
+    );
+
+    frame.render_widget(summary, chunks[0]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Ratio(1, 3),
+            Constraint::Ratio(1, 3),
+            Constraint::Ratio(1, 3),
+        ])
+        .split(chunks[1]);
+
+    render_top(frame, "Top artists", &stats.top_artists, columns[0]);
+    render_top(frame, "Top albums", &stats.top_albums, columns[1]);
+    render_top(frame, "Top tracks", &stats.top_tracks, columns[2]);
+}
+
+/// Counts of what's in the library, by format and genre.
+fn draw_library(frame: &mut Frame, stats: &LibraryStats, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(1)])
+        .split(area);
+
+    let hours = stats.duration_secs / 3600;
+    let minutes = (stats.duration_secs % 3600) / 60;
+    let summary = Paragraph::new(vec![
+        Line::from(format!(
+            "{} tracks, {} albums, {} artists",
+            stats.tracks, stats.albums, stats.artists
+        )),
+        Line::from(format!(
+            "{}h {:02}m of music, {} on disk",
+            hours,
+            minutes,
+            stats::format_size(stats.size_bytes)
+        )),
+    ])
+    .block(
+        Block::default()
+            .title("Stats – Library")
+            .title_bottom("v: history")
+            .borders(Borders::ALL),
+    );
+
+    frame.render_widget(summary, chunks[0]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(chunks[1]);
+
+    let rows: Vec<Row> = stats
+        .formats
+        .iter()
+        .map(|(extension, files, bytes)| {
+            Row::new(vec![
+                extension.clone(),
+                files.to_string(),
+                stats::format_size(*bytes),
+            ])
+        })
+        .collect();
+    let formats = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Min(10),
+        ],
+    )
+    .header(Row::new(vec!["Format", "Files", "Size"]).style(Style::default().fg(Color::Cyan)))
+    .block(Block::default().title("Formats").borders(Borders::ALL));
+
+    frame.render_widget(formats, columns[0]);
+    render_top(frame, "Top genres", &stats.top_genres, columns[1]);
+}
+
+/// Horizontal bar chart of play counts.
+fn render_top(frame: &mut Frame, title: &str, top: &[(String, u64)], area: Rect) {
+    let bars: Vec<Bar> = top
+        .iter()
+        .map(|(name, plays)| {
+            Bar::default()
+                .value(*plays)
+                .label(Line::from(name.as_str()))
+                .text_value(plays.to_string())
+        })
+        .collect();
+
+    let chart = BarChart::default()
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .bar_style(Style::default().fg(Color::Cyan))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Cyan))
+        .data(BarGroup::default().bars(&bars));
+
+    frame.render_widget(chart, area);
+}
@@ -0,0 +1,45 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::history::HistoryEntry;
+
+/// Appends a completed play to a Rockbox style `.scrobbler.log`.
+///
+/// The format (AudioScrobbler portable player log 1.1) is understood by tools
+/// that submit offline plays to Last.fm later. The header is written when the
+/// file is created; times are Unix timestamps in UTC.
+pub fn append(
+    path: &Path,
+    entry: &HistoryEntry,
+    track_number: Option<u32>,
+    length: u64,
+) -> io::Result<()> {
+    let is_new = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    if is_new {
+        writeln!(file, "#AUDIOSCROBBLER/1.1")?;
+        writeln!(file, "#TZ/UTC")?;
+        writeln!(file, "#CLIENT/shelltrax {}", env!("CARGO_PKG_VERSION"))?;
+    }
+
+    // The log has the time the track started; plays are recorded at the end
+    let started_at = entry.played_at.saturating_sub(entry.listened);
+
+    writeln!(
+        file,
+        "{}\t{}\t{}\t{}\t{}\tL\t{}\t",
+        field(&entry.artist),
+        field(&entry.album),
+        field(&entry.title),
+        track_number.map(|n| n.to_string()).unwrap_or_default(),
+        length,
+        started_at,
+    )
+}
+
+/// Tabs and newlines would break the line format.
+fn field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
@@ -0,0 +1,96 @@
+use std::cmp::Reverse;
+
+use crate::library::LibraryTrack;
+use crate::list::ListSelector;
+
+/// Results shown for a query; more than fit on screen is no use.
+const MAX_RESULTS: usize = 100;
+
+/// The library search overlay: what's typed so far and the best matches.
+pub struct Search {
+    pub query: String,
+    pub results: ListSelector<LibraryTrack>,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            results: ListSelector::new(Vec::new()),
+        }
+    }
+
+    /// Ranks `tracks` against the query again, best match first.
+    pub fn update<'a>(&mut self, tracks: impl Iterator<Item = &'a LibraryTrack>) {
+        let words: Vec<Vec<char>> = self
+            .query
+            .split_whitespace()
+            .map(|word| word.to_lowercase().chars().collect())
+            .collect();
+        if words.is_empty() {
+            self.results.set_entries(Vec::new());
+            return;
+        }
+
+        let mut matches: Vec<(u32, &LibraryTrack)> = tracks
+            .filter_map(|track| {
+                let text: Vec<char> = format!("{} {} {}", track.artist, track.album, track.title)
+                    .to_lowercase()
+                    .chars()
+                    .collect();
+                let score = words
+                    .iter()
+                    .map(|word| word_score(word, &text))
+                    .sum::<Option<u32>>()?;
+                Some((score, track))
+            })
+            .collect();
+
+        // Equally good matches go shortest title first, as the closer fit
+        matches.sort_by_key(|(score, track)| (Reverse(*score), track.title.len()));
+        matches.truncate(MAX_RESULTS);
+        self.results.set_entries(
+            matches
+                .into_iter()
+                .map(|(_, track)| track.clone())
+                .collect(),
+        );
+    }
+}
+
+/// Whether each word of `query` matches `text` the way search results do.
+/// An empty query matches everything.
+pub fn matches(query: &str, text: &str) -> bool {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    query
+        .split_whitespace()
+        .all(|word| word_score(&word.to_lowercase().chars().collect::<Vec<_>>(), &text).is_some())
+}
+
+/// How well `word` matches `text` with its letters in order but not
+/// necessarily next to each other, or `None` when it doesn't. Letters that
+/// start a word or follow the previous match count for more, and the whole
+/// word appearing as is counts for most.
+fn word_score(word: &[char], text: &[char]) -> Option<u32> {
+    let mut score = 0;
+    let mut from = 0;
+    let mut previous = None;
+
+    for c in word {
+        let at = from + text[from..].iter().position(|t| t == c)?;
+        score += 1;
+        if at == 0 || !text[at - 1].is_alphanumeric() {
+            score += 6;
+        }
+        if previous.is_some_and(|p| p + 1 == at) {
+            score += 4;
+        }
+        previous = Some(at);
+        from = at + 1;
+    }
+
+    if text.windows(word.len()).any(|window| window == word) {
+        score += 2 * word.len() as u32;
+    }
+    Some(score)
+}
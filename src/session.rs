@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::{AppScreen, RepeatMode};
+
+/// A named snapshot of what was playing and how the app was set up, so
+/// different listening contexts can be switched between.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    /// Unix time the snapshot was taken
+    pub saved_at: u64,
+
+    pub screen: AppScreen,
+    pub label_filter: Option<String>,
+
+    pub queue: Vec<PathBuf>,
+    pub queue_index: usize,
+    pub track: Option<PathBuf>,
+    /// Seconds into `track`
+    pub position: u64,
+    pub paused: bool,
+
+    pub volume: f32,
+    pub autoplay: bool,
+    pub auto_dj: bool,
+    pub karaoke: bool,
+    pub crossfeed: bool,
+    pub night_mode: bool,
+    #[serde(default)]
+    pub repeat: RepeatMode,
+    #[serde(default)]
+    pub shuffle: bool,
+}
@@ -0,0 +1,47 @@
+use std::path::{Path, PathBuf};
+
+use rand::seq::SliceRandom;
+
+/// A random play order for a list of tracks, kept apart from the order they
+/// are shown in. Each track comes up once per round.
+pub struct ShuffleOrder {
+    /// The tracks being shuffled, in display order
+    pool: Vec<PathBuf>,
+    /// Tracks not played yet this round, next one first
+    remaining: Vec<PathBuf>,
+}
+
+impl ShuffleOrder {
+    pub fn new(pool: Vec<PathBuf>) -> Self {
+        let mut order = Self {
+            pool,
+            remaining: Vec::new(),
+        };
+        order.restart();
+        order
+    }
+
+    /// Whether this order was made for exactly `tracks`.
+    pub fn covers(&self, tracks: &[PathBuf]) -> bool {
+        self.pool == tracks
+    }
+
+    pub fn next(&self) -> Option<&PathBuf> {
+        self.remaining.first()
+    }
+
+    /// Takes `path` out of the current round.
+    pub fn played(&mut self, path: &Path) {
+        self.remaining.retain(|p| p != path);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    /// Starts a new round in a fresh random order.
+    pub fn restart(&mut self) {
+        self.remaining = self.pool.clone();
+        self.remaining.shuffle(&mut rand::rng());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::history::HistoryEntry;
+use crate::library::{ArtistNode, FileStamp};
+
+/// How many entries each top list keeps.
+const TOP_COUNT: usize = 10;
+
+/// Time window the stats view covers, counted back from now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsPeriod {
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl StatsPeriod {
+    pub fn next(self) -> Self {
+        match self {
+            StatsPeriod::Week => StatsPeriod::Month,
+            StatsPeriod::Month => StatsPeriod::Year,
+            StatsPeriod::Year => StatsPeriod::All,
+            StatsPeriod::All => StatsPeriod::Week,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StatsPeriod::Week => "Last 7 days",
+            StatsPeriod::Month => "Last 30 days",
+            StatsPeriod::Year => "Last 365 days",
+            StatsPeriod::All => "All time",
+        }
+    }
+
+    fn days(self) -> Option<u64> {
+        match self {
+            StatsPeriod::Week => Some(7),
+            StatsPeriod::Month => Some(30),
+            StatsPeriod::Year => Some(365),
+            StatsPeriod::All => None,
+        }
+    }
+}
+
+/// Totals and top lists for one period; top lists are `(name, plays)`.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub plays: usize,
+    pub listened_secs: u64,
+    pub top_artists: Vec<(String, u64)>,
+    pub top_albums: Vec<(String, u64)>,
+    pub top_tracks: Vec<(String, u64)>,
+}
+
+pub fn compute(entries: &[HistoryEntry], period: StatsPeriod, now: u64) -> Stats {
+    let since = period
+        .days()
+        .map(|days| now.saturating_sub(days * 24 * 60 * 60))
+        .unwrap_or(0);
+
+    let mut stats = Stats::default();
+    let mut artists: HashMap<String, u64> = HashMap::new();
+    let mut albums: HashMap<String, u64> = HashMap::new();
+    let mut tracks: HashMap<String, u64> = HashMap::new();
+
+    for entry in entries.iter().filter(|entry| entry.played_at >= since) {
+        stats.plays += 1;
+        stats.listened_secs += entry.listened;
+
+        *artists.entry(entry.artist.clone()).or_default() += 1;
+        *albums
+            .entry(format!("{} – {}", entry.album_artist, entry.album))
+            .or_default() += 1;
+        *tracks
+            .entry(format!("{} – {}", entry.artist, entry.title))
+            .or_default() += 1;
+    }
+
+    stats.top_artists = top(artists);
+    stats.top_albums = top(albums);
+    stats.top_tracks = top(tracks);
+    stats
+}
+
+/// What's in the library, as opposed to what was played.
+#[derive(Debug, Default)]
+pub struct LibraryStats {
+    pub tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+    /// Of the tracks whose length is known
+    pub duration_secs: u64,
+    pub size_bytes: u64,
+    /// `(extension, files, bytes)`, most files first
+    pub formats: Vec<(String, usize, u64)>,
+    /// `(genre, tracks)`
+    pub top_genres: Vec<(String, u64)>,
+}
+
+/// Counts up the library. Sizes are the ones recorded when the files were
+/// scanned; the audio of cue sheets, which are recorded themselves, is
+/// looked up here.
+pub fn library(artists: &[ArtistNode]) -> LibraryStats {
+    let mut stats = LibraryStats::default();
+    let mut track_artists = HashSet::new();
+    let mut files = HashSet::new();
+    let mut formats: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut genres: HashMap<String, u64> = HashMap::new();
+
+    for album in artists.iter().flat_map(|a| &a.albums) {
+        stats.albums += 1;
+        for track in &album.tracks {
+            stats.tracks += 1;
+            stats.duration_secs += track.duration.unwrap_or(0);
+            track_artists.insert(track.artist.as_str());
+            if let Some(genre) = &track.genre {
+                *genres.entry(genre.clone()).or_default() += 1;
+            }
+
+            // Tracks of one cue sheet share a file, counted once
+            let file = track
+                .cue
+                .as_ref()
+                .map_or(track.path.as_path(), |cue| &cue.file);
+            if !files.insert(file) {
+                continue;
+            }
+            let stamp = match track.cue {
+                Some(_) => FileStamp::of(file),
+                None => track.stamp,
+            };
+            let size = stamp.map_or(0, |stamp| stamp.size);
+            stats.size_bytes += size;
+            let format = formats.entry(extension(file)).or_default();
+            format.0 += 1;
+            format.1 += size;
+        }
+    }
+
+    stats.artists = track_artists.len();
+    stats.formats = formats
+        .into_iter()
+        .map(|(extension, (files, bytes))| (extension, files, bytes))
+        .collect();
+    stats
+        .formats
+        .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    stats.top_genres = top(genres);
+    stats
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or("other".to_string(), str::to_ascii_lowercase)
+}
+
+/// A byte count in the largest unit that keeps it above 1, as in `3.2 GB`.
+pub fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KB", "MB", "GB"] {
+        if size < 1024.0 {
+            return match unit {
+                "B" => format!("{bytes} B"),
+                _ => format!("{size:.1} {unit}"),
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} TB")
+}
+
+fn top(counts: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(TOP_COUNT);
+    counts
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use id3::frame::{ExtendedText, Popularimeter, Unknown};
+use id3::{Content, Frame, Tag, TagLike, Timestamp, Version};
+
+use crate::replaygain::ReplayGain;
+use crate::retag::RetagField;
+
+/// `POPM` frames are per user; this is ours.
+pub const POPM_USER: &str = "shelltrax";
+
+/// Updates are written once no new ones arrived for this long.
+const FLUSH_AFTER: Duration = Duration::from_secs(2);
+
+/// Batches this large are written without waiting for a quiet period.
+const MAX_BATCH: usize = 32;
+
+const FLAC_VORBIS_COMMENT: u8 = 4;
+const FLAC_PADDING: u8 = 1;
+
+/// Block lengths are 24 bits.
+const MAX_BLOCK_LEN: usize = (1 << 24) - 1;
+
+/// Room left for later edits when a FLAC file has to be rewritten anyway.
+const NEW_PADDING: usize = 4096;
+
+/// A track's names and numbers as a release database has them.
+#[derive(Debug, Clone)]
+pub struct AlbumTags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: u32,
+    pub disc_number: u32,
+    pub year: Option<u32>,
+}
+
+/// Library-side values to store in a file's tags.
+#[derive(Debug, Clone)]
+pub struct TagUpdate {
+    pub path: PathBuf,
+    /// Stars, 1 - 5
+    pub rating: Option<u8>,
+    pub play_count: u32,
+}
+
+/// Writes rating and play count tags on a background thread.
+///
+/// Updates for the same file are coalesced, so skipping through a rating or
+/// replaying a track only touches the file once per batch.
+pub struct TagWriter {
+    sender: Sender<TagUpdate>,
+    handle: JoinHandle<()>,
+}
+
+impl TagWriter {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || run(receiver));
+
+        Self { sender, handle }
+    }
+
+    pub fn queue(&self, update: TagUpdate) {
+        if self.sender.send(update).is_err() {
+            log::error!("Tag writer has stopped, dropping update");
+        }
+    }
+
+    /// Writes any pending updates and stops the thread.
+    pub fn finish(self) {
+        drop(self.sender);
+        self.handle.join().ok();
+    }
+}
+
+fn run(receiver: Receiver<TagUpdate>) {
+    let mut pending: HashMap<PathBuf, TagUpdate> = HashMap::new();
+
+    loop {
+        let received = if pending.is_empty() {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(FLUSH_AFTER)
+        };
+
+        match received {
+            Ok(update) => {
+                pending.insert(update.path.clone(), update);
+                if pending.len() >= MAX_BATCH {
+                    flush(&mut pending);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => flush(&mut pending),
+            Err(RecvTimeoutError::Disconnected) => {
+                flush(&mut pending);
+                return;
+            }
+        }
+    }
+}
+
+fn flush(pending: &mut HashMap<PathBuf, TagUpdate>) {
+    log::debug!("Writing tags for {} files", pending.len());
+
+    for (path, update) in pending.drain() {
+        if let Err(err) = write_tags(&update) {
+            log::error!("Failed to write tags to {:?}: {err}", path);
+        }
+    }
+}
+
+fn write_tags(update: &TagUpdate) -> anyhow::Result<()> {
+    match extension(&update.path).as_deref() {
+        Some("mp3") => edit_id3(&update.path, |tag| set_id3_counts(tag, update)),
+        Some("flac") => edit_flac(&update.path, |comments| {
+            comments.set("FMPS_RATING", update.rating.map(fmps_rating));
+            comments.set(
+                "RATING",
+                update
+                    .rating
+                    .map(|stars| (u32::from(stars) * 20).to_string()),
+            );
+            comments.set("FMPS_PLAYCOUNT", Some(update.play_count.to_string()));
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `write_replay_gain` knows how to tag `path`.
+pub fn can_write_replay_gain(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("mp3" | "flac"))
+}
+
+/// Stores `gain` as `REPLAYGAIN_*` tags, as `TXXX` frames in MP3s and Vorbis
+/// comments in FLACs. Values that are `None` remove the tag.
+pub fn write_replay_gain(path: &Path, gain: &ReplayGain) -> anyhow::Result<()> {
+    let values = [
+        (
+            "REPLAYGAIN_TRACK_GAIN",
+            gain.track_gain.map(|db| format!("{db:.2} dB")),
+        ),
+        (
+            "REPLAYGAIN_TRACK_PEAK",
+            gain.track_peak.map(|peak| format!("{peak:.6}")),
+        ),
+        (
+            "REPLAYGAIN_ALBUM_GAIN",
+            gain.album_gain.map(|db| format!("{db:.2} dB")),
+        ),
+        (
+            "REPLAYGAIN_ALBUM_PEAK",
+            gain.album_peak.map(|peak| format!("{peak:.6}")),
+        ),
+    ];
+
+    match extension(path).as_deref() {
+        Some("mp3") => edit_id3(path, |tag| {
+            for (key, value) in values {
+                tag.remove_extended_text(Some(key), None);
+                if let Some(value) = value {
+                    tag.add_frame(ExtendedText {
+                        description: key.to_string(),
+                        value,
+                    });
+                }
+            }
+        }),
+        Some("flac") => edit_flac(path, |comments| {
+            for (key, value) in values {
+                comments.set(key, value);
+            }
+        }),
+        _ => bail!("can only tag MP3 and FLAC files"),
+    }
+}
+
+/// Whether `write_retag` knows how to tag `path`.
+pub fn can_retag(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("mp3" | "flac"))
+}
+
+/// Stores `value` as the file's `field` tag.
+pub fn write_retag(path: &Path, field: RetagField, value: &str) -> anyhow::Result<()> {
+    match extension(path).as_deref() {
+        Some("mp3") => edit_id3(path, |tag| match field {
+            RetagField::AlbumArtist => tag.set_album_artist(value),
+            RetagField::Album => tag.set_album(value),
+            RetagField::TrackNumber => match value.parse() {
+                Ok(number) => tag.set_track(number),
+                Err(_) => tag.remove_track(),
+            },
+        }),
+        Some("flac") => edit_flac(path, |comments| {
+            let key = match field {
+                RetagField::AlbumArtist => "ALBUMARTIST",
+                RetagField::Album => "ALBUM",
+                RetagField::TrackNumber => "TRACKNUMBER",
+            };
+            comments.set(key, Some(value.to_string()));
+        }),
+        _ => bail!("can only tag MP3 and FLAC files"),
+    }
+}
+
+/// Replaces the file's title, artist, album, numbering and year tags with
+/// `tags`. A missing year leaves the file's own.
+pub fn write_album_tags(path: &Path, tags: &AlbumTags) -> anyhow::Result<()> {
+    match extension(path).as_deref() {
+        Some("mp3") => edit_id3(path, |tag| {
+            tag.set_title(tags.title.as_str());
+            tag.set_artist(tags.artist.as_str());
+            tag.set_album(tags.album.as_str());
+            tag.set_album_artist(tags.album_artist.as_str());
+            tag.set_track(tags.track_number);
+            tag.set_disc(tags.disc_number);
+            if let Some(year) = tags.year {
+                // The release date is read before the recording date
+                tag.remove_date_released();
+                tag.set_date_recorded(Timestamp {
+                    year: year as i32,
+                    month: None,
+                    day: None,
+                    hour: None,
+                    minute: None,
+                    second: None,
+                });
+            }
+        }),
+        Some("flac") => edit_flac(path, |comments| {
+            comments.set("TITLE", Some(tags.title.clone()));
+            comments.set("ARTIST", Some(tags.artist.clone()));
+            comments.set("ALBUM", Some(tags.album.clone()));
+            comments.set("ALBUMARTIST", Some(tags.album_artist.clone()));
+            comments.set("TRACKNUMBER", Some(tags.track_number.to_string()));
+            comments.set("DISCNUMBER", Some(tags.disc_number.to_string()));
+            if let Some(year) = tags.year {
+                comments.set("DATE", Some(year.to_string()));
+            }
+        }),
+        _ => bail!("can only tag MP3 and FLAC files"),
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|s| s.to_ascii_lowercase())
+}
+
+/// Maps stars to the `POPM` values most players agree on.
+fn popm_rating(stars: u8) -> u8 {
+    match stars {
+        0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+/// FMPS ratings are a fraction between 0.0 and 1.0.
+fn fmps_rating(stars: u8) -> String {
+    format!("{:.1}", f32::from(stars.min(5)) / 5.0)
+}
+
+/// Reads the file's ID3 tag, or starts a new one, and writes it back after
+/// `edit`.
+fn edit_id3(path: &Path, edit: impl FnOnce(&mut Tag)) -> anyhow::Result<()> {
+    let mut tag = id3::no_tag_ok(Tag::read_from_path(path))?.unwrap_or_default();
+    edit(&mut tag);
+    tag.write_to_path(path, tag.version())?;
+    Ok(())
+}
+
+/// Sets `POPM`, `PCNT` and the FMPS `TXXX` frames.
+fn set_id3_counts(tag: &mut Tag, update: &TagUpdate) {
+    tag.add_frame(Popularimeter {
+        user: POPM_USER.to_string(),
+        rating: update.rating.map(popm_rating).unwrap_or(0),
+        counter: u64::from(update.play_count),
+    });
+
+    tag.remove("PCNT");
+    tag.add_frame(Frame::with_content(
+        "PCNT",
+        Content::Unknown(Unknown {
+            data: update.play_count.to_be_bytes().to_vec(),
+            version: Version::Id3v24,
+        }),
+    ));
+
+    tag.remove_extended_text(Some("FMPS_Rating"), None);
+    if let Some(stars) = update.rating {
+        tag.add_frame(ExtendedText {
+            description: "FMPS_Rating".to_string(),
+            value: fmps_rating(stars),
+        });
+    }
+
+    tag.add_frame(ExtendedText {
+        description: "FMPS_Playcount".to_string(),
+        value: update.play_count.to_string(),
+    });
+}
+
+/// Rewrites the Vorbis comment block of a FLAC file after `edit`, leaving
+/// the audio as is. The metadata is rewritten in place when the file's
+/// padding has room for it; otherwise the file is copied with fresh padding.
+fn edit_flac(path: &Path, edit: impl FnOnce(&mut VorbisComments)) -> anyhow::Result<()> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        bail!("not a FLAC stream");
+    }
+
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    let mut audio_start = 4;
+    loop {
+        let mut header = [0; 4];
+        file.read_exact(&mut header)
+            .map_err(|_| anyhow!("truncated metadata block header"))?;
+        let is_last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        let mut body = vec![0; len];
+        file.read_exact(&mut body)
+            .map_err(|_| anyhow!("truncated metadata block"))?;
+        audio_start += 4 + len as u64;
+        // Padding is sized again below
+        if kind != FLAC_PADDING {
+            blocks.push((kind, body));
+        }
+
+        if is_last {
+            break;
+        }
+    }
+
+    let mut comments = match blocks.iter().find(|(kind, _)| *kind == FLAC_VORBIS_COMMENT) {
+        Some((_, body)) => {
+            VorbisComments::parse(body).ok_or_else(|| anyhow!("bad Vorbis comment block"))?
+        }
+        None => VorbisComments {
+            vendor: POPM_USER.to_string(),
+            comments: Vec::new(),
+        },
+    };
+
+    edit(&mut comments);
+    let comment_block = comments.to_bytes();
+    if comment_block.len() > MAX_BLOCK_LEN {
+        bail!("tags too large for a FLAC comment block");
+    }
+
+    // STREAMINFO has to stay first; the comments go right after it
+    blocks.retain(|(kind, _)| *kind != FLAC_VORBIS_COMMENT);
+    blocks.insert(1.min(blocks.len()), (FLAC_VORBIS_COMMENT, comment_block));
+
+    let needed: usize = blocks.iter().map(|(_, body)| 4 + body.len()).sum();
+    let room = (audio_start - 4) as usize;
+    // What's left over has to hold a padding block's header, or nothing at all
+    let in_place = match room.checked_sub(needed) {
+        Some(0) => Some(None),
+        Some(spare) if (4..=MAX_BLOCK_LEN + 4).contains(&spare) => Some(Some(spare - 4)),
+        _ => None,
+    };
+
+    if let Some(padding) = in_place {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&metadata_bytes(blocks, padding))?;
+        return Ok(());
+    }
+
+    // Write next to the original and swap it in, so a crash can't truncate it
+    let tmp = path.with_extension("flac.shelltrax-tmp");
+    let result = (|| -> anyhow::Result<()> {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(b"fLaC")?;
+        out.write_all(&metadata_bytes(blocks, Some(NEW_PADDING)))?;
+        file.seek(SeekFrom::Start(audio_start))?;
+        io::copy(&mut file, &mut out)?;
+        out.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::set_permissions(&tmp, fs::metadata(path)?.permissions())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Metadata blocks as they're laid out after the `fLaC` marker, followed by
+/// a padding block of the given size.
+fn metadata_bytes(blocks: Vec<(u8, Vec<u8>)>, padding: Option<usize>) -> Vec<u8> {
+    let mut blocks = blocks;
+    if let Some(len) = padding {
+        blocks.push((FLAC_PADDING, vec![0; len]));
+    }
+
+    let mut out = Vec::new();
+    for (i, (kind, body)) in blocks.iter().enumerate() {
+        let last_flag = if i + 1 == blocks.len() { 0x80 } else { 0 };
+        out.push(kind | last_flag);
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(body);
+    }
+    out
+}
+
+struct VorbisComments {
+    vendor: String,
+    comments: Vec<String>,
+}
+
+impl VorbisComments {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut pos = 0;
+        let vendor = read_string(data, &mut pos)?;
+        let count = read_u32_le(data, &mut pos)?;
+
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            comments.push(read_string(data, &mut pos)?);
+        }
+
+        Some(Self { vendor, comments })
+    }
+
+    /// Replaces every `key=` comment (case-insensitively) with `value`.
+    fn set(&mut self, key: &str, value: Option<String>) {
+        self.comments.retain(|comment| {
+            !comment
+                .split_once('=')
+                .is_some_and(|(name, _)| name.eq_ignore_ascii_case(key))
+        });
+
+        if let Some(value) = value {
+            self.comments.push(format!("{key}={value}"));
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, &self.vendor);
+        out.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for comment in &self.comments {
+            write_string(&mut out, comment);
+        }
+        out
+    }
+}
+
+fn read_u32_le(data: &[u8], pos: &mut usize) -> Option<u32> {
+    let bytes = data.get(*pos..*pos + 4)?;
+    *pos += 4;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_string(data: &[u8], pos: &mut usize) -> Option<String> {
+    let len = read_u32_le(data, pos)? as usize;
+    let bytes = data.get(*pos..*pos + len)?;
+    *pos += len;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
@@ -1,15 +1,17 @@
 use crate::{
-    app::{App, AppScreen},
+    app::{App, AppScreen, RepeatMode},
+    replaygain::ReplayGainMode,
     screens,
 };
 use ratatui::prelude::*;
 use ratatui::widgets::{
+    Block,
+    Borders,
+    Clear,
     Gauge,
     Paragraph,
 };
 
-use std::time::Instant;
-
 pub fn draw_ui(frame: &mut Frame, app: &mut App) {
     let layout = Layout::default()
         .direction(Direction::Vertical)
@@ -22,20 +24,178 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
     match app.screen {
         AppScreen::Library => screens::library::draw(frame, app, layout[0]),
         AppScreen::Browser => screens::browser::draw(frame, app, layout[0]),
+        AppScreen::History => screens::history::draw(frame, app, layout[0]),
+        AppScreen::Recent => screens::recent::draw(frame, app, layout[0]),
+        AppScreen::Stats => screens::stats::draw(frame, app, layout[0]),
+        AppScreen::Duplicates => screens::duplicates::draw(frame, app, layout[0]),
         _ => println!("ok"),
     }
 
+    if app.bookmark_list.is_some() {
+        screens::bookmarks::draw(frame, app, layout[0]);
+    }
+
+    if app.chapter_list.is_some() {
+        screens::chapters::draw(frame, app, layout[0]);
+    }
+
+    if app.label_list.is_some() {
+        screens::labels::draw(frame, app, layout[0]);
+    }
+
+    if app.session_list.is_some() {
+        screens::sessions::draw(frame, app, layout[0]);
+    }
+
+    if app.station_list.is_some() {
+        screens::stations::draw(frame, app, layout[0]);
+    }
+
+    if app.album_lookup.is_some() {
+        screens::lookup::draw(frame, app, layout[0]);
+    }
+
+    if app.retag_menu.is_some() || app.retag_preview.is_some() {
+        screens::retag::draw(frame, app, layout[0]);
+    }
+
+    if app.smart_list.is_some() {
+        screens::smartplaylists::draw(frame, app, layout[0]);
+    }
+
+    if app.missing_list.is_some() {
+        screens::missing::draw(frame, app, layout[0]);
+    }
+
+    if app.search.is_some() {
+        screens::search::draw(frame, app, layout[0]);
+    }
+
+    if app.equalizer_band.is_some() {
+        screens::equalizer::draw(frame, app, layout[0]);
+    }
+
+    if app.settings_row.is_some() {
+        screens::settings::draw(frame, app, layout[0]);
+    }
+
+    if app.show_diagnostics {
+        screens::diagnostics::draw(frame, app, layout[0]);
+    }
+
+    if app.library_scan.is_some() {
+        screens::scan::draw(frame, app, layout[0]);
+    }
+
+    if !app.keymap.pending().is_empty() {
+        render_key_hints(frame, app, layout[0]);
+    }
+
+    if let Some(message) = app.toast() {
+        render_toast(frame, message, layout[0]);
+    }
+
     render_footer(frame, app, layout[1]);
 }
 
+/// A rectangle of `percent_x` by `percent_y` centered in `area`, for popups.
+pub fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
 pub fn highlight_style(screen: AppScreen) -> Style {
     match screen {
         AppScreen::Library => Style::default().bg(Color::Green).fg(Color::Black),
         AppScreen::Browser => Style::default().bg(Color::Blue).fg(Color::White),
+        AppScreen::History => Style::default().bg(Color::Magenta).fg(Color::Black),
+        AppScreen::Recent => Style::default().bg(Color::Yellow).fg(Color::Black),
+        AppScreen::Stats => Style::default().bg(Color::Cyan).fg(Color::Black),
+        AppScreen::Duplicates => Style::default().bg(Color::Red).fg(Color::White),
     }
 }
 
+/// which-key style popup listing the keys that can follow the pending sequence.
+fn render_key_hints(f: &mut Frame, app: &App, area: Rect) {
+    let pending: Vec<String> = app.keymap.pending().iter().map(|k| k.to_string()).collect();
+    let hints = app.keymap.hints();
+
+    let lines: Vec<Line> = hints
+        .iter()
+        .map(|(keys, label)| {
+            Line::from(vec![
+                Span::styled(format!("{keys:>8} "), Style::default().fg(Color::Yellow)),
+                Span::raw(format!("→ {label}")),
+            ])
+        })
+        .collect();
+
+    let width = 34.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + area.width - width,
+        y: area.y + area.height - height,
+        width,
+        height,
+    };
+
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .title(pending.join(" "))
+            .borders(Borders::ALL),
+    );
+
+    f.render_widget(Clear, popup);
+    f.render_widget(widget, popup);
+}
+
+/// A one-line notice in the bottom right corner of `area`.
+fn render_toast(f: &mut Frame, message: &str, area: Rect) {
+    let width = (message.chars().count() as u16 + 4).min(area.width);
+    let height = 3.min(area.height);
+    let popup = Rect {
+        x: area.x + area.width - width,
+        y: area.y + area.height - height,
+        width,
+        height,
+    };
+
+    let widget = Paragraph::new(message)
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(Clear, popup);
+    f.render_widget(widget, popup);
+}
+
 fn render_footer(f: &mut Frame, app: &App, area: Rect) {
+    if let Some(prompt) = &app.prompt {
+        let input = match prompt.masked {
+            true => "*".repeat(prompt.input.chars().count()),
+            false => prompt.input.clone(),
+        };
+        let line = Paragraph::new(format!("{}: {}█", prompt.label, input))
+            .style(Style::default().fg(Color::Yellow));
+
+        f.render_widget(line, area);
+        return;
+    }
+
     if let Some(track) = &app.current_track {
         log::debug!(
             "Rendering footer: {} – {}, elapsed: {:?}",
@@ -44,20 +204,13 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
             app.playback_start.map(|t| t.elapsed())
         );
 
-        let elapsed = app.playback_start.map(|start| {
-            let now = if let Some(paused_at) = app.paused_at {
-                paused_at // freeze at time of pause
-            } else {
-                Instant::now()
-            };
-
-            now.duration_since(start)
-                .saturating_sub(app.paused_duration)
-                .as_secs()
-        }).unwrap_or(0);
+        let elapsed = app.elapsed().as_secs();
 
-        let dur = track.duration.unwrap_or(0);
-        let pos = elapsed.min(dur);
+        let dur = app.track_length().map_or(0, |length| length.as_secs());
+        let pos = match dur {
+            0 => elapsed,
+            dur => elapsed.min(dur),
+        };
 
         let percent = if dur > 0 {
             pos as f64 / dur as f64
@@ -65,13 +218,73 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
             0.0
         };
 
+        let mut effects = app
+            .active_effects()
+            .iter()
+            .map(|effect| format!("  [{effect}]"))
+            .collect::<String>();
+        if app.auto_dj {
+            effects.push_str("  [auto-dj]");
+        }
+        if app.muted {
+            effects.push_str("  [muted]");
+        }
+        if app.stop_after_current {
+            effects.push_str("  [stop after]");
+        }
+        if app.shuffle {
+            effects.push_str("  [shuffle]");
+        }
+        if app.repeat != RepeatMode::Off {
+            effects.push_str(&format!("  [repeat {}]", app.repeat.label()));
+        }
+        if app.party_locked {
+            effects.push_str("  [party]");
+        }
+        match app.ab_loop {
+            Some((_, Some(_))) => effects.push_str("  [A-B]"),
+            Some((_, None)) => effects.push_str("  [A-]"),
+            None => {}
+        }
+        if app.playback_speed() != 1.0 {
+            effects.push_str(&format!("  [{}x]", app.playback_speed()));
+        }
+        if app.replay_gain_mode() != ReplayGainMode::Off {
+            effects.push_str(&format!("  [rg {}]", app.replay_gain_mode().label()));
+        }
+        if app.output_missing() {
+            effects.push_str("  [no audio device]");
+        }
+        if app.player_mut().recording_path().is_some() {
+            effects.push_str("  [rec]");
+        }
+
+        let track_gain = if track.gain_db != 0.0 {
+            format!(" ({:+} dB)", track.gain_db)
+        } else {
+            String::new()
+        };
+
+        // Where in an audiobook, or what a radio station is playing
+        let detail = match app.current_chapter() {
+            Some(index) => Some(app.chapters[index].title.clone()),
+            None => app.icy_info().and_then(|icy| icy.title),
+        };
+        let detail = detail
+            .map(|detail| format!(" · {detail}"))
+            .unwrap_or_default();
+
         let info_line = Paragraph::new(format!(
-            "▶ {} – {} - {}  {:02}:{:02} / {:02}:{:02}",
+            "▶ {} – {} - {}{}  {:02}:{:02} / {:02}:{:02}  vol {}%{}{}",
             track.album_artist,
             track.album,
             track.title,
+            detail,
             pos / 60, pos % 60,
             dur / 60, dur % 60,
+            (app.volume * 100.0).round() as u32,
+            track_gain,
+            effects,
         ))
         .style(Style::default().fg(Color::Gray));
 
@@ -94,8 +307,11 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
 
         f.render_widget(gauge, gauge_chunk);
     } else {
-        let empty = Paragraph::new("⏹ Nothing playing")
-            .style(Style::default().fg(Color::DarkGray));
+        let status = match app.output_missing() {
+            true => "⏹ No audio device",
+            false => "⏹ Nothing playing",
+        };
+        let empty = Paragraph::new(status).style(Style::default().fg(Color::DarkGray));
 
         f.render_widget(empty, area);
     }
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long the library folders have to be quiet before changes are picked
+/// up, so a file being copied is only read once it's complete.
+const SETTLE_TIME: Duration = Duration::from_millis(1000);
+
+/// Watches the library folders for files being added, changed or removed.
+pub struct LibraryWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// Paths touched since the last batch was handed out
+    pending: HashSet<PathBuf>,
+    last_event: Instant,
+}
+
+impl LibraryWatcher {
+    /// Starts watching each of `roots` and everything below them.
+    pub fn new(roots: &[PathBuf]) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            sender.send(event).ok();
+        })?;
+
+        let mut watcher = Self {
+            watcher,
+            events,
+            pending: HashSet::new(),
+            last_event: Instant::now(),
+        };
+        for root in roots {
+            watcher.watch(root);
+        }
+        Ok(watcher)
+    }
+
+    /// Adds another folder, e.g. one just added to the library.
+    pub fn watch(&mut self, root: &Path) {
+        if let Err(err) = self.watcher.watch(root, RecursiveMode::Recursive) {
+            log::warn!("Failed to watch {:?} for changes: {err}", root);
+        }
+    }
+
+    /// The paths that were created, changed or removed, once things have
+    /// settled down. Empty while changes are still coming in.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("Library watcher error: {err}");
+                    continue;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+                continue;
+            }
+
+            self.pending.extend(event.paths);
+            self.last_event = Instant::now();
+        }
+
+        if self.pending.is_empty() || self.last_event.elapsed() < SETTLE_TIME {
+            return Vec::new();
+        }
+        self.pending.drain().collect()
+    }
+}